@@ -0,0 +1,166 @@
+/*!
+ * Activation - the per-machine license that unlocks premium node types
+ *
+ * A license key is `<claims>.<signature>`, both base64url without padding.
+ * The claims are JSON naming the licensee and the machine id the license was
+ * issued for; the signature is Ed25519 over the claims bytes, made with the
+ * vendor key whose public half is embedded below. Checking a key needs no
+ * network, so an activated app keeps working offline.
+ *
+ * The accepted key is kept in the app data directory, shared by every
+ * profile, and checked again on each launch. One that no longer checks out
+ * (edited, copied from another machine, expired) leaves the app deactivated,
+ * with the reason in the status; the file itself is left alone.
+ */
+
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD};
+use base64::Engine;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Under the app data directory.
+pub const LICENSE_FILE: &str = "license.key";
+
+// Base64 Ed25519 public key the vendor signs licenses with.
+const LICENSE_PUBLIC_KEY: &str = "CjJ+4+6HMePke+shOBysWiuHJzYebOp2kehrLyNbJg0=";
+
+#[derive(Debug, Error)]
+pub enum ActivationError {
+    #[error("malformed license key: {0}")]
+    Malformed(String),
+    #[error("license signature is invalid")]
+    SignatureInvalid,
+    #[error("license was issued for another machine")]
+    WrongMachine,
+    #[error("license expired at {0}")]
+    Expired(chrono::DateTime<chrono::Utc>),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ActivationError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicenseClaims {
+    pub license_id: String,
+    pub licensee: String,
+    pub machine_id: String,
+    pub issued_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActivationStatus {
+    pub active: bool,
+    pub license: Option<LicenseClaims>,
+    // Why the stored license was not accepted
+    pub error: Option<String>,
+}
+
+fn embedded_key() -> VerifyingKey {
+    let bytes: [u8; 32] = BASE64
+        .decode(LICENSE_PUBLIC_KEY)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .expect("embedded license key is 32 base64 bytes");
+    VerifyingKey::from_bytes(&bytes).expect("embedded license key is a valid Ed25519 key")
+}
+
+// Checks the signature first, so nothing in unsigned claims is trusted.
+pub fn verify(license_key: &str, machine_id: &str, public_key: &VerifyingKey) -> Result<LicenseClaims> {
+    let malformed = |reason: &str| ActivationError::Malformed(reason.to_string());
+    let (claims, signature) = license_key
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| malformed("expected claims and signature separated by '.'"))?;
+    let claims = URL_SAFE_NO_PAD
+        .decode(claims)
+        .map_err(|_| malformed("claims are not base64url"))?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| malformed("signature is not base64url"))?;
+    let signature =
+        Signature::from_slice(&signature).map_err(|_| malformed("signature is not 64 bytes"))?;
+    public_key
+        .verify(&claims, &signature)
+        .map_err(|_| ActivationError::SignatureInvalid)?;
+
+    let claims: LicenseClaims = serde_json::from_slice(&claims)
+        .map_err(|e| ActivationError::Malformed(format!("claims: {}", e)))?;
+    if claims.machine_id != machine_id {
+        return Err(ActivationError::WrongMachine);
+    }
+    if let Some(expires_at) = claims.expires_at {
+        if expires_at <= chrono::Utc::now() {
+            return Err(ActivationError::Expired(expires_at));
+        }
+    }
+    Ok(claims)
+}
+
+pub struct Activation {
+    path: PathBuf,
+    machine_id: String,
+    status: ActivationStatus,
+}
+
+impl Activation {
+    // Never fails: without a usable stored license the app is just not activated.
+    pub fn load(data_dir: &Path, machine_id: &str) -> Self {
+        let path = data_dir.join(LICENSE_FILE);
+        let status = match std::fs::read_to_string(&path) {
+            Ok(license_key) => match verify(&license_key, machine_id, &embedded_key()) {
+                Ok(claims) => ActivationStatus {
+                    active: true,
+                    license: Some(claims),
+                    error: None,
+                },
+                Err(e) => {
+                    tracing::warn!("stored license not accepted, premium nodes are locked: {}", e);
+                    ActivationStatus {
+                        active: false,
+                        license: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => ActivationStatus::default(),
+            Err(e) => {
+                tracing::warn!("failed to read license file {}: {}", path.display(), e);
+                ActivationStatus {
+                    active: false,
+                    license: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        Self {
+            path,
+            machine_id: machine_id.to_string(),
+            status,
+        }
+    }
+
+    pub fn status(&self) -> &ActivationStatus {
+        &self.status
+    }
+
+    // A rejected key leaves the current activation as it was.
+    pub fn activate(&mut self, license_key: &str) -> Result<&ActivationStatus> {
+        let claims = verify(license_key, &self.machine_id, &embedded_key())?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, license_key.trim())?;
+        self.status = ActivationStatus {
+            active: true,
+            license: Some(claims),
+            error: None,
+        };
+        Ok(&self.status)
+    }
+}
@@ -0,0 +1,317 @@
+/*!
+ * Archive - every workflow as its own JSON file in one ZIP, for backups
+ *
+ * `manifest.json` records how many workflows were exported, when, and the
+ * database schema version they came from; each workflow is
+ * `workflows/<id>.json`. Both directions go one workflow at a time, so the
+ * library is never held in memory whole.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use thiserror::Error;
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::database::{Database, DatabaseError};
+use crate::secrets::{self, SecretError};
+use crate::Workflow;
+
+pub const ARCHIVE_FORMAT: &str = "workflow-archive";
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const WORKFLOWS_DIR: &str = "workflows/";
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("invalid archive manifest: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("not a workflow archive: {0}")]
+    InvalidFormat(String),
+    #[error("unsupported archive format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("archive is from schema version {found}; this version supports up to {supported}")]
+    NewerSchema { found: u32, supported: u32 },
+}
+
+pub type Result<T> = std::result::Result<T, ArchiveError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format: String,
+    pub format_version: u32,
+    pub count: usize,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub schema_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveExportSummary {
+    pub path: String,
+    pub workflows: usize,
+    // Strings where a secret value was replaced by its `{{ secrets.NAME }}` reference
+    pub secrets_scrubbed: usize,
+}
+
+// What to do with an archived workflow whose id is already in the library,
+// live or in the trash.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    #[default]
+    Skip,
+    // Saved over the existing workflow as an edit, so it can be undone
+    Overwrite,
+    // Imported alongside under a fresh id
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryOutcome {
+    Imported,
+    Overwritten,
+    Duplicated,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntryResult {
+    pub file: String,
+    // The id the workflow has in the library now; None when skipped or failed
+    pub workflow_id: Option<String>,
+    pub outcome: EntryOutcome,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveImportSummary {
+    pub manifest: ArchiveManifest,
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub entries: Vec<ArchiveEntryResult>,
+}
+
+// Secret values are scrubbed from each workflow as in a JSON export. Refused
+// while any workflow is locked, since the archive would silently lack it. The
+// archive is written beside `dest` and moved into place once complete.
+pub fn export_all(db: &Mutex<Database>, dest: &Path, secret_key: &str) -> Result<ArchiveExportSummary> {
+    let (ids, schema_version) = {
+        let db = db.lock();
+        db.ensure_unlocked()?;
+        (db.workflow_ids()?, db.schema_version()?)
+    };
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let partial = dest.with_extension("partial");
+    match write_archive(db, &partial, &ids, schema_version, secret_key) {
+        Ok((workflows, secrets_scrubbed)) => {
+            std::fs::rename(&partial, dest)?;
+            Ok(ArchiveExportSummary {
+                path: dest.to_string_lossy().to_string(),
+                workflows,
+                secrets_scrubbed,
+            })
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+// Returns how many workflows were written and how many strings scrubbed.
+// Workflows deleted since `ids` was read are left out.
+fn write_archive(
+    db: &Mutex<Database>,
+    path: &Path,
+    ids: &[String],
+    schema_version: u32,
+    secret_key: &str,
+) -> Result<(usize, usize)> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut count = 0;
+    let mut scrubbed = 0;
+    for id in ids {
+        // Locked per workflow, so saves are not held up for the whole export.
+        let (mut workflow, secrets) = {
+            let db = db.lock();
+            match db.get_workflow(id) {
+                Ok(workflow) => (workflow, secrets::visible_values(&db, id, secret_key)?),
+                Err(DatabaseError::NotFound(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        scrubbed += secrets::scrub_export(&mut workflow, &mut [], &secrets);
+        zip.start_file(format!("{}{}.json", WORKFLOWS_DIR, id), options)?;
+        serde_json::to_writer_pretty(&mut zip, &workflow)?;
+        count += 1;
+    }
+
+    let manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        format_version: ARCHIVE_FORMAT_VERSION,
+        count,
+        exported_at: chrono::Utc::now(),
+        schema_version,
+    };
+    zip.start_file(MANIFEST_FILE, options)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+    zip.finish()?.flush()?;
+    Ok((count, scrubbed))
+}
+
+// Every workflow file is tried; one that fails is reported in the summary
+// and the rest still import. Only an unreadable archive or manifest fails
+// the whole import.
+pub fn import_all(
+    db: &Mutex<Database>,
+    src: &Path,
+    strategy: ConflictStrategy,
+) -> Result<ArchiveImportSummary> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(src)?))?;
+    let manifest: ArchiveManifest = serde_json::from_reader(zip.by_name(MANIFEST_FILE)?)?;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(ArchiveError::InvalidFormat(manifest.format));
+    }
+    if manifest.format_version == 0 || manifest.format_version > ARCHIVE_FORMAT_VERSION {
+        return Err(ArchiveError::UnsupportedVersion(manifest.format_version));
+    }
+    let supported = db.lock().schema_version()?;
+    if manifest.schema_version > supported {
+        return Err(ArchiveError::NewerSchema {
+            found: manifest.schema_version,
+            supported,
+        });
+    }
+
+    let mut trashed: HashSet<String> = db.lock().trashed_workflow_ids()?.into_iter().collect();
+    let mut entries = Vec::new();
+    for index in 0..zip.len() {
+        let entry = zip.by_index(index)?;
+        let file = entry.name().to_string();
+        if entry.is_dir() || !file.starts_with(WORKFLOWS_DIR) || !file.ends_with(".json") {
+            continue;
+        }
+        let imported = serde_json::from_reader(entry)
+            .map_err(|e| e.to_string())
+            .and_then(|workflow| {
+                check_structure(&workflow)?;
+                import_entry(db, &mut trashed, workflow, strategy).map_err(|e| e.to_string())
+            });
+        entries.push(match imported {
+            Ok((outcome, workflow_id)) => ArchiveEntryResult {
+                file,
+                workflow_id,
+                outcome,
+                error: None,
+            },
+            Err(error) => {
+                tracing::warn!("failed to import {} from {}: {}", file, src.display(), error);
+                ArchiveEntryResult {
+                    file,
+                    workflow_id: None,
+                    outcome: EntryOutcome::Failed,
+                    error: Some(error),
+                }
+            }
+        });
+    }
+
+    let count = |outcome: &[EntryOutcome]| {
+        entries
+            .iter()
+            .filter(|entry| outcome.contains(&entry.outcome))
+            .count()
+    };
+    Ok(ArchiveImportSummary {
+        imported: count(&[
+            EntryOutcome::Imported,
+            EntryOutcome::Overwritten,
+            EntryOutcome::Duplicated,
+        ]),
+        skipped: count(&[EntryOutcome::Skipped]),
+        failed: count(&[EntryOutcome::Failed]),
+        manifest,
+        entries,
+    })
+}
+
+// Rejects what no workflow can be saved with: duplicate node ids and edges
+// between nodes that are not there. Anything a draft may still be saved with,
+// such as unknown node types, is left to validation.
+fn check_structure(workflow: &Workflow) -> std::result::Result<(), String> {
+    if workflow.id.trim().is_empty() {
+        return Err("workflow has no id".to_string());
+    }
+    let mut node_ids = HashSet::new();
+    for node in &workflow.nodes {
+        if !node_ids.insert(node.id.as_str()) {
+            return Err(format!("node id {} is used more than once", node.id));
+        }
+    }
+    for edge in &workflow.edges {
+        for endpoint in [&edge.source, &edge.target] {
+            if !node_ids.contains(endpoint.as_str()) {
+                return Err(format!("edge {} references missing node {}", edge.id, endpoint));
+            }
+        }
+    }
+    Ok(())
+}
+
+// `trashed` holds the ids in the trash, which clash with an import too.
+fn import_entry(
+    db: &Mutex<Database>,
+    trashed: &mut HashSet<String>,
+    mut workflow: Workflow,
+    strategy: ConflictStrategy,
+) -> Result<(EntryOutcome, Option<String>)> {
+    let mut db = db.lock();
+    let live = match db.get_workflow(&workflow.id) {
+        Ok(_) => true,
+        Err(DatabaseError::NotFound(_)) => false,
+        Err(e) => return Err(e.into()),
+    };
+    workflow.deleted_at = None;
+
+    if !live && !trashed.contains(&workflow.id) {
+        db.import_workflow(&workflow, &[], &[])?;
+        return Ok((EntryOutcome::Imported, Some(workflow.id)));
+    }
+    match strategy {
+        ConflictStrategy::Skip => Ok((EntryOutcome::Skipped, None)),
+        ConflictStrategy::Overwrite => {
+            if trashed.remove(&workflow.id) {
+                db.restore_workflow(&workflow.id)?;
+            }
+            db.update_workflow(&workflow)?;
+            Ok((EntryOutcome::Overwritten, Some(workflow.id)))
+        }
+        ConflictStrategy::Duplicate => {
+            workflow.id = Uuid::new_v4().to_string();
+            db.import_workflow(&workflow, &[], &[])?;
+            Ok((EntryOutcome::Duplicated, Some(workflow.id)))
+        }
+    }
+}
@@ -0,0 +1,68 @@
+/*!
+ * Audit - a record of sensitive commands
+ *
+ * Sign-in and sign-out, deletions, encryption, key and token rotation, and
+ * exports each leave an entry naming the command, the machine and what it
+ * touched. Summaries never carry passwords, keys, plaintext or ciphertext.
+ * Recording is best-effort: a failed write is logged and the command's own
+ * result stands.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::database::Database;
+use crate::AppState;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub command: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub machine_id: String,
+    pub summary: String,
+    // Set when the command failed
+    pub error: Option<String>,
+}
+
+// Filters for `get_audit_log`; unset fields match every entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub command: Option<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<u32>,
+}
+
+// Records `command` and its outcome. Takes the locks itself, so callers must
+// not hold either.
+pub fn record<T, E: Display>(
+    db: &Mutex<Database>,
+    state: &Mutex<AppState>,
+    command: &str,
+    summary: impl Into<String>,
+    outcome: &Result<T, E>,
+) {
+    let entry = AuditEntry {
+        id: 0,
+        command: command.to_string(),
+        timestamp: chrono::Utc::now(),
+        machine_id: state.lock().machine_id.clone(),
+        summary: summary.into(),
+        error: outcome.as_ref().err().map(ToString::to_string),
+    };
+    if let Err(e) = db.lock().insert_audit_entry(&entry) {
+        tracing::warn!("failed to record {} in the audit log: {}", command, e);
+    }
+}
+
+// For commands that cannot fail.
+pub fn record_done(
+    db: &Mutex<Database>,
+    state: &Mutex<AppState>,
+    command: &str,
+    summary: impl Into<String>,
+) {
+    record(db, state, command, summary, &Ok::<(), String>(()));
+}
@@ -0,0 +1,584 @@
+/*!
+ * Backup - full keyframes and incremental deltas of the workflow library, and
+ * whole-database snapshots
+ */
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::database::{self, Database, DatabaseError};
+use crate::Workflow;
+
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+const SNAPSHOT_MAGIC: &str = "workflow-database-snapshot";
+
+// A full keyframe is written after this many incrementals so a restore never
+// has to replay an arbitrarily long chain.
+const KEYFRAME_INTERVAL: u64 = 10;
+
+const LAST_BACKUP_AT_KEY: &str = "backup.last_at";
+const LAST_BACKUP_DIR_KEY: &str = "backup.last_dir";
+const LAST_BACKUP_SEQUENCE_KEY: &str = "backup.last_sequence";
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid backup file: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("unsupported backup format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("no full backup found in {0}")]
+    NoKeyframe(String),
+    #[error("backup chain is broken: expected sequence {expected}, found {found}")]
+    BrokenChain { expected: u64, found: u64 },
+    #[error("not a backup file or directory: {0}")]
+    NotABackup(String),
+    #[error("database snapshot is truncated or corrupt: checksum mismatch")]
+    ChecksumMismatch,
+    #[error("database snapshot has schema version {found}; this version supports 1 to {supported}")]
+    IncompatibleSchema { found: u32, supported: u32 },
+    #[error("cannot restore the database while {0} executions are running")]
+    ExecutionsRunning(usize),
+}
+
+pub type Result<T> = std::result::Result<T, BackupError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    Full,
+    Incremental,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub format_version: u32,
+    pub kind: BackupKind,
+    pub sequence: u64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub workflows: Vec<Workflow>,
+    pub tombstones: Vec<String>,
+    // Every live workflow id at backup time, so permanently purged workflows
+    // are dropped on restore even though no tombstone was recorded for them.
+    pub live_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSummary {
+    pub path: String,
+    pub kind: BackupKind,
+    pub sequence: u64,
+    pub workflows: usize,
+    pub tombstones: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreSummary {
+    pub files_applied: usize,
+    pub workflows_restored: usize,
+    pub workflows_trashed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseBackupSummary {
+    pub path: String,
+    pub schema_version: u32,
+    pub size_bytes: u64,
+    pub checksum: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Written as one JSON line ahead of the raw database image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotHeader {
+    magic: String,
+    format_version: u32,
+    schema_version: u32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    size_bytes: u64,
+    // Hex SHA-256 of the database image
+    checksum: String,
+}
+
+// Workflow ids relative to the backup: `added` exist only in the database,
+// `removed` only in the backup. Ids are sorted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+    pub unchanged: usize,
+}
+
+fn file_name(sequence: u64, kind: BackupKind) -> String {
+    let kind = match kind {
+        BackupKind::Full => "full",
+        BackupKind::Incremental => "incremental",
+    };
+    format!("backup-{:06}-{}.json", sequence, kind)
+}
+
+fn parse_file_name(name: &str) -> Option<(u64, BackupKind)> {
+    let rest = name.strip_prefix("backup-")?.strip_suffix(".json")?;
+    let (sequence, kind) = rest.split_once('-')?;
+    let kind = match kind {
+        "full" => BackupKind::Full,
+        "incremental" => BackupKind::Incremental,
+        _ => return None,
+    };
+    Some((sequence.parse().ok()?, kind))
+}
+
+// Chain files in `dir`, ordered by sequence.
+fn list_chain(dir: &Path) -> Result<Vec<(u64, BackupKind, PathBuf)>> {
+    let mut chain = Vec::new();
+    if !dir.exists() {
+        return Ok(chain);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some((sequence, kind)) = entry.file_name().to_str().and_then(parse_file_name) {
+            chain.push((sequence, kind, entry.path()));
+        }
+    }
+    chain.sort_by_key(|(sequence, _, _)| *sequence);
+    Ok(chain)
+}
+
+fn read_backup(path: &Path) -> Result<BackupFile> {
+    let file: BackupFile = serde_json::from_slice(&std::fs::read(path)?)?;
+    if file.format_version > BACKUP_FORMAT_VERSION {
+        return Err(BackupError::UnsupportedVersion(file.format_version));
+    }
+    Ok(file)
+}
+
+pub fn create_incremental_backup(db: &Database, dest_dir: &Path) -> Result<BackupSummary> {
+    // Locked workflows cannot be read, so a backup would silently lack them.
+    db.ensure_unlocked()?;
+    std::fs::create_dir_all(dest_dir)?;
+    let chain = list_chain(dest_dir)?;
+    let dest = dest_dir.to_string_lossy().to_string();
+
+    // The stored marker is only trusted when it describes the tip of this chain.
+    let last_sequence = chain.last().map(|(sequence, _, _)| *sequence);
+    let marker = match (
+        db.get_meta(LAST_BACKUP_AT_KEY)?,
+        db.get_meta(LAST_BACKUP_DIR_KEY)?,
+        db.get_meta(LAST_BACKUP_SEQUENCE_KEY)?,
+    ) {
+        (Some(at), Some(dir), Some(sequence))
+            if dir == dest && sequence.parse::<u64>().ok() == last_sequence =>
+        {
+            chrono::DateTime::parse_from_rfc3339(&at)
+                .ok()
+                .map(|at| at.with_timezone(&chrono::Utc))
+        }
+        _ => None,
+    };
+
+    let since_keyframe = chain
+        .iter()
+        .rev()
+        .take_while(|(_, kind, _)| *kind == BackupKind::Incremental)
+        .count() as u64;
+    let since = marker.filter(|_| since_keyframe < KEYFRAME_INTERVAL);
+
+    let created_at = chrono::Utc::now();
+    let (kind, workflows, tombstones) = match since {
+        Some(since) => {
+            let (changed, trashed) = db.workflows_changed_since(since)?;
+            (BackupKind::Incremental, changed, trashed)
+        }
+        None => (BackupKind::Full, db.get_workflows()?, Vec::new()),
+    };
+
+    let sequence = last_sequence.map_or(1, |sequence| sequence + 1);
+    let file = BackupFile {
+        format_version: BACKUP_FORMAT_VERSION,
+        kind,
+        sequence,
+        created_at,
+        since,
+        workflows,
+        tombstones,
+        live_ids: db.workflow_ids()?,
+    };
+
+    let path = dest_dir.join(file_name(sequence, kind));
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&file)?)?;
+    std::fs::rename(&tmp, &path)?;
+
+    db.set_meta(LAST_BACKUP_AT_KEY, &created_at.to_rfc3339())?;
+    db.set_meta(LAST_BACKUP_DIR_KEY, &dest)?;
+    db.set_meta(LAST_BACKUP_SEQUENCE_KEY, &sequence.to_string())?;
+
+    Ok(BackupSummary {
+        path: path.to_string_lossy().to_string(),
+        kind,
+        sequence,
+        workflows: file.workflows.len(),
+        tombstones: file.tombstones.len(),
+    })
+}
+
+// Replays the latest full keyframe in `chain` and every incremental after it.
+// Returns the resulting workflows by id and how many files were applied.
+fn replay_chain(
+    dir: &Path,
+    chain: &[(u64, BackupKind, PathBuf)],
+) -> Result<(BTreeMap<String, Workflow>, usize)> {
+    let start = chain
+        .iter()
+        .rposition(|(_, kind, _)| *kind == BackupKind::Full)
+        .ok_or_else(|| BackupError::NoKeyframe(dir.to_string_lossy().to_string()))?;
+
+    let mut state: BTreeMap<String, Workflow> = BTreeMap::new();
+    for ((sequence, _, path), expected) in chain[start..].iter().zip(chain[start].0..) {
+        if *sequence != expected {
+            return Err(BackupError::BrokenChain {
+                expected,
+                found: *sequence,
+            });
+        }
+
+        let file = read_backup(path)?;
+        if file.kind == BackupKind::Full {
+            state.clear();
+        }
+        for id in &file.tombstones {
+            state.remove(id);
+        }
+        for workflow in file.workflows {
+            state.insert(workflow.id.clone(), workflow);
+        }
+        state.retain(|id, _| file.live_ids.binary_search(id).is_ok());
+    }
+
+    Ok((state, chain.len() - start))
+}
+
+// Rebuilds the library from the latest full keyframe in `dir` and every
+// incremental written after it.
+pub fn restore_from_incremental_chain(db: &mut Database, dir: &Path) -> Result<RestoreSummary> {
+    let (state, files_applied) = replay_chain(dir, &list_chain(dir)?)?;
+
+    let workflows: Vec<Workflow> = state.into_values().collect();
+    let (written, trashed) = db.replace_workflows(&workflows)?;
+
+    Ok(RestoreSummary {
+        files_applied,
+        workflows_restored: written,
+        workflows_trashed: trashed,
+    })
+}
+
+// The library as of `path`: a backup directory means its latest state; a
+// backup file means the state once that file was applied to its chain.
+fn backup_state(path: &Path) -> Result<BTreeMap<String, Workflow>> {
+    if path.is_dir() {
+        return Ok(replay_chain(path, &list_chain(path)?)?.0);
+    }
+
+    let (dir, sequence) = match (
+        path.parent(),
+        path.file_name().and_then(|name| name.to_str()).and_then(parse_file_name),
+    ) {
+        (Some(dir), Some((sequence, _))) => (dir, sequence),
+        _ => return Err(BackupError::NotABackup(path.to_string_lossy().to_string())),
+    };
+    let mut chain = list_chain(dir)?;
+    chain.retain(|(found, _, _)| *found <= sequence);
+    Ok(replay_chain(dir, &chain)?.0)
+}
+
+// Hash of what a user edits; timestamps are left out so a save without
+// changes does not count as a modification.
+fn content_hash(workflow: &Workflow) -> Result<u64> {
+    let content = serde_json::json!({
+        "name": workflow.name,
+        "description": workflow.description,
+        "nodes": workflow.nodes,
+        "edges": workflow.edges,
+        "status": workflow.status,
+    });
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(&content)?.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+// Compares live workflows with a backup without writing to either side.
+pub fn diff_against_backup(db: &Database, path: &Path) -> Result<BackupDiff> {
+    let backup = backup_state(path)?;
+    let current: BTreeMap<String, Workflow> = db
+        .get_workflows()?
+        .into_iter()
+        .map(|workflow| (workflow.id.clone(), workflow))
+        .collect();
+
+    let mut diff = BackupDiff::default();
+    for (id, workflow) in &current {
+        match backup.get(id) {
+            None => diff.added.push(id.clone()),
+            Some(backed_up) if content_hash(backed_up)? != content_hash(workflow)? => {
+                diff.modified.push(id.clone())
+            }
+            Some(_) => diff.unchanged += 1,
+        }
+    }
+    diff.removed = backup
+        .keys()
+        .filter(|id| !current.contains_key(*id))
+        .cloned()
+        .collect();
+    Ok(diff)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Writes a snapshot of the whole database (workflows, executions, settings,
+// secrets) to `dest`. The copy is taken with SQLite's online backup API, so
+// the app can keep running meanwhile.
+pub fn backup_database(db: &Database, dest: &Path) -> Result<DatabaseBackupSummary> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let image_path = sibling(dest, ".image");
+    db.backup_to(&image_path)?;
+    let image = std::fs::read(&image_path);
+    std::fs::remove_file(&image_path)?;
+    let image = image?;
+
+    let header = SnapshotHeader {
+        magic: SNAPSHOT_MAGIC.to_string(),
+        format_version: SNAPSHOT_FORMAT_VERSION,
+        schema_version: db.schema_version()?,
+        created_at: chrono::Utc::now(),
+        size_bytes: image.len() as u64,
+        checksum: sha256_hex(&image),
+    };
+    let mut contents = serde_json::to_vec(&header)?;
+    contents.push(b'\n');
+    contents.extend_from_slice(&image);
+
+    // Written under a temporary name so a failed write never leaves a
+    // half-written file at `dest`.
+    let partial = sibling(dest, ".partial");
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, dest)?;
+
+    Ok(DatabaseBackupSummary {
+        path: dest.to_string_lossy().to_string(),
+        schema_version: header.schema_version,
+        size_bytes: header.size_bytes,
+        checksum: header.checksum,
+        created_at: header.created_at,
+    })
+}
+
+// Splits a snapshot into its header and database image, checking the image
+// against the header's size and checksum.
+fn read_snapshot(path: &Path) -> Result<(SnapshotHeader, Vec<u8>)> {
+    let not_a_backup = || BackupError::NotABackup(path.to_string_lossy().to_string());
+    let mut contents = std::fs::read(path)?;
+    let newline = contents.iter().position(|&b| b == b'\n').ok_or_else(not_a_backup)?;
+    let header: SnapshotHeader =
+        serde_json::from_slice(&contents[..newline]).map_err(|_| not_a_backup())?;
+    if header.magic != SNAPSHOT_MAGIC {
+        return Err(not_a_backup());
+    }
+    if header.format_version > SNAPSHOT_FORMAT_VERSION {
+        return Err(BackupError::UnsupportedVersion(header.format_version));
+    }
+
+    let image = contents.split_off(newline + 1);
+    if image.len() as u64 != header.size_bytes || sha256_hex(&image) != header.checksum {
+        return Err(BackupError::ChecksumMismatch);
+    }
+    Ok((header, image))
+}
+
+// Replaces the live database with the snapshot at `src`. The snapshot is
+// checked in full first: a truncated file, or one from a newer schema than
+// this version knows, is refused and the live database left untouched. An
+// older schema is migrated on reopen.
+pub fn restore_database(db: &mut Database, src: &Path) -> Result<DatabaseBackupSummary> {
+    let (header, image) = read_snapshot(src)?;
+    let incompatible = |found| BackupError::IncompatibleSchema {
+        found,
+        supported: database::SCHEMA_VERSION,
+    };
+    if header.schema_version == 0 || header.schema_version > database::SCHEMA_VERSION {
+        return Err(incompatible(header.schema_version));
+    }
+
+    // Staged beside the live file so the swap is a rename on one filesystem.
+    let staged = sibling(db.path(), ".restore");
+    std::fs::write(&staged, &image)?;
+    let swapped = match Database::file_schema_version(&staged) {
+        Ok(version) if version == header.schema_version => {
+            db.replace_file(&staged).map_err(BackupError::from)
+        }
+        Ok(version) => Err(incompatible(version)),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = swapped {
+        let _ = std::fs::remove_file(&staged);
+        return Err(e);
+    }
+
+    Ok(DatabaseBackupSummary {
+        path: src.to_string_lossy().to_string(),
+        schema_version: header.schema_version,
+        size_bytes: header.size_bytes,
+        checksum: header.checksum,
+        created_at: header.created_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{node, temp_db, temp_dir, workflow};
+    use serde_json::json;
+
+    fn named(id: &str) -> Workflow {
+        workflow(id, vec![node("t", "trigger", json!({}))], vec![])
+    }
+
+    // Sorted by id
+    fn live_names(db: &Database) -> Vec<(String, String)> {
+        let mut names: Vec<(String, String)> = db
+            .get_workflows()
+            .unwrap()
+            .into_iter()
+            .map(|workflow| (workflow.id, workflow.name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn incremental_chain_restores_the_latest_backed_up_state() {
+        let mut db = temp_db();
+        let dir = temp_dir();
+        db.create_workflow(&named("a")).unwrap();
+        db.create_workflow(&named("b")).unwrap();
+
+        let full = create_incremental_backup(&db, &dir).unwrap();
+        assert_eq!((full.kind, full.sequence, full.workflows), (BackupKind::Full, 1, 2));
+
+        let mut a = db.get_workflow("a").unwrap();
+        a.name = "a edited".to_string();
+        db.update_workflow(&a).unwrap();
+        db.create_workflow(&named("c")).unwrap();
+        db.delete_workflow("b").unwrap();
+
+        let delta = create_incremental_backup(&db, &dir).unwrap();
+        assert_eq!((delta.kind, delta.sequence), (BackupKind::Incremental, 2));
+        assert_eq!((delta.workflows, delta.tombstones), (2, 1));
+        let expected = live_names(&db);
+
+        // Changes after the last backup are undone by the restore.
+        a.name = "a edited again".to_string();
+        db.update_workflow(&a).unwrap();
+        db.create_workflow(&named("d")).unwrap();
+
+        let restored = restore_from_incremental_chain(&mut db, &dir).unwrap();
+        assert_eq!(restored.files_applied, 2);
+        assert_eq!(restored.workflows_trashed, 1);
+        assert_eq!(live_names(&db), expected);
+        assert_eq!(
+            expected,
+            [
+                ("a".to_string(), "a edited".to_string()),
+                ("c".to_string(), "Workflow c".to_string()),
+            ]
+        );
+
+        let mut fresh = temp_db();
+        restore_from_incremental_chain(&mut fresh, &dir).unwrap();
+        assert_eq!(live_names(&fresh), expected);
+    }
+
+    #[test]
+    fn restore_needs_a_keyframe_and_an_unbroken_chain() {
+        let mut db = temp_db();
+        let dir = temp_dir();
+        assert!(matches!(
+            restore_from_incremental_chain(&mut db, &dir),
+            Err(BackupError::NoKeyframe(_))
+        ));
+
+        db.create_workflow(&named("a")).unwrap();
+        create_incremental_backup(&db, &dir).unwrap();
+        create_incremental_backup(&db, &dir).unwrap();
+        create_incremental_backup(&db, &dir).unwrap();
+        std::fs::remove_file(dir.join(file_name(2, BackupKind::Incremental))).unwrap();
+        assert!(matches!(
+            restore_from_incremental_chain(&mut db, &dir),
+            Err(BackupError::BrokenChain { expected: 2, found: 3 })
+        ));
+    }
+
+    #[test]
+    fn diff_reports_changes_since_the_backup_without_writing() {
+        let mut db = temp_db();
+        let dir = temp_dir();
+        for id in ["a", "b", "c"] {
+            db.create_workflow(&named(id)).unwrap();
+        }
+        let full = create_incremental_backup(&db, &dir).unwrap();
+        let full_path = dir.join(file_name(full.sequence, full.kind));
+
+        let mut a = db.get_workflow("a").unwrap();
+        a.name = "a edited".to_string();
+        db.update_workflow(&a).unwrap();
+        // Saved again, but with the same content
+        let c = db.get_workflow("c").unwrap();
+        db.update_workflow(&c).unwrap();
+        db.delete_workflow("b").unwrap();
+        db.create_workflow(&named("d")).unwrap();
+
+        let before = std::fs::read(&full_path).unwrap();
+        let live = live_names(&db);
+        for path in [&dir, &full_path] {
+            let diff = diff_against_backup(&db, path).unwrap();
+            assert_eq!(diff.added, ["d"]);
+            assert_eq!(diff.removed, ["b"]);
+            assert_eq!(diff.modified, ["a"]);
+            assert_eq!(diff.unchanged, 1);
+        }
+        assert_eq!(std::fs::read(&full_path).unwrap(), before);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(live_names(&db), live);
+
+        // A newer backup catches up, but the earlier file still shows its state.
+        create_incremental_backup(&db, &dir).unwrap();
+        let caught_up = diff_against_backup(&db, &dir).unwrap();
+        assert!(caught_up.added.is_empty() && caught_up.removed.is_empty());
+        assert!(caught_up.modified.is_empty());
+        assert_eq!(diff_against_backup(&db, &full_path).unwrap().modified, ["a"]);
+    }
+}
@@ -0,0 +1,128 @@
+/*!
+ * Blob - how a workflow's nodes and edges JSON is stored
+ *
+ * A workflow row's `blob_flags` describe both its `nodes` and `edges` columns.
+ * Rows whose JSON is larger than `COMPRESS_THRESHOLD` are gzip-compressed;
+ * smaller ones, and every row written before compression existed, hold plain
+ * JSON text with no flags set. Given a key, the bytes are then encrypted, so
+ * compression always runs on plaintext, where it can actually save space.
+ *
+ * Stored values: plain JSON as TEXT, compressed bytes as a BLOB, and
+ * encrypted data as base64 TEXT from `encryption`.
+ */
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::types::Value as SqlValue;
+use std::io::{Read, Write};
+use thiserror::Error;
+
+use crate::encryption::{self, EncryptionError, KdfParams};
+
+// Combined nodes and edges JSON size, in bytes, above which a row is compressed.
+pub const COMPRESS_THRESHOLD: usize = 8 * 1024;
+
+pub const COMPRESSED: i64 = 1;
+pub const ENCRYPTED: i64 = 2;
+
+const KNOWN_FLAGS: i64 = COMPRESSED | ENCRYPTED;
+
+#[derive(Debug, Error)]
+pub enum BlobError {
+    #[error("compression error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("encryption error: {0}")]
+    Encryption(Box<EncryptionError>),
+    #[error("stored workflow is encrypted and no key is loaded")]
+    Locked,
+    #[error("unsupported blob flags {0:#x}")]
+    UnknownFlags(i64),
+    #[error("stored workflow JSON is not valid UTF-8")]
+    Utf8,
+    #[error("stored workflow has an unexpected {0} value")]
+    UnexpectedType(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, BlobError>;
+
+impl From<EncryptionError> for BlobError {
+    fn from(e: EncryptionError) -> Self {
+        Self::Encryption(Box::new(e))
+    }
+}
+
+// Passphrase and KDF cost for encrypting blobs at rest.
+#[derive(Debug, Clone, Copy)]
+pub struct BlobKey<'a> {
+    pub passphrase: &'a str,
+    pub params: &'a KdfParams,
+}
+
+// Flags for a row whose nodes and edges JSON are `nodes` and `edges`.
+pub fn row_flags(nodes: &str, edges: &str, key: Option<BlobKey<'_>>) -> i64 {
+    let mut flags = 0;
+    if nodes.len() + edges.len() > COMPRESS_THRESHOLD {
+        flags |= COMPRESSED;
+    }
+    if key.is_some() {
+        flags |= ENCRYPTED;
+    }
+    flags
+}
+
+pub fn encode(json: &str, flags: i64, key: Option<BlobKey<'_>>) -> Result<SqlValue> {
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(BlobError::UnknownFlags(flags));
+    }
+    let bytes = if flags & COMPRESSED != 0 {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.as_bytes())?;
+        encoder.finish()?
+    } else {
+        json.as_bytes().to_vec()
+    };
+
+    if flags & ENCRYPTED != 0 {
+        let key = key.ok_or(BlobError::Locked)?;
+        return Ok(SqlValue::Text(encryption::encrypt_bytes_with(
+            &bytes,
+            key.passphrase,
+            key.params,
+        )?));
+    }
+    if flags & COMPRESSED != 0 {
+        Ok(SqlValue::Blob(bytes))
+    } else {
+        Ok(SqlValue::Text(json.to_string()))
+    }
+}
+
+pub fn decode(stored: SqlValue, flags: i64, key: Option<BlobKey<'_>>) -> Result<String> {
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(BlobError::UnknownFlags(flags));
+    }
+    let bytes = match stored {
+        SqlValue::Text(text) if flags & ENCRYPTED != 0 => {
+            let key = key.ok_or(BlobError::Locked)?;
+            encryption::decrypt_bytes_with(&text, key.passphrase, key.params)?
+        }
+        SqlValue::Text(text) if flags == 0 => return Ok(text),
+        SqlValue::Blob(bytes) if flags == COMPRESSED => bytes,
+        SqlValue::Null => return Err(BlobError::UnexpectedType("null")),
+        SqlValue::Integer(_) | SqlValue::Real(_) => {
+            return Err(BlobError::UnexpectedType("numeric"))
+        }
+        SqlValue::Text(_) => return Err(BlobError::UnexpectedType("text")),
+        SqlValue::Blob(_) => return Err(BlobError::UnexpectedType("blob")),
+    };
+
+    let bytes = if flags & COMPRESSED != 0 {
+        let mut json = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+        json
+    } else {
+        bytes
+    };
+    String::from_utf8(bytes).map_err(|_| BlobError::Utf8)
+}
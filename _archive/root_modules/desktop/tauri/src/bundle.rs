@@ -0,0 +1,215 @@
+/*!
+ * Bundle - portable single-file export of a workflow with its tags and history
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::database::{Database, DatabaseError};
+use crate::encryption::{self, EncryptionError, KdfParams};
+use crate::nodes::NodeRegistry;
+use crate::secrets;
+use crate::{Execution, Workflow};
+
+pub const BUNDLE_FORMAT: &str = "workflow-bundle";
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+// Only the most recent executions travel with a bundle.
+const BUNDLE_EXECUTION_LIMIT: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid bundle: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("not a workflow bundle: {0}")]
+    InvalidFormat(String),
+    #[error("unsupported bundle format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("bundle is encrypted; a passphrase is required")]
+    PassphraseRequired,
+}
+
+pub type Result<T> = std::result::Result<T, BundleError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleHeader {
+    pub format: String,
+    pub format_version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    // Present when the contents are encrypted. Carried in the bundle because
+    // the importing machine may be configured with different KDF costs.
+    pub kdf_params: Option<KdfParams>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleContents {
+    pub workflow: Workflow,
+    pub tags: Vec<String>,
+    pub executions: Vec<Execution>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundleFile {
+    header: BundleHeader,
+    #[serde(default)]
+    contents: Option<BundleContents>,
+    #[serde(default)]
+    encrypted_contents: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleExportSummary {
+    pub path: String,
+    pub workflow_id: String,
+    pub tags: usize,
+    pub executions: usize,
+    pub encrypted: bool,
+    // Strings where a secret value was replaced by its `{{ secrets.NAME }}` reference
+    pub secrets_scrubbed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleImportSummary {
+    // The imported workflow gets a fresh id so it never collides locally.
+    pub workflow_id: String,
+    pub original_workflow_id: String,
+    pub tags: usize,
+    pub executions: usize,
+    // Node types used by the workflow that this install has no executor for.
+    pub missing_node_types: Vec<String>,
+}
+
+pub fn export_bundle(
+    db: &Database,
+    workflow_id: &str,
+    path: &Path,
+    passphrase: Option<&str>,
+    secrets: &BTreeMap<String, String>,
+) -> Result<BundleExportSummary> {
+    let mut workflow = db.get_workflow(workflow_id)?;
+    let tags = db.get_workflow_tags(workflow_id)?;
+    let mut executions = db.get_executions(Some(workflow_id))?;
+    executions.truncate(BUNDLE_EXECUTION_LIMIT);
+    let secrets_scrubbed = secrets::scrub_export(&mut workflow, &mut executions, secrets);
+
+    let summary = BundleExportSummary {
+        path: path.to_string_lossy().to_string(),
+        workflow_id: workflow_id.to_string(),
+        tags: tags.len(),
+        executions: executions.len(),
+        encrypted: passphrase.is_some(),
+        secrets_scrubbed,
+    };
+    let contents = BundleContents {
+        workflow,
+        tags,
+        executions,
+    };
+
+    let mut file = BundleFile {
+        header: BundleHeader {
+            format: BUNDLE_FORMAT.to_string(),
+            format_version: BUNDLE_FORMAT_VERSION,
+            created_at: chrono::Utc::now(),
+            kdf_params: None,
+        },
+        contents: None,
+        encrypted_contents: None,
+    };
+    match passphrase {
+        Some(passphrase) => {
+            let params = KdfParams::default();
+            let plaintext = serde_json::to_string(&contents)?;
+            file.encrypted_contents = Some(encryption::encrypt_with(&plaintext, passphrase, &params)?);
+            file.header.kdf_params = Some(params);
+        }
+        None => file.contents = Some(contents),
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(&file)?)?;
+    Ok(summary)
+}
+
+fn read_bundle(path: &Path, passphrase: Option<&str>) -> Result<BundleContents> {
+    let file: BundleFile = serde_json::from_slice(&std::fs::read(path)?)?;
+    if file.header.format != BUNDLE_FORMAT {
+        return Err(BundleError::InvalidFormat(file.header.format));
+    }
+    // Older versions would be migrated here; there are none yet.
+    if file.header.format_version == 0 || file.header.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(BundleError::UnsupportedVersion(file.header.format_version));
+    }
+
+    match (file.contents, file.encrypted_contents) {
+        (Some(contents), None) => Ok(contents),
+        (None, Some(ciphertext)) => {
+            let passphrase = passphrase.ok_or(BundleError::PassphraseRequired)?;
+            let params = file.header.kdf_params.unwrap_or_default();
+            let plaintext = encryption::decrypt_with(&ciphertext, passphrase, &params)?;
+            Ok(serde_json::from_str(&plaintext)?)
+        }
+        _ => Err(BundleError::InvalidFormat(
+            "expected exactly one of contents or encrypted_contents".to_string(),
+        )),
+    }
+}
+
+// Imports even when node types are unknown here; they are reported instead so
+// the user can install what is missing before running the workflow.
+pub fn import_bundle(
+    db: &mut Database,
+    registry: &NodeRegistry,
+    path: &Path,
+    passphrase: Option<&str>,
+) -> Result<BundleImportSummary> {
+    let BundleContents {
+        mut workflow,
+        tags,
+        mut executions,
+    } = read_bundle(path, passphrase)?;
+
+    let original_workflow_id = std::mem::replace(&mut workflow.id, Uuid::new_v4().to_string());
+    workflow.deleted_at = None;
+    for execution in &mut executions {
+        execution.id = Uuid::new_v4().to_string();
+        execution.workflow_id = workflow.id.clone();
+    }
+
+    let missing_node_types: Vec<String> = workflow
+        .nodes
+        .iter()
+        .filter(|node| registry.get(&node.node_type).is_none())
+        .map(|node| node.node_type.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if !missing_node_types.is_empty() {
+        tracing::warn!(
+            "imported workflow {} uses unknown node types: {}",
+            workflow.id,
+            missing_node_types.join(", ")
+        );
+    }
+
+    db.import_workflow(&workflow, &tags, &executions)?;
+
+    Ok(BundleImportSummary {
+        workflow_id: workflow.id,
+        original_workflow_id,
+        tags: tags.len(),
+        executions: executions.len(),
+        missing_node_types,
+    })
+}
@@ -0,0 +1,468 @@
+/*!
+ * Command Error - the error every Tauri command returns
+ *
+ * Serializes as `{ "code", "message", "details" }`. `code` is stable, so the
+ * frontend can pick messaging (and localized text) by it; `message` is the
+ * backend's English description; `details` carries structured context for
+ * some errors, such as the stored workflow on a save conflict.
+ */
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::io::ErrorKind;
+use thiserror::Error;
+
+use crate::activation::ActivationError;
+use crate::archive::ArchiveError;
+use crate::backup::BackupError;
+use crate::blob::BlobError;
+use crate::bundle::BundleError;
+use crate::control_api::ControlApiError;
+use crate::database::DatabaseError;
+use crate::deadlines::DeadlineError;
+use crate::encryption::EncryptionError;
+use crate::environments::EnvironmentError;
+use crate::execution_log::ExecutionLogError;
+use crate::file_watcher::FileWatchError;
+use crate::folders::FolderError;
+use crate::graph::GraphError;
+use crate::notifications::NotificationError;
+use crate::plans::PlanError;
+use crate::profiles::ProfileError;
+use crate::secrets::SecretError;
+use crate::service::ServiceError;
+use crate::signing::SigningError;
+use crate::templates::CatalogError;
+use crate::webhook_server::WebhookError;
+use crate::websocket_client::WsError;
+use crate::workflow_engine::EngineError;
+use crate::workflow_state::StateError;
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("{0}")]
+    NotFound(String),
+    // The request itself is wrong: bad input, an invalid graph or file
+    #[error("{message}")]
+    Validation {
+        message: String,
+        details: Option<Value>,
+    },
+    // The request clashes with the current state, e.g. a stale save
+    #[error("{message}")]
+    Conflict {
+        message: String,
+        details: Option<Value>,
+    },
+    // A key, passphrase or signature did not check out
+    #[error("{0}")]
+    Auth(String),
+    // Temporarily refused (database locked, queue full); retrying may work
+    #[error("{0}")]
+    Busy(String),
+    // The subsystem is not running, e.g. during shutdown
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl CommandError {
+    pub fn validation(message: impl Into<String>) -> Self {
+        CommandError::Validation {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        CommandError::Conflict {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            CommandError::NotFound(_) => "not_found",
+            CommandError::Validation { .. } => "validation",
+            CommandError::Conflict { .. } => "conflict",
+            CommandError::Auth(_) => "auth",
+            CommandError::Busy(_) => "busy",
+            CommandError::Unavailable(_) => "unavailable",
+            CommandError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn details(&self) -> Option<&Value> {
+        match self {
+            CommandError::Validation { details, .. } | CommandError::Conflict { details, .. } => {
+                details.as_ref()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("CommandError", 3)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("details", &self.details())?;
+        error.end()
+    }
+}
+
+impl From<DatabaseError> for CommandError {
+    fn from(e: DatabaseError) -> Self {
+        match e {
+            DatabaseError::NotFound(_) => CommandError::NotFound(e.to_string()),
+            DatabaseError::Conflict(ref current) => CommandError::Conflict {
+                message: e.to_string(),
+                details: serde_json::to_value(current).ok(),
+            },
+            DatabaseError::Sqlite(ref sqlite)
+                if matches!(
+                    sqlite.sqlite_error_code(),
+                    Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+                ) =>
+            {
+                CommandError::Busy(e.to_string())
+            }
+            DatabaseError::ExecutionsRunning(_) => CommandError::Busy(e.to_string()),
+            DatabaseError::StateIncrement { .. } => CommandError::conflict(e.to_string()),
+            DatabaseError::EncryptionUnavailable(_) => CommandError::Auth(e.to_string()),
+            DatabaseError::Encryption(ref source)
+            | DatabaseError::Blob(BlobError::Encryption(ref source)) => {
+                encryption_failure(source, e.to_string())
+            }
+            DatabaseError::TooLarge(ref exceeded) => CommandError::Validation {
+                message: e.to_string(),
+                details: serde_json::to_value(exceeded).ok(),
+            },
+            e => CommandError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<DeadlineError> for CommandError {
+    fn from(e: DeadlineError) -> Self {
+        match e {
+            DeadlineError::Passed(_) => CommandError::validation(e.to_string()),
+            DeadlineError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl From<EngineError> for CommandError {
+    fn from(e: EngineError) -> Self {
+        match e {
+            EngineError::AlreadyRunning {
+                ref workflow_id,
+                ref execution_id,
+            } => CommandError::Conflict {
+                details: Some(serde_json::json!({
+                    "workflow_id": workflow_id,
+                    "execution_id": execution_id,
+                })),
+                message: e.to_string(),
+            },
+            EngineError::QueueFull { .. } => CommandError::Busy(e.to_string()),
+            EngineError::ExecutionNotFound(_)
+            | EngineError::NodeNotFound(_)
+            | EngineError::EdgeNotFound(_) => CommandError::NotFound(e.to_string()),
+            EngineError::MissingSeed { ref missing, .. } => CommandError::Validation {
+                details: Some(serde_json::json!({ "missing": missing })),
+                message: e.to_string(),
+            },
+            EngineError::CycleDetected | EngineError::UnknownProfile(_) => {
+                CommandError::validation(e.to_string())
+            }
+            EngineError::Cancelled
+            | EngineError::NotAwaitingInput { .. }
+            | EngineError::NodeNotRunning { .. } => CommandError::conflict(e.to_string()),
+            EngineError::ShuttingDown => CommandError::Unavailable(e.to_string()),
+            EngineError::NodeFailed { .. } | EngineError::PreviewTimedOut { .. } => {
+                CommandError::Internal(e.to_string())
+            }
+            EngineError::Database(e) => e.into(),
+        }
+    }
+}
+
+// Classifies by the encryption failure, keeping the caller's message.
+fn encryption_failure(e: &EncryptionError, message: String) -> CommandError {
+    match e {
+        EncryptionError::WrongKey | EncryptionError::AuthenticationFailed => {
+            CommandError::Auth(message)
+        }
+        EncryptionError::InvalidConfig(_) | EncryptionError::Format(_) => {
+            CommandError::validation(message)
+        }
+        EncryptionError::Database(_) | EncryptionError::Kdf(_) | EncryptionError::Encrypt => {
+            CommandError::Internal(message)
+        }
+    }
+}
+
+impl From<EncryptionError> for CommandError {
+    fn from(e: EncryptionError) -> Self {
+        match e {
+            EncryptionError::Database(e) => e.into(),
+            e => encryption_failure(&e, e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::NotFound => CommandError::NotFound(e.to_string()),
+            ErrorKind::PermissionDenied => CommandError::Auth(e.to_string()),
+            _ => CommandError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(e: serde_json::Error) -> Self {
+        CommandError::Internal(e.to_string())
+    }
+}
+
+impl From<tauri::Error> for CommandError {
+    fn from(e: tauri::Error) -> Self {
+        CommandError::Internal(e.to_string())
+    }
+}
+
+impl From<ServiceError> for CommandError {
+    fn from(e: ServiceError) -> Self {
+        match e {
+            ServiceError::Database(e) => e.into(),
+            ServiceError::Engine(e) => e.into(),
+        }
+    }
+}
+
+impl From<EnvironmentError> for CommandError {
+    fn from(e: EnvironmentError) -> Self {
+        match e {
+            EnvironmentError::InvalidName(_)
+            | EnvironmentError::InvalidKey(_)
+            | EnvironmentError::AmbiguousKey(_) => CommandError::validation(e.to_string()),
+            EnvironmentError::Exists(_) => CommandError::conflict(e.to_string()),
+            EnvironmentError::NotFound(_) => CommandError::NotFound(e.to_string()),
+            EnvironmentError::Database(e) => e.into(),
+            EnvironmentError::Secret(e) => e.into(),
+        }
+    }
+}
+
+impl From<GraphError> for CommandError {
+    fn from(e: GraphError) -> Self {
+        match e {
+            GraphError::NodeNotFound(_) => CommandError::NotFound(e.to_string()),
+            GraphError::Invalid(_) | GraphError::TypeMismatch { .. } => {
+                CommandError::validation(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<NotificationError> for CommandError {
+    fn from(e: NotificationError) -> Self {
+        match e {
+            NotificationError::InvalidRule { .. } => CommandError::validation(e.to_string()),
+        }
+    }
+}
+
+impl From<PlanError> for CommandError {
+    fn from(e: PlanError) -> Self {
+        match e {
+            PlanError::Invalid { .. } => CommandError::validation(e.to_string()),
+            PlanError::HashMismatch(_) | PlanError::Serialization(_) => {
+                CommandError::Internal(e.to_string())
+            }
+            PlanError::Database(e) => e.into(),
+            PlanError::Engine(e) => e.into(),
+        }
+    }
+}
+
+impl From<ProfileError> for CommandError {
+    fn from(e: ProfileError) -> Self {
+        match e {
+            ProfileError::InvalidName(_) => CommandError::validation(e.to_string()),
+            ProfileError::ExecutionsRunning(_) => CommandError::Busy(e.to_string()),
+            ProfileError::Io(e) => e.into(),
+        }
+    }
+}
+
+impl From<SecretError> for CommandError {
+    fn from(e: SecretError) -> Self {
+        match e {
+            SecretError::InvalidName(_) | SecretError::EmptyWorkflowId => {
+                CommandError::validation(e.to_string())
+            }
+            SecretError::Database(e) => e.into(),
+            SecretError::Decrypt { ref source, .. } => encryption_failure(source, e.to_string()),
+            SecretError::Encryption(e) => e.into(),
+            SecretError::KeyNotStored(_) => CommandError::Unavailable(e.to_string()),
+        }
+    }
+}
+
+impl From<BackupError> for CommandError {
+    fn from(e: BackupError) -> Self {
+        match e {
+            BackupError::Database(e) => e.into(),
+            BackupError::Io(e) => e.into(),
+            BackupError::Serialization(_)
+            | BackupError::UnsupportedVersion(_)
+            | BackupError::NoKeyframe(_)
+            | BackupError::BrokenChain { .. }
+            | BackupError::NotABackup(_)
+            | BackupError::ChecksumMismatch
+            | BackupError::IncompatibleSchema { .. } => CommandError::validation(e.to_string()),
+            BackupError::ExecutionsRunning(_) => CommandError::Busy(e.to_string()),
+        }
+    }
+}
+
+impl From<ArchiveError> for CommandError {
+    fn from(e: ArchiveError) -> Self {
+        match e {
+            ArchiveError::Database(e) => e.into(),
+            ArchiveError::Secret(e) => e.into(),
+            ArchiveError::Io(e) => e.into(),
+            ArchiveError::Zip(_)
+            | ArchiveError::Serialization(_)
+            | ArchiveError::InvalidFormat(_)
+            | ArchiveError::UnsupportedVersion(_)
+            | ArchiveError::NewerSchema { .. } => CommandError::validation(e.to_string()),
+        }
+    }
+}
+
+impl From<BundleError> for CommandError {
+    fn from(e: BundleError) -> Self {
+        match e {
+            BundleError::Database(e) => e.into(),
+            BundleError::Encryption(e) => e.into(),
+            BundleError::Io(e) => e.into(),
+            BundleError::PassphraseRequired => CommandError::Auth(e.to_string()),
+            BundleError::Serialization(_)
+            | BundleError::InvalidFormat(_)
+            | BundleError::UnsupportedVersion(_) => CommandError::validation(e.to_string()),
+        }
+    }
+}
+
+impl From<SigningError> for CommandError {
+    fn from(e: SigningError) -> Self {
+        match e {
+            SigningError::Database(e) => e.into(),
+            SigningError::Io(e) => e.into(),
+            // Keeps its bare "signature_invalid" message.
+            SigningError::SignatureInvalid => CommandError::Auth(e.to_string()),
+            SigningError::Serialization(_)
+            | SigningError::InvalidKey(_)
+            | SigningError::UnsupportedAlgorithm(_) => CommandError::validation(e.to_string()),
+        }
+    }
+}
+
+impl From<ActivationError> for CommandError {
+    fn from(e: ActivationError) -> Self {
+        match e {
+            ActivationError::Io(e) => e.into(),
+            ActivationError::Malformed(_) => CommandError::validation(e.to_string()),
+            ActivationError::SignatureInvalid
+            | ActivationError::WrongMachine
+            | ActivationError::Expired(_) => CommandError::Auth(e.to_string()),
+        }
+    }
+}
+
+impl From<ExecutionLogError> for CommandError {
+    fn from(e: ExecutionLogError) -> Self {
+        match e {
+            ExecutionLogError::Database(e) => e.into(),
+            ExecutionLogError::Io(e) => e.into(),
+            ExecutionLogError::Serialization(e) => e.into(),
+        }
+    }
+}
+
+impl From<FileWatchError> for CommandError {
+    fn from(e: FileWatchError) -> Self {
+        match e {
+            FileWatchError::Database(e) => e.into(),
+            FileWatchError::Notify(_) => CommandError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<FolderError> for CommandError {
+    fn from(e: FolderError) -> Self {
+        match e {
+            FolderError::Database(e) => e.into(),
+            FolderError::NotFound(_) => CommandError::NotFound(e.to_string()),
+            FolderError::EmptyName | FolderError::Cycle { .. } => {
+                CommandError::validation(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<CatalogError> for CommandError {
+    fn from(e: CatalogError) -> Self {
+        match e {
+            CatalogError::Database(e) => e.into(),
+            CatalogError::NotFound(_) => CommandError::NotFound(e.to_string()),
+            CatalogError::Invalid { .. } => CommandError::validation(e.to_string()),
+        }
+    }
+}
+
+impl From<WebhookError> for CommandError {
+    fn from(e: WebhookError) -> Self {
+        match e {
+            WebhookError::Database(e) => e.into(),
+            WebhookError::Settings(_) => CommandError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ControlApiError> for CommandError {
+    fn from(e: ControlApiError) -> Self {
+        match e {
+            ControlApiError::Database(e) => e.into(),
+            ControlApiError::Settings(_) => CommandError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<WsError> for CommandError {
+    fn from(e: WsError) -> Self {
+        match e {
+            WsError::NotConnected => CommandError::Unavailable(e.to_string()),
+            WsError::AlreadyConnected => CommandError::conflict(e.to_string()),
+            WsError::InvalidUrl(_) => CommandError::validation(e.to_string()),
+        }
+    }
+}
+
+impl From<StateError> for CommandError {
+    fn from(e: StateError) -> Self {
+        match e {
+            StateError::InvalidKey(_) => CommandError::validation(e.to_string()),
+            StateError::Database(e) => e.into(),
+        }
+    }
+}
@@ -0,0 +1,899 @@
+/*!
+ * Tauri commands - node, validation and tooling commands exposed to the frontend
+ */
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, State, Window};
+
+use crate::activation::{Activation, ActivationStatus};
+use crate::archive::{self, ArchiveExportSummary, ArchiveImportSummary, ConflictStrategy};
+use crate::audit;
+use crate::backup::{self, BackupDiff, BackupError, BackupSummary, DatabaseBackupSummary, RestoreSummary};
+use crate::bundle::{self, BundleExportSummary, BundleImportSummary};
+use crate::command_error::CommandError;
+use crate::control_api::ControlApiSettings;
+use crate::database::{Database, DatabaseError, DatabaseStats, VacuumSummary};
+use crate::deadlines::{self, DeadlineManager, DeadlineSchedule};
+use crate::diagnostics::{self, DiagnosticsReport};
+use crate::diagram;
+use crate::diff::{self, WorkflowDiff};
+use crate::encryption::{self, EncryptionHealth, KdfParams};
+use crate::estimate::{self, ExecutionEstimate};
+use crate::execution_log::{self, LogExportSummary, LogFormat};
+use crate::file_watcher::FileWatchers;
+use crate::hooks::NodeMetrics;
+use crate::logging::{self, LogLevel};
+use crate::nodes::plugin::PluginInfo;
+use crate::nodes::NodeTypeInfo;
+use crate::resources::{self, EnergyEstimate, ResourceMonitor};
+use crate::schema::FieldError;
+use crate::script;
+use crate::secrets;
+use crate::signing::{self, SignedExportSummary, SigningKeyPair};
+use crate::template::Resolution;
+use crate::templates::{self, TemplateSummary, WorkflowTemplate};
+use crate::validation::{
+    self, LibraryValidationProgress, LibraryValidationSummary, Severity, ValidationProfile,
+    ValidationProfiles, ValidationReport,
+};
+use crate::webhook_server::WebhookSettings;
+use crate::websocket_client::WebSocketClient;
+use crate::windows::{self, WindowContext, WindowContexts, WindowSummary};
+use crate::workflow_engine::{self, EdgeSimulation, NodePreview, TemplateSample, WorkflowEngine};
+use crate::{AppState, ExportFormat, UserPreferences, Workflow, WorkflowNode};
+
+#[tauri::command]
+pub async fn get_node_types(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<NodeTypeInfo>, CommandError> {
+    Ok(engine.lock().node_types())
+}
+
+// Plugins found at startup with their load status, to diagnose one whose
+// node type did not register.
+#[tauri::command]
+pub async fn list_plugins(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<PluginInfo>, CommandError> {
+    Ok(engine.lock().registry().plugins().to_vec())
+}
+
+// Runs, failures and timings per node type since the app started.
+#[tauri::command]
+pub async fn get_node_metrics(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<NodeMetrics>, CommandError> {
+    Ok(engine.lock().node_metrics())
+}
+
+// Changes that turn `a` into `b`.
+#[tauri::command]
+pub async fn diff_workflows(a: Workflow, b: Workflow) -> Result<WorkflowDiff, CommandError> {
+    Ok(diff::diff_workflows(&a, &b))
+}
+
+// Changes from version `v1` of the workflow to version `v2`, or to the current
+// workflow when `v2` is not given.
+#[tauri::command]
+pub async fn diff_workflow_versions(
+    id: String,
+    v1: i64,
+    v2: Option<i64>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<WorkflowDiff, CommandError> {
+    let db = db.lock();
+    let before = db.get_workflow_version(&id, v1)?;
+    let after = match v2 {
+        Some(v2) => db.get_workflow_version(&id, v2)?,
+        None => db.get_workflow(&id)?,
+    };
+    Ok(diff::diff_workflows(&before, &after))
+}
+
+#[tauri::command]
+pub async fn get_required_scopes(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<String>, CommandError> {
+    let workflow = db.lock()
+        .get_workflow(&id)?;
+
+    Ok(engine.lock().registry().required_scopes(&workflow.nodes))
+}
+
+#[tauri::command]
+pub async fn get_node_schema(
+    node_type: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<serde_json::Value, CommandError> {
+    engine.lock()
+        .registry()
+        .get(&node_type)
+        .map(|executor| executor.config_schema())
+        .ok_or_else(|| {
+            CommandError::NotFound(format!("no executor registered for node type {}", node_type))
+        })
+}
+
+// An empty list means the config is valid. Problems not tied to one field
+// (such as an unknown node type) have an empty path.
+#[tauri::command]
+pub async fn validate_node_config(
+    node_type: String,
+    config: serde_json::Value,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<FieldError>, CommandError> {
+    let issues = validation::validate_node_config(engine.lock().registry(), &node_type, &config);
+
+    Ok(issues
+        .into_iter()
+        .map(|issue| FieldError {
+            path: issue.path.unwrap_or_default(),
+            message: issue.message,
+        })
+        .collect())
+}
+
+// Runs one node on `input` for the node editor, as a dry run unless
+// `dry_run` is false. A config with errors is refused up front, with the
+// issues as the error's details.
+#[tauri::command]
+pub async fn preview_node(
+    node: WorkflowNode,
+    input: Option<serde_json::Value>,
+    dry_run: Option<bool>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<NodePreview, CommandError> {
+    let preview = {
+        let engine = engine.lock();
+        let issues: Vec<_> =
+            validation::validate_node_config(engine.registry(), &node.node_type, &node.data)
+                .into_iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .collect();
+        if !issues.is_empty() {
+            return Err(CommandError::Validation {
+                message: format!("node {} has an invalid config", node.id),
+                details: serde_json::to_value(&issues).ok(),
+            });
+        }
+        engine.preview_node(&node, input.unwrap_or_default(), dry_run.unwrap_or(true))
+    };
+    preview.await.map_err(CommandError::from)
+}
+
+// What every `{{ }}` reference in the workflow's nodes resolves to against
+// `sample_context`, keyed by node id and then reference. Nothing runs, and
+// secret values are masked.
+#[tauri::command]
+pub async fn resolve_templates(
+    workflow_id: String,
+    sample_context: Option<TemplateSample>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<BTreeMap<String, BTreeMap<String, Resolution>>, CommandError> {
+    let workflow = {
+        let readers = db.lock().readers();
+        readers.get_workflow(&workflow_id)?
+    };
+    let resolved = engine
+        .lock()
+        .resolve_templates(&workflow, sample_context.unwrap_or_default());
+    Ok(resolved.await)
+}
+
+// What the edge's target would receive if its source output
+// `sample_source_output`, and whether that matches the target's declared
+// input. Neither node runs.
+#[tauri::command]
+pub async fn test_edge(
+    workflow_id: String,
+    edge_id: String,
+    sample_source_output: serde_json::Value,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<EdgeSimulation, CommandError> {
+    let workflow = {
+        let readers = db.lock().readers();
+        readers.get_workflow(&workflow_id)?
+    };
+    workflow_engine::simulate_edge(
+        engine.lock().registry(),
+        &workflow,
+        &edge_id,
+        sample_source_output,
+    )
+    .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn validate_workflow(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    profiles: State<'_, Arc<Mutex<ValidationProfiles>>>,
+) -> Result<ValidationReport, CommandError> {
+    let workflow = db.lock()
+        .get_workflow(&id)?;
+
+    let (report, profile) = validation::validate_workflow(engine.lock().registry(), &workflow);
+    profiles.lock().record(profile);
+
+    Ok(report)
+}
+
+// Validates every stored workflow, graph and node configs, as a check before
+// an upgrade. Progress goes to the calling window as
+// `library-validation-progress` events. The engine is locked per workflow so
+// runs aren't held up by a large library.
+#[tauri::command]
+pub async fn validate_all_workflows(
+    window: Window,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    profiles: State<'_, Arc<Mutex<ValidationProfiles>>>,
+) -> Result<LibraryValidationSummary, CommandError> {
+    let readers = db.lock().readers();
+    let workflows = readers.get_workflows()?;
+    let total = workflows.len();
+
+    let mut summary = LibraryValidationSummary::default();
+    for (index, workflow) in workflows.iter().enumerate() {
+        let (report, profile) = validation::validate_workflow(engine.lock().registry(), workflow);
+        profiles.lock().record(profile);
+        summary.add(workflow, report);
+        let _ = window.emit(
+            "library-validation-progress",
+            LibraryValidationProgress {
+                validated: index + 1,
+                total,
+                workflow_id: workflow.id.clone(),
+            },
+        );
+    }
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn get_validation_profile(
+    workflow_id: String,
+    profiles: State<'_, Arc<Mutex<ValidationProfiles>>>,
+) -> Result<Option<ValidationProfile>, CommandError> {
+    Ok(profiles.lock().get(&workflow_id))
+}
+
+#[tauri::command]
+pub async fn create_incremental_backup(
+    dest_dir: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<BackupSummary, CommandError> {
+    backup::create_incremental_backup(&db.lock(), &PathBuf::from(dest_dir))
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn restore_from_incremental_chain(
+    dir: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<RestoreSummary, CommandError> {
+    let summary = backup::restore_from_incremental_chain(&mut db.lock(), &PathBuf::from(dir))?;
+    // Restored workflows may have changed status or been trashed.
+    watchers.lock().sync_all()?;
+    Ok(summary)
+}
+
+// `backup_path` is a backup directory or one backup file in it.
+#[tauri::command]
+pub async fn diff_against_backup(
+    backup_path: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<BackupDiff, CommandError> {
+    backup::diff_against_backup(&db.lock(), &PathBuf::from(backup_path))
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn backup_database(
+    dest_path: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<DatabaseBackupSummary, CommandError> {
+    let summary = backup::backup_database(&db.lock(), &PathBuf::from(&dest_path));
+    audit::record(&db, &state, "backup_database", format!("to {}", dest_path), &summary);
+    summary.map_err(CommandError::from)
+}
+
+// Swaps in the snapshot, then reloads the preferences and file watches it
+// brings. Refused while runs are active, since they would finish against the
+// replaced database.
+#[tauri::command]
+pub async fn restore_database(
+    src_path: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<DatabaseBackupSummary, CommandError> {
+    let summary = {
+        // Holding the engine keeps new runs from starting during the swap.
+        let mut engine = engine.lock();
+        let running = engine.list_active_executions().len();
+        if running > 0 {
+            return Err(BackupError::ExecutionsRunning(running).into());
+        }
+        let mut db = db.lock();
+        let summary = backup::restore_database(&mut db, &PathBuf::from(&src_path))?;
+        let preferences = UserPreferences::load(&db)?;
+        preferences.apply(&mut engine, &mut db);
+        state.lock().user_preferences = preferences;
+        summary
+    };
+    // Recorded in the restored database, so the log shows where it came from.
+    audit::record_done(&db, &state, "restore_database", format!("from {}", src_path));
+
+    if let Err(e) = watchers.lock().sync_all() {
+        tracing::warn!("failed to reload file watches after restore: {}", e);
+    }
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn get_database_stats(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<DatabaseStats, CommandError> {
+    db.lock().stats().map_err(CommandError::from)
+}
+
+// Refused while runs are active; holding the engine keeps new ones from
+// starting until the vacuum is done.
+#[tauri::command]
+pub async fn vacuum_database(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<VacuumSummary, CommandError> {
+    let summary = {
+        let engine = engine.lock();
+        let running = engine.list_active_executions().len();
+        if running > 0 {
+            return Err(DatabaseError::ExecutionsRunning(running).into());
+        }
+        db.lock().vacuum()
+    };
+    let detail = match &summary {
+        Ok(summary) => format!("reclaimed {} bytes", summary.reclaimed_bytes),
+        Err(_) => "vacuum".to_string(),
+    };
+    audit::record(&db, &state, "vacuum_database", detail, &summary);
+    summary.map_err(CommandError::from)
+}
+
+// Returns the workflow as text in `format` (JSON by default). Secret values
+// pasted into the JSON are replaced by their `{{ secrets.NAME }}` reference.
+#[tauri::command]
+pub async fn export_workflow(
+    id: String,
+    format: Option<ExportFormat>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    let format = format.unwrap_or_default();
+    let secret_key = state.lock().secret_key.clone();
+    let exported = workflow_text(&db.lock(), &id, format, &secret_key);
+    audit::record(
+        &db,
+        &state,
+        "export_workflow",
+        format!("workflow {} as {:?}", id, format),
+        &exported,
+    );
+    exported
+}
+
+fn workflow_text(
+    db: &Database,
+    id: &str,
+    format: ExportFormat,
+    secret_key: &str,
+) -> Result<String, CommandError> {
+    let mut workflow = db.get_workflow(id)?;
+    match format {
+        ExportFormat::Json => {
+            let secrets = secrets::visible_values(db, id, secret_key)?;
+            secrets::scrub_export(&mut workflow, &mut [], &secrets);
+            serde_json::to_string_pretty(&workflow).map_err(CommandError::from)
+        }
+        ExportFormat::Mermaid => Ok(diagram::mermaid(&workflow)),
+        ExportFormat::Dot => Ok(diagram::dot(&workflow)),
+        ExportFormat::Script => {
+            let secrets = secrets::visible_values(db, id, secret_key)?;
+            secrets::scrub_export(&mut workflow, &mut [], &secrets);
+            Ok(script::python(&workflow)?)
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn export_bundle(
+    id: String,
+    path: String,
+    passphrase: Option<String>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<BundleExportSummary, CommandError> {
+    let secret_key = state.lock().secret_key.clone();
+    let exported = {
+        let db = db.lock();
+        secrets::visible_values(&db, &id, &secret_key)
+            .map_err(CommandError::from)
+            .and_then(|secrets| {
+                bundle::export_bundle(
+                    &db,
+                    &id,
+                    &PathBuf::from(&path),
+                    passphrase.as_deref(),
+                    &secrets,
+                )
+                .map_err(CommandError::from)
+            })
+    };
+    audit::record(
+        &db,
+        &state,
+        "export_bundle",
+        format!("workflow {} to {}", id, path),
+        &exported,
+    );
+    exported
+}
+
+#[tauri::command]
+pub async fn import_bundle(
+    path: String,
+    passphrase: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<BundleImportSummary, CommandError> {
+    bundle::import_bundle(
+        &mut db.lock(),
+        engine.lock().registry(),
+        &PathBuf::from(path),
+        passphrase.as_deref(),
+    )
+    .map_err(CommandError::from)
+}
+
+// Every live workflow as its own JSON file in a ZIP at `dest_path`.
+#[tauri::command]
+pub async fn export_all_workflows(
+    dest_path: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<ArchiveExportSummary, CommandError> {
+    let secret_key = state.lock().secret_key.clone();
+    let exported = archive::export_all(&db, &PathBuf::from(&dest_path), &secret_key)
+        .map_err(CommandError::from);
+    let summary = match &exported {
+        Ok(summary) => format!("{} workflows to {}", summary.workflows, dest_path),
+        Err(_) => format!("workflows to {}", dest_path),
+    };
+    audit::record(&db, &state, "export_all_workflows", summary, &exported);
+    exported
+}
+
+// Imports an archive from `export_all_workflows`, resolving id clashes by
+// `conflict_strategy` (skip by default). Workflow files that fail are listed
+// in the summary rather than failing the import.
+#[tauri::command]
+pub async fn import_all_workflows(
+    src_path: String,
+    conflict_strategy: Option<ConflictStrategy>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<ArchiveImportSummary, CommandError> {
+    let summary = archive::import_all(
+        &db,
+        &PathBuf::from(src_path),
+        conflict_strategy.unwrap_or_default(),
+    )?;
+    if let Err(e) = watchers.lock().sync_all() {
+        tracing::warn!("failed to reload file watches after import: {}", e);
+    }
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn export_execution_log(
+    execution_id: String,
+    path: String,
+    format: Option<LogFormat>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<LogExportSummary, CommandError> {
+    let exported = execution_log::export(
+        &db.lock(),
+        &execution_id,
+        &PathBuf::from(&path),
+        format.unwrap_or_default(),
+    );
+    audit::record(
+        &db,
+        &state,
+        "export_execution_log",
+        format!("execution {} to {}", execution_id, path),
+        &exported,
+    );
+    exported.map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn generate_signing_key() -> Result<SigningKeyPair, CommandError> {
+    Ok(signing::generate_keypair())
+}
+
+#[tauri::command]
+pub async fn export_signed_workflow(
+    id: String,
+    signing_key: String,
+    path: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<SignedExportSummary, CommandError> {
+    let secret_key = state.lock().secret_key.clone();
+    let exported = {
+        let db = db.lock();
+        secrets::visible_values(&db, &id, &secret_key)
+            .map_err(CommandError::from)
+            .and_then(|secrets| {
+                signing::export_signed(&db, &id, &signing_key, &PathBuf::from(&path), &secrets)
+                    .map_err(CommandError::from)
+            })
+    };
+    audit::record(
+        &db,
+        &state,
+        "export_signed_workflow",
+        format!("workflow {} to {}", id, path),
+        &exported,
+    );
+    exported
+}
+
+// Returns the workflow only if it was signed by `public_key`; tampering yields
+// the error "signature_invalid".
+#[tauri::command]
+pub async fn verify_signed_workflow(
+    path: String,
+    public_key: String,
+) -> Result<Workflow, CommandError> {
+    signing::verify_signed(&PathBuf::from(path), &public_key).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn get_webhook_settings(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<WebhookSettings, CommandError> {
+    WebhookSettings::load(&db.lock()).map_err(CommandError::from)
+}
+
+// The listener picks up `enabled` and `port` on the next launch.
+#[tauri::command]
+pub async fn set_webhook_listener(
+    enabled: bool,
+    port: u16,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<WebhookSettings, CommandError> {
+    let db = db.lock();
+    let mut settings = WebhookSettings::load(&db)?;
+    settings.enabled = enabled;
+    settings.port = port;
+    settings.save(&db)?;
+    Ok(settings)
+}
+
+#[tauri::command]
+pub async fn rotate_webhook_secret(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<WebhookSettings, CommandError> {
+    let rotated = rotated_webhook_secret(&db.lock());
+    audit::record(&db, &state, "rotate_webhook_secret", "webhook secret", &rotated);
+    rotated
+}
+
+fn rotated_webhook_secret(db: &Database) -> Result<WebhookSettings, CommandError> {
+    let mut settings = WebhookSettings::load(db)?;
+    settings.rotate_secret();
+    settings.save(db)?;
+    Ok(settings)
+}
+
+#[tauri::command]
+pub async fn get_control_api_settings(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<ControlApiSettings, CommandError> {
+    ControlApiSettings::load(&db.lock()).map_err(CommandError::from)
+}
+
+// Takes effect on the next launch. `host` defaults to localhost; binding any
+// other address exposes the API to the network.
+#[tauri::command]
+pub async fn set_control_api_listener(
+    enabled: bool,
+    host: Option<IpAddr>,
+    port: u16,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<ControlApiSettings, CommandError> {
+    let db = db.lock();
+    let mut settings = ControlApiSettings::load(&db)?;
+    settings.enabled = enabled;
+    settings.host = host.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    settings.port = port;
+    settings.save(&db)?;
+    Ok(settings)
+}
+
+#[tauri::command]
+pub async fn rotate_control_api_token(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<ControlApiSettings, CommandError> {
+    let rotated = rotated_control_api_token(&db.lock());
+    audit::record(&db, &state, "rotate_control_api_token", "control API token", &rotated);
+    rotated
+}
+
+fn rotated_control_api_token(db: &Database) -> Result<ControlApiSettings, CommandError> {
+    let mut settings = ControlApiSettings::load(db)?;
+    settings.rotate_token();
+    settings.save(db)?;
+    Ok(settings)
+}
+
+#[tauri::command]
+pub async fn list_templates(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<TemplateSummary>, CommandError> {
+    let templates = templates::all_templates(&db.lock())?;
+    Ok(templates.iter().map(WorkflowTemplate::summary).collect())
+}
+
+#[tauri::command]
+pub async fn create_from_template(
+    template_id: String,
+    name: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Workflow, CommandError> {
+    let db = db.lock();
+    let template = templates::find_template(&db, &template_id)?;
+
+    let workflow = template.instantiate(&name);
+    db.create_workflow(&workflow)?;
+    Ok(workflow)
+}
+
+#[tauri::command]
+pub async fn save_as_template(
+    workflow_id: String,
+    name: String,
+    description: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<TemplateSummary, CommandError> {
+    let db = db.lock();
+    let workflow = db.get_workflow(&workflow_id)?;
+
+    let template = WorkflowTemplate::from_workflow(&workflow, &name, &description);
+    templates::validate_template(engine.lock().registry(), &template)?;
+    db.insert_user_template(&template)?;
+    Ok(template.summary())
+}
+
+// A failure means secrets encrypted with the secrets key may be unreadable, so it
+// is logged as an error as well as returned.
+#[tauri::command]
+pub async fn test_encryption_health(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<EncryptionHealth, CommandError> {
+    let key = state.lock().secret_key.clone();
+    KdfParams::load(&db.lock())
+        .and_then(|params| encryption::check_health(&key, &params))
+        .map_err(|e| {
+            tracing::error!("encryption health check failed: {}", e);
+            CommandError::from(e)
+        })
+}
+
+// Never fails: a broken subsystem shows up as a failed check in the report.
+#[tauri::command]
+pub async fn run_diagnostics(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    resources: State<'_, Arc<ResourceMonitor>>,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<DiagnosticsReport, CommandError> {
+    let secret_key = state.lock().secret_key.clone();
+    let websocket_url = ws_client.lock().url().to_string();
+    Ok(diagnostics::run(
+        db.inner().clone(),
+        engine.inner().clone(),
+        resources.inner().clone(),
+        secret_key,
+        websocket_url,
+    )
+    .await)
+}
+
+// Takes effect at once and is saved as the profile's `log_level` preference.
+#[tauri::command]
+pub async fn set_log_level(
+    level: LogLevel,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    let mut preferences = state.lock().user_preferences.clone();
+    preferences.log_level = level;
+    preferences.save(&db.lock())?;
+    logging::set_level(level);
+    state.lock().user_preferences = preferences;
+    Ok(())
+}
+
+fn log_file() -> Result<&'static Path, CommandError> {
+    logging::log_path()
+        .ok_or_else(|| CommandError::Unavailable("logs are not being written to a file".to_string()))
+}
+
+#[tauri::command]
+pub async fn get_log_path() -> Result<PathBuf, CommandError> {
+    Ok(log_file()?.to_path_buf())
+}
+
+// Opens the folder holding the current and rotated log files.
+#[tauri::command]
+pub async fn open_log_folder() -> Result<(), CommandError> {
+    let dir = log_file()?
+        .parent()
+        .ok_or_else(|| CommandError::Internal("log file has no parent folder".to_string()))?;
+    logging::reveal(dir)?;
+    Ok(())
+}
+
+// Checks the key offline against this machine's id and, once accepted,
+// unlocks premium node types right away.
+#[tauri::command]
+pub async fn activate(
+    license_key: String,
+    activation: State<'_, Arc<Mutex<Activation>>>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<ActivationStatus, CommandError> {
+    let activated = activation.lock().activate(&license_key).cloned();
+    audit::record(&db, &state, "activate", "license key", &activated);
+    let status = activated?;
+    engine.lock().registry().set_premium_unlocked(status.active);
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn get_activation_status(
+    activation: State<'_, Arc<Mutex<Activation>>>,
+) -> Result<ActivationStatus, CommandError> {
+    Ok(activation.lock().status().clone())
+}
+
+// Projects how long a run would take from past node timings; nothing runs.
+#[tauri::command]
+pub async fn estimate_execution(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<ExecutionEstimate, CommandError> {
+    let db = db.lock();
+    let workflow = db.get_workflow(&id)?;
+    let stats = db.get_node_type_stats()?;
+    Ok(estimate::estimate(&workflow, &stats)?)
+}
+
+// Starts the workflow early enough to finish by `finish_by`, by its estimate
+// plus `margin_ms` (default `deadlines::DEFAULT_MARGIN_MS`).
+#[tauri::command]
+pub async fn create_deadline_schedule(
+    workflow_id: String,
+    finish_by: chrono::DateTime<chrono::Utc>,
+    margin_ms: Option<u64>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    deadlines: State<'_, DeadlineManager>,
+) -> Result<DeadlineSchedule, CommandError> {
+    let schedule = deadlines::create(&db.lock(), &workflow_id, finish_by, margin_ms)?;
+    deadlines.reschedule();
+    Ok(schedule)
+}
+
+// Fired schedules are kept, with the run they started.
+#[tauri::command]
+pub async fn get_deadline_schedules(
+    workflow_id: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<DeadlineSchedule>, CommandError> {
+    let readers = db.lock().readers();
+    Ok(readers.get_deadline_schedules(workflow_id.as_deref())?)
+}
+
+#[tauri::command]
+pub async fn delete_deadline_schedule(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    Ok(db.lock().delete_deadline_schedule(&id)?)
+}
+
+#[tauri::command]
+pub async fn get_execution_energy_estimate(
+    execution_id: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<EnergyEstimate, CommandError> {
+    let execution = db.lock()
+        .get_execution(&execution_id)?;
+    let cpu_time_ms = execution
+        .cpu_time_ms
+        .ok_or_else(|| {
+            CommandError::NotFound(format!("no cpu time recorded for execution {}", execution_id))
+        })?;
+
+    let preferences = state.lock().user_preferences.clone();
+    Ok(resources::estimate_energy(
+        &execution_id,
+        cpu_time_ms,
+        preferences.watts_per_core,
+        preferences.grams_co2_per_kwh,
+    ))
+}
+
+#[tauri::command]
+pub async fn open_workflow_window(
+    id: String,
+    app: AppHandle,
+    db: State<'_, Arc<Mutex<Database>>>,
+    contexts: State<'_, Arc<Mutex<WindowContexts>>>,
+) -> Result<String, CommandError> {
+    let workflow = db.lock()
+        .get_workflow(&id)?;
+    let window = windows::open_workflow_window(&app, &workflow, &contexts)?;
+    Ok(window.label().to_string())
+}
+
+#[tauri::command]
+pub async fn get_window_context(
+    window: Window,
+    contexts: State<'_, Arc<Mutex<WindowContexts>>>,
+) -> Result<WindowContext, CommandError> {
+    Ok(contexts.lock().get(window.label()))
+}
+
+#[tauri::command]
+pub async fn set_window_workflow(
+    workflow_id: Option<String>,
+    window: Window,
+    contexts: State<'_, Arc<Mutex<WindowContexts>>>,
+) -> Result<(), CommandError> {
+    contexts.lock().set_workflow(window.label(), workflow_id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_windows(
+    app: AppHandle,
+    contexts: State<'_, Arc<Mutex<WindowContexts>>>,
+) -> Result<Vec<WindowSummary>, CommandError> {
+    Ok(windows::list_windows(&app, &contexts.lock()))
+}
@@ -0,0 +1,213 @@
+/*!
+ * Control API - local HTTP API for running workflows without the UI
+ *
+ * Exposes the workflow list, single workflows, run starts and execution
+ * status as JSON, through the same `service` functions the Tauri commands
+ * use. Off unless enabled in its settings or launched with `--control-api`.
+ * Every request must carry the API token as `Authorization: Bearer <token>`.
+ *
+ *   GET  /api/workflows
+ *   GET  /api/workflows/:id
+ *   POST /api/workflows/:id/execute   {"profile": ..., "debug": ..., "idempotency_key": ...}
+ *   GET  /api/executions/:id
+ */
+
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+use crate::listener::{self, error_response};
+use crate::scheduler::Priority;
+use crate::service::{self, RunSettings, ServiceError};
+use crate::workflow_engine::{EngineError, WorkflowEngine};
+
+pub const DEFAULT_PORT: u16 = 8788;
+// Enables the API for this launch regardless of the stored setting.
+pub const LAUNCH_FLAG: &str = "--control-api";
+
+const SETTINGS_KEY: &str = "control_api.settings";
+
+#[derive(Debug, Error)]
+pub enum ControlApiError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("invalid control API settings: {0}")]
+    Settings(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ControlApiError>;
+
+// The listener reads `enabled`, `host` and `port` at launch; the token is
+// checked against the stored value on every request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlApiSettings {
+    pub enabled: bool,
+    pub host: IpAddr,
+    pub port: u16,
+    pub token: String,
+}
+
+impl ControlApiSettings {
+    // Stored settings; on first use, defaults with a fresh token are saved.
+    pub fn load(db: &Database) -> Result<Self> {
+        listener::load_settings(db, SETTINGS_KEY, || Self {
+            enabled: false,
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            token: listener::generate_token(),
+        })
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        listener::save_settings(db, SETTINGS_KEY, self)
+    }
+
+    pub fn rotate_token(&mut self) {
+        self.token = listener::generate_token();
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    db: Arc<Mutex<Database>>,
+    engine: Arc<Mutex<WorkflowEngine>>,
+}
+
+// Like the webhook listener, a bind failure is logged rather than failing
+// setup.
+pub fn spawn_listener(
+    db: Arc<Mutex<Database>>,
+    engine: Arc<Mutex<WorkflowEngine>>,
+    force_enabled: bool,
+) -> Result<()> {
+    let settings = ControlApiSettings::load(&db.lock())?;
+    if !settings.enabled && !force_enabled {
+        return Ok(());
+    }
+
+    let state = ApiState { db, engine };
+    let router = Router::new()
+        .route("/api/workflows", get(list_workflows))
+        .route("/api/workflows/:id", get(get_workflow))
+        .route("/api/workflows/:id/execute", post(execute_workflow))
+        .route("/api/executions/:id", get(execution_status))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state);
+    let addr = SocketAddr::new(settings.host, settings.port);
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!("control API could not bind {}: {}", addr, e);
+                return;
+            }
+        };
+        tracing::info!("control API on http://{}", addr);
+        if let Err(e) = axum::serve(listener, router).await {
+            tracing::warn!("control API stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn require_token(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let settings = ControlApiSettings::load(&state.db.lock());
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !listener::tokens_match(provided.as_bytes(), settings.token.as_bytes()) {
+        return error_response(StatusCode::UNAUTHORIZED, "missing or invalid API token");
+    }
+    next.run(request).await
+}
+
+impl IntoResponse for ServiceError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ServiceError::Database(DatabaseError::NotFound(_))
+            | ServiceError::Engine(EngineError::ExecutionNotFound(_)) => StatusCode::NOT_FOUND,
+            ServiceError::Engine(EngineError::UnknownProfile(_)) => StatusCode::BAD_REQUEST,
+            ServiceError::Engine(EngineError::AlreadyRunning { .. }) => StatusCode::CONFLICT,
+            ServiceError::Engine(EngineError::QueueFull { .. }) => StatusCode::TOO_MANY_REQUESTS,
+            ServiceError::Engine(EngineError::ShuttingDown) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        error_response(status, self.to_string())
+    }
+}
+
+async fn list_workflows(State(state): State<ApiState>) -> Response {
+    match service::list_workflows(&state.db).await {
+        Ok(workflows) => Json(workflows).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn get_workflow(State(state): State<ApiState>, Path(id): Path<String>) -> Response {
+    match service::get_workflow(&state.db, &id).await {
+        Ok(workflow) => Json(workflow).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ExecuteRequest {
+    profile: Option<String>,
+    debug: bool,
+    priority: Priority,
+    idempotency_key: Option<String>,
+}
+
+// The body is optional; an empty one runs with the default profile.
+async fn execute_workflow(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    request: Option<Json<ExecuteRequest>>,
+) -> Response {
+    let Json(request) = request.unwrap_or_default();
+    let started = service::execute_workflow(
+        &state.db,
+        &state.engine,
+        &id,
+        RunSettings {
+            profile: request.profile.as_deref(),
+            debug: request.debug,
+            priority: request.priority,
+        },
+        request.idempotency_key.as_deref(),
+    )
+    .await;
+    match started {
+        Ok(execution_id) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "execution_id": execution_id })),
+        )
+            .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn execution_status(State(state): State<ApiState>, Path(id): Path<String>) -> Response {
+    match service::execution_status(&state.db, &state.engine, &id).await {
+        Ok(execution) => Json(execution).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,125 @@
+/*!
+ * Credentials - auth token and secrets key persistence in the OS credential store
+ *
+ * The token is kept only in the platform keychain, keyed by machine id. When
+ * the keychain is unavailable every operation degrades to a no-op with a
+ * warning, leaving the token in memory for the current session only. The key
+ * workflow secrets are encrypted with is kept there too, one per profile;
+ * storing it fails loudly instead, since secrets encrypted with a key that was
+ * not kept could never be read again. Log messages here never include the
+ * token or the key.
+ */
+
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+
+const SERVICE: &str = "com.workflow.desktop.auth";
+const SECRET_KEY_SERVICE: &str = "com.workflow.desktop.secret-key";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub token: String,
+    pub issued_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub struct TokenStore {
+    entry: Option<Entry>,
+}
+
+impl TokenStore {
+    pub fn new(machine_id: &str) -> Self {
+        let entry = match Entry::new(SERVICE, machine_id) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("keychain unavailable, auth token will not persist: {}", e);
+                None
+            }
+        };
+        Self { entry }
+    }
+
+    pub fn load(&self) -> Option<StoredToken> {
+        let entry = self.entry.as_ref()?;
+        match entry.get_password() {
+            Ok(raw) => match serde_json::from_str(&raw) {
+                Ok(stored) => Some(stored),
+                Err(_) => {
+                    tracing::warn!("ignoring unreadable auth token in keychain");
+                    None
+                }
+            },
+            Err(keyring::Error::NoEntry) => None,
+            Err(e) => {
+                tracing::warn!("could not read auth token from keychain: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, stored: &StoredToken) {
+        let Some(entry) = &self.entry else {
+            return;
+        };
+        let result = serde_json::to_string(stored)
+            .map_err(|e| e.to_string())
+            .and_then(|raw| entry.set_password(&raw).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            tracing::warn!("could not store auth token in keychain: {}", e);
+        }
+    }
+
+    pub fn clear(&self) {
+        let Some(entry) = &self.entry else {
+            return;
+        };
+        match entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => tracing::warn!("could not remove auth token from keychain: {}", e),
+        }
+    }
+}
+
+// The key a profile's workflow secrets are encrypted with.
+pub struct SecretKeyStore {
+    entry: Option<Entry>,
+}
+
+impl SecretKeyStore {
+    pub fn new(profile: &str) -> Self {
+        let entry = match Entry::new(SECRET_KEY_SERVICE, profile) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("keychain unavailable, secrets key cannot be stored: {}", e);
+                None
+            }
+        };
+        Self { entry }
+    }
+
+    // None if no key was ever stored. An unreadable keychain is an error, not
+    // a missing key, so it is never mistaken for a first start.
+    pub fn load(&self) -> Result<Option<String>, String> {
+        let entry = self.entry.as_ref().ok_or("keychain unavailable")?;
+        match entry.get_password() {
+            Ok(key) => Ok(Some(key)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    // Fails, with the reason, if the key did not reach the keychain.
+    pub fn save(&self, key: &str) -> Result<(), String> {
+        let entry = self.entry.as_ref().ok_or("keychain unavailable")?;
+        entry.set_password(key).map_err(|e| e.to_string())
+    }
+
+    pub fn clear(&self) {
+        let Some(entry) = &self.entry else {
+            return;
+        };
+        match entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => tracing::warn!("could not remove secrets key from keychain: {}", e),
+        }
+    }
+}
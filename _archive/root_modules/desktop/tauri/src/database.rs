@@ -0,0 +1,3663 @@
+/*!
+ * Database - SQLite persistence for workflows and app metadata
+ */
+
+use parking_lot::{Mutex, RwLock};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, Connection, DatabaseName, OpenFlags, OptionalExtension, Row, Transaction};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::blob::{self, BlobError, BlobKey};
+use crate::encryption::{self, EncryptionError, KdfParams};
+use crate::deadlines::DeadlineSchedule;
+use crate::estimate::NodeTypeStats;
+use crate::events::ExecutionProgress;
+use crate::execution_log::LogEntry;
+use crate::folders::Folder;
+use crate::environments::Environment;
+use crate::limits::{LimitExceeded, WorkflowLimits};
+use crate::nodes::webhook;
+use crate::notifications::NotificationRule;
+use crate::plans::ExecutionPlan;
+use crate::recovery::InterruptedExecution;
+use crate::retention::RetentionPolicy;
+use crate::sink::OutputSink;
+use crate::templates::{TemplateSource, WorkflowTemplate};
+use crate::{
+    Execution, ExecutionStatus, FailedExecution, NodeHit, NodeQuery, PinnedWorkflow, Position,
+    KeyRotation, UndoState, Workflow, WorkflowNode, WorkflowStatus, WorkflowVersion,
+};
+
+#[derive(Debug, Error)]
+pub enum DatabaseError {
+    #[error("workflow not found: {0}")]
+    NotFound(String),
+    // Carries the stored workflow the caller's copy is behind.
+    #[error("workflow {} was modified elsewhere at {}", .0.id, .0.updated_at)]
+    Conflict(Box<Workflow>),
+    #[error("database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("encryption error: {0}")]
+    Encryption(Box<EncryptionError>),
+    #[error("cannot decode stored workflow: {0}")]
+    Blob(#[from] BlobError),
+    #[error("workflow {0} is encrypted and cannot be read until the database is unlocked")]
+    EncryptionUnavailable(String),
+    #[error(transparent)]
+    TooLarge(#[from] LimitExceeded),
+    #[error("cannot re-encrypt secret {name} of workflow {workflow_id}: {source}")]
+    Rotation {
+        workflow_id: String,
+        name: String,
+        #[source]
+        source: Box<EncryptionError>,
+    },
+    #[error("cannot vacuum the database while {0} executions are running")]
+    ExecutionsRunning(usize),
+    #[error("cannot increment state {key} of workflow {workflow_id}: {reason}")]
+    StateIncrement {
+        workflow_id: String,
+        key: String,
+        reason: &'static str,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, DatabaseError>;
+
+// Each entry is applied once, in order; PRAGMA user_version tracks progress.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS workflows (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT,
+        nodes TEXT NOT NULL,
+        edges TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS app_meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    "ALTER TABLE workflows ADD COLUMN deleted_at TEXT;
+    CREATE TABLE IF NOT EXISTS executions (
+        id TEXT PRIMARY KEY,
+        workflow_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        profile TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_executions_workflow ON executions (workflow_id, started_at);",
+    "CREATE TABLE IF NOT EXISTS workflow_versions (
+        workflow_id TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        snapshot TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, version)
+    );",
+    "ALTER TABLE executions ADD COLUMN cpu_time_ms INTEGER;",
+    "CREATE TABLE IF NOT EXISTS workflow_secrets (
+        workflow_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, name)
+    );",
+    "CREATE TABLE IF NOT EXISTS workflow_tags (
+        workflow_id TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, tag)
+    );",
+    "CREATE TABLE IF NOT EXISTS workflow_output_sinks (
+        workflow_id TEXT PRIMARY KEY,
+        config TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    "CREATE TABLE IF NOT EXISTS user_templates (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL,
+        nodes TEXT NOT NULL,
+        edges TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );",
+    "ALTER TABLE executions ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE IF NOT EXISTS execution_logs (
+        execution_id TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        level TEXT NOT NULL,
+        node_id TEXT,
+        message TEXT NOT NULL,
+        data TEXT,
+        PRIMARY KEY (execution_id, seq)
+    );",
+    "CREATE TABLE IF NOT EXISTS workflow_undo (
+        workflow_id TEXT NOT NULL,
+        stack TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        snapshot TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, stack, seq)
+    );",
+    "CREATE TABLE IF NOT EXISTS failed_executions (
+        id TEXT PRIMARY KEY,
+        execution_id TEXT NOT NULL,
+        workflow_id TEXT NOT NULL,
+        profile TEXT NOT NULL,
+        node_id TEXT,
+        input TEXT NOT NULL,
+        error TEXT NOT NULL,
+        failed_at TEXT NOT NULL,
+        replay_count INTEGER NOT NULL DEFAULT 0,
+        last_replay_id TEXT,
+        resolved_at TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_failed_executions_workflow
+        ON failed_executions (workflow_id, failed_at);",
+    "ALTER TABLE executions ADD COLUMN rate_limit_wait_ms INTEGER;",
+    "CREATE TABLE IF NOT EXISTS node_cache (
+        node_type TEXT NOT NULL,
+        input_hash TEXT NOT NULL,
+        workflow_id TEXT NOT NULL,
+        output TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        expires_at TEXT NOT NULL,
+        PRIMARY KEY (node_type, input_hash)
+    );
+    CREATE INDEX IF NOT EXISTS idx_node_cache_workflow ON node_cache (workflow_id);",
+    "ALTER TABLE workflows ADD COLUMN resume_on_startup INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE executions ADD COLUMN input TEXT;
+    ALTER TABLE executions ADD COLUMN stop_requested INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE IF NOT EXISTS folders (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        parent_id TEXT,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders (parent_id);
+    ALTER TABLE workflows ADD COLUMN folder_id TEXT;",
+    "CREATE TABLE IF NOT EXISTS audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        command TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        machine_id TEXT NOT NULL,
+        summary TEXT NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log (timestamp);",
+    "ALTER TABLE workflows ADD COLUMN blob_flags INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE IF NOT EXISTS node_type_stats (
+        node_type TEXT PRIMARY KEY,
+        runs INTEGER NOT NULL,
+        total_ms INTEGER NOT NULL,
+        max_ms INTEGER NOT NULL
+    );",
+    "CREATE TABLE IF NOT EXISTS idempotency_keys (
+        workflow_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        execution_id TEXT NOT NULL,
+        expires_at TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, key)
+    );",
+    "CREATE TABLE IF NOT EXISTS pinned_workflows (
+        workflow_id TEXT PRIMARY KEY,
+        pinned_at TEXT NOT NULL
+    );",
+    "CREATE TABLE IF NOT EXISTS workflow_notifications (
+        workflow_id TEXT PRIMARY KEY,
+        rules TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    "CREATE TABLE IF NOT EXISTS environments (
+        name TEXT PRIMARY KEY,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS environment_variables (
+        environment TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (environment, key)
+    );",
+    "ALTER TABLE workflows ADD COLUMN last_execution_id TEXT;
+    ALTER TABLE workflows ADD COLUMN last_execution_status TEXT;
+    ALTER TABLE workflows ADD COLUMN last_execution_at TEXT;
+    UPDATE workflows SET (last_execution_id, last_execution_status, last_execution_at) = (
+        SELECT id, status, finished_at FROM executions
+        WHERE executions.workflow_id = workflows.id AND finished_at IS NOT NULL
+        ORDER BY finished_at DESC LIMIT 1
+    );",
+    "CREATE TABLE IF NOT EXISTS plans (
+        id TEXT PRIMARY KEY,
+        workflow_id TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        hash TEXT NOT NULL,
+        plan TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        UNIQUE (workflow_id, version),
+        UNIQUE (workflow_id, hash)
+    );",
+    "CREATE TABLE IF NOT EXISTS workflow_state (
+        workflow_id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, key)
+    );",
+    "ALTER TABLE executions ADD COLUMN nodes_completed INTEGER;
+    ALTER TABLE executions ADD COLUMN nodes_total INTEGER;",
+    "CREATE TABLE IF NOT EXISTS deadline_schedules (
+        id TEXT PRIMARY KEY,
+        workflow_id TEXT NOT NULL,
+        finish_by TEXT NOT NULL,
+        margin_ms INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        fired_at TEXT,
+        execution_id TEXT,
+        at_risk INTEGER NOT NULL DEFAULT 0,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_deadline_schedules_workflow ON deadline_schedules (workflow_id);",
+    "CREATE TABLE IF NOT EXISTS webhook_routes (
+        path TEXT NOT NULL,
+        workflow_id TEXT NOT NULL,
+        PRIMARY KEY (path, workflow_id)
+    );
+    CREATE INDEX IF NOT EXISTS idx_webhook_routes_workflow ON webhook_routes (workflow_id);",
+    "ALTER TABLE workflow_versions ADD COLUMN blob_flags INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE workflow_undo ADD COLUMN blob_flags INTEGER NOT NULL DEFAULT 0;",
+];
+
+// First schema version with `webhook_routes`, which is filled in from the
+// stored workflows when a database is migrated past it.
+const WEBHOOK_ROUTES_VERSION: u32 = 29;
+
+// Tables whose rows belong to a workflow via a `workflow_id` column. Cascade
+// deletes walk this list, so new per-workflow tables must be added here.
+const WORKFLOW_ARTIFACT_TABLES: &[&str] = &[
+    "executions",
+    "workflow_secrets",
+    "workflow_tags",
+    "workflow_output_sinks",
+    "workflow_undo",
+    "workflow_versions",
+    "failed_executions",
+    "node_cache",
+    "idempotency_keys",
+    "pinned_workflows",
+    "workflow_notifications",
+    "plans",
+    "workflow_state",
+    "deadline_schedules",
+    "webhook_routes",
+];
+
+// Tables whose rows belong to an execution via an `execution_id` column.
+// Retention cleanup walks this list when it deletes executions.
+const EXECUTION_ARTIFACT_TABLES: &[&str] = &["execution_logs"];
+
+// Entries kept per workflow on each of the undo and redo stacks.
+const UNDO_LIMIT: i64 = 50;
+
+// Versions kept per workflow unless the `max_versions` preference says otherwise.
+pub const DEFAULT_MAX_VERSIONS: usize = 50;
+const UNDO_STACK: &str = "undo";
+const REDO_STACK: &str = "redo";
+
+const FAILED_EXECUTION_COLUMNS: &str = "id, execution_id, workflow_id, profile, node_id, input, error,
+     failed_at, replay_count, last_replay_id, resolved_at";
+
+const WORKFLOW_COLUMNS: &str = "id, name, description, nodes, edges, status, created_at, updated_at,
+     deleted_at, resume_on_startup, blob_flags, last_execution_id, last_execution_status,
+     last_execution_at";
+
+const DEADLINE_SCHEDULE_COLUMNS: &str =
+    "id, workflow_id, finish_by, margin_ms, created_at, fired_at, execution_id, at_risk, error";
+
+const EXECUTION_COLUMNS: &str =
+    "id, workflow_id, status, profile, started_at, finished_at, error, cpu_time_ms, pinned,
+     rate_limit_wait_ms, nodes_completed, nodes_total";
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// How long a connection waits for another one's lock before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+// Read connections kept open between uses; more are opened under load.
+const MAX_IDLE_READERS: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseStats {
+    pub path: String,
+    // The database file and its write-ahead log
+    pub size_bytes: u64,
+    pub page_size: u64,
+    pub page_count: u64,
+    // Pages left empty by deletes, reused by later writes but only returned
+    // to the disk by a vacuum
+    pub free_pages: u64,
+    // Share of the file that is free pages, from 0 to 1
+    pub fragmentation: f64,
+    pub schema_version: u32,
+    pub row_counts: BTreeMap<String, u64>,
+}
+
+// A workflow's metadata, which is never encrypted, so it can be listed while
+// the workflow itself is locked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowSummary {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub status: WorkflowStatus,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    // Encrypted and unreadable until the database is unlocked
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VacuumSummary {
+    pub size_before: u64,
+    pub size_after: u64,
+    pub reclaimed_bytes: u64,
+}
+
+// Every write goes through the one connection here, behind the mutex the app
+// keeps the database in, so SQLite only ever sees a single writer. The file
+// is in WAL mode, so `readers` can serve reads alongside a write.
+pub struct Database {
+    conn: Connection,
+    readers: Arc<ReadPool>,
+    path: PathBuf,
+    // Versions kept per workflow; 0 turns versioning off
+    max_versions: usize,
+    limits: WorkflowLimits,
+}
+
+impl Database {
+    pub fn new(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // Persists in the file; in-memory databases answer "memory" and stay so.
+        let _: String =
+            conn.pragma_update_and_check(None, "journal_mode", "wal", |row| row.get(0))?;
+
+        let mut db = Self {
+            conn,
+            readers: Arc::new(ReadPool::new(path)),
+            path: path.to_path_buf(),
+            max_versions: DEFAULT_MAX_VERSIONS,
+            limits: WorkflowLimits::default(),
+        };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Take this, release the database lock, then query: reads on the pool
+    // neither wait for the lock nor hold it up.
+    pub fn readers(&self) -> Arc<ReadPool> {
+        self.readers.clone()
+    }
+
+    // Takes effect on the next save; older versions beyond it are pruned then.
+    pub fn set_max_versions(&mut self, max_versions: usize) {
+        self.max_versions = max_versions;
+    }
+
+    // Applies to later creates, saves and imports; stored workflows already
+    // over the limits stay as they are until next saved.
+    pub fn set_workflow_limits(&mut self, limits: WorkflowLimits) {
+        self.limits = limits;
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        let current = self.schema_version()?;
+
+        let tx = self.conn.transaction()?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", (index + 1) as u32)?;
+        }
+        if current < WEBHOOK_ROUTES_VERSION {
+            // Workflows encrypted at rest are added once the database is unlocked.
+            rebuild_webhook_routes(&tx, None)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Workflows
+
+    pub fn create_workflow(&self, workflow: &Workflow) -> Result<()> {
+        self.limits.check(workflow)?;
+        let tx = self.conn.unchecked_transaction()?;
+        insert_workflow_row(&tx, workflow, self.key().as_ref())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Inserts a workflow together with its tags and execution history, all or nothing.
+    pub fn import_workflow(
+        &mut self,
+        workflow: &Workflow,
+        tags: &[String],
+        executions: &[Execution],
+    ) -> Result<()> {
+        self.limits.check(workflow)?;
+        let key = self.key();
+        let tx = self.conn.transaction()?;
+        insert_workflow_row(&tx, workflow, key.as_ref())?;
+        write_workflow_tags(&tx, &workflow.id, tags)?;
+        for execution in executions {
+            insert_execution_row(&tx, execution)?;
+        }
+        refresh_last_execution(&tx, &workflow.id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_workflows(&self) -> Result<Vec<Workflow>> {
+        self.query_workflows(
+            "WHERE deleted_at IS NULL ORDER BY updated_at DESC",
+            params![],
+        )
+    }
+
+    pub fn get_workflow(&self, id: &str) -> Result<Workflow> {
+        load_workflow(&self.conn, id, self.key().as_ref())
+    }
+
+    // Live, active workflows with a webhook node listening on `path`.
+    pub fn webhook_workflow_ids(&self, path: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.workflow_id FROM webhook_routes r
+             JOIN workflows w ON w.id = r.workflow_id
+             WHERE r.path = ?1 AND w.deleted_at IS NULL AND w.status = ?2
+             ORDER BY r.workflow_id",
+        )?;
+        let rows = stmt.query_map(
+            params![path, enum_to_str(&WorkflowStatus::Active)?],
+            |row| row.get(0),
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn query_workflows(&self, clause: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<Workflow>> {
+        query_workflows(&self.conn, clause, params, self.key().as_ref())
+    }
+
+    fn key(&self) -> Option<AtRestKey> {
+        self.readers.key.read().clone()
+    }
+
+    // Loads the passphrase for workflows encrypted at rest, once it opens the
+    // check value `encrypt_workflows` stored (or, without one, an encrypted
+    // workflow), so they read and save like any other. Returns how many
+    // workflows are encrypted. With encryption never enabled there is nothing
+    // to unlock and no key is loaded.
+    pub fn unlock(&self, passphrase: &str) -> Result<usize> {
+        let sample = match self.get_meta(AT_REST_CHECK_KEY)? {
+            Some(check) => Some((SqlValue::Text(check), blob::ENCRYPTED)),
+            None => self
+                .conn
+                .query_row(
+                    "SELECT nodes, blob_flags FROM workflows WHERE blob_flags & ?1 != 0 LIMIT 1",
+                    params![blob::ENCRYPTED],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?,
+        };
+        let Some((stored, flags)) = sample else {
+            return Ok(0);
+        };
+
+        let key = AtRestKey {
+            passphrase: passphrase.to_string(),
+            params: KdfParams::load(self).map_err(|e| DatabaseError::Encryption(Box::new(e)))?,
+        };
+        blob::decode(stored, flags, Some(key.blob_key()))?;
+        rebuild_webhook_routes(&self.conn, Some(&key))?;
+        *self.readers.key.write() = Some(key);
+
+        let encrypted: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM workflows WHERE blob_flags & ?1 != 0",
+            params![blob::ENCRYPTED],
+            |row| row.get(0),
+        )?;
+        Ok(encrypted as usize)
+    }
+
+    // Encrypts every workflow at rest with `passphrase`, trashed ones and
+    // their versions and undo history included, and keeps it loaded so later
+    // saves are encrypted too; large rows are compressed first. A check value
+    // encrypted with it is stored as well, so `unlock` can tell the passphrase
+    // even before any workflow exists. Rows already encrypted must be unlocked
+    // beforehand. Returns how many workflows were encrypted.
+    pub fn encrypt_workflows(&mut self, passphrase: &str) -> Result<usize> {
+        let current = self.key();
+        let key = AtRestKey {
+            passphrase: passphrase.to_string(),
+            params: KdfParams::load(self).map_err(|e| DatabaseError::Encryption(Box::new(e)))?,
+        };
+
+        let tx = self.conn.transaction()?;
+        let encrypted = reencode_at_rest(&tx, current.as_ref(), &key)?;
+        tx.commit()?;
+
+        *self.readers.key.write() = Some(key);
+        Ok(encrypted)
+    }
+
+    // Fails with `EncryptionUnavailable` while a live workflow is locked, for
+    // operations that must not leave any workflow out.
+    pub fn ensure_unlocked(&self) -> Result<()> {
+        if self.key().is_some() {
+            return Ok(());
+        }
+        let locked: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT id FROM workflows WHERE deleted_at IS NULL AND blob_flags & ?1 != 0 LIMIT 1",
+                params![blob::ENCRYPTED],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match locked {
+            Some(id) => Err(DatabaseError::EncryptionUnavailable(id)),
+            None => Ok(()),
+        }
+    }
+
+    // Saves an edit, recording the prior state as a version and on the
+    // workflow's undo stack, and dropping its redo stack. Saves that change
+    // nothing leave all of them alone.
+    pub fn update_workflow(&mut self, workflow: &Workflow) -> Result<()> {
+        self.save_workflow(workflow, None).map(|_| ())
+    }
+
+    // Saves only if the stored workflow is still at `expected_updated_at`,
+    // failing with `Conflict` otherwise. Returns the new `updated_at`.
+    pub fn update_workflow_if_unchanged(
+        &mut self,
+        workflow: &Workflow,
+        expected_updated_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<chrono::DateTime<chrono::Utc>> {
+        self.save_workflow(workflow, Some(expected_updated_at))
+    }
+
+    fn save_workflow(
+        &mut self,
+        workflow: &Workflow,
+        expected_updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<chrono::DateTime<chrono::Utc>> {
+        self.limits.check(workflow)?;
+        let key = self.key();
+        let tx = self.conn.transaction()?;
+        let prior = load_workflow(&tx, &workflow.id, key.as_ref())?;
+        if expected_updated_at.is_some_and(|expected| expected != prior.updated_at) {
+            return Err(DatabaseError::Conflict(Box::new(prior)));
+        }
+        let updated_at = write_workflow(&tx, workflow, key.as_ref())?;
+
+        if !same_content(&prior, workflow)? {
+            push_version(&tx, &prior, self.max_versions, key.as_ref())?;
+            push_history(&tx, &workflow.id, UNDO_STACK, &prior, key.as_ref())?;
+            tx.execute(
+                "DELETE FROM workflow_undo WHERE workflow_id = ?1 AND stack = ?2",
+                params![workflow.id, REDO_STACK],
+            )?;
+        }
+        tx.commit()?;
+        Ok(updated_at)
+    }
+
+    // The workflow as it was saved before version `version` was recorded.
+    pub fn get_workflow_version(&self, id: &str, version: i64) -> Result<Workflow> {
+        load_workflow_version(&self.conn, id, version, self.key().as_ref())
+    }
+
+    // Saves the version's content as an ordinary edit, so the state it
+    // replaces becomes a version itself and the restore can be undone.
+    pub fn restore_workflow_version(&mut self, id: &str, version: i64) -> Result<Workflow> {
+        let snapshot = self.get_workflow_version(id, version)?;
+        self.save_workflow(
+            &Workflow {
+                id: id.to_string(),
+                ..snapshot
+            },
+            None,
+        )?;
+        self.get_workflow(id)
+    }
+
+    // Both return the restored workflow, or None when there is nothing to
+    // undo or redo.
+    pub fn undo_workflow(&mut self, id: &str) -> Result<Option<Workflow>> {
+        self.step_history(id, UNDO_STACK, REDO_STACK)
+    }
+
+    pub fn redo_workflow(&mut self, id: &str) -> Result<Option<Workflow>> {
+        self.step_history(id, REDO_STACK, UNDO_STACK)
+    }
+
+    // Replaces the workflow with the newest snapshot on `from`, saving the
+    // current state onto `to`.
+    fn step_history(&mut self, id: &str, from: &str, to: &str) -> Result<Option<Workflow>> {
+        let key = self.key();
+        let tx = self.conn.transaction()?;
+        let current = load_workflow(&tx, id, key.as_ref())?;
+        let newest: Option<(i64, SqlValue, i64)> = tx
+            .query_row(
+                "SELECT seq, snapshot, blob_flags FROM workflow_undo
+                 WHERE workflow_id = ?1 AND stack = ?2
+                 ORDER BY seq DESC LIMIT 1",
+                params![id, from],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let Some((seq, snapshot, flags)) = newest else {
+            return Ok(None);
+        };
+
+        let snapshot = decode_snapshot(id, snapshot, flags, key.as_ref())?;
+        tx.execute(
+            "DELETE FROM workflow_undo WHERE workflow_id = ?1 AND stack = ?2 AND seq = ?3",
+            params![id, from, seq],
+        )?;
+        push_history(&tx, id, to, &current, key.as_ref())?;
+        write_workflow(&tx, &Workflow { id: id.to_string(), ..snapshot }, key.as_ref())?;
+
+        let restored = load_workflow(&tx, id, key.as_ref())?;
+        tx.commit()?;
+        Ok(Some(restored))
+    }
+
+    // Patches node positions inside the stored nodes JSON without rewriting the
+    // rest of the workflow. Unknown node ids are skipped; returns how many
+    // nodes were moved. `updated_at` is bumped once if anything changed.
+    pub fn update_node_positions(
+        &mut self,
+        workflow_id: &str,
+        positions: &[(String, Position)],
+    ) -> Result<usize> {
+        let key = self.key();
+        let tx = self.conn.transaction()?;
+        let (mut nodes, flags) = load_stored_nodes(&tx, workflow_id, key.as_ref())?;
+
+        let mut updated = 0;
+        for (node_id, position) in positions {
+            if let Some(node) = nodes.iter_mut().find(|node| node.id == *node_id) {
+                node.position = position.clone();
+                updated += 1;
+            }
+        }
+
+        if updated > 0 {
+            // Keeps the row's flags, so edges stay readable as stored.
+            let nodes = blob::encode(
+                &serde_json::to_string(&nodes)?,
+                flags,
+                key.as_ref().map(AtRestKey::blob_key),
+            )?;
+            tx.execute(
+                "UPDATE workflows SET nodes = ?2, updated_at = ?3 WHERE id = ?1",
+                params![workflow_id, nodes, chrono::Utc::now()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    // Moves the workflow to the trash; its executions are kept but hidden.
+    pub fn delete_workflow(&self, id: &str) -> Result<()> {
+        let deleted = self.conn.execute(
+            "UPDATE workflows SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, chrono::Utc::now()],
+        )?;
+
+        if deleted == 0 {
+            return Err(DatabaseError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn restore_workflow(&self, id: &str) -> Result<()> {
+        let restored = self.conn.execute(
+            "UPDATE workflows SET deleted_at = NULL, updated_at = ?2
+             WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![id, chrono::Utc::now()],
+        )?;
+
+        if restored == 0 {
+            return Err(DatabaseError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    // Live workflows modified after `since`, plus ids of workflows trashed after it.
+    pub fn workflows_changed_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(Vec<Workflow>, Vec<String>)> {
+        let changed = self.query_workflows(
+            "WHERE deleted_at IS NULL AND updated_at > ?1 ORDER BY id",
+            params![since],
+        )?;
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM workflows WHERE deleted_at > ?1 ORDER BY id")?;
+        let trashed = stmt
+            .query_map(params![since], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok((changed, trashed))
+    }
+
+    pub fn workflow_ids(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM workflows WHERE deleted_at IS NULL ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn trashed_workflow_ids(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM workflows WHERE deleted_at IS NOT NULL ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    // Makes the live workflow set match `workflows` exactly: each is inserted or
+    // overwritten with its timestamps intact, and any other live workflow is
+    // moved to the trash. Returns (written, trashed).
+    pub fn replace_workflows(&mut self, workflows: &[Workflow]) -> Result<(usize, usize)> {
+        let key = self.key();
+        let tx = self.conn.transaction()?;
+        for workflow in workflows {
+            let stored = StoredJson::new(workflow, key.as_ref())?;
+            tx.execute(
+                &format!(
+                    "INSERT INTO workflows ({})
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, NULL, ?9, ?10, NULL, NULL, NULL)
+                     ON CONFLICT(id) DO UPDATE SET
+                        name = excluded.name,
+                        description = excluded.description,
+                        nodes = excluded.nodes,
+                        edges = excluded.edges,
+                        status = excluded.status,
+                        created_at = excluded.created_at,
+                        updated_at = excluded.updated_at,
+                        deleted_at = NULL,
+                        resume_on_startup = excluded.resume_on_startup,
+                        blob_flags = excluded.blob_flags",
+                    WORKFLOW_COLUMNS
+                ),
+                params![
+                    workflow.id,
+                    workflow.name,
+                    workflow.description,
+                    stored.nodes,
+                    stored.edges,
+                    enum_to_str(&workflow.status)?,
+                    workflow.created_at,
+                    workflow.updated_at,
+                    workflow.resume_on_startup,
+                    stored.flags,
+                ],
+            )?;
+            write_webhook_routes(&tx, workflow)?;
+        }
+
+        let keep: Vec<&str> = workflows.iter().map(|workflow| workflow.id.as_str()).collect();
+        let keep = serde_json::to_string(&keep)?;
+        let trashed = tx.execute(
+            "UPDATE workflows SET deleted_at = ?2
+             WHERE deleted_at IS NULL AND id NOT IN (SELECT value FROM json_each(?1))",
+            params![keep, chrono::Utc::now()],
+        )?;
+
+        tx.commit()?;
+        Ok((workflows.len(), trashed))
+    }
+
+    pub fn purge_trash(&mut self, older_than_days: u32) -> Result<usize> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(older_than_days as i64);
+
+        let tx = self.conn.transaction()?;
+        let ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM workflows WHERE deleted_at IS NOT NULL AND deleted_at <= ?1",
+            )?;
+            let rows = stmt.query_map(params![cutoff], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for id in &ids {
+            delete_workflow_rows(&tx, id)?;
+        }
+        tx.commit()?;
+        Ok(ids.len())
+    }
+
+    // Removes the workflow and every artifact row in one transaction,
+    // returning the number of rows removed per table.
+    pub fn delete_workflow_cascade(&mut self, id: &str) -> Result<BTreeMap<String, usize>> {
+        let tx = self.conn.transaction()?;
+        let removed = delete_workflow_rows(&tx, id)?;
+
+        if removed.get("workflows").copied().unwrap_or(0) == 0 {
+            return Err(DatabaseError::NotFound(id.to_string()));
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    // Executions
+
+    pub fn insert_execution(&self, execution: &Execution) -> Result<()> {
+        insert_execution_row(&self.conn, execution)
+    }
+
+    pub fn set_execution_progress(&self, id: &str, progress: &ExecutionProgress) -> Result<()> {
+        self.conn.execute(
+            "UPDATE executions SET nodes_completed = ?2, nodes_total = ?3 WHERE id = ?1",
+            params![id, progress.completed, progress.total],
+        )?;
+        Ok(())
+    }
+
+    // Also records the run as its workflow's last one.
+    pub fn finish_execution(
+        &mut self,
+        id: &str,
+        status: ExecutionStatus,
+        error: Option<&str>,
+        cpu_time_ms: Option<u64>,
+        rate_limit_wait_ms: Option<u64>,
+    ) -> Result<()> {
+        let status = enum_to_str(&status)?;
+        let finished_at = chrono::Utc::now();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE executions SET status = ?2, finished_at = ?3, error = ?4, cpu_time_ms = ?5,
+                 rate_limit_wait_ms = ?6
+             WHERE id = ?1",
+            params![id, status, finished_at, error, cpu_time_ms, rate_limit_wait_ms],
+        )?;
+        tx.execute(
+            "UPDATE workflows
+             SET last_execution_id = ?1, last_execution_status = ?2, last_execution_at = ?3
+             WHERE id = (SELECT workflow_id FROM executions WHERE id = ?1)",
+            params![id, status, finished_at],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn schema_version(&self) -> Result<u32> {
+        Ok(self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    // Schema version of the database file at `path`, read without migrating it.
+    pub fn file_schema_version(path: &Path) -> Result<u32> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    // Copies the database to `dest` with SQLite's online backup API, which
+    // gives a consistent copy without closing the live connection.
+    pub fn backup_to(&self, dest: &Path) -> Result<()> {
+        self.conn.backup(DatabaseName::Main, dest, None)?;
+        Ok(())
+    }
+
+    // Closes the connection, moves `replacement` over the database file and
+    // reopens it, migrating it to the current schema. If the replacement
+    // cannot be opened, the previous file is put back and reopened.
+    pub fn replace_file(&mut self, replacement: &Path) -> Result<()> {
+        let mut previous = self.path.clone().into_os_string();
+        previous.push(".pre-restore");
+        let previous = PathBuf::from(previous);
+
+        // The file must have no connections left when it moves, or the WAL
+        // of the old file would be read into the new one.
+        self.readers.close();
+        let live = std::mem::replace(&mut self.conn, Connection::open_in_memory()?);
+        live.close().map_err(|(_, e)| e)?;
+
+        std::fs::rename(&self.path, &previous)?;
+        if let Err(e) = std::fs::rename(replacement, &self.path) {
+            std::fs::rename(&previous, &self.path)?;
+            *self = Database::new(&self.path)?;
+            return Err(e.into());
+        }
+        match Database::new(&self.path) {
+            Ok(db) => {
+                *self = db;
+                if let Err(e) = std::fs::remove_file(&previous) {
+                    tracing::warn!("could not remove {}: {}", previous.display(), e);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                std::fs::rename(&previous, &self.path)?;
+                *self = Database::new(&self.path)?;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn running_execution_ids(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM executions WHERE status = ?1 ORDER BY started_at")?;
+        let ids = stmt
+            .query_map(params![enum_to_str(&ExecutionStatus::Running)?], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(ids)
+    }
+
+    // Closes out executions still marked running, e.g. runs that did not stop
+    // in time during shutdown. Returns how many were updated.
+    pub fn interrupt_running_executions(&mut self, error: &str) -> Result<usize> {
+        let running = enum_to_str(&ExecutionStatus::Running)?;
+        let tx = self.conn.transaction()?;
+        let workflow_ids: Vec<String> = {
+            let mut stmt =
+                tx.prepare("SELECT DISTINCT workflow_id FROM executions WHERE status = ?1")?;
+            let rows = stmt.query_map(params![running], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        let updated = tx.execute(
+            "UPDATE executions SET status = ?1, finished_at = ?2, error = ?3 WHERE status = ?4",
+            params![
+                enum_to_str(&ExecutionStatus::Failed)?,
+                chrono::Utc::now(),
+                error,
+                running
+            ],
+        )?;
+        for workflow_id in &workflow_ids {
+            refresh_last_execution(&tx, workflow_id)?;
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    // Kept so an interrupted run can be started again with the same input.
+    pub fn set_execution_input(&self, id: &str, input: &serde_json::Value) -> Result<()> {
+        self.conn.execute(
+            "UPDATE executions SET input = ?2 WHERE id = ?1",
+            params![id, serde_json::to_string(input)?],
+        )?;
+        Ok(())
+    }
+
+    // Flags a running execution the user asked to stop, so startup recovery
+    // does not resume it if the app exits before it records its final status.
+    pub fn mark_stop_requested(&self, id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE executions SET stop_requested = 1 WHERE id = ?1 AND status = ?2",
+            params![id, enum_to_str(&ExecutionStatus::Running)?],
+        )?;
+        Ok(())
+    }
+
+    // Closes out every execution still marked running: those with a stop
+    // request become cancelled, the rest interrupted with `error`. Returns
+    // the interrupted ones, oldest first, and how many were cancelled.
+    pub fn reconcile_running_executions(
+        &mut self,
+        error: &str,
+    ) -> Result<(Vec<InterruptedExecution>, usize)> {
+        let running = enum_to_str(&ExecutionStatus::Running)?;
+        let now = chrono::Utc::now();
+
+        let tx = self.conn.transaction()?;
+        let interrupted = {
+            let mut stmt = tx.prepare(
+                "SELECT id, workflow_id, profile, input FROM executions
+                 WHERE status = ?1 AND stop_requested = 0
+                 ORDER BY started_at",
+            )?;
+            let rows = stmt.query_map(params![running], |row| {
+                let input: Option<String> = row.get(3)?;
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, input))
+            })?;
+            let mut interrupted = Vec::new();
+            for row in rows {
+                let (execution_id, workflow_id, profile, input) = row?;
+                interrupted.push(InterruptedExecution {
+                    execution_id,
+                    workflow_id,
+                    profile,
+                    input: match input {
+                        Some(input) => serde_json::from_str(&input)?,
+                        None => serde_json::Value::Null,
+                    },
+                });
+            }
+            interrupted
+        };
+        let cancelled = tx.execute(
+            "UPDATE executions SET status = ?1, finished_at = ?2
+             WHERE status = ?3 AND stop_requested = 1",
+            params![enum_to_str(&ExecutionStatus::Cancelled)?, now, running],
+        )?;
+        tx.execute(
+            "UPDATE executions SET status = ?1, finished_at = ?2, error = ?3 WHERE status = ?4",
+            params![enum_to_str(&ExecutionStatus::Interrupted)?, now, error, running],
+        )?;
+        tx.commit()?;
+        Ok((interrupted, cancelled))
+    }
+
+    pub fn get_execution(&self, id: &str) -> Result<Execution> {
+        load_execution(&self.conn, id)
+    }
+
+    pub fn set_execution_pinned(&self, id: &str, pinned: bool) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE executions SET pinned = ?2 WHERE id = ?1",
+            params![id, pinned],
+        )?;
+        if updated == 0 {
+            return Err(DatabaseError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn insert_execution_log(&mut self, execution_id: &str, entries: &[LogEntry]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO execution_logs (execution_id, seq, timestamp, level, node_id, message, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for (seq, entry) in entries.iter().enumerate() {
+                let data = entry.data.as_ref().map(serde_json::to_string).transpose()?;
+                stmt.execute(params![
+                    execution_id,
+                    seq as i64,
+                    entry.timestamp,
+                    enum_to_str(&entry.level)?,
+                    entry.node_id,
+                    entry.message,
+                    data,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_execution_log(&self, execution_id: &str) -> Result<Vec<LogEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, level, node_id, message, data FROM execution_logs
+             WHERE execution_id = ?1 ORDER BY seq",
+        )?;
+        let rows = stmt.query_map(params![execution_id], read_log_entry_row)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(row??);
+        }
+        Ok(entries)
+    }
+
+    // Unexpired output cached for a node type and input hash.
+    // Stores or refreshes an entry, dropping expired ones on the way.
+    pub fn put_cached_output(
+        &self,
+        node_type: &str,
+        input_hash: &str,
+        workflow_id: &str,
+        output: &serde_json::Value,
+        ttl: std::time::Duration,
+    ) -> Result<()> {
+        let now = chrono::Utc::now();
+        let ttl = chrono::Duration::from_std(ttl).unwrap_or_else(|_| chrono::Duration::zero());
+        let expires_at = now + ttl;
+        self.conn
+            .execute("DELETE FROM node_cache WHERE expires_at <= ?1", params![now])?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO node_cache
+                 (node_type, input_hash, workflow_id, output, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                node_type,
+                input_hash,
+                workflow_id,
+                serde_json::to_string(output)?,
+                now,
+                expires_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Drops the entries this workflow's runs stored; returns how many.
+    pub fn clear_node_cache(&self, workflow_id: &str) -> Result<usize> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM node_cache WHERE workflow_id = ?1", params![workflow_id])?)
+    }
+
+    // Execution an unexpired idempotency key already started.
+    pub fn get_idempotent_execution(&self, workflow_id: &str, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT execution_id FROM idempotency_keys
+                 WHERE workflow_id = ?1 AND key = ?2 AND expires_at > ?3",
+                params![workflow_id, key, chrono::Utc::now()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Replaces an expired entry for the same key that has not been swept yet.
+    pub fn put_idempotency_key(
+        &self,
+        workflow_id: &str,
+        key: &str,
+        execution_id: &str,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO idempotency_keys (workflow_id, key, execution_id, expires_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(workflow_id, key) DO UPDATE SET
+                 execution_id = excluded.execution_id,
+                 expires_at = excluded.expires_at",
+            params![workflow_id, key, execution_id, expires_at],
+        )?;
+        Ok(())
+    }
+
+    // Returns how many expired keys were removed.
+    pub fn sweep_idempotency_keys(&self) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM idempotency_keys WHERE expires_at <= ?1",
+            params![chrono::Utc::now()],
+        )?)
+    }
+
+    pub fn get_workflow_state(&self, workflow_id: &str, key: &str) -> Result<Option<serde_json::Value>> {
+        let value: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM workflow_state WHERE workflow_id = ?1 AND key = ?2",
+                params![workflow_id, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value.map(|value| serde_json::from_str(&value)).transpose()?)
+    }
+
+    pub fn set_workflow_state(&self, workflow_id: &str, key: &str, value: &serde_json::Value) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO workflow_state (workflow_id, key, value, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(workflow_id, key) DO UPDATE SET
+                 value = excluded.value,
+                 updated_at = excluded.updated_at",
+            params![workflow_id, key, serde_json::to_string(value)?, chrono::Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    // Adds `by` to the integer at `key`, which counts as 0 while unset, and
+    // returns the new value. The read and the write share one transaction, so
+    // concurrent increments cannot lose an update.
+    pub fn increment_workflow_state(&mut self, workflow_id: &str, key: &str, by: i64) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT value FROM workflow_state WHERE workflow_id = ?1 AND key = ?2",
+                params![workflow_id, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let refused = |reason| DatabaseError::StateIncrement {
+            workflow_id: workflow_id.to_string(),
+            key: key.to_string(),
+            reason,
+        };
+        let current = match current {
+            Some(value) => serde_json::from_str::<serde_json::Value>(&value)?
+                .as_i64()
+                .ok_or_else(|| refused("it does not hold an integer"))?,
+            None => 0,
+        };
+        let value = current
+            .checked_add(by)
+            .ok_or_else(|| refused("the result would overflow"))?;
+        tx.execute(
+            "INSERT INTO workflow_state (workflow_id, key, value, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(workflow_id, key) DO UPDATE SET
+                 value = excluded.value,
+                 updated_at = excluded.updated_at",
+            params![workflow_id, key, value.to_string(), chrono::Utc::now()],
+        )?;
+        tx.commit()?;
+        Ok(value)
+    }
+
+    // Dead letters are not subject to execution retention; they stay until
+    // their workflow is deleted.
+    pub fn insert_failed_execution(&self, failed: &FailedExecution) -> Result<()> {
+        self.conn.execute(
+            &format!(
+                "INSERT INTO failed_executions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                FAILED_EXECUTION_COLUMNS
+            ),
+            params![
+                failed.id,
+                failed.execution_id,
+                failed.workflow_id,
+                failed.profile,
+                failed.node_id,
+                serde_json::to_string(&failed.input)?,
+                failed.error,
+                failed.failed_at,
+                failed.replay_count,
+                failed.last_replay_id,
+                failed.resolved_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_failed_execution(&self, id: &str) -> Result<FailedExecution> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM failed_executions WHERE id = ?1",
+                    FAILED_EXECUTION_COLUMNS
+                ),
+                params![id],
+                read_failed_execution_row,
+            )
+            .optional()?
+            .ok_or_else(|| DatabaseError::NotFound(id.to_string()))?
+    }
+
+    pub fn record_replay_failure(
+        &self,
+        id: &str,
+        replay_id: &str,
+        node_id: Option<&str>,
+        error: &str,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE failed_executions
+             SET node_id = ?3, error = ?4, last_replay_id = ?2, replay_count = replay_count + 1
+             WHERE id = ?1",
+            params![id, replay_id, node_id, error],
+        )?;
+        Ok(())
+    }
+
+    pub fn resolve_failed_execution(&self, id: &str, replay_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE failed_executions
+             SET resolved_at = ?3, last_replay_id = ?2, replay_count = replay_count + 1
+             WHERE id = ?1",
+            params![id, replay_id, chrono::Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    // Deletes finished, unpinned executions that are older than the policy's
+    // age limit or beyond its per-workflow count, newest kept first. Returns
+    // rows removed per table.
+    pub fn cleanup_executions(&mut self, policy: &RetentionPolicy) -> Result<BTreeMap<String, usize>> {
+        let cutoff = policy
+            .max_age_days
+            .map(|days| chrono::Utc::now() - chrono::Duration::days(days as i64));
+
+        let tx = self.conn.transaction()?;
+        let ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM (
+                     SELECT id, started_at, ROW_NUMBER() OVER (
+                         PARTITION BY workflow_id ORDER BY started_at DESC
+                     ) AS position
+                     FROM executions
+                     WHERE pinned = 0 AND status != ?3
+                 )
+                 WHERE (?1 IS NOT NULL AND started_at < ?1)
+                    OR (?2 IS NOT NULL AND position > ?2)",
+            )?;
+            let rows = stmt.query_map(
+                params![
+                    cutoff,
+                    policy.max_per_workflow,
+                    enum_to_str(&ExecutionStatus::Running)?
+                ],
+                |row| row.get(0),
+            )?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut removed: BTreeMap<String, usize> = EXECUTION_ARTIFACT_TABLES
+            .iter()
+            .chain(&["executions"])
+            .map(|table| (table.to_string(), 0))
+            .collect();
+        for id in &ids {
+            for table in EXECUTION_ARTIFACT_TABLES {
+                let count = tx.execute(
+                    &format!("DELETE FROM {} WHERE execution_id = ?1", table),
+                    params![id],
+                )?;
+                *removed.entry(table.to_string()).or_default() += count;
+            }
+            let count = tx.execute("DELETE FROM executions WHERE id = ?1", params![id])?;
+            *removed.entry("executions".to_string()).or_default() += count;
+        }
+        // Workflows whose last run was removed fall back to the latest one left.
+        let stale: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM workflows
+                 WHERE last_execution_id IS NOT NULL
+                   AND last_execution_id NOT IN (SELECT id FROM executions)",
+            )?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for workflow_id in &stale {
+            refresh_last_execution(&tx, workflow_id)?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    // Executions of trashed workflows are hidden, not deleted.
+    pub fn get_executions(&self, workflow_id: Option<&str>) -> Result<Vec<Execution>> {
+        query_executions(&self.conn, workflow_id)
+    }
+
+    // Tags
+
+    pub fn get_workflow_tags(&self, workflow_id: &str) -> Result<Vec<String>> {
+        query_workflow_tags(&self.conn, workflow_id)
+    }
+
+    pub fn set_workflow_tags(&mut self, workflow_id: &str, tags: &[String]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        write_workflow_tags(&tx, workflow_id, tags)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Pins
+
+    // Pinning twice keeps the original pin time.
+    pub fn pin_workflow(&self, workflow_id: &str) -> Result<()> {
+        let exists: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM workflows WHERE id = ?1 AND deleted_at IS NULL)",
+            params![workflow_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(DatabaseError::NotFound(workflow_id.to_string()));
+        }
+        self.conn.execute(
+            "INSERT INTO pinned_workflows (workflow_id, pinned_at) VALUES (?1, ?2)
+             ON CONFLICT(workflow_id) DO NOTHING",
+            params![workflow_id, chrono::Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    // Returns whether the workflow was pinned.
+    pub fn unpin_workflow(&self, workflow_id: &str) -> Result<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM pinned_workflows WHERE workflow_id = ?1",
+            params![workflow_id],
+        )?;
+        Ok(removed > 0)
+    }
+
+    // Folders
+
+    pub fn insert_folder(&self, folder: &Folder) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO folders (id, name, parent_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![folder.id, folder.name, folder.parent_id, folder.created_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_folder(&self, id: &str) -> Result<Option<Folder>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT id, name, parent_id, created_at FROM folders WHERE id = ?1",
+                params![id],
+                read_folder_row,
+            )
+            .optional()?)
+    }
+
+    // Direct children of `parent`, or the top-level folders for `None`.
+    pub fn child_folders(&self, parent: Option<&str>) -> Result<Vec<Folder>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, parent_id, created_at FROM folders
+             WHERE parent_id IS ?1 ORDER BY name COLLATE NOCASE, id",
+        )?;
+        let folders = stmt
+            .query_map(params![parent], read_folder_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(folders)
+    }
+
+    pub fn set_folder_parent(&self, id: &str, parent: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE folders SET parent_id = ?2 WHERE id = ?1",
+            params![id, parent],
+        )?;
+        Ok(())
+    }
+
+    // Live workflows directly in `folder`, or at the root for `None`.
+    pub fn workflows_in_folder(&self, folder: Option<&str>) -> Result<Vec<Workflow>> {
+        self.query_workflows(
+            "WHERE deleted_at IS NULL AND folder_id IS ?1 ORDER BY name COLLATE NOCASE, id",
+            params![folder],
+        )
+    }
+
+    // `None` moves the workflow to the root.
+    pub fn set_workflow_folder(&self, workflow_id: &str, folder: Option<&str>) -> Result<()> {
+        let moved = self.conn.execute(
+            "UPDATE workflows SET folder_id = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![workflow_id, folder],
+        )?;
+
+        if moved == 0 {
+            return Err(DatabaseError::NotFound(workflow_id.to_string()));
+        }
+        Ok(())
+    }
+
+    // Deletes the folders in `ids`. Their workflows, trashed ones included,
+    // and any subfolders not in `ids` move to the root; with
+    // `trash_workflows`, live workflows are also trashed.
+    pub fn delete_folders(&mut self, ids: &[String], trash_workflows: bool) -> Result<()> {
+        let now = chrono::Utc::now();
+        let tx = self.conn.transaction()?;
+        for id in ids {
+            if trash_workflows {
+                tx.execute(
+                    "UPDATE workflows SET deleted_at = ?2 WHERE folder_id = ?1 AND deleted_at IS NULL",
+                    params![id, now],
+                )?;
+            }
+            tx.execute(
+                "UPDATE workflows SET folder_id = NULL WHERE folder_id = ?1",
+                params![id],
+            )?;
+            tx.execute(
+                "UPDATE folders SET parent_id = NULL WHERE parent_id = ?1",
+                params![id],
+            )?;
+            tx.execute("DELETE FROM folders WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Audit log
+
+    pub fn insert_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO audit_log (command, timestamp, machine_id, summary, error)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![entry.command, entry.timestamp, entry.machine_id, entry.summary, entry.error],
+        )?;
+        Ok(())
+    }
+
+    // Output sinks
+
+    // `None` removes the sink.
+    pub fn set_output_sink(&self, workflow_id: &str, sink: Option<&OutputSink>) -> Result<()> {
+        match sink {
+            Some(sink) => self.conn.execute(
+                "INSERT INTO workflow_output_sinks (workflow_id, config, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(workflow_id) DO UPDATE
+                 SET config = excluded.config, updated_at = excluded.updated_at",
+                params![workflow_id, serde_json::to_string(sink)?, chrono::Utc::now()],
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM workflow_output_sinks WHERE workflow_id = ?1",
+                params![workflow_id],
+            )?,
+        };
+        Ok(())
+    }
+
+    // Notification rules
+
+    // An empty list removes the workflow's rules.
+    pub fn set_notification_rules(&self, workflow_id: &str, rules: &[NotificationRule]) -> Result<()> {
+        if rules.is_empty() {
+            self.conn.execute(
+                "DELETE FROM workflow_notifications WHERE workflow_id = ?1",
+                params![workflow_id],
+            )?;
+        } else {
+            self.conn.execute(
+                "INSERT INTO workflow_notifications (workflow_id, rules, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(workflow_id) DO UPDATE
+                 SET rules = excluded.rules, updated_at = excluded.updated_at",
+                params![workflow_id, serde_json::to_string(rules)?, chrono::Utc::now()],
+            )?;
+        }
+        Ok(())
+    }
+
+    // User templates
+
+    pub fn insert_user_template(&self, template: &WorkflowTemplate) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO user_templates (id, name, description, nodes, edges, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                template.id,
+                template.name,
+                template.description,
+                serde_json::to_string(&template.nodes)?,
+                serde_json::to_string(&template.edges)?,
+                chrono::Utc::now(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_user_templates(&self) -> Result<Vec<WorkflowTemplate>> {
+        self.query_user_templates("ORDER BY created_at", params![])
+    }
+
+    pub fn get_user_template(&self, id: &str) -> Result<Option<WorkflowTemplate>> {
+        Ok(self.query_user_templates("WHERE id = ?1", params![id])?.pop())
+    }
+
+    fn query_user_templates(
+        &self,
+        clause: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<WorkflowTemplate>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, name, description, nodes, edges FROM user_templates {}",
+            clause
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+
+        let mut templates = Vec::new();
+        for row in rows {
+            let (id, name, description, nodes, edges) = row?;
+            templates.push(WorkflowTemplate {
+                id,
+                name,
+                description,
+                source: TemplateSource::User,
+                nodes: serde_json::from_str(&nodes)?,
+                edges: serde_json::from_str(&edges)?,
+            });
+        }
+        Ok(templates)
+    }
+
+    // Environments
+
+    // Returns false if an environment of that name already exists.
+    pub fn create_environment(&self, name: &str) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT INTO environments (name, created_at) VALUES (?1, ?2)
+             ON CONFLICT(name) DO NOTHING",
+            params![name, chrono::Utc::now()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    pub fn environment_exists(&self, name: &str) -> Result<bool> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT 1 FROM environments WHERE name = ?1",
+                params![name],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    pub fn get_environments(&self) -> Result<Vec<Environment>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, created_at FROM environments ORDER BY name COLLATE NOCASE")?;
+        let environments = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        environments
+            .into_iter()
+            .map(|(name, created_at)| {
+                Ok(Environment {
+                    variables: self.get_environment_variables(&name)?,
+                    name,
+                    active: false,
+                    created_at,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_environment_variables(&self, environment: &str) -> Result<BTreeMap<String, String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT key, value FROM environment_variables WHERE environment = ?1",
+        )?;
+        let variables = stmt
+            .query_map(params![environment], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(variables)
+    }
+
+    pub fn set_environment_var(&self, environment: &str, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO environment_variables (environment, key, value, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(environment, key) DO UPDATE
+             SET value = excluded.value, updated_at = excluded.updated_at",
+            params![environment, key, value, chrono::Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    // Returns false if no such variable existed.
+    pub fn delete_environment_var(&self, environment: &str, key: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM environment_variables WHERE environment = ?1 AND key = ?2",
+            params![environment, key],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    // Workflow secrets (values are stored encrypted)
+
+    pub fn set_workflow_secret(&self, workflow_id: &str, name: &str, ciphertext: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO workflow_secrets (workflow_id, name, value, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(workflow_id, name) DO UPDATE
+             SET value = excluded.value, updated_at = excluded.updated_at",
+            params![workflow_id, name, ciphertext, chrono::Utc::now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_workflow_secret(&self, workflow_id: &str, name: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT value FROM workflow_secrets WHERE workflow_id = ?1 AND name = ?2",
+                params![workflow_id, name],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Returns false if no such secret existed.
+    pub fn delete_workflow_secret(&self, workflow_id: &str, name: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM workflow_secrets WHERE workflow_id = ?1 AND name = ?2",
+            params![workflow_id, name],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_workflow_secret_names(&self, workflow_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM workflow_secrets WHERE workflow_id = ?1 ORDER BY name")?;
+        let names = stmt
+            .query_map(params![workflow_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(names)
+    }
+
+    // Re-encrypts every stored secret from `old_key` to `new_key`. Given
+    // `passphrase`, every workflow encrypted at rest, its snapshots and the
+    // passphrase check are re-encrypted from the loaded passphrase to it as
+    // well, which is kept loaded afterwards; a locked database cannot be
+    // rotated that way. It all happens in one transaction, so a single row
+    // that does not decrypt aborts the rotation with the database untouched.
+    pub fn rotate_encryption_key(
+        &mut self,
+        old_key: &str,
+        new_key: &str,
+        passphrase: Option<&str>,
+    ) -> Result<KeyRotation> {
+        let params = KdfParams::load(self).map_err(|e| DatabaseError::Encryption(Box::new(e)))?;
+        let current = self.key();
+        let at_rest = passphrase.map(|passphrase| AtRestKey {
+            passphrase: passphrase.to_string(),
+            params,
+        });
+
+        let tx = self.conn.transaction()?;
+        let rows = {
+            let mut stmt = tx.prepare("SELECT workflow_id, name, value FROM workflow_secrets")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut rotated = Vec::with_capacity(rows.len());
+        for (workflow_id, name, value) in rows {
+            match encryption::reencrypt_with(&value, old_key, new_key, &params) {
+                Ok(value) => rotated.push((workflow_id, name, value)),
+                Err(e) => {
+                    return Err(DatabaseError::Rotation {
+                        workflow_id,
+                        name,
+                        source: Box::new(e),
+                    })
+                }
+            }
+        }
+
+        for (workflow_id, name, value) in &rotated {
+            tx.execute(
+                "UPDATE workflow_secrets SET value = ?3, updated_at = ?4
+                 WHERE workflow_id = ?1 AND name = ?2",
+                params![workflow_id, name, value, chrono::Utc::now()],
+            )?;
+        }
+        let workflows = match &at_rest {
+            Some(key) => reencode_at_rest(&tx, current.as_ref(), key)?,
+            None => 0,
+        };
+        tx.commit()?;
+
+        if let Some(key) = at_rest {
+            *self.readers.key.write() = Some(key);
+        }
+        Ok(KeyRotation {
+            secrets: rotated.len(),
+            workflows,
+        })
+    }
+
+    // Execution plans
+
+    // Stores the plan as its workflow's next version. If the workflow already
+    // has a plan with the same hash, that plan is returned instead.
+    pub fn insert_execution_plan(&mut self, plan: &ExecutionPlan) -> Result<ExecutionPlan> {
+        let tx = self.conn.transaction()?;
+        let existing: Option<String> = tx
+            .query_row(
+                "SELECT plan FROM plans WHERE workflow_id = ?1 AND hash = ?2",
+                params![plan.workflow_id, plan.hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(existing) = existing {
+            return Ok(serde_json::from_str(&existing)?);
+        }
+
+        let version: i64 = tx.query_row(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM plans WHERE workflow_id = ?1",
+            params![plan.workflow_id],
+            |row| row.get(0),
+        )?;
+        let plan = ExecutionPlan {
+            version,
+            ..plan.clone()
+        };
+        tx.execute(
+            "INSERT INTO plans (id, workflow_id, version, hash, plan, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                plan.id,
+                plan.workflow_id,
+                plan.version,
+                plan.hash,
+                serde_json::to_string(&plan)?,
+                plan.created_at,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(plan)
+    }
+
+    // Deadline schedules
+
+    pub fn insert_deadline_schedule(&self, schedule: &DeadlineSchedule) -> Result<()> {
+        self.conn.execute(
+            &format!(
+                "INSERT INTO deadline_schedules ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                DEADLINE_SCHEDULE_COLUMNS
+            ),
+            params![
+                schedule.id,
+                schedule.workflow_id,
+                schedule.finish_by,
+                schedule.margin_ms,
+                schedule.created_at,
+                schedule.fired_at,
+                schedule.execution_id,
+                schedule.at_risk,
+                schedule.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Schedules that have not fired, soonest deadline first. Those of trashed
+    // workflows wait until the workflow is restored.
+    pub fn pending_deadline_schedules(&self) -> Result<Vec<DeadlineSchedule>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM deadline_schedules
+             WHERE fired_at IS NULL
+               AND workflow_id IN (SELECT id FROM workflows WHERE deleted_at IS NULL)
+             ORDER BY finish_by",
+            DEADLINE_SCHEDULE_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], read_deadline_schedule_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    // `execution_id` is None when the run could not be started; `error` says why.
+    pub fn mark_deadline_fired(
+        &self,
+        id: &str,
+        execution_id: Option<&str>,
+        at_risk: bool,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE deadline_schedules SET fired_at = ?2, execution_id = ?3, at_risk = ?4, error = ?5
+             WHERE id = ?1",
+            params![id, chrono::Utc::now(), execution_id, at_risk, error],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_deadline_schedule(&self, id: &str) -> Result<()> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM deadline_schedules WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(DatabaseError::NotFound(format!("deadline schedule {}", id)));
+        }
+        Ok(())
+    }
+
+    // Maintenance
+
+    pub fn stats(&self) -> Result<DatabaseStats> {
+        let pragma = |name: &str| -> Result<u64> {
+            Ok(self.conn.pragma_query_value(None, name, |row| row.get(0))?)
+        };
+        let page_count = pragma("page_count")?;
+        let free_pages = pragma("freelist_count")?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+             ORDER BY name",
+        )?;
+        let tables = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut row_counts = BTreeMap::new();
+        for table in tables {
+            let count = self.conn.query_row(
+                &format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\"")),
+                [],
+                |row| row.get(0),
+            )?;
+            row_counts.insert(table, count);
+        }
+
+        Ok(DatabaseStats {
+            path: self.path.to_string_lossy().to_string(),
+            size_bytes: self.size_on_disk()?,
+            page_size: pragma("page_size")?,
+            page_count,
+            free_pages,
+            fragmentation: if page_count == 0 {
+                0.0
+            } else {
+                free_pages as f64 / page_count as f64
+            },
+            schema_version: self.schema_version()?,
+            row_counts,
+        })
+    }
+
+    // Rewrites the file without its free pages, then checkpoints so the log
+    // the rewrite went through is emptied too. Callers make sure no run is
+    // writing meanwhile; reads on the pool carry on.
+    pub fn vacuum(&self) -> Result<VacuumSummary> {
+        let size_before = self.size_on_disk()?;
+        self.conn.execute_batch("VACUUM")?;
+        // Reports busy rather than failing if a reader holds the log; what
+        // was checkpointed stays so, and the log is truncated next time.
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        let size_after = self.size_on_disk()?;
+        Ok(VacuumSummary {
+            size_before,
+            size_after,
+            reclaimed_bytes: size_before.saturating_sub(size_after),
+        })
+    }
+
+    fn size_on_disk(&self) -> Result<u64> {
+        let mut wal = self.path.clone().into_os_string();
+        wal.push("-wal");
+        let wal = match std::fs::metadata(PathBuf::from(wal)) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(std::fs::metadata(&self.path)?.len() + wal)
+    }
+
+    // Node timings
+
+    // Adds a completed node's duration to its type's running totals.
+    pub fn record_node_duration(&self, node_type: &str, duration_ms: u64) -> Result<()> {
+        let duration_ms = i64::try_from(duration_ms).unwrap_or(i64::MAX);
+        self.conn.execute(
+            "INSERT INTO node_type_stats (node_type, runs, total_ms, max_ms)
+             VALUES (?1, 1, ?2, ?2)
+             ON CONFLICT(node_type) DO UPDATE SET
+                 runs = runs + 1,
+                 total_ms = total_ms + excluded.total_ms,
+                 max_ms = MAX(max_ms, excluded.max_ms)",
+            params![node_type, duration_ms],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_node_type_stats(&self) -> Result<HashMap<String, NodeTypeStats>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT node_type, runs, total_ms, max_ms FROM node_type_stats")?;
+        let rows = stmt.query_map([], |row| {
+            let runs: i64 = row.get(1)?;
+            let total_ms: i64 = row.get(2)?;
+            let max_ms: i64 = row.get(3)?;
+            Ok((
+                row.get::<_, String>(0)?,
+                NodeTypeStats {
+                    runs: runs as u64,
+                    average_ms: (total_ms / runs.max(1)) as u64,
+                    max_ms: max_ms as u64,
+                },
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    // App metadata
+
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT value FROM app_meta WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO app_meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_meta(&self, key: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM app_meta WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+}
+
+// Read-only connections to the database file, opened as needed and reused.
+// Each read sees the last committed state, never a write in progress.
+pub struct ReadPool {
+    path: PathBuf,
+    // None once closed; connections handed back then are dropped
+    idle: Mutex<Option<Vec<Connection>>>,
+    // Shared with the database, which sets it when unlocked
+    key: RwLock<Option<AtRestKey>>,
+}
+
+impl ReadPool {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            idle: Mutex::new(Some(Vec::new())),
+            key: RwLock::new(None),
+        }
+    }
+
+    fn acquire(&self) -> Result<PooledConnection<'_>> {
+        let idle = self.idle.lock().as_mut().and_then(Vec::pop);
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = Connection::open_with_flags(
+                    &self.path,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )?;
+                conn.busy_timeout(BUSY_TIMEOUT)?;
+                conn
+            }
+        };
+        Ok(PooledConnection {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+
+    // Closes the idle connections; ones in use close when handed back.
+    fn close(&self) {
+        self.idle.lock().take();
+    }
+
+    pub fn get_workflows(&self) -> Result<Vec<Workflow>> {
+        let conn = self.acquire()?;
+        query_workflows(
+            &conn,
+            "WHERE deleted_at IS NULL ORDER BY updated_at DESC",
+            params![],
+            self.key.read().as_ref(),
+        )
+    }
+
+    // Every live workflow, including the ones `get_workflows` leaves out
+    // while they cannot be decrypted.
+    pub fn get_workflow_summaries(&self) -> Result<Vec<WorkflowSummary>> {
+        let unlocked = self.key.read().is_some();
+        let conn = self.acquire()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, description, status, updated_at, blob_flags FROM workflows
+             WHERE deleted_at IS NULL ORDER BY updated_at DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let status: String = row.get(3)?;
+            let flags: i64 = row.get(5)?;
+            let build = || -> Result<WorkflowSummary> {
+                Ok(WorkflowSummary {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    description: row.get(2)?,
+                    status: enum_from_str(status)?,
+                    updated_at: row.get(4)?,
+                    locked: flags & blob::ENCRYPTED != 0 && !unlocked,
+                })
+            };
+            Ok(build())
+        })?;
+
+        let mut summaries = Vec::new();
+        for row in rows {
+            summaries.push(row??);
+        }
+        Ok(summaries)
+    }
+
+    pub fn get_workflow(&self, id: &str) -> Result<Workflow> {
+        let conn = self.acquire()?;
+        load_workflow(&conn, id, self.key.read().as_ref())
+    }
+
+    pub fn get_execution(&self, id: &str) -> Result<Execution> {
+        let conn = self.acquire()?;
+        load_execution(&conn, id)
+    }
+
+    pub fn get_executions(&self, workflow_id: Option<&str>) -> Result<Vec<Execution>> {
+        let conn = self.acquire()?;
+        query_executions(&conn, workflow_id)
+    }
+
+    // Newest deadline first.
+    pub fn get_deadline_schedules(&self, workflow_id: Option<&str>) -> Result<Vec<DeadlineSchedule>> {
+        let conn = self.acquire()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM deadline_schedules
+             WHERE ?1 IS NULL OR workflow_id = ?1
+             ORDER BY finish_by DESC",
+            DEADLINE_SCHEDULE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![workflow_id], read_deadline_schedule_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn get_execution_plan(&self, id: &str) -> Result<ExecutionPlan> {
+        let plan: String = self
+            .acquire()?
+            .query_row("SELECT plan FROM plans WHERE id = ?1", params![id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| DatabaseError::NotFound(format!("plan {}", id)))?;
+        Ok(serde_json::from_str(&plan)?)
+    }
+
+    pub fn get_cached_output(&self, node_type: &str, input_hash: &str) -> Result<Option<serde_json::Value>> {
+        let output: Option<String> = self
+            .acquire()?
+            .query_row(
+                "SELECT output FROM node_cache
+                 WHERE node_type = ?1 AND input_hash = ?2 AND expires_at > ?3",
+                params![node_type, input_hash, chrono::Utc::now()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(output.map(|output| serde_json::from_str(&output)).transpose()?)
+    }
+
+    pub fn get_workflow_versions(&self, id: &str) -> Result<Vec<WorkflowVersion>> {
+        let conn = self.acquire()?;
+        query_workflow_versions(&conn, id, self.key.read().as_ref())
+    }
+
+    pub fn get_workflow_version(&self, id: &str, version: i64) -> Result<Workflow> {
+        let conn = self.acquire()?;
+        load_workflow_version(&conn, id, version, self.key.read().as_ref())
+    }
+
+    pub fn get_undo_state(&self, id: &str) -> Result<UndoState> {
+        let conn = self.acquire()?;
+        load_undo_state(&conn, id)
+    }
+
+    pub fn find_nodes(&self, query: &NodeQuery) -> Result<Vec<NodeHit>> {
+        let conn = self.acquire()?;
+        query_nodes(&conn, query, self.key.read().as_ref())
+    }
+
+    pub fn list_failed_executions(
+        &self,
+        workflow_id: Option<&str>,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Vec<FailedExecution>> {
+        let conn = self.acquire()?;
+        query_failed_executions(&conn, workflow_id, since, until)
+    }
+
+    pub fn get_workflow_tags(&self, workflow_id: &str) -> Result<Vec<String>> {
+        let conn = self.acquire()?;
+        query_workflow_tags(&conn, workflow_id)
+    }
+
+    pub fn get_pinned_workflows(&self) -> Result<Vec<PinnedWorkflow>> {
+        let conn = self.acquire()?;
+        query_pinned_workflows(&conn)
+    }
+
+    pub fn get_audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let conn = self.acquire()?;
+        query_audit_log(&conn, filter)
+    }
+
+    pub fn get_output_sink(&self, workflow_id: &str) -> Result<Option<OutputSink>> {
+        let conn = self.acquire()?;
+        load_output_sink(&conn, workflow_id)
+    }
+
+    pub fn get_notification_rules(&self, workflow_id: &str) -> Result<Vec<NotificationRule>> {
+        let conn = self.acquire()?;
+        load_notification_rules(&conn, workflow_id)
+    }
+
+    pub fn list_trash(&self) -> Result<Vec<Workflow>> {
+        let conn = self.acquire()?;
+        query_workflows(
+            &conn,
+            "WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            params![],
+            self.key.read().as_ref(),
+        )
+    }
+}
+
+struct PooledConnection<'a> {
+    pool: &'a ReadPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        if let Some(idle) = self.pool.idle.lock().as_mut() {
+            if idle.len() < MAX_IDLE_READERS {
+                idle.push(conn);
+            }
+        }
+    }
+}
+
+// Meta key of a known value encrypted with the at-rest passphrase.
+const AT_REST_CHECK_KEY: &str = "encryption.at_rest_check";
+const AT_REST_CHECK: &str = "workflows at rest";
+
+// Passphrase for workflows encrypted at rest, with the KDF cost to derive
+// their key with. Loaded by `Database::unlock`.
+#[derive(Clone)]
+struct AtRestKey {
+    passphrase: String,
+    params: KdfParams,
+}
+
+impl AtRestKey {
+    fn blob_key(&self) -> BlobKey<'_> {
+        BlobKey {
+            passphrase: &self.passphrase,
+            params: &self.params,
+        }
+    }
+}
+
+// A workflow's nodes and edges as stored, with the row's `blob_flags`. Rows
+// are encrypted once the database is unlocked, otherwise at most compressed.
+struct StoredJson {
+    nodes: SqlValue,
+    edges: SqlValue,
+    flags: i64,
+}
+
+impl StoredJson {
+    fn new(workflow: &Workflow, key: Option<&AtRestKey>) -> Result<Self> {
+        let nodes = serde_json::to_string(&workflow.nodes)?;
+        let edges = serde_json::to_string(&workflow.edges)?;
+        Self::encode(&nodes, &edges, key)
+    }
+
+    fn encode(nodes: &str, edges: &str, key: Option<&AtRestKey>) -> Result<Self> {
+        let key = key.map(AtRestKey::blob_key);
+        let flags = blob::row_flags(nodes, edges, key);
+        Ok(Self {
+            nodes: blob::encode(nodes, flags, key)?,
+            edges: blob::encode(edges, flags, key)?,
+            flags,
+        })
+    }
+}
+
+// Decodes a workflow's stored JSON, reporting a row that is encrypted with no
+// key loaded, or with another key, as `EncryptionUnavailable`.
+fn decode_stored(
+    workflow_id: &str,
+    stored: SqlValue,
+    flags: i64,
+    key: Option<&AtRestKey>,
+) -> Result<String> {
+    match blob::decode(stored, flags, key.map(AtRestKey::blob_key)) {
+        Ok(json) => Ok(json),
+        Err(BlobError::Locked) => Err(DatabaseError::EncryptionUnavailable(workflow_id.to_string())),
+        Err(BlobError::Encryption(e)) if matches!(*e, EncryptionError::WrongKey) => {
+            Err(DatabaseError::EncryptionUnavailable(workflow_id.to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Whole-workflow snapshots in `workflow_versions` and `workflow_undo`, with
+// their `blob_flags`, are stored the way a workflow row's JSON is.
+fn encode_snapshot(workflow: &Workflow, key: Option<&AtRestKey>) -> Result<(SqlValue, i64)> {
+    let json = serde_json::to_string(workflow)?;
+    let key = key.map(AtRestKey::blob_key);
+    let flags = blob::row_flags(&json, "", key);
+    Ok((blob::encode(&json, flags, key)?, flags))
+}
+
+fn decode_snapshot(
+    workflow_id: &str,
+    stored: SqlValue,
+    flags: i64,
+    key: Option<&AtRestKey>,
+) -> Result<Workflow> {
+    Ok(serde_json::from_str(&decode_stored(workflow_id, stored, flags, key)?)?)
+}
+
+// Tables of workflow snapshots, re-encoded along with the workflows.
+const SNAPSHOT_TABLES: &[&str] = &["workflow_versions", "workflow_undo"];
+
+// Re-encodes everything kept encrypted at rest from `from` (None for rows
+// never encrypted) to `to`: every workflow row, every snapshot and the check
+// value `unlock` tests passphrases against. A row that does not decode
+// fails the whole call, leaving the transaction to be dropped. Returns how
+// many workflows were re-encoded.
+fn reencode_at_rest(
+    tx: &Transaction<'_>,
+    from: Option<&AtRestKey>,
+    to: &AtRestKey,
+) -> Result<usize> {
+    let rows = {
+        let mut stmt = tx.prepare("SELECT id, nodes, edges, blob_flags FROM workflows")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, SqlValue>(1)?,
+                row.get::<_, SqlValue>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (id, nodes, edges, flags) in &rows {
+        let nodes = decode_stored(id, nodes.clone(), *flags, from)?;
+        let edges = decode_stored(id, edges.clone(), *flags, from)?;
+        let stored = StoredJson::encode(&nodes, &edges, Some(to))?;
+        tx.execute(
+            "UPDATE workflows SET nodes = ?2, edges = ?3, blob_flags = ?4 WHERE id = ?1",
+            params![id, stored.nodes, stored.edges, stored.flags],
+        )?;
+    }
+
+    for table in SNAPSHOT_TABLES {
+        let snapshots = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT rowid, workflow_id, snapshot, blob_flags FROM {}",
+                table
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, SqlValue>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        for (rowid, workflow_id, snapshot, flags) in snapshots {
+            let snapshot = decode_snapshot(&workflow_id, snapshot, flags, from)?;
+            let (snapshot, flags) = encode_snapshot(&snapshot, Some(to))?;
+            tx.execute(
+                &format!("UPDATE {} SET snapshot = ?2, blob_flags = ?3 WHERE rowid = ?1", table),
+                params![rowid, snapshot, flags],
+            )?;
+        }
+    }
+
+    tx.execute(
+        "INSERT INTO app_meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![
+            AT_REST_CHECK_KEY,
+            blob::encode(AT_REST_CHECK, blob::ENCRYPTED, Some(to.blob_key()))?
+        ],
+    )?;
+    Ok(rows.len())
+}
+
+// The live workflow's nodes, decoded from however the row stores them.
+fn load_stored_nodes(
+    conn: &Connection,
+    workflow_id: &str,
+    key: Option<&AtRestKey>,
+) -> Result<(Vec<WorkflowNode>, i64)> {
+    let (nodes, flags): (SqlValue, i64) = conn
+        .query_row(
+            "SELECT nodes, blob_flags FROM workflows WHERE id = ?1 AND deleted_at IS NULL",
+            params![workflow_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(workflow_id.to_string()))?;
+    Ok((serde_json::from_str(&decode_stored(workflow_id, nodes, flags, key)?)?, flags))
+}
+
+fn insert_workflow_row(conn: &Connection, workflow: &Workflow, key: Option<&AtRestKey>) -> Result<()> {
+    let stored = StoredJson::new(workflow, key)?;
+    conn.execute(
+        &format!(
+            "INSERT INTO workflows ({})
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, NULL, NULL, NULL)",
+            WORKFLOW_COLUMNS
+        ),
+        params![
+            workflow.id,
+            workflow.name,
+            workflow.description,
+            stored.nodes,
+            stored.edges,
+            enum_to_str(&workflow.status)?,
+            workflow.created_at,
+            workflow.updated_at,
+            workflow.deleted_at,
+            workflow.resume_on_startup,
+            stored.flags,
+        ],
+    )?;
+    write_webhook_routes(conn, workflow)
+}
+
+// Indexes the paths the workflow's webhook nodes listen on, so a request is
+// matched to its workflow without reading every workflow.
+fn write_webhook_routes(conn: &Connection, workflow: &Workflow) -> Result<()> {
+    conn.execute(
+        "DELETE FROM webhook_routes WHERE workflow_id = ?1",
+        params![workflow.id],
+    )?;
+    let paths: BTreeSet<String> = workflow
+        .nodes
+        .iter()
+        .filter(|node| node.node_type == webhook::NODE_TYPE)
+        .filter_map(|node| webhook::hook_path(&node.data))
+        .collect();
+    for path in paths {
+        conn.execute(
+            "INSERT INTO webhook_routes (path, workflow_id) VALUES (?1, ?2)",
+            params![path, workflow.id],
+        )?;
+    }
+    Ok(())
+}
+
+// Re-indexes every workflow `key` can read, trashed ones included.
+fn rebuild_webhook_routes(conn: &Connection, key: Option<&AtRestKey>) -> Result<()> {
+    for workflow in query_workflows(conn, "", params![], key)? {
+        write_webhook_routes(conn, &workflow)?;
+    }
+    Ok(())
+}
+
+// Points the workflow's last run at its most recently finished execution,
+// or clears it when none is left.
+fn refresh_last_execution(conn: &Connection, workflow_id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE workflows SET (last_execution_id, last_execution_status, last_execution_at) = (
+             SELECT id, status, finished_at FROM executions
+             WHERE workflow_id = ?1 AND finished_at IS NOT NULL
+             ORDER BY finished_at DESC LIMIT 1
+         )
+         WHERE id = ?1",
+        params![workflow_id],
+    )?;
+    Ok(())
+}
+
+fn insert_execution_row(conn: &Connection, execution: &Execution) -> Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO executions ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            EXECUTION_COLUMNS
+        ),
+        params![
+            execution.id,
+            execution.workflow_id,
+            enum_to_str(&execution.status)?,
+            execution.profile,
+            execution.started_at,
+            execution.finished_at,
+            execution.error,
+            execution.cpu_time_ms,
+            execution.pinned,
+            execution.rate_limit_wait_ms,
+            execution.progress.map(|progress| progress.completed),
+            execution.progress.map(|progress| progress.total),
+        ],
+    )?;
+    Ok(())
+}
+
+// Replaces the tag set; tags are trimmed and empty ones dropped.
+fn write_workflow_tags(conn: &Connection, workflow_id: &str, tags: &[String]) -> Result<()> {
+    conn.execute(
+        "DELETE FROM workflow_tags WHERE workflow_id = ?1",
+        params![workflow_id],
+    )?;
+    for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+        conn.execute(
+            "INSERT OR IGNORE INTO workflow_tags (workflow_id, tag) VALUES (?1, ?2)",
+            params![workflow_id, tag],
+        )?;
+    }
+    Ok(())
+}
+
+// Leaves out workflows that cannot be decrypted; `get_workflow_summaries`
+// still lists them.
+fn query_workflows(
+    conn: &Connection,
+    clause: &str,
+    params: &[&dyn rusqlite::ToSql],
+    key: Option<&AtRestKey>,
+) -> Result<Vec<Workflow>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM workflows {}", WORKFLOW_COLUMNS, clause))?;
+    let rows = stmt.query_map(params, |row| read_workflow_row(row, key))?;
+
+    let mut workflows = Vec::new();
+    for row in rows {
+        match row? {
+            Ok(workflow) => workflows.push(workflow),
+            Err(DatabaseError::EncryptionUnavailable(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(workflows)
+}
+
+fn load_execution(conn: &Connection, id: &str) -> Result<Execution> {
+    conn.query_row(
+        &format!("SELECT {} FROM executions WHERE id = ?1", EXECUTION_COLUMNS),
+        params![id],
+        read_execution_row,
+    )
+    .optional()?
+    .ok_or_else(|| DatabaseError::NotFound(id.to_string()))?
+}
+
+fn query_executions(conn: &Connection, workflow_id: Option<&str>) -> Result<Vec<Execution>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM executions
+         WHERE workflow_id IN (SELECT id FROM workflows WHERE deleted_at IS NULL)
+           AND (?1 IS NULL OR workflow_id = ?1)
+         ORDER BY started_at DESC",
+        EXECUTION_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![workflow_id], read_execution_row)?;
+
+    let mut executions = Vec::new();
+    for row in rows {
+        executions.push(row??);
+    }
+    Ok(executions)
+}
+
+fn load_workflow(conn: &Connection, id: &str, key: Option<&AtRestKey>) -> Result<Workflow> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM workflows WHERE id = ?1 AND deleted_at IS NULL",
+            WORKFLOW_COLUMNS
+        ),
+        params![id],
+        |row| read_workflow_row(row, key),
+    )
+    .optional()?
+    .ok_or_else(|| DatabaseError::NotFound(id.to_string()))?
+}
+
+// Newest first.
+fn query_workflow_versions(
+    conn: &Connection,
+    id: &str,
+    key: Option<&AtRestKey>,
+) -> Result<Vec<WorkflowVersion>> {
+    load_workflow(conn, id, key)?;
+    let mut stmt = conn.prepare(
+        "SELECT version, snapshot, blob_flags, created_at FROM workflow_versions
+         WHERE workflow_id = ?1
+         ORDER BY version DESC",
+    )?;
+    let rows = stmt.query_map(params![id], |row| {
+        let snapshot: SqlValue = row.get(1)?;
+        Ok((row.get(0)?, snapshot, row.get(2)?, row.get(3)?))
+    })?;
+
+    let mut versions = Vec::new();
+    for row in rows {
+        let (version, snapshot, flags, created_at) = row?;
+        let snapshot = decode_snapshot(id, snapshot, flags, key)?;
+        versions.push(WorkflowVersion {
+            version,
+            created_at,
+            name: snapshot.name,
+            node_count: snapshot.nodes.len(),
+            edge_count: snapshot.edges.len(),
+        });
+    }
+    Ok(versions)
+}
+
+fn load_workflow_version(
+    conn: &Connection,
+    id: &str,
+    version: i64,
+    key: Option<&AtRestKey>,
+) -> Result<Workflow> {
+    let (snapshot, flags): (SqlValue, i64) = conn
+        .query_row(
+            "SELECT snapshot, blob_flags FROM workflow_versions
+             WHERE workflow_id = ?1 AND version = ?2",
+            params![id, version],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| DatabaseError::NotFound(format!("{} version {}", id, version)))?;
+    decode_snapshot(id, snapshot, flags, key)
+}
+
+fn load_undo_state(conn: &Connection, id: &str) -> Result<UndoState> {
+    let depth = |stack: &str| -> Result<usize> {
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM workflow_undo WHERE workflow_id = ?1 AND stack = ?2",
+            params![id, stack],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    };
+    Ok(UndoState {
+        undo: depth(UNDO_STACK)?,
+        redo: depth(REDO_STACK)?,
+    })
+}
+
+// Nodes of live workflows matching every filter set in `query`, grouped
+// by workflow name and in each workflow's node order.
+fn query_nodes(
+    conn: &Connection,
+    query: &NodeQuery,
+    key: Option<&AtRestKey>,
+) -> Result<Vec<NodeHit>> {
+    let text = query.text.as_deref().map(str::to_lowercase);
+    let mut stmt = conn.prepare(
+        "SELECT id, name, nodes, blob_flags FROM workflows
+         WHERE deleted_at IS NULL
+         ORDER BY name, id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, SqlValue>(2)?,
+            row.get::<_, i64>(3)?,
+        ))
+    })?;
+
+    let mut hits = Vec::new();
+    for row in rows {
+        let (workflow_id, workflow_name, nodes, flags) = row?;
+        let nodes: Vec<WorkflowNode> = match decode_stored(&workflow_id, nodes, flags, key) {
+            Ok(nodes) => serde_json::from_str(&nodes)?,
+            Err(DatabaseError::EncryptionUnavailable(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        for node in nodes {
+            if query.node_type.as_ref().is_some_and(|node_type| *node_type != node.node_type) {
+                continue;
+            }
+            if let Some(text) = &text {
+                if !node.data.to_string().to_lowercase().contains(text.as_str()) {
+                    continue;
+                }
+            }
+            hits.push(NodeHit {
+                workflow_id: workflow_id.clone(),
+                workflow_name: workflow_name.clone(),
+                node_id: node.id,
+                node_type: node.node_type,
+            });
+        }
+    }
+    Ok(hits)
+}
+
+// Newest first. Bounds are inclusive and apply to when the run first
+// failed; entries of trashed workflows are hidden.
+fn query_failed_executions(
+    conn: &Connection,
+    workflow_id: Option<&str>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<FailedExecution>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM failed_executions
+         WHERE workflow_id IN (SELECT id FROM workflows WHERE deleted_at IS NULL)
+           AND (?1 IS NULL OR workflow_id = ?1)
+           AND (?2 IS NULL OR failed_at >= ?2)
+           AND (?3 IS NULL OR failed_at <= ?3)
+         ORDER BY failed_at DESC",
+        FAILED_EXECUTION_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![workflow_id, since, until], read_failed_execution_row)?;
+
+    let mut failed = Vec::new();
+    for row in rows {
+        failed.push(row??);
+    }
+    Ok(failed)
+}
+
+fn query_workflow_tags(conn: &Connection, workflow_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn
+        .prepare("SELECT tag FROM workflow_tags WHERE workflow_id = ?1 ORDER BY tag")?;
+    let rows = stmt.query_map(params![workflow_id], |row| row.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// Pins of trashed workflows are kept, so a restored workflow comes back
+// pinned, but are not listed.
+fn query_pinned_workflows(conn: &Connection) -> Result<Vec<PinnedWorkflow>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.workflow_id, w.name, p.pinned_at FROM pinned_workflows p
+         JOIN workflows w ON w.id = p.workflow_id
+         WHERE w.deleted_at IS NULL
+         ORDER BY w.name COLLATE NOCASE, w.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(PinnedWorkflow {
+            workflow_id: row.get(0)?,
+            name: row.get(1)?,
+            pinned_at: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// Entries matching every filter set in `filter`, newest first.
+fn query_audit_log(conn: &Connection, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, command, timestamp, machine_id, summary, error FROM audit_log
+         WHERE (?1 IS NULL OR command = ?1)
+           AND (?2 IS NULL OR timestamp >= ?2)
+           AND (?3 IS NULL OR timestamp <= ?3)
+         ORDER BY timestamp DESC, id DESC
+         LIMIT ?4",
+    )?;
+    let limit = filter.limit.map_or(-1, i64::from);
+    let rows = stmt.query_map(
+        params![filter.command, filter.since, filter.until, limit],
+        |row| {
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                command: row.get(1)?,
+                timestamp: row.get(2)?,
+                machine_id: row.get(3)?,
+                summary: row.get(4)?,
+                error: row.get(5)?,
+            })
+        },
+    )?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+fn load_output_sink(conn: &Connection, workflow_id: &str) -> Result<Option<OutputSink>> {
+    let config: Option<String> = conn
+        .query_row(
+            "SELECT config FROM workflow_output_sinks WHERE workflow_id = ?1",
+            params![workflow_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(config.map(|config| serde_json::from_str(&config)).transpose()?)
+}
+
+fn load_notification_rules(conn: &Connection, workflow_id: &str) -> Result<Vec<NotificationRule>> {
+    let rules: Option<String> = conn
+        .query_row(
+            "SELECT rules FROM workflow_notifications WHERE workflow_id = ?1",
+            params![workflow_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(rules
+        .map(|rules| serde_json::from_str(&rules))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+// Returns the new `updated_at`.
+fn write_workflow(
+    conn: &Connection,
+    workflow: &Workflow,
+    key: Option<&AtRestKey>,
+) -> Result<chrono::DateTime<chrono::Utc>> {
+    let updated_at = chrono::Utc::now();
+    let stored = StoredJson::new(workflow, key)?;
+    let updated = conn.execute(
+        "UPDATE workflows
+         SET name = ?2, description = ?3, nodes = ?4, edges = ?5, status = ?6, updated_at = ?7,
+             resume_on_startup = ?8, blob_flags = ?9
+         WHERE id = ?1 AND deleted_at IS NULL",
+        params![
+            workflow.id,
+            workflow.name,
+            workflow.description,
+            stored.nodes,
+            stored.edges,
+            enum_to_str(&workflow.status)?,
+            updated_at,
+            workflow.resume_on_startup,
+            stored.flags,
+        ],
+    )?;
+
+    if updated == 0 {
+        return Err(DatabaseError::NotFound(workflow.id.clone()));
+    }
+    write_webhook_routes(conn, workflow)?;
+    Ok(updated_at)
+}
+
+// Compares what an edit can change; timestamps are ignored.
+fn same_content(a: &Workflow, b: &Workflow) -> Result<bool> {
+    Ok(a.name == b.name
+        && a.description == b.description
+        && enum_to_str(&a.status)? == enum_to_str(&b.status)?
+        && a.resume_on_startup == b.resume_on_startup
+        && serde_json::to_value(&a.nodes)? == serde_json::to_value(&b.nodes)?
+        && serde_json::to_value(&a.edges)? == serde_json::to_value(&b.edges)?)
+}
+
+// Pushes `workflow` onto `stack`, dropping the oldest entries past UNDO_LIMIT.
+fn push_history(
+    tx: &Transaction<'_>,
+    workflow_id: &str,
+    stack: &str,
+    workflow: &Workflow,
+    key: Option<&AtRestKey>,
+) -> Result<()> {
+    let (snapshot, flags) = encode_snapshot(workflow, key)?;
+    let seq: i64 = tx.query_row(
+        "SELECT COALESCE(MAX(seq), 0) + 1 FROM workflow_undo WHERE workflow_id = ?1 AND stack = ?2",
+        params![workflow_id, stack],
+        |row| row.get(0),
+    )?;
+    tx.execute(
+        "INSERT INTO workflow_undo (workflow_id, stack, seq, snapshot, blob_flags, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![workflow_id, stack, seq, snapshot, flags, chrono::Utc::now()],
+    )?;
+    tx.execute(
+        "DELETE FROM workflow_undo WHERE workflow_id = ?1 AND stack = ?2 AND seq <= ?3",
+        params![workflow_id, stack, seq - UNDO_LIMIT],
+    )?;
+    Ok(())
+}
+
+// Records `workflow` as the next version of itself, dropping the oldest
+// versions past `max_versions`.
+fn push_version(
+    tx: &Transaction<'_>,
+    workflow: &Workflow,
+    max_versions: usize,
+    key: Option<&AtRestKey>,
+) -> Result<()> {
+    if max_versions == 0 {
+        return Ok(());
+    }
+    let (snapshot, flags) = encode_snapshot(workflow, key)?;
+    let version: i64 = tx.query_row(
+        "SELECT COALESCE(MAX(version), 0) + 1 FROM workflow_versions WHERE workflow_id = ?1",
+        params![workflow.id],
+        |row| row.get(0),
+    )?;
+    tx.execute(
+        "INSERT INTO workflow_versions (workflow_id, version, snapshot, blob_flags, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![workflow.id, version, snapshot, flags, chrono::Utc::now()],
+    )?;
+    tx.execute(
+        "DELETE FROM workflow_versions WHERE workflow_id = ?1 AND version <= ?2",
+        params![workflow.id, version - max_versions as i64],
+    )?;
+    Ok(())
+}
+
+fn delete_workflow_rows(tx: &Transaction<'_>, id: &str) -> Result<BTreeMap<String, usize>> {
+    let mut removed = BTreeMap::new();
+    // Before `executions` goes, while its rows still link these to the workflow.
+    for table in EXECUTION_ARTIFACT_TABLES {
+        let count = tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE execution_id IN (SELECT id FROM executions WHERE workflow_id = ?1)",
+                table
+            ),
+            params![id],
+        )?;
+        removed.insert(table.to_string(), count);
+    }
+    for table in WORKFLOW_ARTIFACT_TABLES {
+        let count = tx.execute(
+            &format!("DELETE FROM {} WHERE workflow_id = ?1", table),
+            params![id],
+        )?;
+        removed.insert(table.to_string(), count);
+    }
+    let count = tx.execute("DELETE FROM workflows WHERE id = ?1", params![id])?;
+    removed.insert("workflows".to_string(), count);
+    Ok(removed)
+}
+
+fn read_folder_row(row: &Row<'_>) -> rusqlite::Result<Folder> {
+    Ok(Folder {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+fn enum_to_str<T: Serialize>(value: &T) -> Result<String> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(s) => Ok(s),
+        other => Ok(other.to_string()),
+    }
+}
+
+fn enum_from_str<T: DeserializeOwned>(value: String) -> Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(value))?)
+}
+
+fn read_workflow_row(row: &Row<'_>, key: Option<&AtRestKey>) -> rusqlite::Result<Result<Workflow>> {
+    let id: String = row.get(0)?;
+    let nodes: SqlValue = row.get(3)?;
+    let edges: SqlValue = row.get(4)?;
+    let status: String = row.get(5)?;
+    let flags: i64 = row.get(10)?;
+    let last_execution_status: Option<String> = row.get(12)?;
+
+    let build = || -> Result<Workflow> {
+        Ok(Workflow {
+            id: id.clone(),
+            name: row.get(1)?,
+            description: row.get(2)?,
+            nodes: serde_json::from_str(&decode_stored(&id, nodes, flags, key)?)?,
+            edges: serde_json::from_str(&decode_stored(&id, edges, flags, key)?)?,
+            status: enum_from_str(status)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+            deleted_at: row.get(8)?,
+            resume_on_startup: row.get(9)?,
+            last_execution_id: row.get(11)?,
+            last_execution_status: last_execution_status.map(enum_from_str).transpose()?,
+            last_execution_at: row.get(13)?,
+        })
+    };
+    Ok(build())
+}
+
+fn read_deadline_schedule_row(row: &Row<'_>) -> rusqlite::Result<DeadlineSchedule> {
+    Ok(DeadlineSchedule {
+        id: row.get(0)?,
+        workflow_id: row.get(1)?,
+        finish_by: row.get(2)?,
+        margin_ms: row.get(3)?,
+        created_at: row.get(4)?,
+        fired_at: row.get(5)?,
+        execution_id: row.get(6)?,
+        at_risk: row.get(7)?,
+        error: row.get(8)?,
+    })
+}
+
+fn read_failed_execution_row(row: &Row<'_>) -> rusqlite::Result<Result<FailedExecution>> {
+    let input: String = row.get(5)?;
+
+    let build = || -> Result<FailedExecution> {
+        Ok(FailedExecution {
+            id: row.get(0)?,
+            execution_id: row.get(1)?,
+            workflow_id: row.get(2)?,
+            profile: row.get(3)?,
+            node_id: row.get(4)?,
+            input: serde_json::from_str(&input)?,
+            error: row.get(6)?,
+            failed_at: row.get(7)?,
+            replay_count: row.get(8)?,
+            last_replay_id: row.get(9)?,
+            resolved_at: row.get(10)?,
+        })
+    };
+    Ok(build())
+}
+
+fn read_log_entry_row(row: &Row<'_>) -> rusqlite::Result<Result<LogEntry>> {
+    let level: String = row.get(1)?;
+    let data: Option<String> = row.get(4)?;
+
+    let build = || -> Result<LogEntry> {
+        Ok(LogEntry {
+            timestamp: row.get(0)?,
+            level: enum_from_str(level)?,
+            node_id: row.get(2)?,
+            message: row.get(3)?,
+            data: data.map(|data| serde_json::from_str(&data)).transpose()?,
+        })
+    };
+    Ok(build())
+}
+
+fn read_execution_row(row: &Row<'_>) -> rusqlite::Result<Result<Execution>> {
+    let status: String = row.get(2)?;
+
+    let build = || -> Result<Execution> {
+        Ok(Execution {
+            id: row.get(0)?,
+            workflow_id: row.get(1)?,
+            status: enum_from_str(status)?,
+            profile: row.get(3)?,
+            started_at: row.get(4)?,
+            finished_at: row.get(5)?,
+            error: row.get(6)?,
+            cpu_time_ms: row.get(7)?,
+            pinned: row.get(8)?,
+            rate_limit_wait_ms: row.get(9)?,
+            progress: match (row.get(10)?, row.get(11)?) {
+                (Some(completed), Some(total)) => Some(ExecutionProgress::new(completed, total)),
+                _ => None,
+            },
+        })
+    };
+    Ok(build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution_log::LogLevel;
+    use crate::test_support::{execution, node, temp_db, workflow};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn log_entry(message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: chrono::Utc::now(),
+            level: LogLevel::Info,
+            node_id: None,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    fn count(db: &Database, table: &str, workflow_id: &str) -> i64 {
+        db.conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE workflow_id = ?1", table),
+                params![workflow_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    // A workflow with a run, its log, tags, a version, state and a cache entry
+    fn seed(db: &mut Database, id: &str) {
+        let mut seeded = workflow(id, vec![node("t", "trigger", json!({}))], vec![]);
+        db.create_workflow(&seeded).unwrap();
+        seeded.name = format!("{} renamed", id);
+        db.update_workflow(&seeded).unwrap();
+        let run = format!("{}-run", id);
+        db.insert_execution(&execution(&run, id, chrono::Utc::now())).unwrap();
+        db.insert_execution_log(&run, &[log_entry("started"), log_entry("finished")])
+            .unwrap();
+        db.set_workflow_tags(id, &["nightly".to_string()]).unwrap();
+        db.set_workflow_state(id, "cursor", &json!(7)).unwrap();
+        db.put_cached_output("http", &format!("{}-hash", id), id, &json!({}), Duration::from_secs(60))
+            .unwrap();
+    }
+
+    #[test]
+    fn cascade_delete_removes_every_artifact() {
+        let mut db = temp_db();
+        seed(&mut db, "doomed");
+        seed(&mut db, "kept");
+
+        let removed = db.delete_workflow_cascade("doomed").unwrap();
+        assert_eq!(removed["workflows"], 1);
+        assert_eq!(removed["executions"], 1);
+        assert_eq!(removed["execution_logs"], 2);
+        assert_eq!(removed["workflow_tags"], 1);
+        assert_eq!(removed["workflow_versions"], 1);
+        assert_eq!(removed["workflow_state"], 1);
+        assert_eq!(removed["node_cache"], 1);
+
+        for table in WORKFLOW_ARTIFACT_TABLES {
+            assert_eq!(count(&db, table, "doomed"), 0, "{} left behind", table);
+        }
+        assert!(db.get_execution_log("doomed-run").unwrap().is_empty());
+        assert!(matches!(db.get_workflow("doomed"), Err(DatabaseError::NotFound(_))));
+
+        // Other workflows keep theirs.
+        assert_eq!(db.get_execution_log("kept-run").unwrap().len(), 2);
+        assert_eq!(count(&db, "workflow_versions", "kept"), 1);
+        assert!(matches!(
+            db.delete_workflow_cascade("doomed"),
+            Err(DatabaseError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn cascade_delete_is_all_or_nothing() {
+        let mut db = temp_db();
+        seed(&mut db, "stuck");
+        // Fails the last statement of the cascade, after the artifacts went.
+        db.conn
+            .execute_batch(
+                "CREATE TEMP TRIGGER keep_workflows BEFORE DELETE ON workflows
+                 BEGIN SELECT RAISE(ABORT, 'workflow rows are kept'); END;",
+            )
+            .unwrap();
+
+        assert!(db.delete_workflow_cascade("stuck").is_err());
+        assert_eq!(count(&db, "executions", "stuck"), 1);
+        assert_eq!(count(&db, "workflow_versions", "stuck"), 1);
+        assert_eq!(count(&db, "workflow_state", "stuck"), 1);
+        assert_eq!(db.get_execution_log("stuck-run").unwrap().len(), 2);
+        assert!(db.get_workflow("stuck").is_ok());
+    }
+
+    #[test]
+    fn trashed_workflows_hide_their_runs_until_purged() {
+        let mut db = temp_db();
+        seed(&mut db, "trashed");
+
+        db.delete_workflow("trashed").unwrap();
+        assert!(db.get_executions(None).unwrap().is_empty());
+        assert_eq!(count(&db, "executions", "trashed"), 1);
+
+        db.restore_workflow("trashed").unwrap();
+        assert_eq!(db.get_executions(None).unwrap().len(), 1);
+
+        db.delete_workflow("trashed").unwrap();
+        assert_eq!(db.purge_trash(0).unwrap(), 1);
+        assert_eq!(count(&db, "executions", "trashed"), 0);
+        assert!(db.get_execution_log("trashed-run").unwrap().is_empty());
+    }
+
+    fn execution_ids(db: &Database, workflow_id: &str) -> BTreeSet<String> {
+        db.get_executions(Some(workflow_id))
+            .unwrap()
+            .into_iter()
+            .map(|execution| execution.id)
+            .collect()
+    }
+
+    #[test]
+    fn retention_prunes_the_oldest_runs_but_not_pinned_ones() {
+        let mut db = temp_db();
+        db.create_workflow(&workflow("busy", vec![], vec![])).unwrap();
+        db.create_workflow(&workflow("quiet", vec![], vec![])).unwrap();
+        let now = chrono::Utc::now();
+        for hours in 0..5 {
+            let run = format!("run-{}", hours);
+            db.insert_execution(&execution(&run, "busy", now - chrono::Duration::hours(hours)))
+                .unwrap();
+            db.insert_execution_log(&run, &[log_entry("started")]).unwrap();
+        }
+        let mut running = execution("running", "busy", now - chrono::Duration::hours(9));
+        running.status = ExecutionStatus::Running;
+        running.finished_at = None;
+        db.insert_execution(&running).unwrap();
+        db.insert_execution(&execution("only", "quiet", now - chrono::Duration::hours(9)))
+            .unwrap();
+        db.set_execution_pinned("run-4", true).unwrap();
+
+        let removed = db
+            .cleanup_executions(&RetentionPolicy {
+                max_age_days: None,
+                max_per_workflow: Some(2),
+            })
+            .unwrap();
+        assert_eq!(removed["executions"], 2);
+        assert_eq!(removed["execution_logs"], 2);
+
+        let expected: BTreeSet<String> = ["run-0", "run-1", "run-4", "running"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(execution_ids(&db, "busy"), expected);
+        assert!(db.get_execution_log("run-2").unwrap().is_empty());
+        assert_eq!(execution_ids(&db, "quiet").len(), 1);
+    }
+
+    #[test]
+    fn retention_by_age_keeps_exactly_the_recent_runs() {
+        let mut db = temp_db();
+        db.create_workflow(&workflow("aged", vec![], vec![])).unwrap();
+        let now = chrono::Utc::now();
+        for days in [0, 2, 6, 8, 30, 90] {
+            let run = format!("day-{}", days);
+            db.insert_execution(&execution(&run, "aged", now - chrono::Duration::days(days)))
+                .unwrap();
+            db.insert_execution_log(&run, &[log_entry("started")]).unwrap();
+        }
+        let mut running = execution("running", "aged", now - chrono::Duration::days(60));
+        running.status = ExecutionStatus::Running;
+        running.finished_at = None;
+        db.insert_execution(&running).unwrap();
+        db.set_execution_pinned("day-90", true).unwrap();
+
+        let removed = db
+            .cleanup_executions(&RetentionPolicy {
+                max_age_days: Some(7),
+                max_per_workflow: None,
+            })
+            .unwrap();
+        assert_eq!(removed["executions"], 2);
+        assert_eq!(removed["execution_logs"], 2);
+        let expected: BTreeSet<String> = ["day-0", "day-2", "day-6", "day-90", "running"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(execution_ids(&db, "aged"), expected);
+
+        // With both limits, a run goes once it breaks either.
+        let removed = db
+            .cleanup_executions(&RetentionPolicy {
+                max_age_days: Some(5),
+                max_per_workflow: Some(1),
+            })
+            .unwrap();
+        assert_eq!(removed["executions"], 2);
+        let expected: BTreeSet<String> = ["day-0", "day-90", "running"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(execution_ids(&db, "aged"), expected);
+    }
+
+    fn hooked(id: &str, path: &str, status: WorkflowStatus) -> Workflow {
+        let hook = node("hook", "webhook", json!({ "path": path }));
+        let mut hooked = workflow(id, vec![hook], vec![]);
+        hooked.status = status;
+        hooked
+    }
+
+    #[test]
+    fn webhook_routes_follow_saves_and_skip_inactive_workflows() {
+        let mut db = temp_db();
+        db.create_workflow(&hooked("live", "/orders/", WorkflowStatus::Active)).unwrap();
+        db.create_workflow(&hooked("draft", "orders", WorkflowStatus::Draft)).unwrap();
+        db.create_workflow(&hooked("other", "refunds", WorkflowStatus::Active)).unwrap();
+        assert_eq!(db.webhook_workflow_ids("orders").unwrap(), ["live"]);
+        assert!(db.webhook_workflow_ids("missing").unwrap().is_empty());
+
+        let mut moved = db.get_workflow("other").unwrap();
+        moved.nodes[0].data = json!({ "path": "orders" });
+        db.update_workflow(&moved).unwrap();
+        assert_eq!(db.webhook_workflow_ids("orders").unwrap(), ["live", "other"]);
+        assert!(db.webhook_workflow_ids("refunds").unwrap().is_empty());
+
+        db.delete_workflow("live").unwrap();
+        assert_eq!(db.webhook_workflow_ids("orders").unwrap(), ["other"]);
+        db.restore_workflow("live").unwrap();
+        db.delete_workflow_cascade("other").unwrap();
+        assert_eq!(db.webhook_workflow_ids("orders").unwrap(), ["live"]);
+    }
+
+    #[test]
+    fn migrating_indexes_existing_webhooks() {
+        let path = crate::test_support::temp_db_path();
+        {
+            let db = Database::new(&path).unwrap();
+            db.create_workflow(&hooked("live", "orders", WorkflowStatus::Active)).unwrap();
+            db.conn
+                .execute_batch(&format!(
+                    "DELETE FROM webhook_routes;
+                     ALTER TABLE workflow_versions DROP COLUMN blob_flags;
+                     ALTER TABLE workflow_undo DROP COLUMN blob_flags;
+                     PRAGMA user_version = {};",
+                    WEBHOOK_ROUTES_VERSION - 1
+                ))
+                .unwrap();
+        }
+        let db = Database::new(&path).unwrap();
+        assert_eq!(db.webhook_workflow_ids("orders").unwrap(), ["live"]);
+    }
+
+    fn blob_flags(db: &Database, id: &str) -> i64 {
+        db.conn
+            .query_row("SELECT blob_flags FROM workflows WHERE id = ?1", params![id], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    // Key derivation cheap enough for tests.
+    fn cheap_kdf(db: &Database) {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+        .save(db)
+        .unwrap();
+    }
+
+    #[test]
+    fn blobs_round_trip_compressed_and_encrypted() {
+        let path = crate::test_support::temp_db_path();
+        let small = workflow("small", vec![node("t", "trigger", json!({}))], vec![]);
+        let large = workflow(
+            "large",
+            vec![node("t", "trigger", json!({ "payload": "x".repeat(blob::COMPRESS_THRESHOLD) }))],
+            vec![],
+        );
+        let as_json = |w: &Workflow| json!([w.nodes, w.edges]);
+        {
+            let mut db = Database::new(&path).unwrap();
+            cheap_kdf(&db);
+            db.create_workflow(&small).unwrap();
+            db.create_workflow(&large).unwrap();
+            assert_eq!(blob_flags(&db, "small"), 0);
+            assert_eq!(blob_flags(&db, "large"), blob::COMPRESSED);
+
+            assert_eq!(db.encrypt_workflows("hunter2").unwrap(), 2);
+            assert_eq!(blob_flags(&db, "small"), blob::ENCRYPTED);
+            assert_eq!(blob_flags(&db, "large"), blob::COMPRESSED | blob::ENCRYPTED);
+            for expected in [&small, &large] {
+                assert_eq!(as_json(&db.get_workflow(&expected.id).unwrap()), as_json(expected));
+            }
+
+            // Saves made while the key is loaded stay encrypted.
+            let mut edited = small.clone();
+            edited.nodes.push(node("d", "delay", json!({ "duration_ms": 1 })));
+            db.update_workflow(&edited).unwrap();
+            assert_eq!(blob_flags(&db, "small"), blob::ENCRYPTED);
+        }
+
+        let db = Database::new(&path).unwrap();
+        assert!(matches!(
+            db.get_workflow("large"),
+            Err(DatabaseError::EncryptionUnavailable(id)) if id == "large"
+        ));
+        assert_eq!(db.unlock("hunter2").unwrap(), 2);
+        assert_eq!(as_json(&db.get_workflow("large").unwrap()), as_json(&large));
+        assert_eq!(db.get_workflow("small").unwrap().nodes.len(), 2);
+    }
+
+    #[test]
+    fn pooled_reads_run_side_by_side_and_past_a_writer() {
+        const READERS: usize = 8;
+        let db = temp_db();
+        db.create_workflow(&workflow("hot", vec![node("t", "trigger", json!({}))], vec![]))
+            .unwrap();
+        let readers = db.readers();
+
+        // Readers see the last commit while a write is in progress.
+        db.conn
+            .execute_batch("BEGIN IMMEDIATE; UPDATE workflows SET name = 'uncommitted';")
+            .unwrap();
+        let barrier = std::sync::Barrier::new(READERS);
+        std::thread::scope(|scope| {
+            for _ in 0..READERS {
+                scope.spawn(|| {
+                    // Only passes once every reader holds a connection at once.
+                    let conn = readers.acquire().unwrap();
+                    barrier.wait();
+                    assert_eq!(load_workflow(&conn, "hot", None).unwrap().name, "Workflow hot");
+                });
+            }
+        });
+        db.conn.execute_batch("ROLLBACK").unwrap();
+    }
+
+    // Commands hold the shared `Mutex<Database>` for whole writes; reads
+    // through the pool must get all their work done while it is held, where
+    // reads through the mutex get none of theirs done.
+    #[test]
+    fn pooled_reads_do_not_queue_behind_the_database_lock() {
+        const THREADS: usize = MAX_IDLE_READERS;
+        const READS: usize = 100;
+        let db = temp_db();
+        let nodes = (0..50)
+            .map(|i| node(&format!("n{}", i), "delay", json!({ "duration_ms": i })))
+            .collect();
+        db.create_workflow(&workflow("hot", nodes, vec![])).unwrap();
+        let readers = db.readers();
+        let db = Mutex::new(db);
+        let pooled_reads = AtomicUsize::new(0);
+
+        let (while_locked, seen_by_shared) = std::thread::scope(|scope| {
+            let writer = db.lock();
+            let shared = scope.spawn(|| {
+                db.lock().get_workflow("hot").unwrap();
+                pooled_reads.load(Ordering::SeqCst)
+            });
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..READS {
+                        readers.get_workflow("hot").unwrap();
+                        pooled_reads.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+            let deadline = std::time::Instant::now() + Duration::from_secs(10);
+            while pooled_reads.load(Ordering::SeqCst) < THREADS * READS
+                && std::time::Instant::now() < deadline
+            {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            let while_locked = pooled_reads.load(Ordering::SeqCst);
+            drop(writer);
+            (while_locked, shared.join().unwrap())
+        });
+        assert_eq!(while_locked, THREADS * READS);
+        assert_eq!(seen_by_shared, THREADS * READS);
+    }
+
+    #[test]
+    fn encrypted_workflows_stay_listed_but_locked_until_unlocked() {
+        let path = crate::test_support::temp_db_path();
+        {
+            let mut db = Database::new(&path).unwrap();
+            cheap_kdf(&db);
+            // Enabled with nothing to encrypt yet; later saves are encrypted.
+            assert_eq!(db.encrypt_workflows("hunter2").unwrap(), 0);
+            db.create_workflow(&workflow("secret", vec![node("t", "trigger", json!({}))], vec![]))
+                .unwrap();
+            assert_eq!(blob_flags(&db, "secret"), blob::ENCRYPTED);
+        }
+
+        let db = Database::new(&path).unwrap();
+        let summaries = db.readers().get_workflow_summaries().unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert!(summaries[0].locked);
+        assert!(matches!(
+            db.get_workflow("secret"),
+            Err(DatabaseError::EncryptionUnavailable(_))
+        ));
+
+        assert!(db.unlock("hunter3").is_err());
+        assert!(db.readers().get_workflow("secret").is_err());
+
+        assert_eq!(db.unlock("hunter2").unwrap(), 1);
+        assert!(!db.readers().get_workflow_summaries().unwrap()[0].locked);
+        assert_eq!(db.readers().get_workflow("secret").unwrap().nodes.len(), 1);
+    }
+
+    #[test]
+    fn an_empty_encrypted_database_still_checks_the_passphrase() {
+        let path = crate::test_support::temp_db_path();
+        {
+            let mut db = Database::new(&path).unwrap();
+            cheap_kdf(&db);
+            db.encrypt_workflows("hunter2").unwrap();
+        }
+
+        let db = Database::new(&path).unwrap();
+        assert!(db.unlock("hunter3").is_err());
+        assert!(db.key().is_none());
+        assert_eq!(db.unlock("hunter2").unwrap(), 0);
+        db.create_workflow(&workflow("fresh", vec![], vec![])).unwrap();
+        assert_eq!(blob_flags(&db, "fresh"), blob::ENCRYPTED);
+    }
+
+    // Every stored value of `column` in `table`, read straight from the file.
+    fn raw_values(path: &Path, table: &str, column: &str) -> Vec<Vec<u8>> {
+        let conn = Connection::open(path).unwrap();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM {}", column, table)).unwrap();
+        let rows = stmt.query_map([], |row| row.get::<_, SqlValue>(0)).unwrap();
+        rows.map(|value| match value.unwrap() {
+            SqlValue::Text(text) => text.into_bytes(),
+            SqlValue::Blob(bytes) => bytes,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn encryption_covers_versions_and_undo_history() {
+        let path = crate::test_support::temp_db_path();
+        let needle = b"needle-config";
+        let trigger = node("t", "trigger", json!({ "note": "needle-config" }));
+        let mut edited = workflow("w", vec![trigger], vec![]);
+        {
+            let mut db = Database::new(&path).unwrap();
+            cheap_kdf(&db);
+            db.create_workflow(&edited).unwrap();
+            edited.name = "first edit".to_string();
+            db.update_workflow(&edited).unwrap();
+            edited.name = "second edit".to_string();
+            db.update_workflow(&edited).unwrap();
+            db.undo_workflow("w").unwrap();
+            assert!(raw_values(&path, "workflow_versions", "snapshot")
+                .iter()
+                .any(|value| value.windows(needle.len()).any(|w| w == needle)));
+
+            db.encrypt_workflows("hunter2").unwrap();
+            // Snapshots taken after encryption are encrypted too.
+            edited.name = "third edit".to_string();
+            db.update_workflow(&edited).unwrap();
+        }
+
+        for (table, column) in [
+            ("workflows", "nodes"),
+            ("workflow_versions", "snapshot"),
+            ("workflow_undo", "snapshot"),
+        ] {
+            let values = raw_values(&path, table, column);
+            assert!(!values.is_empty(), "{} is empty", table);
+            for value in values {
+                let found = value.windows(needle.len()).any(|w| w == needle);
+                assert!(!found, "plaintext in {}", table);
+            }
+        }
+
+        let mut db = Database::new(&path).unwrap();
+        assert!(matches!(
+            db.get_workflow_version("w", 1),
+            Err(DatabaseError::EncryptionUnavailable(_))
+        ));
+        db.unlock("hunter2").unwrap();
+        let versions = db.readers().get_workflow_versions("w").unwrap();
+        assert_eq!(versions.len(), 3);
+        let first = db.get_workflow_version("w", 1).unwrap();
+        assert_eq!(first.nodes[0].data["note"], "needle-config");
+        assert_eq!(db.undo_workflow("w").unwrap().unwrap().name, "first edit");
+        assert_eq!(db.redo_workflow("w").unwrap().unwrap().name, "third edit");
+    }
+
+    #[test]
+    fn rotation_moves_workflows_to_the_new_passphrase() {
+        let path = crate::test_support::temp_db_path();
+        {
+            let mut db = Database::new(&path).unwrap();
+            cheap_kdf(&db);
+            let params = KdfParams::load(&db).unwrap();
+            let secret = encryption::encrypt_with("s3cret", "old-key", &params).unwrap();
+            db.set_workflow_secret("w", "API_KEY", &secret).unwrap();
+            let mut edited = workflow("w", vec![node("t", "trigger", json!({}))], vec![]);
+            db.create_workflow(&edited).unwrap();
+            edited.name = "edited".to_string();
+            db.update_workflow(&edited).unwrap();
+            db.encrypt_workflows("old-pass").unwrap();
+
+            let rotation = db
+                .rotate_encryption_key("old-key", "new-key", Some("new-pass"))
+                .unwrap();
+            assert_eq!(rotation, KeyRotation { secrets: 1, workflows: 1 });
+            assert_eq!(db.get_workflow("w").unwrap().name, "edited");
+        }
+
+        let db = Database::new(&path).unwrap();
+        assert!(db.unlock("old-pass").is_err());
+        db.unlock("new-pass").unwrap();
+        assert_eq!(db.get_workflow_version("w", 1).unwrap().name, "Workflow w");
+    }
+
+    #[test]
+    fn a_bad_workflow_row_aborts_the_whole_rotation() {
+        let path = crate::test_support::temp_db_path();
+        let mut db = Database::new(&path).unwrap();
+        cheap_kdf(&db);
+        let params = KdfParams::load(&db).unwrap();
+        let secret = encryption::encrypt_with("s3cret", "old-key", &params).unwrap();
+        db.set_workflow_secret("good", "API_KEY", &secret).unwrap();
+        for id in ["good", "bad"] {
+            db.create_workflow(&workflow(id, vec![node("t", "trigger", json!({}))], vec![]))
+                .unwrap();
+        }
+        db.encrypt_workflows("old-pass").unwrap();
+        db.conn
+            .execute("UPDATE workflows SET nodes = 'not encrypted' WHERE id = 'bad'", [])
+            .unwrap();
+        let before = |table: &str, column: &str| raw_values(&path, table, column);
+        let (workflows, secrets, meta) = (
+            before("workflows", "nodes"),
+            before("workflow_secrets", "value"),
+            before("app_meta", "value"),
+        );
+
+        assert!(db.rotate_encryption_key("old-key", "new-key", Some("new-pass")).is_err());
+        assert_eq!(before("workflows", "nodes"), workflows);
+        assert_eq!(before("workflow_secrets", "value"), secrets);
+        assert_eq!(before("app_meta", "value"), meta);
+        // The loaded passphrase is still the old one.
+        assert!(db.get_workflow("good").is_ok());
+    }
+}
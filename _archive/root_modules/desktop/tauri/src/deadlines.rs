@@ -0,0 +1,218 @@
+/*!
+ * Deadlines - runs that must finish by a given time
+ *
+ * A deadline schedule starts its workflow once the projected run (see
+ * `estimate`) plus a margin would only just finish by the deadline. Start
+ * times are recomputed from fresh estimates on every check, so they follow
+ * the node timings recorded meanwhile, and pending schedules are picked up
+ * again when the app starts. A schedule whose start time has already passed
+ * fires at once; if even then the run is projected to finish late, it runs at
+ * high priority and reports `DeadlineAtRisk`. Each schedule fires once.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::database::{Database, DatabaseError};
+use crate::estimate;
+use crate::events::ExecutionEvent;
+use crate::scheduler::Priority;
+use crate::workflow_engine::{self, WorkflowEngine};
+
+// Slack on top of the estimate, for worker waits and timings that vary.
+pub const DEFAULT_MARGIN_MS: u64 = 60_000;
+
+// Longest the manager sleeps between checks, so start times follow new
+// estimates even while nothing is due.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum DeadlineError {
+    #[error("deadline {0} has already passed")]
+    Passed(chrono::DateTime<chrono::Utc>),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+}
+
+pub type Result<T> = std::result::Result<T, DeadlineError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadlineSchedule {
+    pub id: String,
+    pub workflow_id: String,
+    pub finish_by: chrono::DateTime<chrono::Utc>,
+    // Lead time added to the estimate
+    pub margin_ms: u64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    // Set once the schedule fired, whether or not its run started
+    pub fired_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub execution_id: Option<String>,
+    // The run was projected to finish after the deadline when it started
+    pub at_risk: bool,
+    // Why the run could not be started
+    pub error: Option<String>,
+}
+
+pub fn create(
+    db: &Database,
+    workflow_id: &str,
+    finish_by: chrono::DateTime<chrono::Utc>,
+    margin_ms: Option<u64>,
+) -> Result<DeadlineSchedule> {
+    let now = chrono::Utc::now();
+    if finish_by <= now {
+        return Err(DeadlineError::Passed(finish_by));
+    }
+    db.get_workflow(workflow_id)?;
+
+    let schedule = DeadlineSchedule {
+        id: Uuid::new_v4().to_string(),
+        workflow_id: workflow_id.to_string(),
+        finish_by,
+        margin_ms: margin_ms.unwrap_or(DEFAULT_MARGIN_MS),
+        created_at: now,
+        fired_at: None,
+        execution_id: None,
+        at_risk: false,
+        error: None,
+    };
+    db.insert_deadline_schedule(&schedule)?;
+    Ok(schedule)
+}
+
+fn millis(ms: u64) -> chrono::Duration {
+    chrono::Duration::milliseconds(i64::try_from(ms).unwrap_or(i64::MAX))
+}
+
+// Starts every pending schedule whose start time has come. Returns the
+// earliest start time still ahead, if any.
+pub fn fire_due(
+    db: &Mutex<Database>,
+    engine: &Mutex<WorkflowEngine>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    let loaded = {
+        let db = db.lock();
+        db.pending_deadline_schedules()
+            .and_then(|pending| Ok((pending, db.get_node_type_stats()?)))
+    };
+    let (pending, stats) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::warn!("failed to load deadline schedules: {}", e);
+            return None;
+        }
+    };
+
+    let mut next: Option<chrono::DateTime<chrono::Utc>> = None;
+    for schedule in pending {
+        let projected = db
+            .lock()
+            .get_workflow(&schedule.workflow_id)
+            .map_err(|e| e.to_string())
+            .and_then(|workflow| {
+                let estimate = estimate::estimate(&workflow, &stats).map_err(|e| e.to_string())?;
+                Ok((workflow, estimate.duration_ms))
+            });
+        let (workflow, duration_ms) = match projected {
+            Ok(projected) => projected,
+            Err(error) => {
+                record_fired(db, &schedule, None, false, Some(&error));
+                continue;
+            }
+        };
+
+        let lead = millis(duration_ms.saturating_add(schedule.margin_ms));
+        if let Some(start_at) = schedule.finish_by.checked_sub_signed(lead) {
+            if start_at > now {
+                next = Some(next.map_or(start_at, |next| next.min(start_at)));
+                continue;
+            }
+        }
+
+        let projected_finish = now
+            .checked_add_signed(millis(duration_ms))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MAX_UTC);
+        let at_risk = projected_finish > schedule.finish_by;
+        let priority = if at_risk { Priority::High } else { Priority::Normal };
+        let started = workflow_engine::find_profile(workflow_engine::DEFAULT_PROFILE)
+            .and_then(|profile| engine.lock().execute_workflow(&workflow, &profile, priority, None));
+        match started {
+            Ok(execution_id) => {
+                record_fired(db, &schedule, Some(&execution_id), at_risk, None);
+                if at_risk {
+                    tracing::warn!(
+                        "workflow {} started for its {} deadline but is projected to miss it",
+                        schedule.workflow_id,
+                        schedule.finish_by
+                    );
+                    engine.lock().emit_event(ExecutionEvent::DeadlineAtRisk {
+                        execution_id,
+                        workflow_id: schedule.workflow_id.clone(),
+                        schedule_id: schedule.id.clone(),
+                        finish_by: schedule.finish_by,
+                        projected_finish,
+                    });
+                }
+            }
+            Err(e) => record_fired(db, &schedule, None, at_risk, Some(&e.to_string())),
+        }
+    }
+    next
+}
+
+fn record_fired(
+    db: &Mutex<Database>,
+    schedule: &DeadlineSchedule,
+    execution_id: Option<&str>,
+    at_risk: bool,
+    error: Option<&str>,
+) {
+    if let Some(error) = error {
+        tracing::warn!(
+            "deadline schedule {} could not start workflow {}: {}",
+            schedule.id,
+            schedule.workflow_id,
+            error
+        );
+    }
+    if let Err(e) = db.lock().mark_deadline_fired(&schedule.id, execution_id, at_risk, error) {
+        tracing::warn!("failed to record deadline schedule {} as fired: {}", schedule.id, e);
+    }
+}
+
+// Runs `fire_due` in the background: when the next start time comes, when
+// woken, and at least every `RECHECK_INTERVAL`.
+#[derive(Clone, Default)]
+pub struct DeadlineManager {
+    wake: Arc<Notify>,
+}
+
+impl DeadlineManager {
+    pub fn spawn(&self, db: Arc<Mutex<Database>>, engine: Arc<Mutex<WorkflowEngine>>) {
+        let wake = self.wake.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let now = chrono::Utc::now();
+                let wait = fire_due(&db, &engine, now)
+                    .and_then(|next| (next - now).to_std().ok())
+                    .map_or(RECHECK_INTERVAL, |until| until.min(RECHECK_INTERVAL));
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = wake.notified() => {}
+                }
+            }
+        });
+    }
+
+    // Checks now instead of at the next planned time, e.g. after a schedule
+    // was added or the database was switched.
+    pub fn reschedule(&self) {
+        self.wake.notify_one();
+    }
+}
@@ -0,0 +1,121 @@
+/*!
+ * Debugger - breakpoints and step-through for debug runs
+ *
+ * In a run with breakpoints enabled, the engine holds before any node with
+ * `breakpoint: true` in its `data`, and before every node once the user
+ * steps. While held, a snapshot of the execution context is available for
+ * inspection; `step` runs the next node and holds again, `resume` runs on to
+ * the next breakpoint.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+use crate::nodes::ExecutionContext;
+use crate::WorkflowNode;
+
+pub const BREAKPOINT_KEY: &str = "breakpoint";
+
+// The execution context as seen while held before `node_id`. Secrets are
+// listed by name only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextSnapshot {
+    pub node_id: String,
+    // What `node_id` will receive when it runs
+    pub node_input: Value,
+    pub input: Value,
+    pub node_outputs: BTreeMap<String, Value>,
+    pub variables: BTreeMap<String, Value>,
+    pub secrets: Vec<String>,
+    pub dry_run: bool,
+    pub held_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ContextSnapshot {
+    pub fn capture(ctx: &ExecutionContext, node_id: &str, node_input: &Value) -> Self {
+        Self {
+            node_id: node_id.to_string(),
+            node_input: node_input.clone(),
+            input: ctx.input.clone(),
+            node_outputs: ctx
+                .node_outputs
+                .iter()
+                .map(|(id, output)| (id.clone(), output.clone()))
+                .collect(),
+            variables: ctx
+                .variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            secrets: ctx.secrets.names().map(str::to_string).collect(),
+            dry_run: ctx.dry_run,
+            held_at: chrono::Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Hold before every node, not just breakpoints
+    stepping: bool,
+    held: Option<ContextSnapshot>,
+}
+
+// Breakpoint state of one execution, shared by the engine and the commands
+// that step it.
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    state: Arc<Mutex<State>>,
+    released: Arc<Notify>,
+}
+
+impl Debugger {
+    pub fn should_hold(&self, node: &WorkflowNode) -> bool {
+        self.state.lock().stepping
+            || node.data.get(BREAKPOINT_KEY).and_then(Value::as_bool) == Some(true)
+    }
+
+    // Holds until `step` or `resume`. The caller races this against
+    // cancellation; the hold is cleared either way.
+    pub async fn hold(&self, snapshot: ContextSnapshot) {
+        self.state.lock().held = Some(snapshot);
+        let _clear = ClearHeld(self);
+        self.released.notified().await;
+    }
+
+    pub fn held(&self) -> Option<ContextSnapshot> {
+        self.state.lock().held.clone()
+    }
+
+    // Both return false, doing nothing, unless the run is held.
+    pub fn step(&self) -> bool {
+        self.release(true)
+    }
+
+    pub fn resume(&self) -> bool {
+        self.release(false)
+    }
+
+    fn release(&self, stepping: bool) -> bool {
+        let mut state = self.state.lock();
+        if state.held.take().is_none() {
+            return false;
+        }
+        state.stepping = stepping;
+        // Stores a permit if the engine has not started waiting yet.
+        self.released.notify_one();
+        true
+    }
+}
+
+struct ClearHeld<'a>(&'a Debugger);
+
+impl Drop for ClearHeld<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().held = None;
+    }
+}
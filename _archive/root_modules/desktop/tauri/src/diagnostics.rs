@@ -0,0 +1,352 @@
+/*!
+ * Diagnostics - self-checks behind the support "is everything OK" report
+ *
+ * Every check runs on its own task and takes shared locks with a timeout, so
+ * a subsystem that panics or hangs is reported as a failed check instead of
+ * taking the report down with it.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::database::{self, Database};
+use crate::encryption::{self, KdfParams};
+use crate::resources::{ResourceMonitor, BYTES_PER_MB};
+use crate::workflow_engine::WorkflowEngine;
+
+// Below this much free space in the app data dir, writes start to fail soon.
+pub const MIN_FREE_DISK_MB: u64 = 500;
+
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+    // What the user or support can do about a warning or failure
+    pub remediation: Option<String>,
+}
+
+impl DiagnosticCheck {
+    fn ok(name: &str, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Ok,
+            message: message.into(),
+            remediation: None,
+        }
+    }
+
+    fn warning(name: &str, message: impl Into<String>, remediation: &str) -> Self {
+        Self {
+            status: CheckStatus::Warning,
+            remediation: Some(remediation.to_string()),
+            ..Self::ok(name, message)
+        }
+    }
+
+    fn failed(name: &str, message: impl Into<String>, remediation: &str) -> Self {
+        Self {
+            status: CheckStatus::Failed,
+            remediation: Some(remediation.to_string()),
+            ..Self::ok(name, message)
+        }
+    }
+
+    fn busy(name: &str, subsystem: &str) -> Self {
+        Self::warning(
+            name,
+            format!("{} stayed busy for {:?}", subsystem, LOCK_TIMEOUT),
+            "Wait for running operations to finish and run diagnostics again.",
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    // The worst status of any check
+    pub status: CheckStatus,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+pub async fn run(
+    db: Arc<Mutex<Database>>,
+    engine: Arc<Mutex<WorkflowEngine>>,
+    resources: Arc<ResourceMonitor>,
+    secret_key: String,
+    websocket_url: String,
+) -> DiagnosticsReport {
+    let checks = vec![
+        isolated("database", {
+            let db = db.clone();
+            async move { check_database(&db) }
+        })
+        .await,
+        isolated("encryption", {
+            let db = db.clone();
+            async move { check_encryption(&db, &secret_key) }
+        })
+        .await,
+        isolated("websocket", check_websocket(websocket_url)).await,
+        isolated("disk_space", {
+            let db = db.clone();
+            let resources = resources.clone();
+            async move { check_disk_space(&db, &resources) }
+        })
+        .await,
+        isolated("memory", async move { check_memory(&resources) }).await,
+        isolated("stuck_executions", async move {
+            check_stuck_executions(&db, &engine)
+        })
+        .await,
+    ];
+
+    DiagnosticsReport {
+        checked_at: chrono::Utc::now(),
+        status: checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok),
+        checks,
+    }
+}
+
+// Runs a check on its own task so a panic inside it becomes a failed check.
+async fn isolated<F>(name: &str, check: F) -> DiagnosticCheck
+where
+    F: Future<Output = DiagnosticCheck> + Send + 'static,
+{
+    match tokio::spawn(check).await {
+        Ok(check) => check,
+        Err(e) => DiagnosticCheck::failed(
+            name,
+            format!("check crashed: {}", e),
+            "Restart the app and send the app log to support.",
+        ),
+    }
+}
+
+fn check_database(db: &Mutex<Database>) -> DiagnosticCheck {
+    const NAME: &str = "database";
+    let Some(db) = db.try_lock_for(LOCK_TIMEOUT) else {
+        return DiagnosticCheck::busy(NAME, "the database");
+    };
+    let path = db.path().display().to_string();
+
+    match db.schema_version() {
+        Ok(version) if version == database::SCHEMA_VERSION => DiagnosticCheck::ok(
+            NAME,
+            format!("{} is open at schema version {}", path, version),
+        ),
+        Ok(version) if version < database::SCHEMA_VERSION => DiagnosticCheck::failed(
+            NAME,
+            format!(
+                "{} is at schema version {}, expected {}",
+                path,
+                version,
+                database::SCHEMA_VERSION
+            ),
+            "Restart the app to apply the pending migrations.",
+        ),
+        Ok(version) => DiagnosticCheck::failed(
+            NAME,
+            format!(
+                "{} is at schema version {}, newer than this app's {}",
+                path,
+                version,
+                database::SCHEMA_VERSION
+            ),
+            "Update the app; the database was written by a newer version.",
+        ),
+        Err(e) => DiagnosticCheck::failed(
+            NAME,
+            format!("{} cannot be queried: {}", path, e),
+            "Restore the database from a backup.",
+        ),
+    }
+}
+
+fn check_encryption(db: &Mutex<Database>, secret_key: &str) -> DiagnosticCheck {
+    const NAME: &str = "encryption";
+    let params = match db.try_lock_for(LOCK_TIMEOUT) {
+        Some(db) => KdfParams::load(&db),
+        None => return DiagnosticCheck::busy(NAME, "the database"),
+    };
+
+    match params.and_then(|params| encryption::check_health(secret_key, &params)) {
+        Ok(health) => DiagnosticCheck::ok(
+            NAME,
+            format!(
+                "{} with {} round-trips in {} ms",
+                health.algorithm, health.kdf, health.round_trip_ms
+            ),
+        ),
+        Err(e) => DiagnosticCheck::failed(
+            NAME,
+            format!("round trip with the secrets key failed: {}", e),
+            "Secrets encrypted with this key may be unreadable; re-enter them or restore the previous key.",
+        ),
+    }
+}
+
+// Reachable means a TCP connection to the endpoint's host and port opens.
+async fn check_websocket(url: String) -> DiagnosticCheck {
+    const NAME: &str = "websocket";
+    const REMEDIATION: &str = "Check the network connection and any proxy or firewall blocking the endpoint.";
+
+    let address = reqwest::Url::parse(&url).ok().and_then(|parsed| {
+        let host = parsed.host_str()?.to_string();
+        Some((host, parsed.port_or_known_default()?))
+    });
+    let Some(address) = address else {
+        return DiagnosticCheck::failed(
+            NAME,
+            format!("invalid endpoint URL: {}", url),
+            "Reinstall the app; its WebSocket endpoint is misconfigured.",
+        );
+    };
+
+    let started = Instant::now();
+    match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(address)).await {
+        Ok(Ok(_)) => DiagnosticCheck::ok(
+            NAME,
+            format!("{} reachable in {} ms", url, started.elapsed().as_millis()),
+        ),
+        Ok(Err(e)) => {
+            DiagnosticCheck::failed(NAME, format!("{} unreachable: {}", url, e), REMEDIATION)
+        }
+        Err(_) => DiagnosticCheck::failed(
+            NAME,
+            format!("{} did not answer within {:?}", url, CONNECT_TIMEOUT),
+            REMEDIATION,
+        ),
+    }
+}
+
+fn check_disk_space(db: &Mutex<Database>, resources: &ResourceMonitor) -> DiagnosticCheck {
+    const NAME: &str = "disk_space";
+    let data_dir = match db.try_lock_for(LOCK_TIMEOUT) {
+        Some(db) => db.path().parent().map(PathBuf::from),
+        None => return DiagnosticCheck::busy(NAME, "the database"),
+    };
+    let Some(data_dir) = data_dir else {
+        return DiagnosticCheck::warning(
+            NAME,
+            "the app data directory is unknown",
+            "Run diagnostics again after restarting the app.",
+        );
+    };
+
+    match resources.disk_space(&data_dir) {
+        Some(disk) if disk.available_space / BYTES_PER_MB >= MIN_FREE_DISK_MB => {
+            DiagnosticCheck::ok(
+                NAME,
+                format!(
+                    "{} MB free on {}",
+                    disk.available_space / BYTES_PER_MB,
+                    disk.mount_point
+                ),
+            )
+        }
+        Some(disk) => DiagnosticCheck::failed(
+            NAME,
+            format!(
+                "only {} MB free on {} (minimum {} MB)",
+                disk.available_space / BYTES_PER_MB,
+                disk.mount_point,
+                MIN_FREE_DISK_MB
+            ),
+            "Free up disk space, or apply the execution retention policy to remove old runs.",
+        ),
+        None => DiagnosticCheck::warning(
+            NAME,
+            format!("no mounted disk found for {}", data_dir.display()),
+            "Check that the app data directory is on a mounted local disk.",
+        ),
+    }
+}
+
+fn check_memory(resources: &ResourceMonitor) -> DiagnosticCheck {
+    const NAME: &str = "memory";
+    let info = resources.system_info();
+    let available_mb = info.available_memory / BYTES_PER_MB;
+    let floor = resources.min_free_memory_mb();
+
+    if floor > 0 && available_mb < floor {
+        DiagnosticCheck::warning(
+            NAME,
+            format!(
+                "{} MB available, below the {} MB floor; runs are held until memory recovers",
+                available_mb, floor
+            ),
+            "Close other applications or lower the minimum free memory preference.",
+        )
+    } else {
+        DiagnosticCheck::ok(
+            NAME,
+            format!(
+                "{} of {} MB available",
+                available_mb,
+                info.total_memory / BYTES_PER_MB
+            ),
+        )
+    }
+}
+
+// Stuck runs are recorded as running but unknown to the engine, e.g. left
+// behind by a crash.
+fn check_stuck_executions(
+    db: &Mutex<Database>,
+    engine: &Mutex<WorkflowEngine>,
+) -> DiagnosticCheck {
+    const NAME: &str = "stuck_executions";
+    let active: HashSet<String> = match engine.try_lock_for(LOCK_TIMEOUT) {
+        Some(engine) => engine
+            .list_active_executions()
+            .into_iter()
+            .map(|execution| execution.execution_id)
+            .collect(),
+        None => return DiagnosticCheck::busy(NAME, "the engine"),
+    };
+    let running = match db.try_lock_for(LOCK_TIMEOUT) {
+        Some(db) => db.running_execution_ids(),
+        None => return DiagnosticCheck::busy(NAME, "the database"),
+    };
+
+    match running {
+        Ok(running) => {
+            let stuck = running.iter().filter(|id| !active.contains(*id)).count();
+            if stuck == 0 {
+                DiagnosticCheck::ok(NAME, format!("{} executions running", active.len()))
+            } else {
+                DiagnosticCheck::warning(
+                    NAME,
+                    format!("{} executions are marked running but are not active", stuck),
+                    "These runs were interrupted without being closed out; delete them from the execution history.",
+                )
+            }
+        }
+        Err(e) => DiagnosticCheck::failed(
+            NAME,
+            format!("executions cannot be listed: {}", e),
+            "Restore the database from a backup.",
+        ),
+    }
+}
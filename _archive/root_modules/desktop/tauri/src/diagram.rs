@@ -0,0 +1,191 @@
+/*!
+ * Diagram - renders a workflow as a Mermaid flowchart or Graphviz digraph
+ *
+ * Nodes are labeled with their editor label (if any) and type, in the
+ * workflow's node order. Edges leaving a named source handle are branches of
+ * a conditional node and are drawn dashed, labeled with the handle names.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::{Workflow, WorkflowEdge, WorkflowNode};
+
+fn node_label(node: &WorkflowNode) -> String {
+    match node.data.get("label").and_then(|label| label.as_str()) {
+        Some(label) if !label.trim().is_empty() => {
+            format!("{}\n({})", label.trim(), node.node_type)
+        }
+        _ => node.node_type.clone(),
+    }
+}
+
+// Handle names joined as "source → target", if the edge has either.
+fn edge_label(edge: &WorkflowEdge) -> Option<String> {
+    match (&edge.source_handle, &edge.target_handle) {
+        (None, None) => None,
+        (Some(source), None) => Some(source.clone()),
+        (None, Some(target)) => Some(format!("→ {}", target)),
+        (Some(source), Some(target)) => Some(format!("{} → {}", source, target)),
+    }
+}
+
+fn is_branch(edge: &WorkflowEdge) -> bool {
+    edge.source_handle.is_some()
+}
+
+// Mermaid ids are positional (`n0`, `n1`, ...) since node ids may contain
+// characters Mermaid does not accept. In both formats, edges to unknown
+// nodes are left out.
+pub fn mermaid(workflow: &Workflow) -> String {
+    let ids: HashMap<&str, String> = workflow
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (node.id.as_str(), format!("n{}", index)))
+        .collect();
+
+    let mut out = String::from("flowchart LR\n");
+    for node in &workflow.nodes {
+        let _ = writeln!(
+            out,
+            "    {}[\"{}\"]",
+            ids[node.id.as_str()],
+            mermaid_text(&node_label(node))
+        );
+    }
+    for edge in &workflow.edges {
+        let (Some(source), Some(target)) =
+            (ids.get(edge.source.as_str()), ids.get(edge.target.as_str()))
+        else {
+            continue;
+        };
+        let arrow = match (edge_label(edge), is_branch(edge)) {
+            (Some(label), true) => format!("-. \"{}\" .->", mermaid_text(&label)),
+            (Some(label), false) => format!("-- \"{}\" -->", mermaid_text(&label)),
+            (None, _) => "-->".to_string(),
+        };
+        let _ = writeln!(out, "    {} {} {}", source, arrow, target);
+    }
+    out
+}
+
+fn mermaid_text(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+pub fn dot(workflow: &Workflow) -> String {
+    let mut out = format!("digraph {} {{\n", dot_string(&workflow.name));
+    out.push_str("    rankdir=LR;\n");
+    out.push_str("    node [shape=box];\n");
+    for node in &workflow.nodes {
+        let _ = writeln!(
+            out,
+            "    {} [label={}];",
+            dot_string(&node.id),
+            dot_string(&node_label(node))
+        );
+    }
+    let known: HashSet<&str> = workflow.nodes.iter().map(|node| node.id.as_str()).collect();
+    for edge in workflow
+        .edges
+        .iter()
+        .filter(|edge| known.contains(edge.source.as_str()) && known.contains(edge.target.as_str()))
+    {
+        let mut attributes = Vec::new();
+        if let Some(label) = edge_label(edge) {
+            attributes.push(format!("label={}", dot_string(&label)));
+        }
+        if is_branch(edge) {
+            attributes.push("style=dashed".to_string());
+        }
+        let attributes = if attributes.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", attributes.join(", "))
+        };
+        let _ = writeln!(
+            out,
+            "    {} -> {}{};",
+            dot_string(&edge.source),
+            dot_string(&edge.target),
+            attributes
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn dot_string(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{edge, node, workflow};
+    use serde_json::json;
+
+    // A condition whose true branch is labeled and whose false branch feeds a
+    // named input, plus an edge to a node that does not exist.
+    fn branching() -> Workflow {
+        let mut branching = workflow(
+            "branching",
+            vec![
+                node("t", "trigger", json!({})),
+                node("check", "condition", json!({ "label": "Has \"items\"?" })),
+                node("fetch", "http", json!({ "label": "  " })),
+                node("skip", "delay", json!({})),
+            ],
+            vec![
+                edge("t", "check"),
+                WorkflowEdge {
+                    source_handle: Some("true".to_string()),
+                    ..edge("check", "fetch")
+                },
+                WorkflowEdge {
+                    source_handle: Some("false".to_string()),
+                    target_handle: Some("value".to_string()),
+                    ..edge("check", "skip")
+                },
+                edge("skip", "ghost"),
+            ],
+        );
+        branching.name = "Branching".to_string();
+        branching
+    }
+
+    #[test]
+    fn mermaid_output_is_stable() {
+        let expected = "flowchart LR\n    \
+            n0[\"trigger\"]\n    \
+            n1[\"Has #quot;items#quot;?<br/>(condition)\"]\n    \
+            n2[\"http\"]\n    \
+            n3[\"delay\"]\n    \
+            n0 --> n1\n    \
+            n1 -. \"true\" .-> n2\n    \
+            n1 -. \"false → value\" .-> n3\n";
+        assert_eq!(mermaid(&branching()), expected);
+    }
+
+    #[test]
+    fn dot_output_is_stable() {
+        let expected = "digraph \"Branching\" {\n    \
+            rankdir=LR;\n    \
+            node [shape=box];\n    \
+            \"t\" [label=\"trigger\"];\n    \
+            \"check\" [label=\"Has \\\"items\\\"?\\n(condition)\"];\n    \
+            \"fetch\" [label=\"http\"];\n    \
+            \"skip\" [label=\"delay\"];\n    \
+            \"t\" -> \"check\";\n    \
+            \"check\" -> \"fetch\" [label=\"true\", style=dashed];\n    \
+            \"check\" -> \"skip\" [label=\"false → value\", style=dashed];\n\
+            }\n";
+        assert_eq!(dot(&branching()), expected);
+    }
+}
@@ -0,0 +1,188 @@
+/*!
+ * Diff - structural differences between two workflows
+ *
+ * Nodes are matched by id. A node in both workflows is modified when its
+ * type, position or `data` differ, and its `data` changes are listed field by
+ * field. Edges are matched by what they connect (source, target and handles),
+ * not by id, so an edge recreated between the same ports is unchanged.
+ * Everything is sorted, so diffing the same pair always gives the same output.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Workflow, WorkflowEdge, WorkflowNode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+// One changed value inside a node's `data`, at a path like `headers.accept`
+// or `mappings[2].target`; the empty path is `data` itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeChange {
+    pub node_id: String,
+    // Set when the type changed
+    pub previous_node_type: Option<String>,
+    pub node_type: String,
+    pub moved: bool,
+    pub data: Vec<FieldChange>,
+}
+
+// Changes that turn `before` into `after`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkflowDiff {
+    pub added_nodes: Vec<WorkflowNode>,
+    pub removed_nodes: Vec<WorkflowNode>,
+    pub modified_nodes: Vec<NodeChange>,
+    pub added_edges: Vec<WorkflowEdge>,
+    pub removed_edges: Vec<WorkflowEdge>,
+}
+
+pub fn diff_workflows(before: &Workflow, after: &Workflow) -> WorkflowDiff {
+    let old: BTreeMap<&str, &WorkflowNode> =
+        before.nodes.iter().map(|node| (node.id.as_str(), node)).collect();
+    let new: BTreeMap<&str, &WorkflowNode> =
+        after.nodes.iter().map(|node| (node.id.as_str(), node)).collect();
+
+    let mut diff = WorkflowDiff::default();
+    for (id, node) in &new {
+        match old.get(id) {
+            None => diff.added_nodes.push((*node).clone()),
+            Some(previous) => {
+                if let Some(change) = diff_node(previous, node) {
+                    diff.modified_nodes.push(change);
+                }
+            }
+        }
+    }
+    diff.removed_nodes = old
+        .iter()
+        .filter(|(id, _)| !new.contains_key(*id))
+        .map(|(_, node)| (*node).clone())
+        .collect();
+
+    let old_edges = edges_by_connection(&before.edges);
+    let new_edges = edges_by_connection(&after.edges);
+    diff.added_edges = new_edges
+        .iter()
+        .filter(|(key, _)| !old_edges.contains_key(*key))
+        .map(|(_, edge)| (*edge).clone())
+        .collect();
+    diff.removed_edges = old_edges
+        .iter()
+        .filter(|(key, _)| !new_edges.contains_key(*key))
+        .map(|(_, edge)| (*edge).clone())
+        .collect();
+    diff
+}
+
+fn diff_node(before: &WorkflowNode, after: &WorkflowNode) -> Option<NodeChange> {
+    let mut data = Vec::new();
+    diff_values(String::new(), &before.data, &after.data, &mut data);
+    let retyped = before.node_type != after.node_type;
+    let moved = before.position.x != after.position.x || before.position.y != after.position.y;
+    if data.is_empty() && !retyped && !moved {
+        return None;
+    }
+    Some(NodeChange {
+        node_id: after.id.clone(),
+        previous_node_type: retyped.then(|| before.node_type.clone()),
+        node_type: after.node_type.clone(),
+        moved,
+        data,
+    })
+}
+
+type EdgeKey<'a> = (&'a str, &'a str, Option<&'a str>, Option<&'a str>);
+
+// Duplicate connections collapse into one, keyed by the lowest edge id.
+fn edges_by_connection(edges: &[WorkflowEdge]) -> BTreeMap<EdgeKey<'_>, &WorkflowEdge> {
+    let mut by_connection: BTreeMap<EdgeKey<'_>, &WorkflowEdge> = BTreeMap::new();
+    for edge in edges {
+        let key = (
+            edge.source.as_str(),
+            edge.target.as_str(),
+            edge.source_handle.as_deref(),
+            edge.target_handle.as_deref(),
+        );
+        by_connection
+            .entry(key)
+            .and_modify(|kept| {
+                if edge.id < kept.id {
+                    *kept = edge;
+                }
+            })
+            .or_insert(edge);
+    }
+    by_connection
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// Recurses into objects and arrays (by index); anything else that differs is
+// reported whole.
+fn diff_values(path: String, before: &Value, after: &Value, changes: &mut Vec<FieldChange>) {
+    match (before, after) {
+        (Value::Object(old), Value::Object(new)) => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                diff_entry(join(&path, key), old.get(key), new.get(key), changes);
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                let path = format!("{}[{}]", path, index);
+                diff_entry(path, old.get(index), new.get(index), changes);
+            }
+        }
+        _ if before != after => changes.push(FieldChange {
+            path,
+            kind: ChangeKind::Changed,
+            before: Some(before.clone()),
+            after: Some(after.clone()),
+        }),
+        _ => {}
+    }
+}
+
+// A key or index present on at least one side.
+fn diff_entry(
+    path: String,
+    before: Option<&Value>,
+    after: Option<&Value>,
+    changes: &mut Vec<FieldChange>,
+) {
+    match (before, after) {
+        (Some(before), Some(after)) => diff_values(path, before, after, changes),
+        _ => changes.push(FieldChange {
+            path,
+            kind: if before.is_none() {
+                ChangeKind::Added
+            } else {
+                ChangeKind::Removed
+            },
+            before: before.cloned(),
+            after: after.cloned(),
+        }),
+    }
+}
@@ -0,0 +1,311 @@
+/*!
+ * Encryption - passphrase-based AES-256-GCM with an Argon2id-derived key
+ */
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+
+pub const ALGORITHM: &str = "AES-256-GCM";
+pub const KDF: &str = "Argon2id";
+
+const KDF_PARAMS_KEY: &str = "encryption.kdf_params";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+const KEY_CHECK_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+
+const HEALTH_SENTINEL: &str = "workflow-encryption-health-check";
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("invalid encryption config: {0}")]
+    InvalidConfig(String),
+    #[error("key derivation failed: {0}")]
+    Kdf(String),
+    #[error("encryption failed")]
+    Encrypt,
+    #[error("wrong key")]
+    WrongKey,
+    #[error("authentication failed: ciphertext was modified")]
+    AuthenticationFailed,
+    #[error("malformed ciphertext: {0}")]
+    Format(String),
+}
+
+pub type Result<T> = std::result::Result<T, EncryptionError>;
+
+// Argon2id cost parameters used to derive the cipher key from a passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    fn argon2(&self) -> Result<Argon2<'static>> {
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN + KEY_CHECK_LEN),
+        )
+        .map_err(|e| EncryptionError::InvalidConfig(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    // Stored params, falling back to the defaults when none were ever saved.
+    pub fn load(db: &Database) -> Result<Self> {
+        match db.get_meta(KDF_PARAMS_KEY)? {
+            Some(raw) => {
+                let params: Self = serde_json::from_str(&raw)
+                    .map_err(|e| EncryptionError::InvalidConfig(e.to_string()))?;
+                params.argon2()?;
+                Ok(params)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        self.argon2()?;
+        let raw = serde_json::to_string(self)
+            .map_err(|e| EncryptionError::InvalidConfig(e.to_string()))?;
+        db.set_meta(KDF_PARAMS_KEY, &raw)?;
+        Ok(())
+    }
+}
+
+// Derives the cipher key plus a key check value. The check value lets decrypt
+// tell a wrong passphrase apart from a ciphertext that was tampered with.
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: &KdfParams,
+) -> Result<([u8; KEY_LEN], [u8; KEY_CHECK_LEN])> {
+    let mut derived = [0u8; KEY_LEN + KEY_CHECK_LEN];
+    params
+        .argon2()?
+        .hash_password_into(passphrase.as_bytes(), salt, &mut derived)
+        .map_err(|e| EncryptionError::Kdf(e.to_string()))?;
+
+    let mut key = [0u8; KEY_LEN];
+    let mut check = [0u8; KEY_CHECK_LEN];
+    key.copy_from_slice(&derived[..KEY_LEN]);
+    check.copy_from_slice(&derived[KEY_LEN..]);
+    Ok((key, check))
+}
+
+pub fn encrypt(data: &str, key: &str) -> Result<String> {
+    encrypt_with(data, key, &KdfParams::default())
+}
+
+pub fn decrypt(data: &str, key: &str) -> Result<String> {
+    decrypt_with(data, key, &KdfParams::default())
+}
+
+pub fn encrypt_with(data: &str, key: &str, params: &KdfParams) -> Result<String> {
+    encrypt_bytes_with(data.as_bytes(), key, params)
+}
+
+pub fn decrypt_with(data: &str, key: &str, params: &KdfParams) -> Result<String> {
+    String::from_utf8(decrypt_bytes_with(data, key, params)?)
+        .map_err(|e| EncryptionError::Format(e.to_string()))
+}
+
+// Output is base64 of `salt || nonce || key_check || ciphertext || tag`, with a
+// fresh random salt and nonce per call. The key check is authenticated as AAD.
+pub fn encrypt_bytes_with(data: &[u8], key: &str, params: &KdfParams) -> Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let (cipher_key, check) = derive_key(key, &salt, params)?;
+    let cipher = Aes256Gcm::new_from_slice(&cipher_key).map_err(|_| EncryptionError::Encrypt)?;
+    let payload = Payload {
+        msg: data,
+        aad: &check,
+    };
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), payload)
+        .map_err(|_| EncryptionError::Encrypt)?;
+
+    let mut out = Vec::with_capacity(SALT_LEN + NONCE_LEN + KEY_CHECK_LEN + sealed.len());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&check);
+    out.extend_from_slice(&sealed);
+    Ok(BASE64.encode(out))
+}
+
+pub fn decrypt_bytes_with(data: &str, key: &str, params: &KdfParams) -> Result<Vec<u8>> {
+    let raw = BASE64
+        .decode(data.trim())
+        .map_err(|e| EncryptionError::Format(e.to_string()))?;
+    if raw.len() < SALT_LEN + NONCE_LEN + KEY_CHECK_LEN + TAG_LEN {
+        return Err(EncryptionError::Format("ciphertext is truncated".to_string()));
+    }
+    let (salt, rest) = raw.split_at(SALT_LEN);
+    let (nonce, rest) = rest.split_at(NONCE_LEN);
+    let (stored_check, sealed) = rest.split_at(KEY_CHECK_LEN);
+
+    let (cipher_key, check) = derive_key(key, salt, params)?;
+    if stored_check != check {
+        return Err(EncryptionError::WrongKey);
+    }
+
+    let cipher = Aes256Gcm::new_from_slice(&cipher_key)
+        .map_err(|_| EncryptionError::AuthenticationFailed)?;
+    let payload = Payload {
+        msg: sealed,
+        aad: stored_check,
+    };
+    cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| EncryptionError::AuthenticationFailed)
+}
+
+pub fn reencrypt(data: &str, old_key: &str, new_key: &str) -> Result<String> {
+    reencrypt_with(data, old_key, new_key, &KdfParams::default())
+}
+
+pub fn reencrypt_with(data: &str, old_key: &str, new_key: &str, params: &KdfParams) -> Result<String> {
+    encrypt_with(&decrypt_with(data, old_key, params)?, new_key, params)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionHealth {
+    pub algorithm: String,
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    pub round_trip_ms: u64,
+}
+
+// Encrypts a sentinel and decrypts it again with the configured key and KDF.
+pub fn check_health(key: &str, params: &KdfParams) -> Result<EncryptionHealth> {
+    let started = Instant::now();
+    let ciphertext = encrypt_with(HEALTH_SENTINEL, key, params)?;
+    if decrypt_with(&ciphertext, key, params)? != HEALTH_SENTINEL {
+        return Err(EncryptionError::AuthenticationFailed);
+    }
+
+    Ok(EncryptionHealth {
+        algorithm: ALGORITHM.to_string(),
+        kdf: KDF.to_string(),
+        kdf_params: *params,
+        round_trip_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_db;
+
+    // Keeps the tests fast; real params come from `KdfParams::load`.
+    const CHEAP: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn configured_health(db: &Database) -> Result<EncryptionHealth> {
+        KdfParams::load(db).and_then(|params| check_health("machine-key", &params))
+    }
+
+    #[test]
+    fn health_check_passes_with_a_valid_config() {
+        let db = temp_db();
+        CHEAP.save(&db).unwrap();
+
+        let health = configured_health(&db).unwrap();
+        assert_eq!(health.algorithm, ALGORITHM);
+        assert_eq!(health.kdf, KDF);
+        assert_eq!(health.kdf_params, CHEAP);
+    }
+
+    #[test]
+    fn health_check_fails_with_a_corrupted_config() {
+        let db = temp_db();
+        db.set_meta(KDF_PARAMS_KEY, "{\"memory_kib\": 64").unwrap();
+        assert!(matches!(configured_health(&db), Err(EncryptionError::InvalidConfig(_))));
+
+        // Parseable, but no Argon2 instance accepts zero iterations.
+        db.set_meta(
+            KDF_PARAMS_KEY,
+            r#"{"memory_kib": 64, "iterations": 0, "parallelism": 1}"#,
+        )
+        .unwrap();
+        assert!(matches!(configured_health(&db), Err(EncryptionError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn round_trip_uses_a_fresh_salt_and_nonce() {
+        let first = encrypt("top secret", "passphrase").unwrap();
+        let second = encrypt("top secret", "passphrase").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(decrypt(&first, "passphrase").unwrap(), "top secret");
+        assert_eq!(decrypt(&second, "passphrase").unwrap(), "top secret");
+
+        let empty = encrypt_with("", "passphrase", &CHEAP).unwrap();
+        assert_eq!(decrypt_with(&empty, "passphrase", &CHEAP).unwrap(), "");
+    }
+
+    #[test]
+    fn tampering_is_told_apart_from_a_wrong_key() {
+        let sealed = encrypt_with("top secret", "passphrase", &CHEAP).unwrap();
+        assert!(matches!(
+            decrypt_with(&sealed, "other passphrase", &CHEAP),
+            Err(EncryptionError::WrongKey)
+        ));
+
+        // Flip one bit in the ciphertext, past the salt, nonce and key check.
+        let mut raw = BASE64.decode(&sealed).unwrap();
+        raw[SALT_LEN + NONCE_LEN + KEY_CHECK_LEN] ^= 1;
+        assert!(matches!(
+            decrypt_with(&BASE64.encode(&raw), "passphrase", &CHEAP),
+            Err(EncryptionError::AuthenticationFailed)
+        ));
+
+        // And in the tag at the end.
+        let mut raw = BASE64.decode(&sealed).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 1;
+        assert!(matches!(
+            decrypt_with(&BASE64.encode(&raw), "passphrase", &CHEAP),
+            Err(EncryptionError::AuthenticationFailed)
+        ));
+
+        assert!(matches!(
+            decrypt_with("not base64!", "passphrase", &CHEAP),
+            Err(EncryptionError::Format(_))
+        ));
+        assert!(matches!(
+            decrypt_with(&BASE64.encode([0u8; 8]), "passphrase", &CHEAP),
+            Err(EncryptionError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+/*!
+ * Environments - named variable sets that node templates reference as `{{ env.NAME }}`
+ *
+ * The same workflow runs against dev, staging or prod by switching the active
+ * environment rather than editing it. A run takes the active environment's
+ * variables when it starts. Secrets layer on top: a secret visible to the
+ * workflow wins over a variable of the same name, so `env.API_KEY` can hold a
+ * workflow secret's value. To keep that from silently hiding a variable, a
+ * variable may not share its name with a global secret.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+use crate::secrets::{self, SecretError, SecretScope, SecretValues};
+use crate::template;
+use crate::Workflow;
+
+// Template root environment variables are addressed under.
+pub const SCOPE_ROOT: &str = "env";
+
+const ACTIVE_ENVIRONMENT_KEY: &str = "environments.active";
+const MAX_NAME_LEN: usize = 64;
+const MAX_KEY_LEN: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum EnvironmentError {
+    #[error("invalid environment name {0:?}: use letters, digits, '-' and '_'")]
+    InvalidName(String),
+    #[error("invalid variable name {0:?}: use letters, digits and '_', not starting with a digit")]
+    InvalidKey(String),
+    #[error("environment {0} already exists")]
+    Exists(String),
+    #[error("environment {0} not found")]
+    NotFound(String),
+    #[error(
+        "{0} is ambiguous: a global secret has the same name, and secrets take precedence over \
+         environment variables in `env.{0}`; rename the variable or delete the secret"
+    )]
+    AmbiguousKey(String),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+}
+
+pub type Result<T> = std::result::Result<T, EnvironmentError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Environment {
+    pub name: String,
+    pub active: bool,
+    pub variables: BTreeMap<String, String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(EnvironmentError::InvalidName(name.to_string()))
+    }
+}
+
+// Keys must be usable as a template path segment.
+fn validate_key(key: &str) -> Result<()> {
+    let valid = key.len() <= MAX_KEY_LEN
+        && key
+            .chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(EnvironmentError::InvalidKey(key.to_string()))
+    }
+}
+
+fn ensure_exists(db: &Database, name: &str) -> Result<()> {
+    if db.environment_exists(name)? {
+        Ok(())
+    } else {
+        Err(EnvironmentError::NotFound(name.to_string()))
+    }
+}
+
+pub fn create_environment(db: &Database, name: &str) -> Result<()> {
+    validate_name(name)?;
+    if !db.create_environment(name)? {
+        return Err(EnvironmentError::Exists(name.to_string()));
+    }
+    Ok(())
+}
+
+pub fn list_environments(db: &Database) -> Result<Vec<Environment>> {
+    let active = active_environment(db)?;
+    let mut environments = db.get_environments()?;
+    for environment in &mut environments {
+        environment.active = active.as_deref() == Some(environment.name.as_str());
+    }
+    Ok(environments)
+}
+
+pub fn active_environment(db: &Database) -> Result<Option<String>> {
+    Ok(db.get_meta(ACTIVE_ENVIRONMENT_KEY)?)
+}
+
+// None deactivates environments; `env.*` then resolves from secrets only.
+pub fn set_active_environment(db: &Database, name: Option<&str>) -> Result<()> {
+    match name {
+        Some(name) => {
+            ensure_exists(db, name)?;
+            db.set_meta(ACTIVE_ENVIRONMENT_KEY, name)?;
+        }
+        None => db.delete_meta(ACTIVE_ENVIRONMENT_KEY)?,
+    }
+    Ok(())
+}
+
+// A None value removes the variable.
+pub fn set_environment_var(
+    db: &Database,
+    environment: &str,
+    key: &str,
+    value: Option<&str>,
+) -> Result<()> {
+    ensure_exists(db, environment)?;
+    match value {
+        Some(value) => {
+            validate_key(key)?;
+            if secrets::secret_names(db, &SecretScope::Global)?.iter().any(|name| name == key) {
+                return Err(EnvironmentError::AmbiguousKey(key.to_string()));
+            }
+            db.set_environment_var(environment, key, value)?;
+        }
+        None => {
+            db.delete_environment_var(environment, key)?;
+        }
+    }
+    Ok(())
+}
+
+// Variables of the active environment, empty when none is active.
+pub fn active_variables(db: &Database) -> Result<BTreeMap<String, String>> {
+    match active_environment(db)? {
+        Some(name) => Ok(db.get_environment_variables(&name)?),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+// Names the workflow's node templates reference under `env`, so secrets of
+// the same name can be decrypted for the run.
+pub fn referenced_names(workflow: &Workflow) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for node in &workflow.nodes {
+        for reference in template::value_references(&node.data) {
+            let mut segments = reference.split('.');
+            if segments.next() == Some(SCOPE_ROOT) {
+                if let Some(name) = segments.next() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+// The `env` object templates resolve against: the variables, overlaid with
+// the run's secrets.
+pub fn scope(variables: &BTreeMap<String, String>, secrets: &SecretValues) -> Value {
+    let mut scope: serde_json::Map<String, Value> = variables
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
+    if let Value::Object(secrets) = secrets.scope() {
+        scope.extend(secrets);
+    }
+    Value::Object(scope)
+}
@@ -0,0 +1,131 @@
+/*!
+ * Estimate - how long a run of a workflow should take, from past node timings
+ *
+ * Every node is estimated at the average duration of its node type over all
+ * past runs (cache hits and dry runs are not counted); delay nodes at their
+ * configured wait, and disabled nodes at nothing. A node whose type has never
+ * run gets `DEFAULT_NODE_ESTIMATE_MS` and is flagged uncertain. Independent
+ * branches run side by side, so the projected duration is the critical path:
+ * the longest chain of estimates through the graph. Every node is assumed to
+ * run, including ones a condition might skip.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::workflow_engine::{self, Result};
+use crate::Workflow;
+
+pub const DEFAULT_NODE_ESTIMATE_MS: u64 = 1_000;
+
+// Durations recorded for one node type across every workflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeTypeStats {
+    pub runs: u64,
+    pub average_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeEstimate {
+    pub node_id: String,
+    pub node_type: String,
+    pub estimate_ms: u64,
+    // Past runs of the node type the estimate is based on
+    pub runs: u64,
+    pub uncertain: bool,
+    // Offsets from the start of the run, assuming every estimate holds
+    pub start_ms: u64,
+    pub finish_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionEstimate {
+    pub workflow_id: String,
+    // Projected duration of the run: the length of the critical path
+    pub duration_ms: u64,
+    // Sum over every node, as if none ran in parallel
+    pub total_work_ms: u64,
+    pub critical_path: Vec<String>,
+    // In execution order
+    pub nodes: Vec<NodeEstimate>,
+    // At least one node had no history to go on
+    pub uncertain: bool,
+}
+
+pub fn estimate(
+    workflow: &Workflow,
+    stats: &HashMap<String, NodeTypeStats>,
+) -> Result<ExecutionEstimate> {
+    let order = workflow_engine::topological_order(workflow)?;
+    let mut nodes: Vec<NodeEstimate> = Vec::with_capacity(order.len());
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    // Predecessor each node waits on longest, for walking the critical path back
+    let mut waits_on: HashMap<&str, &str> = HashMap::new();
+
+    for id in &order {
+        let Some(node) = workflow.nodes.iter().find(|node| &node.id == id) else {
+            continue;
+        };
+        let configured = (node.node_type == "delay")
+            .then(|| node.data.get("duration_ms").and_then(Value::as_u64))
+            .flatten();
+        let history = stats.get(&node.node_type).filter(|stats| stats.runs > 0);
+        let (estimate_ms, runs, uncertain) = match (configured, history) {
+            _ if workflow_engine::is_disabled(node) => (0, 0, false),
+            (Some(duration_ms), _) => (duration_ms, history.map_or(0, |stats| stats.runs), false),
+            (None, Some(stats)) => (stats.average_ms, stats.runs, false),
+            (None, None) => (DEFAULT_NODE_ESTIMATE_MS, 0, true),
+        };
+
+        let mut start_ms = 0;
+        let mut waits_for = None;
+        for edge in workflow.edges.iter().filter(|edge| &edge.target == id) {
+            let Some(&source) = index.get(edge.source.as_str()) else {
+                continue;
+            };
+            if waits_for.is_none() || nodes[source].finish_ms > start_ms {
+                start_ms = nodes[source].finish_ms;
+                waits_for = Some(edge.source.as_str());
+            }
+        }
+        if let Some(source) = waits_for {
+            waits_on.insert(id.as_str(), source);
+        }
+
+        index.insert(id.as_str(), nodes.len());
+        nodes.push(NodeEstimate {
+            node_id: node.id.clone(),
+            node_type: node.node_type.clone(),
+            estimate_ms,
+            runs,
+            uncertain,
+            start_ms,
+            finish_ms: start_ms + estimate_ms,
+        });
+    }
+
+    // Ends at the node finishing last, the first one in execution order on a tie.
+    let mut critical_path = Vec::new();
+    let last = nodes
+        .iter()
+        .rev()
+        .max_by_key(|node| node.finish_ms)
+        .map(|node| node.node_id.as_str());
+    let mut current = last;
+    while let Some(id) = current {
+        critical_path.push(id.to_string());
+        current = waits_on.get(id).copied();
+    }
+    critical_path.reverse();
+
+    Ok(ExecutionEstimate {
+        workflow_id: workflow.id.clone(),
+        duration_ms: nodes.iter().map(|node| node.finish_ms).max().unwrap_or(0),
+        total_work_ms: nodes.iter().map(|node| node.estimate_ms).sum(),
+        critical_path,
+        uncertain: nodes.iter().any(|node| node.uncertain),
+        nodes,
+    })
+}
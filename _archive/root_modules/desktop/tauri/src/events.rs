@@ -0,0 +1,337 @@
+/*!
+ * Events - execution progress events broadcast to the frontend
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::debugger::ContextSnapshot;
+use crate::schema::FieldError;
+use crate::ExecutionStatus;
+
+const EVENT_CAPACITY: usize = 1024;
+
+// Events kept per execution for replay. Past this, progress events and
+// output chunks are no longer retained but lifecycle events still are.
+const REPLAY_CAPACITY: usize = 10_000;
+// How long a finished execution's events stay available for replay.
+const REPLAY_GRACE: Duration = Duration::from_secs(300);
+
+// How far a run has got, by nodes. Workflows are acyclic and each node runs
+// at most once, so `total` is fixed when the run starts: every node, or the
+// nodes in a partial run's scope. A disabled node counts once passed over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionProgress {
+    pub completed: usize,
+    pub total: usize,
+    // 0 to 100, rounded down; 100 for a run without nodes
+    pub percent: u8,
+}
+
+impl ExecutionProgress {
+    pub fn new(completed: usize, total: usize) -> Self {
+        let percent = match total {
+            0 => 100,
+            total => (completed.min(total) * 100 / total) as u8,
+        };
+        Self {
+            completed,
+            total,
+            percent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionEvent {
+    ExecutionStarted {
+        execution_id: String,
+        workflow_id: String,
+    },
+    NodeStarted {
+        execution_id: String,
+        node_id: String,
+    },
+    NodeProgress {
+        execution_id: String,
+        node_id: String,
+        progress: serde_json::Value,
+    },
+    // A piece of a streaming node's output, sent as it is produced. The
+    // node's final output still arrives with its completion.
+    NodeOutputChunk {
+        execution_id: String,
+        node_id: String,
+        chunk: serde_json::Value,
+    },
+    NodeCompleted {
+        execution_id: String,
+        node_id: String,
+        duration_ms: u64,
+    },
+    NodeFailed {
+        execution_id: String,
+        node_id: String,
+        error: String,
+    },
+    // The node is disabled; its input was passed on as its output.
+    NodeSkipped {
+        execution_id: String,
+        node_id: String,
+    },
+    // Sent when the run starts and after each node completes or is skipped.
+    ExecutionProgress {
+        execution_id: String,
+        progress: ExecutionProgress,
+    },
+    ExecutionPaused {
+        execution_id: String,
+    },
+    ExecutionResumed {
+        execution_id: String,
+    },
+    // The run is holding before `node_id` because free memory is below the
+    // configured floor; it continues once memory recovers.
+    ResourceThrottled {
+        execution_id: String,
+        node_id: String,
+        available_memory_mb: u64,
+        min_free_memory_mb: u64,
+    },
+    // A manual input node is waiting for values matching `schema`; `errors`
+    // is set when re-prompting after rejected values.
+    InputRequested {
+        execution_id: String,
+        node_id: String,
+        schema: serde_json::Value,
+        prompt: Option<String>,
+        errors: Vec<FieldError>,
+    },
+    // A debug run is holding before `context.node_id` until stepped or
+    // continued.
+    BreakpointHit {
+        execution_id: String,
+        context: ContextSnapshot,
+    },
+    ExecutionFinished {
+        execution_id: String,
+        workflow_id: String,
+        status: ExecutionStatus,
+        error: Option<String>,
+    },
+    // A desktop notification rule of the workflow fired for this run.
+    Notification {
+        execution_id: String,
+        workflow_id: String,
+        title: String,
+        message: String,
+    },
+    // Node `node_id` of `parent_execution_id` started running `workflow_id`.
+    // The nested run's own events carry `execution_id`, which is
+    // `<parent_execution_id>/<node_id>`.
+    SubWorkflowStarted {
+        execution_id: String,
+        parent_execution_id: String,
+        node_id: String,
+        workflow_id: String,
+    },
+    SubWorkflowFinished {
+        execution_id: String,
+        parent_execution_id: String,
+        node_id: String,
+        workflow_id: String,
+        status: ExecutionStatus,
+        error: Option<String>,
+    },
+    // A deadline schedule started this run, but its estimate says it will
+    // finish after `finish_by`; see `deadlines`.
+    DeadlineAtRisk {
+        execution_id: String,
+        workflow_id: String,
+        schedule_id: String,
+        finish_by: chrono::DateTime<chrono::Utc>,
+        projected_finish: chrono::DateTime<chrono::Utc>,
+    },
+}
+
+impl ExecutionEvent {
+    pub fn execution_id(&self) -> &str {
+        match self {
+            ExecutionEvent::ExecutionStarted { execution_id, .. }
+            | ExecutionEvent::NodeStarted { execution_id, .. }
+            | ExecutionEvent::NodeProgress { execution_id, .. }
+            | ExecutionEvent::NodeOutputChunk { execution_id, .. }
+            | ExecutionEvent::NodeCompleted { execution_id, .. }
+            | ExecutionEvent::NodeFailed { execution_id, .. }
+            | ExecutionEvent::NodeSkipped { execution_id, .. }
+            | ExecutionEvent::ExecutionProgress { execution_id, .. }
+            | ExecutionEvent::ExecutionPaused { execution_id }
+            | ExecutionEvent::ExecutionResumed { execution_id }
+            | ExecutionEvent::ResourceThrottled { execution_id, .. }
+            | ExecutionEvent::InputRequested { execution_id, .. }
+            | ExecutionEvent::BreakpointHit { execution_id, .. }
+            | ExecutionEvent::ExecutionFinished { execution_id, .. }
+            | ExecutionEvent::Notification { execution_id, .. }
+            | ExecutionEvent::SubWorkflowStarted { execution_id, .. }
+            | ExecutionEvent::SubWorkflowFinished { execution_id, .. }
+            | ExecutionEvent::DeadlineAtRisk { execution_id, .. } => execution_id,
+        }
+    }
+}
+
+// The top-level run a nested run's execution id belongs to.
+pub fn root_execution_id(execution_id: &str) -> &str {
+    execution_id
+        .split_once('/')
+        .map_or(execution_id, |(root, _)| root)
+}
+
+#[derive(Default)]
+struct ReplayBuffer {
+    events: Vec<ExecutionEvent>,
+    finished_at: Option<Instant>,
+}
+
+impl ReplayBuffer {
+    fn expired(&self) -> bool {
+        self.finished_at
+            .is_some_and(|finished_at| finished_at.elapsed() >= REPLAY_GRACE)
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ExecutionEvent>,
+    replay: Arc<Mutex<HashMap<String, ReplayBuffer>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            sender,
+            replay: Arc::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("receivers", &self.sender.receiver_count())
+            .finish()
+    }
+}
+
+impl EventBus {
+    // Emitting with no subscribers is not an error; the event is only kept for
+    // replay, with the top-level run's events when it comes from a nested run.
+    // Sending under the replay lock keeps both in the same order.
+    pub fn emit(&self, event: ExecutionEvent) {
+        let mut replay = self.replay.lock();
+        replay.retain(|_, buffer| !buffer.expired());
+
+        let buffer = replay
+            .entry(root_execution_id(event.execution_id()).to_string())
+            .or_default();
+        let is_progress = matches!(
+            event,
+            ExecutionEvent::NodeProgress { .. }
+                | ExecutionEvent::NodeOutputChunk { .. }
+                | ExecutionEvent::ExecutionProgress { .. }
+        );
+        if !is_progress || buffer.events.len() < REPLAY_CAPACITY {
+            buffer.events.push(event.clone());
+        }
+        if matches!(event, ExecutionEvent::ExecutionFinished { .. }) {
+            buffer.finished_at = Some(Instant::now());
+        }
+
+        let _ = self.sender.send(event);
+    }
+
+    // Events emitted so far for an execution and the runs nested in it, in
+    // emission order.
+    pub fn replay(&self, execution_id: &str) -> Option<Vec<ExecutionEvent>> {
+        self.replay
+            .lock()
+            .get(execution_id)
+            .filter(|buffer| !buffer.expired())
+            .map(|buffer| buffer.events.clone())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ExecutionEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Priority;
+    use crate::test_support::{edge, engine, node, wait_for_runs, workflow};
+    use crate::workflow_engine::find_profile;
+    use serde_json::{json, Value};
+
+    fn as_json(events: &[ExecutionEvent]) -> Vec<Value> {
+        events.iter().map(|e| serde_json::to_value(e).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn replay_matches_the_emitted_event_order() {
+        let (db, engine) = engine();
+        let fan_out = workflow(
+            "fan-out",
+            vec![
+                node("t", "trigger", json!({})),
+                node("a", "delay", json!({ "duration_ms": 20 })),
+                node("b", "delay", json!({ "duration_ms": 10 })),
+            ],
+            vec![edge("t", "a"), edge("t", "b")],
+        );
+        db.lock().create_workflow(&fan_out).unwrap();
+
+        let mut events = engine.subscribe_events();
+        let profile = find_profile("default").unwrap();
+        let execution_id = engine
+            .execute_workflow(&fan_out, &profile, Priority::default(), None)
+            .unwrap();
+        wait_for_runs(&engine, &fan_out.id).await;
+
+        let mut emitted = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if root_execution_id(event.execution_id()) == execution_id {
+                emitted.push(event);
+            }
+        }
+        assert!(matches!(emitted.first(), Some(ExecutionEvent::ExecutionStarted { .. })));
+        assert!(matches!(emitted.last(), Some(ExecutionEvent::ExecutionFinished { .. })));
+
+        let replayed = engine.event_replay(&execution_id).unwrap();
+        assert_eq!(as_json(&replayed), as_json(&emitted));
+    }
+
+    #[test]
+    fn nested_runs_replay_with_their_top_level_run() {
+        let bus = EventBus::default();
+        bus.emit(ExecutionEvent::ExecutionPaused {
+            execution_id: "run-1".to_string(),
+        });
+        bus.emit(ExecutionEvent::ExecutionPaused {
+            execution_id: "run-1/child".to_string(),
+        });
+        bus.emit(ExecutionEvent::ExecutionResumed {
+            execution_id: "run-2".to_string(),
+        });
+
+        let replayed = bus.replay("run-1").unwrap();
+        let ids: Vec<&str> = replayed.iter().map(ExecutionEvent::execution_id).collect();
+        assert_eq!(ids, ["run-1", "run-1/child"]);
+        assert!(bus.replay("run-3").is_none());
+    }
+}
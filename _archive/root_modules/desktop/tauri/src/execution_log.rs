@@ -0,0 +1,288 @@
+/*!
+ * Execution log - per-run log buffer, persistence and export
+ *
+ * The engine records node lifecycle entries (with inputs, outputs and errors)
+ * and executors add their own through `ExecutionContext::log`. The buffer is
+ * capped per run; entries past the cap are counted rather than kept. Logged
+ * values are redacted by key, and the run's secret values wherever they
+ * appear, before they are stored.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+use crate::nodes::http::REDACTED;
+use crate::Execution;
+
+pub const MAX_LOG_ENTRIES: usize = 5_000;
+
+// Object keys whose values never reach the log, matched case-insensitively
+// as substrings so `api_key` and `X-Auth-Token` are caught too.
+const SENSITIVE_KEYS: &[&str] = &[
+    "authorization",
+    "password",
+    "passphrase",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "cookie",
+    "private_key",
+];
+
+#[derive(Debug, Error)]
+pub enum ExecutionLogError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ExecutionLogError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub level: LogLevel,
+    pub node_id: Option<String>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+#[derive(Debug, Default)]
+struct LogBuffer {
+    entries: Vec<LogEntry>,
+    dropped: usize,
+    // Secret values replaced in messages and data
+    masked: Vec<String>,
+    // Entries below it are discarded; None keeps every level
+    min_level: Option<LogLevel>,
+}
+
+// Shared by clones of an ExecutionContext, so entries from every node of a
+// run land in one buffer.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionLog {
+    buffer: Arc<Mutex<LogBuffer>>,
+}
+
+impl ExecutionLog {
+    // Masks these values in entries pushed from now on.
+    pub fn mask<'a>(&self, values: impl IntoIterator<Item = &'a str>) {
+        let mut buffer = self.buffer.lock();
+        buffer.masked.extend(
+            values
+                .into_iter()
+                .filter(|value| !value.is_empty())
+                .map(str::to_string),
+        );
+        // Longest first, so a value containing another is masked whole.
+        buffer.masked.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    }
+
+    // Keeps only entries at `level` or above from now on.
+    pub fn keep_from(&self, level: LogLevel) {
+        self.buffer.lock().min_level = Some(level);
+    }
+
+    pub fn push(
+        &self,
+        level: LogLevel,
+        node_id: Option<&str>,
+        message: impl Into<String>,
+        data: Option<&Value>,
+    ) {
+        let mut buffer = self.buffer.lock();
+        if buffer.min_level.is_some_and(|min_level| level < min_level) {
+            return;
+        }
+        if buffer.entries.len() >= MAX_LOG_ENTRIES {
+            buffer.dropped += 1;
+            return;
+        }
+        let message = mask_text(message.into(), &buffer.masked);
+        let data = data.map(|data| mask_value(redact(data), &buffer.masked));
+        buffer.entries.push(LogEntry {
+            timestamp: chrono::Utc::now(),
+            level,
+            node_id: node_id.map(str::to_string),
+            message,
+            data,
+        });
+    }
+
+    // Adds the entries of a run nested under node `node_id`, keeping their
+    // timestamps and attributing them to `<node_id>/<their node>`.
+    pub fn append_nested(&self, node_id: &str, entries: Vec<LogEntry>) {
+        let mut buffer = self.buffer.lock();
+        for mut entry in entries {
+            if buffer.entries.len() >= MAX_LOG_ENTRIES {
+                buffer.dropped += 1;
+                continue;
+            }
+            entry.node_id = Some(match entry.node_id {
+                Some(inner) => format!("{}/{}", node_id, inner),
+                None => node_id.to_string(),
+            });
+            entry.message = mask_text(entry.message, &buffer.masked);
+            entry.data = entry.data.map(|data| mask_value(data, &buffer.masked));
+            buffer.entries.push(entry);
+        }
+    }
+
+    // Entries in order, ending with a warning if any were dropped.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        let buffer = self.buffer.lock();
+        let mut entries = buffer.entries.clone();
+        if buffer.dropped > 0 {
+            entries.push(LogEntry {
+                timestamp: chrono::Utc::now(),
+                level: LogLevel::Warn,
+                node_id: None,
+                message: format!(
+                    "{} log entries dropped after reaching the limit of {}",
+                    buffer.dropped, MAX_LOG_ENTRIES
+                ),
+                data: None,
+            });
+        }
+        entries
+    }
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS.iter().any(|sensitive| key.contains(sensitive))
+}
+
+// Copy of `value` with the values of sensitive keys replaced, at any depth.
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    if is_sensitive_key(key) {
+                        (key.clone(), Value::String(REDACTED.to_string()))
+                    } else {
+                        (key.clone(), redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+// Copy of `value` with each of `values` replaced wherever it appears in a string.
+pub fn mask_values<'a>(value: Value, values: impl IntoIterator<Item = &'a str>) -> Value {
+    let mut masked: Vec<String> = values
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect();
+    masked.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    mask_value(value, &masked)
+}
+
+fn mask_text(text: String, masked: &[String]) -> String {
+    masked
+        .iter()
+        .fold(text, |text, value| text.replace(value.as_str(), REDACTED))
+}
+
+fn mask_value(value: Value, masked: &[String]) -> Value {
+    if masked.is_empty() {
+        return value;
+    }
+    match value {
+        Value::String(text) => Value::String(mask_text(text, masked)),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| mask_value(item, masked))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, mask_value(value, masked)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionLogExport {
+    pub execution: Execution,
+    pub entries: Vec<LogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogExportSummary {
+    pub path: String,
+    pub execution_id: String,
+    pub format: LogFormat,
+    pub entries: usize,
+}
+
+// Json writes one document `{ execution, entries }`. Ndjson writes the
+// execution record on the first line and one entry per line after it.
+pub fn export(
+    db: &Database,
+    execution_id: &str,
+    path: &Path,
+    format: LogFormat,
+) -> Result<LogExportSummary> {
+    let export = ExecutionLogExport {
+        execution: db.get_execution(execution_id)?,
+        entries: db.get_execution_log(execution_id)?,
+    };
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    match format {
+        LogFormat::Json => serde_json::to_writer_pretty(&mut file, &export)?,
+        LogFormat::Ndjson => {
+            serde_json::to_writer(&mut file, &export.execution)?;
+            writeln!(file)?;
+            for entry in &export.entries {
+                serde_json::to_writer(&mut file, entry)?;
+                writeln!(file)?;
+            }
+        }
+    }
+    file.flush()?;
+
+    Ok(LogExportSummary {
+        path: path.to_string_lossy().to_string(),
+        execution_id: execution_id.to_string(),
+        format,
+        entries: export.entries.len(),
+    })
+}
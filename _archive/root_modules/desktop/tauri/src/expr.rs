@@ -0,0 +1,702 @@
+/*!
+ * Expr - a small, side-effect-free expression language over a JSON scope
+ *
+ * Supports literals (numbers, 'single' or "double" quoted strings, true,
+ * false, null), field access (`input.user.name`, `items[0]`, `row["a b"]`),
+ * arithmetic (+ - * / %, with + also joining strings), comparisons
+ * (== != < <= > >=), boolean logic (&& || !) and the functions `len`,
+ * `contains`, `lower` and `upper`. Expressions cannot call out of the
+ * evaluator, and nesting is capped so hostile input cannot exhaust the stack.
+ *
+ * Values are typed: `1 + "a"` and `!3` are errors rather than coercions. A
+ * missing field evaluates to null, but an unknown top-level name is an error.
+ */
+
+use serde_json::{Map, Number, Value};
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+// Longest expression accepted, in bytes.
+pub const MAX_LENGTH: usize = 4096;
+// Deepest nesting of sub-expressions accepted.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExprError {
+    #[error("syntax error at {position}: {message}")]
+    Syntax { position: usize, message: String },
+    #[error("expression is longer than {MAX_LENGTH} bytes")]
+    TooLong,
+    #[error("expression nests deeper than {MAX_DEPTH} levels")]
+    TooDeep,
+    #[error("unknown identifier: {0}")]
+    UnknownIdentifier(String),
+    #[error("unknown function: {0}")]
+    UnknownFunction(String),
+    #[error("{function}() takes {expected} argument(s), got {found}")]
+    Arity {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("division by zero")]
+    DivisionByZero,
+}
+
+pub type Result<T> = std::result::Result<T, ExprError>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Dot,
+    Comma,
+}
+
+// Longest first, so `<=` is not read as `<` then `=`.
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!",
+];
+
+fn syntax(position: usize, message: impl Into<String>) -> ExprError {
+    ExprError::Syntax {
+        position,
+        message: message.into(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' | ')' | '[' | ']' | ',' | '.' => {
+                chars.next();
+                match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    ',' => Token::Comma,
+                    _ => Token::Dot,
+                }
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, ch)) if ch == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, escaped)) => text.push(escaped),
+                            None => return Err(syntax(start, "unterminated string")),
+                        },
+                        Some((_, ch)) => text.push(ch),
+                        None => return Err(syntax(start, "unterminated string")),
+                    }
+                }
+                Token::Str(text)
+            }
+            '0'..='9' => {
+                let mut end = start;
+                while let Some(&(i, ch)) = chars.peek() {
+                    if !(ch.is_ascii_digit() || ch == '.') {
+                        break;
+                    }
+                    end = i + ch.len_utf8();
+                    chars.next();
+                }
+                let text = &source[start..end];
+                Token::Number(
+                    text.parse()
+                        .map_err(|_| syntax(start, format!("invalid number '{}'", text)))?,
+                )
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i, ch)) = chars.peek() {
+                    if !(ch.is_alphanumeric() || ch == '_') {
+                        break;
+                    }
+                    end = i + ch.len_utf8();
+                    chars.next();
+                }
+                Token::Ident(source[start..end].to_string())
+            }
+            _ => {
+                let rest = &source[start..];
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| rest.starts_with(**op))
+                    .ok_or_else(|| syntax(start, format!("unexpected character '{}'", c)))?;
+                for _ in 0..op.len() {
+                    chars.next();
+                }
+                Token::Op(op)
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Value),
+    Identifier(String),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(position, _)| *position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).map(|(_, token)| token.clone());
+        self.next += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<()> {
+        if self.peek() == Some(&expected) {
+            self.next += 1;
+            Ok(())
+        } else {
+            Err(syntax(self.position(), format!("expected {}", what)))
+        }
+    }
+
+    fn accept_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.next += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    // Depth counts nesting and operator chains alike, as both deepen the
+    // tree that evaluation recurses through.
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(ExprError::TooDeep);
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.enter()?;
+        let expr = self.or()?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[&'static str],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let depth = self.depth;
+        let mut left = operand(self)?;
+        while let Some(op) = self.accept_op(ops) {
+            self.enter()?;
+            let right = operand(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&["&&"], Self::comparison)
+    }
+
+    // Comparisons do not chain: `a < b < c` is a syntax error.
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        match self.accept_op(&["==", "!=", "<", "<=", ">", ">="]) {
+            Some(op) => {
+                let right = self.additive()?;
+                Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        self.binary(&["+", "-"], Self::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        self.binary(&["*", "/", "%"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.accept_op(&["!", "-"]) {
+            Some(op) => {
+                self.enter()?;
+                let operand = self.unary()?;
+                self.depth -= 1;
+                Ok(if op == "!" {
+                    Expr::Not(Box::new(operand))
+                } else {
+                    Expr::Negate(Box::new(operand))
+                })
+            }
+            None => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.enter()?;
+                    self.next += 1;
+                    let position = self.position();
+                    match self.advance() {
+                        Some(Token::Ident(name)) => expr = Expr::Field(Box::new(expr), name),
+                        Some(Token::Number(index)) if index.fract() == 0.0 => {
+                            expr = Expr::Index(
+                                Box::new(expr),
+                                Box::new(Expr::Literal(Value::from(index as u64))),
+                            )
+                        }
+                        _ => return Err(syntax(position, "expected a field name after '.'")),
+                    }
+                }
+                Some(Token::LBracket) => {
+                    self.enter()?;
+                    self.next += 1;
+                    let index = self.expression()?;
+                    self.expect(Token::RBracket, "']'")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                _ => {
+                    self.depth = depth;
+                    return Ok(expr);
+                }
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let position = self.position();
+        match self.advance() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(number(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ if self.peek() == Some(&Token::LParen) => {
+                    self.next += 1;
+                    let mut args = Vec::new();
+                    if self.peek() != Some(&Token::RParen) {
+                        loop {
+                            args.push(self.expression()?);
+                            if self.peek() != Some(&Token::Comma) {
+                                break;
+                            }
+                            self.next += 1;
+                        }
+                    }
+                    self.expect(Token::RParen, "')' after arguments")?;
+                    Ok(Expr::Call(name, args))
+                }
+                _ => Ok(Expr::Identifier(name)),
+            },
+            Some(Token::LParen) => {
+                let expr = self.expression()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(expr)
+            }
+            Some(token) => Err(syntax(position, format!("unexpected {}", describe(&token)))),
+            None => Err(syntax(position, "unexpected end of expression")),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => format!("number {}", n),
+        Token::Str(_) => "string".to_string(),
+        Token::Ident(name) => format!("'{}'", name),
+        Token::Op(op) => format!("'{}'", op),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::LBracket => "'['".to_string(),
+        Token::RBracket => "']'".to_string(),
+        Token::Dot => "'.'".to_string(),
+        Token::Comma => "','".to_string(),
+    }
+}
+
+// A parsed expression, reusable across evaluations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Expr,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self> {
+        if source.len() > MAX_LENGTH {
+            return Err(ExprError::TooLong);
+        }
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: source.len(),
+            depth: 0,
+        };
+        let root = parser.expression()?;
+        if parser.peek().is_some() {
+            let position = parser.position();
+            let token = parser.advance().expect("token was peeked");
+            return Err(syntax(position, format!("unexpected {}", describe(&token))));
+        }
+        Ok(Self { root })
+    }
+
+    // Evaluates against `scope`, whose top-level keys are the names the
+    // expression may use.
+    pub fn evaluate(&self, scope: &Value) -> Result<Value> {
+        eval(&self.root, scope)
+    }
+
+    // Top-level names the expression reads, e.g. `upstream` for
+    // `len(upstream.items) > 0`.
+    pub fn references(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        collect_references(&self.root, &mut names);
+        names
+    }
+}
+
+fn collect_references<'a>(expr: &'a Expr, names: &mut BTreeSet<&'a str>) {
+    match expr {
+        Expr::Literal(_) => {}
+        Expr::Identifier(name) => {
+            names.insert(name);
+        }
+        Expr::Field(target, _) | Expr::Not(target) | Expr::Negate(target) => {
+            collect_references(target, names)
+        }
+        Expr::Index(left, right) | Expr::Binary(_, left, right) => {
+            collect_references(left, names);
+            collect_references(right, names);
+        }
+        Expr::Call(_, args) => args.iter().for_each(|arg| collect_references(arg, names)),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// Integral results stay integers, so `2 * 3` is 6 rather than 6.0.
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 {
+        Value::from(n as i64)
+    } else {
+        Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn as_bool(value: &Value, context: &str) -> Result<bool> {
+    value.as_bool().ok_or_else(|| {
+        ExprError::TypeMismatch(format!("{} expects a boolean, got {}", context, type_name(value)))
+    })
+}
+
+fn eval(expr: &Expr, scope: &Value) -> Result<Value> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Identifier(name) => scope
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ExprError::UnknownIdentifier(name.clone())),
+        Expr::Field(target, name) => match eval(target, scope)? {
+            Value::Object(map) => Ok(map.get(name).cloned().unwrap_or(Value::Null)),
+            Value::Null => Ok(Value::Null),
+            other => Err(ExprError::TypeMismatch(format!(
+                "cannot read field '{}' of {}",
+                name,
+                type_name(&other)
+            ))),
+        },
+        Expr::Index(target, index) => {
+            let target = eval(target, scope)?;
+            let index = eval(index, scope)?;
+            match (&target, &index) {
+                (Value::Array(items), Value::Number(n)) => Ok(n
+                    .as_u64()
+                    .and_then(|i| items.get(i as usize))
+                    .cloned()
+                    .unwrap_or(Value::Null)),
+                (Value::Object(map), Value::String(key)) => {
+                    Ok(map.get(key).cloned().unwrap_or(Value::Null))
+                }
+                (Value::Null, _) => Ok(Value::Null),
+                _ => Err(ExprError::TypeMismatch(format!(
+                    "cannot index {} with {}",
+                    type_name(&target),
+                    type_name(&index)
+                ))),
+            }
+        }
+        Expr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, scope))
+                .collect::<Result<Vec<_>>>()?;
+            call(name, args)
+        }
+        Expr::Not(operand) => Ok(Value::Bool(!as_bool(&eval(operand, scope)?, "'!'")?)),
+        Expr::Negate(operand) => match eval(operand, scope)? {
+            Value::Number(n) => Ok(number(-n.as_f64().unwrap_or_default())),
+            other => Err(ExprError::TypeMismatch(format!(
+                "'-' expects a number, got {}",
+                type_name(&other)
+            ))),
+        },
+        // Short-circuits: the right side is only evaluated when needed.
+        Expr::Binary(op @ ("&&" | "||"), left, right) => {
+            let context = format!("'{}'", op);
+            let left = as_bool(&eval(left, scope)?, &context)?;
+            if (*op == "&&" && !left) || (*op == "||" && left) {
+                return Ok(Value::Bool(left));
+            }
+            Ok(Value::Bool(as_bool(&eval(right, scope)?, &context)?))
+        }
+        Expr::Binary(op, left, right) => binary(op, eval(left, scope)?, eval(right, scope)?),
+    }
+}
+
+fn mismatch(op: &str, left: &Value, right: &Value) -> ExprError {
+    ExprError::TypeMismatch(format!(
+        "cannot apply '{}' to {} and {}",
+        op,
+        type_name(left),
+        type_name(right)
+    ))
+}
+
+fn binary(op: &str, left: Value, right: Value) -> Result<Value> {
+    match op {
+        "==" => return Ok(Value::Bool(equal(&left, &right))),
+        "!=" => return Ok(Value::Bool(!equal(&left, &right))),
+        _ => {}
+    }
+
+    match (&left, &right) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap_or_default(), b.as_f64().unwrap_or_default());
+            Ok(match op {
+                "+" => number(a + b),
+                "-" => number(a - b),
+                "*" => number(a * b),
+                "/" | "%" if b == 0.0 => return Err(ExprError::DivisionByZero),
+                "/" => number(a / b),
+                "%" => number(a % b),
+                "<" => Value::Bool(a < b),
+                "<=" => Value::Bool(a <= b),
+                ">" => Value::Bool(a > b),
+                ">=" => Value::Bool(a >= b),
+                _ => return Err(mismatch(op, &left, &right)),
+            })
+        }
+        (Value::String(a), Value::String(b)) => Ok(match op {
+            "+" => Value::String(format!("{}{}", a, b)),
+            "<" => Value::Bool(a < b),
+            "<=" => Value::Bool(a <= b),
+            ">" => Value::Bool(a > b),
+            ">=" => Value::Bool(a >= b),
+            _ => return Err(mismatch(op, &left, &right)),
+        }),
+        _ => Err(mismatch(op, &left, &right)),
+    }
+}
+
+// JSON equality, except that numbers compare by value (1 == 1.0).
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => left == right,
+    }
+}
+
+fn call(name: &str, args: Vec<Value>) -> Result<Value> {
+    let expected = match name {
+        "len" | "lower" | "upper" => 1,
+        "contains" => 2,
+        _ => return Err(ExprError::UnknownFunction(name.to_string())),
+    };
+    if args.len() != expected {
+        return Err(ExprError::Arity {
+            function: name.to_string(),
+            expected,
+            found: args.len(),
+        });
+    }
+    let wrong = |value: &Value, wanted: &str| {
+        ExprError::TypeMismatch(format!(
+            "{}() expects {}, got {}",
+            name,
+            wanted,
+            type_name(value)
+        ))
+    };
+
+    match (name, args.as_slice()) {
+        ("len", [value]) => match value {
+            Value::String(s) => Ok(Value::from(s.chars().count())),
+            Value::Array(items) => Ok(Value::from(items.len())),
+            Value::Object(map) => Ok(Value::from(map.len())),
+            other => Err(wrong(other, "a string, array or object")),
+        },
+        ("lower", [value]) => match value {
+            Value::String(s) => Ok(Value::String(s.to_lowercase())),
+            other => Err(wrong(other, "a string")),
+        },
+        ("upper", [value]) => match value {
+            Value::String(s) => Ok(Value::String(s.to_uppercase())),
+            other => Err(wrong(other, "a string")),
+        },
+        // Substring, array element or object key.
+        ("contains", [haystack, needle]) => match (haystack, needle) {
+            (Value::String(s), Value::String(part)) => Ok(Value::Bool(s.contains(part.as_str()))),
+            (Value::Array(items), needle) => {
+                Ok(Value::Bool(items.iter().any(|item| equal(item, needle))))
+            }
+            (Value::Object(map), Value::String(key)) => Ok(Value::Bool(map.contains_key(key))),
+            (Value::String(_), other) | (Value::Object(_), other) => Err(wrong(other, "a string")),
+            (other, _) => Err(wrong(other, "a string, array or object")),
+        },
+        _ => unreachable!("arity checked above"),
+    }
+}
+
+// Name under which node expressions see the output delivered to the node.
+pub const UPSTREAM: &str = "upstream";
+
+// The scope a node's expressions see: the template scope plus `upstream`.
+pub fn node_scope(template_scope: Value, upstream: &Value) -> Value {
+    let mut scope = match template_scope {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+    scope.insert(UPSTREAM.to_string(), upstream.clone());
+    Value::Object(scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(source: &str) -> Result<Value> {
+        let scope = json!({
+            "upstream": { "name": "Ada", "tags": ["x", "y"], "n": 3 },
+            "vars": { "limit": 2 },
+        });
+        Expression::parse(source)?.evaluate(&scope)
+    }
+
+    // xorshift64, so every run feeds the same inputs
+    fn xorshift(mut seed: u64) -> impl FnMut() -> usize {
+        move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        }
+    }
+
+    #[test]
+    fn malformed_expressions_never_panic() {
+        let mut next = xorshift(0x9E37_79B9_7F4A_7C15);
+        let chars: Vec<char> = "()[].,!<>=&|+-*/%'\"\\ 0123456789e_abcupstreamlentruenullé\u{0}\n"
+            .chars()
+            .collect();
+        for _ in 0..20_000 {
+            let source: String = (0..next() % 40).map(|_| chars[next() % chars.len()]).collect();
+            let _ = eval(&source);
+        }
+
+        let tokens = [
+            "upstream", ".", "name", "(", ")", "[", "]", "1", "'s'", "+", "-", "*", "/", "%",
+            "==", "<", "&&", "||", "!", "len", "contains", ",", "true", "null",
+            "99999999999999999999999", "0.5",
+        ];
+        for _ in 0..20_000 {
+            let source: Vec<&str> = (0..next() % 25)
+                .map(|_| tokens[next() % tokens.len()])
+                .collect();
+            let _ = eval(&source.join(" "));
+        }
+    }
+
+    #[test]
+    fn nesting_and_length_are_bounded() {
+        assert!(matches!(eval(&"(".repeat(500)), Err(ExprError::TooDeep)));
+        assert!(matches!(eval(&"!".repeat(500)), Err(ExprError::TooDeep)));
+        assert!(matches!(eval(&"-".repeat(500)), Err(ExprError::TooDeep)));
+        assert!(matches!(
+            eval(&format!("upstream{}", ".a".repeat(MAX_DEPTH * 2))),
+            Err(ExprError::TooDeep)
+        ));
+        assert!(matches!(eval(&"1+".repeat(MAX_LENGTH)), Err(ExprError::TooLong)));
+        assert!(matches!(eval(""), Err(ExprError::Syntax { .. })));
+        assert!(matches!(eval("'abc"), Err(ExprError::Syntax { .. })));
+        assert_eq!(eval("(1 + 2) * upstream.n").unwrap(), json!(9));
+    }
+}
@@ -0,0 +1,258 @@
+/*!
+ * File watcher - starts workflows from their file watch triggers
+ *
+ * Each Active workflow with file watch nodes gets one filesystem watcher.
+ * Events are debounced per file, so an editor that writes a file several
+ * times in one save starts a single run.
+ */
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::database::{Database, DatabaseError};
+use crate::nodes::file_watch::{self, WatchSpec};
+use crate::workflow_engine::{self, WorkflowEngine};
+use crate::WorkflowStatus;
+
+// Quiet period after the last event for a file before the run starts.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum FileWatchError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("file watch failed: {0}")]
+    Notify(#[from] notify::Error),
+}
+
+pub type Result<T> = std::result::Result<T, FileWatchError>;
+
+#[derive(Debug, Clone)]
+struct FileEvent {
+    path: PathBuf,
+    kind: &'static str,
+}
+
+struct WorkflowWatch {
+    specs: Vec<WatchSpec>,
+    // Dropping the watcher closes the event channel, which ends the task.
+    _watcher: RecommendedWatcher,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+impl Drop for WorkflowWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+pub struct FileWatchers {
+    db: Arc<Mutex<Database>>,
+    engine: Arc<Mutex<WorkflowEngine>>,
+    watches: HashMap<String, WorkflowWatch>,
+}
+
+impl FileWatchers {
+    pub fn new(db: Arc<Mutex<Database>>, engine: Arc<Mutex<WorkflowEngine>>) -> Self {
+        Self {
+            db,
+            engine,
+            watches: HashMap::new(),
+        }
+    }
+
+    // Also drops watches for workflows no longer in the database, such as
+    // after switching profiles.
+    pub fn sync_all(&mut self) -> Result<()> {
+        let ids: Vec<String> = self
+            .db
+            .lock()
+            .get_workflows()?
+            .into_iter()
+            .map(|workflow| workflow.id)
+            .collect();
+        self.watches.retain(|id, _| ids.contains(id));
+        for id in ids {
+            if let Err(e) = self.sync(&id) {
+                tracing::warn!("could not watch files for workflow {}: {}", id, e);
+            }
+        }
+        Ok(())
+    }
+
+    // Brings the watcher for `workflow_id` in line with its stored state:
+    // watched while Active, torn down when paused, archived, trashed or gone.
+    // Call after anything that changes a workflow's status or nodes.
+    pub fn sync(&mut self, workflow_id: &str) -> Result<()> {
+        let workflow = match self.db.lock().get_workflow(workflow_id) {
+            Ok(workflow) => Some(workflow),
+            Err(DatabaseError::NotFound(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let specs: Vec<WatchSpec> = workflow
+            .filter(|workflow| matches!(workflow.status, WorkflowStatus::Active))
+            .map(|workflow| {
+                workflow
+                    .nodes
+                    .iter()
+                    .filter(|node| node.node_type == file_watch::NODE_TYPE)
+                    .filter_map(|node| WatchSpec::from_config(&node.data))
+                    .map(|mut spec| {
+                        // Event paths are absolute and resolved; match them alike.
+                        if let Ok(path) = spec.path.canonicalize() {
+                            spec.path = path;
+                        }
+                        spec
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if specs.is_empty() {
+            self.watches.remove(workflow_id);
+            return Ok(());
+        }
+        if self.watches.get(workflow_id).is_some_and(|watch| watch.specs == specs) {
+            return Ok(());
+        }
+
+        self.watches.remove(workflow_id);
+        let watch = self.watch(workflow_id, specs)?;
+        self.watches.insert(workflow_id.to_string(), watch);
+        Ok(())
+    }
+
+    fn watch(&self, workflow_id: &str, specs: Vec<WatchSpec>) -> Result<WorkflowWatch> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+            let event = match result {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!("file watch error: {}", e);
+                    return;
+                }
+            };
+            let kind = match event.kind {
+                EventKind::Create(_) => "created",
+                EventKind::Modify(ModifyKind::Metadata(_)) => return,
+                EventKind::Modify(_) => "modified",
+                _ => return,
+            };
+            for path in event.paths {
+                let _ = sender.send(FileEvent { path, kind });
+            }
+        })?;
+
+        for spec in &specs {
+            let mode = if spec.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            watcher.watch(&spec.path, mode)?;
+        }
+
+        let task = tauri::async_runtime::spawn(debounce_events(
+            receiver,
+            specs.clone(),
+            workflow_id.to_string(),
+            self.db.clone(),
+            self.engine.clone(),
+        ));
+        Ok(WorkflowWatch {
+            specs,
+            _watcher: watcher,
+            task,
+        })
+    }
+}
+
+async fn debounce_events(
+    mut receiver: mpsc::UnboundedReceiver<FileEvent>,
+    specs: Vec<WatchSpec>,
+    workflow_id: String,
+    db: Arc<Mutex<Database>>,
+    engine: Arc<Mutex<WorkflowEngine>>,
+) {
+    // Latest event per file and when it becomes due.
+    let mut pending: HashMap<PathBuf, (FileEvent, Instant)> = HashMap::new();
+
+    loop {
+        let next_due = pending.values().map(|(_, due)| *due).min();
+        tokio::select! {
+            received = receiver.recv() => match received {
+                Some(event) if specs.iter().any(|spec| spec.matches(&event.path)) => {
+                    // A create followed by writes is still reported as created.
+                    let kind = match pending.get(&event.path) {
+                        Some((earlier, _)) if earlier.kind == "created" => "created",
+                        _ => event.kind,
+                    };
+                    let event = FileEvent { kind, ..event };
+                    pending.insert(event.path.clone(), (event, Instant::now() + DEBOUNCE));
+                }
+                Some(_) => {}
+                None => break,
+            },
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                let now = Instant::now();
+                let due: Vec<PathBuf> = pending
+                    .iter()
+                    .filter(|(_, (_, due))| *due <= now)
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                for path in due {
+                    if let Some((event, _)) = pending.remove(&path) {
+                        start_run(&workflow_id, &event, &db, &engine);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn start_run(
+    workflow_id: &str,
+    event: &FileEvent,
+    db: &Arc<Mutex<Database>>,
+    engine: &Arc<Mutex<WorkflowEngine>>,
+) {
+    let workflow = match db.lock().get_workflow(workflow_id) {
+        Ok(workflow) => workflow,
+        Err(e) => {
+            tracing::warn!("file trigger for workflow {} skipped: {}", workflow_id, e);
+            return;
+        }
+    };
+    let input = serde_json::json!({
+        "path": event.path.to_string_lossy(),
+        "event": event.kind,
+    });
+
+    let started = workflow_engine::find_profile(workflow_engine::DEFAULT_PROFILE).and_then(|profile| {
+        engine
+            .lock()
+            .execute_workflow_with_input(&workflow, &profile, input, None)
+    });
+    match started {
+        Ok((execution_id, _)) => tracing::info!(
+            "file {} started execution {} of workflow {}",
+            event.path.display(),
+            execution_id,
+            workflow_id
+        ),
+        Err(e) => tracing::warn!(
+            "file {} could not start workflow {}: {}",
+            event.path.display(),
+            workflow_id,
+            e
+        ),
+    }
+}
@@ -0,0 +1,183 @@
+/*!
+ * Folders - hierarchical organization of the workflow library
+ *
+ * Each folder has at most one parent; folders without one are top-level. A
+ * workflow sits in at most one folder, or at the root. Tags stay orthogonal
+ * to folders.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::database::{Database, DatabaseError};
+use crate::Workflow;
+
+#[derive(Debug, Error)]
+pub enum FolderError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("folder not found: {0}")]
+    NotFound(String),
+    #[error("folder name must not be empty")]
+    EmptyName,
+    #[error("cannot move folder {folder_id} into {parent_id}, which is inside it")]
+    Cycle { folder_id: String, parent_id: String },
+}
+
+pub type Result<T> = std::result::Result<T, FolderError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// One level of the tree. `folder` is None at the root; `path` lists its
+// ancestors from the top down, for breadcrumbs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderListing {
+    pub folder: Option<Folder>,
+    pub path: Vec<Folder>,
+    pub folders: Vec<Folder>,
+    pub workflows: Vec<Workflow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderDeleteSummary {
+    pub folders_deleted: usize,
+    // Ids of the live workflows that were in the deleted folders
+    pub workflows_moved: Vec<String>,
+    pub workflows_trashed: Vec<String>,
+}
+
+fn require(db: &Database, id: &str) -> Result<Folder> {
+    db.get_folder(id)?
+        .ok_or_else(|| FolderError::NotFound(id.to_string()))
+}
+
+// Ancestors of `folder`, nearest first. Stops at a repeat, so a cycle left
+// in the data cannot loop forever.
+fn ancestors(db: &Database, folder: &Folder) -> Result<Vec<Folder>> {
+    let mut seen = HashSet::from([folder.id.clone()]);
+    let mut ancestors = Vec::new();
+    let mut next = folder.parent_id.clone();
+    while let Some(id) = next.filter(|id| seen.insert(id.clone())) {
+        let Some(parent) = db.get_folder(&id)? else {
+            break;
+        };
+        next = parent.parent_id.clone();
+        ancestors.push(parent);
+    }
+    Ok(ancestors)
+}
+
+pub fn create_folder(db: &Database, name: &str, parent_id: Option<&str>) -> Result<Folder> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(FolderError::EmptyName);
+    }
+    if let Some(parent_id) = parent_id {
+        require(db, parent_id)?;
+    }
+
+    let folder = Folder {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        parent_id: parent_id.map(str::to_string),
+        created_at: chrono::Utc::now(),
+    };
+    db.insert_folder(&folder)?;
+    Ok(folder)
+}
+
+// Reparents a folder; `None` makes it top-level. Moving a folder into itself
+// or one of its descendants is refused.
+pub fn move_folder(db: &Database, id: &str, parent_id: Option<&str>) -> Result<Folder> {
+    let mut folder = require(db, id)?;
+    if let Some(parent_id) = parent_id {
+        let parent = require(db, parent_id)?;
+        if parent.id == folder.id || ancestors(db, &parent)?.iter().any(|a| a.id == folder.id) {
+            return Err(FolderError::Cycle {
+                folder_id: folder.id,
+                parent_id: parent.id,
+            });
+        }
+    }
+
+    db.set_folder_parent(id, parent_id)?;
+    folder.parent_id = parent_id.map(str::to_string);
+    Ok(folder)
+}
+
+// `None` moves the workflow to the root.
+pub fn move_workflow(db: &Database, workflow_id: &str, folder_id: Option<&str>) -> Result<()> {
+    if let Some(folder_id) = folder_id {
+        require(db, folder_id)?;
+    }
+    db.set_workflow_folder(workflow_id, folder_id)?;
+    Ok(())
+}
+
+// Direct subfolders and live workflows of `folder_id`, or of the root.
+pub fn list_folder(db: &Database, folder_id: Option<&str>) -> Result<FolderListing> {
+    let (folder, path) = match folder_id {
+        Some(id) => {
+            let folder = require(db, id)?;
+            let mut path = ancestors(db, &folder)?;
+            path.reverse();
+            (Some(folder), path)
+        }
+        None => (None, Vec::new()),
+    };
+    Ok(FolderListing {
+        folder,
+        path,
+        folders: db.child_folders(folder_id)?,
+        workflows: db.workflows_in_folder(folder_id)?,
+    })
+}
+
+// With `cascade`, the folder's whole subtree is deleted and the workflows in
+// it trashed (they can still be restored, at the root). Without, only the
+// folder goes: its workflows and subfolders move to the root.
+pub fn delete_folder(db: &mut Database, id: &str, cascade: bool) -> Result<FolderDeleteSummary> {
+    require(db, id)?;
+    let mut ids = vec![id.to_string()];
+    if cascade {
+        let mut seen = HashSet::from([id.to_string()]);
+        let mut index = 0;
+        while index < ids.len() {
+            for child in db.child_folders(Some(&ids[index]))? {
+                if seen.insert(child.id.clone()) {
+                    ids.push(child.id);
+                }
+            }
+            index += 1;
+        }
+    }
+
+    let mut workflows = Vec::new();
+    for folder_id in &ids {
+        workflows.extend(
+            db.workflows_in_folder(Some(folder_id))?
+                .into_iter()
+                .map(|workflow| workflow.id),
+        );
+    }
+    db.delete_folders(&ids, cascade)?;
+
+    let (workflows_trashed, workflows_moved) = if cascade {
+        (workflows, Vec::new())
+    } else {
+        (Vec::new(), workflows)
+    };
+    Ok(FolderDeleteSummary {
+        folders_deleted: ids.len(),
+        workflows_moved,
+        workflows_trashed,
+    })
+}
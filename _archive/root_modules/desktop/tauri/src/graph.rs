@@ -0,0 +1,401 @@
+/*!
+ * Graph - structural edits to a workflow's nodes and edges, pasting fragments
+ * into a workflow, and what a node's removal would affect
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::nodes::NodeRegistry;
+use crate::validation::{self, Severity};
+use crate::workflow_engine;
+use crate::{Position, Workflow, WorkflowEdge, WorkflowNode};
+
+#[derive(Debug, Error)]
+pub enum GraphError {
+    #[error("node not found: {0}")]
+    NodeNotFound(String),
+    #[error("edit would leave an invalid workflow: {0}")]
+    Invalid(String),
+    #[error("cannot copy the config of a {source_type} node onto a {target_type} node")]
+    TypeMismatch {
+        source_type: String,
+        target_type: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, GraphError>;
+
+// Rejects the edit if the graph no longer passes structural validation.
+fn ensure_valid(registry: &NodeRegistry, workflow: &Workflow) -> Result<()> {
+    let errors: Vec<String> = validation::validate_graph(registry, workflow)
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.message)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(GraphError::Invalid(errors.join("; ")))
+    }
+}
+
+// Removes a node and its edges. With `reconnect`, every upstream node is wired
+// to every downstream node so data still flows through the gap.
+pub fn delete_node(
+    registry: &NodeRegistry,
+    workflow: &mut Workflow,
+    node_id: &str,
+    reconnect: bool,
+) -> Result<()> {
+    let index = workflow
+        .nodes
+        .iter()
+        .position(|node| node.id == node_id)
+        .ok_or_else(|| GraphError::NodeNotFound(node_id.to_string()))?;
+
+    let (touching, mut edges): (Vec<WorkflowEdge>, Vec<WorkflowEdge>) = workflow
+        .edges
+        .drain(..)
+        .partition(|edge| edge.source == node_id || edge.target == node_id);
+
+    if reconnect {
+        let incoming = touching.iter().filter(|edge| edge.target == node_id && edge.source != node_id);
+        for inbound in incoming {
+            let outgoing = touching.iter().filter(|edge| edge.source == node_id && edge.target != node_id);
+            for outbound in outgoing {
+                let exists = edges.iter().any(|edge| {
+                    edge.source == inbound.source
+                        && edge.target == outbound.target
+                        && edge.source_handle == inbound.source_handle
+                        && edge.target_handle == outbound.target_handle
+                });
+                if !exists {
+                    edges.push(WorkflowEdge {
+                        id: Uuid::new_v4().to_string(),
+                        source: inbound.source.clone(),
+                        target: outbound.target.clone(),
+                        source_handle: inbound.source_handle.clone(),
+                        target_handle: outbound.target_handle.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    workflow.nodes.remove(index);
+    workflow.edges = edges;
+    ensure_valid(registry, workflow)
+}
+
+// Nodes and the edges between them, copied from a workflow to paste into
+// another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowFragment {
+    pub nodes: Vec<WorkflowNode>,
+    #[serde(default)]
+    pub edges: Vec<WorkflowEdge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FragmentMerge {
+    // Fragment node id to the id the node was given in the workflow
+    pub node_ids: BTreeMap<String, String>,
+    pub warnings: Vec<String>,
+}
+
+// Appends the fragment's nodes to the workflow under new ids, moved by
+// `offset`, with the edges between them. Edges to nodes outside the
+// fragment are dropped, each with a warning.
+pub fn merge_fragment(
+    workflow: &mut Workflow,
+    fragment: WorkflowFragment,
+    offset: &Position,
+) -> Result<FragmentMerge> {
+    if fragment.nodes.is_empty() {
+        return Err(GraphError::Invalid("fragment has no nodes".to_string()));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = fragment.nodes.iter().find(|node| !seen.insert(node.id.as_str())) {
+        return Err(GraphError::Invalid(format!(
+            "fragment has more than one node {}",
+            duplicate.id
+        )));
+    }
+
+    let node_ids: BTreeMap<String, String> = fragment
+        .nodes
+        .iter()
+        .map(|node| (node.id.clone(), Uuid::new_v4().to_string()))
+        .collect();
+    let mut warnings = Vec::new();
+    for edge in fragment.edges {
+        match (node_ids.get(&edge.source), node_ids.get(&edge.target)) {
+            (Some(source), Some(target)) => workflow.edges.push(WorkflowEdge {
+                id: Uuid::new_v4().to_string(),
+                source: source.clone(),
+                target: target.clone(),
+                ..edge
+            }),
+            _ => {
+                let warning = format!(
+                    "edge {} from {} to {} leaves the fragment and was dropped",
+                    edge.id, edge.source, edge.target
+                );
+                tracing::warn!("merging into {}: {}", workflow.id, warning);
+                warnings.push(warning);
+            }
+        }
+    }
+    for node in fragment.nodes {
+        workflow.nodes.push(WorkflowNode {
+            id: node_ids[&node.id].clone(),
+            position: Position {
+                x: node.position.x + offset.x,
+                y: node.position.y + offset.y,
+            },
+            ..node
+        });
+    }
+
+    Ok(FragmentMerge { node_ids, warnings })
+}
+
+// Flips the node's disabled flag and returns whether it is now disabled.
+// Enabling removes the flag rather than storing false.
+pub fn toggle_node_disabled(workflow: &mut Workflow, node_id: &str) -> Result<bool> {
+    let node = workflow
+        .nodes
+        .iter_mut()
+        .find(|node| node.id == node_id)
+        .ok_or_else(|| GraphError::NodeNotFound(node_id.to_string()))?;
+    let disabled = !workflow_engine::is_disabled(node);
+    if node.data.is_null() {
+        node.data = Value::Object(Map::new());
+    }
+    let data = node.data.as_object_mut().ok_or_else(|| {
+        GraphError::Invalid(format!("data of node {} is not an object", node_id))
+    })?;
+    if disabled {
+        data.insert(workflow_engine::DISABLED_KEY.to_string(), Value::Bool(true));
+    } else {
+        data.remove(workflow_engine::DISABLED_KEY);
+    }
+    Ok(disabled)
+}
+
+// Replaces the target node's `data` with the source node's. Top-level fields
+// named in `preserve` keep the target's values, or stay absent if the target
+// has none; whether the target is disabled is always kept. Its id, position
+// and edges are untouched.
+pub fn copy_node_config(
+    source: &WorkflowNode,
+    workflow: &mut Workflow,
+    target_node_id: &str,
+    preserve: &[String],
+) -> Result<()> {
+    let target = workflow
+        .nodes
+        .iter_mut()
+        .find(|node| node.id == target_node_id)
+        .ok_or_else(|| GraphError::NodeNotFound(target_node_id.to_string()))?;
+    if source.node_type != target.node_type {
+        return Err(GraphError::TypeMismatch {
+            source_type: source.node_type.clone(),
+            target_type: target.node_type.clone(),
+        });
+    }
+
+    let mut data = source.data.clone();
+    let kept = preserve
+        .iter()
+        .map(String::as_str)
+        .chain([workflow_engine::DISABLED_KEY]);
+    if let Some(fields) = data.as_object_mut() {
+        for key in kept {
+            match target.data.get(key) {
+                Some(value) => fields.insert(key.to_string(), value.clone()),
+                None => fields.remove(key),
+            };
+        }
+    }
+    target.data = data;
+    Ok(())
+}
+
+// What depends on a node, and what removing it would cut off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeImpact {
+    pub node_id: String,
+    // Nodes downstream of it, transitively
+    pub dependents: BTreeSet<String>,
+    // Nodes upstream of it, transitively
+    pub dependencies: BTreeSet<String>,
+    // Terminal nodes some trigger reaches now but none would without it
+    pub disconnected_terminals: BTreeSet<String>,
+    pub disconnects_terminal: bool,
+}
+
+fn find_node(workflow: &Workflow, node_id: &str) -> Result<()> {
+    if workflow.nodes.iter().any(|node| node.id == node_id) {
+        Ok(())
+    } else {
+        Err(GraphError::NodeNotFound(node_id.to_string()))
+    }
+}
+
+// Nodes reachable from `starts` by following edges forward (or backward),
+// never entering `skip`. Each node is visited once, so cycles terminate.
+fn walk<'a>(
+    workflow: &'a Workflow,
+    starts: impl IntoIterator<Item = &'a str>,
+    forward: bool,
+    skip: Option<&str>,
+) -> BTreeSet<&'a str> {
+    let mut seen = BTreeSet::new();
+    let mut stack: Vec<&str> = starts.into_iter().filter(|id| Some(*id) != skip).collect();
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        for edge in &workflow.edges {
+            let (from, to) = if forward {
+                (&edge.source, &edge.target)
+            } else {
+                (&edge.target, &edge.source)
+            };
+            if from == id && Some(to.as_str()) != skip && !seen.contains(to.as_str()) {
+                stack.push(to);
+            }
+        }
+    }
+    seen
+}
+
+fn neighbours(workflow: &Workflow, node_id: &str, forward: bool) -> BTreeSet<String> {
+    let mut found = walk(workflow, [node_id], forward, None);
+    found.remove(node_id);
+    found.into_iter().map(str::to_string).collect()
+}
+
+// Every node downstream of `node_id`. The node itself is left out, even when
+// a cycle leads back to it.
+pub fn node_dependents(workflow: &Workflow, node_id: &str) -> Result<BTreeSet<String>> {
+    find_node(workflow, node_id)?;
+    Ok(neighbours(workflow, node_id, true))
+}
+
+// Every node upstream of `node_id`, the node itself left out.
+pub fn node_dependencies(workflow: &Workflow, node_id: &str) -> Result<BTreeSet<String>> {
+    find_node(workflow, node_id)?;
+    Ok(neighbours(workflow, node_id, false))
+}
+
+// Triggers are the nodes whose executor takes no input; terminals are nodes
+// with no outgoing edges.
+pub fn analyze_node_impact(
+    registry: &NodeRegistry,
+    workflow: &Workflow,
+    node_id: &str,
+) -> Result<NodeImpact> {
+    let dependents = node_dependents(workflow, node_id)?;
+    let dependencies = node_dependencies(workflow, node_id)?;
+
+    let triggers: Vec<&str> = workflow
+        .nodes
+        .iter()
+        .filter(|node| {
+            registry
+                .get(&node.node_type)
+                .and_then(|executor| executor.input_ports())
+                .is_some_and(|ports| ports.is_empty())
+        })
+        .map(|node| node.id.as_str())
+        .collect();
+    let reached = walk(workflow, triggers.iter().copied(), true, None);
+    let reached_without = walk(workflow, triggers.iter().copied(), true, Some(node_id));
+
+    let disconnected_terminals: BTreeSet<String> = workflow
+        .nodes
+        .iter()
+        .map(|node| node.id.as_str())
+        .filter(|id| *id != node_id && !workflow.edges.iter().any(|edge| edge.source == *id))
+        .filter(|id| reached.contains(id) && !reached_without.contains(id))
+        .map(str::to_string)
+        .collect();
+
+    Ok(NodeImpact {
+        node_id: node_id.to_string(),
+        dependents,
+        dependencies,
+        disconnects_terminal: !disconnected_terminals.is_empty(),
+        disconnected_terminals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{edge, node, workflow};
+    use serde_json::json;
+
+    // t -> a -> b -> c
+    fn chain() -> Workflow {
+        workflow(
+            "chain",
+            vec![
+                node("t", "trigger", json!({})),
+                node("a", "delay", json!({ "duration_ms": 1 })),
+                node("b", "delay", json!({ "duration_ms": 1 })),
+                node("c", "delay", json!({ "duration_ms": 1 })),
+            ],
+            vec![edge("t", "a"), edge("a", "b"), edge("b", "c")],
+        )
+    }
+
+    fn links(workflow: &Workflow) -> Vec<(&str, &str)> {
+        let mut links: Vec<(&str, &str)> = workflow
+            .edges
+            .iter()
+            .map(|edge| (edge.source.as_str(), edge.target.as_str()))
+            .collect();
+        links.sort_unstable();
+        links
+    }
+
+    #[test]
+    fn deleting_with_reconnect_bridges_upstream_to_downstream() {
+        let registry = NodeRegistry::with_builtin();
+        let mut workflow = chain();
+
+        delete_node(&registry, &mut workflow, "b", true).unwrap();
+        assert!(workflow.nodes.iter().all(|node| node.id != "b"));
+        assert_eq!(links(&workflow), [("a", "c"), ("t", "a")]);
+    }
+
+    #[test]
+    fn deleting_without_reconnect_drops_the_edges() {
+        let registry = NodeRegistry::with_builtin();
+        let mut workflow = chain();
+
+        delete_node(&registry, &mut workflow, "b", false).unwrap();
+        assert!(workflow.nodes.iter().all(|node| node.id != "b"));
+        assert_eq!(links(&workflow), [("t", "a")]);
+    }
+
+    #[test]
+    fn deleting_an_unknown_node_changes_nothing() {
+        let registry = NodeRegistry::with_builtin();
+        let mut workflow = chain();
+
+        assert!(matches!(
+            delete_node(&registry, &mut workflow, "zz", true),
+            Err(GraphError::NodeNotFound(_))
+        ));
+        assert_eq!(workflow.nodes.len(), 4);
+        assert_eq!(links(&workflow).len(), 3);
+    }
+}
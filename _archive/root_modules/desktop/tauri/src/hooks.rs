@@ -0,0 +1,359 @@
+/*!
+ * Hooks - cross-cutting behaviour run around every node of every run
+ *
+ * An `ExecutionHook` sees each node before it runs, where it may rewrite the
+ * node's input or the run's context, and after it runs, where it may rewrite
+ * the output. Returning an error fails the node with it. Hooks run in the
+ * order they were registered before a node and in reverse order after it,
+ * so the first hook registered wraps all the others. A hook that panics
+ * fails the node it was called for; the run and the engine carry on as for
+ * any other node failure.
+ *
+ * The engine registers `MetricsHook` itself and the app adds `AuditHook` at
+ * startup. Node previews run no hooks.
+ */
+
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::nodes::{ExecutionContext, NodeError};
+use crate::WorkflowNode;
+
+// The error fails the node.
+pub type HookResult = std::result::Result<(), String>;
+
+pub trait ExecutionHook: Send + Sync {
+    // Names the hook in node errors; unique among registered hooks.
+    fn name(&self) -> &str;
+
+    fn before_node(
+        &self,
+        _node: &WorkflowNode,
+        _input: &mut Value,
+        _ctx: &mut ExecutionContext,
+    ) -> HookResult {
+        Ok(())
+    }
+
+    // Only for nodes that succeeded. `duration` is the node's own run time.
+    fn after_node(
+        &self,
+        _node: &WorkflowNode,
+        _output: &mut Value,
+        _duration: Duration,
+        _ctx: &mut ExecutionContext,
+    ) -> HookResult {
+        Ok(())
+    }
+
+    // For nodes that failed, including those failed by a hook. Informational:
+    // the node has failed whatever this does.
+    fn node_failed(
+        &self,
+        _node: &WorkflowNode,
+        _error: &NodeError,
+        _duration: Duration,
+        _ctx: &ExecutionContext,
+    ) {
+    }
+}
+
+#[derive(Default)]
+pub struct HookChain {
+    hooks: RwLock<Vec<Arc<dyn ExecutionHook>>>,
+}
+
+impl HookChain {
+    // Adds `hook` after the hooks already registered. Returns false, leaving
+    // the chain as it was, if a hook of the same name is registered.
+    pub fn register(&self, hook: Arc<dyn ExecutionHook>) -> bool {
+        let mut hooks = self.hooks.write();
+        if hooks.iter().any(|registered| registered.name() == hook.name()) {
+            return false;
+        }
+        hooks.push(hook);
+        true
+    }
+
+    // Hooks registered while a node runs apply from the next node on.
+    fn snapshot(&self) -> Vec<Arc<dyn ExecutionHook>> {
+        self.hooks.read().clone()
+    }
+
+    // Stops at the first hook that fails.
+    pub fn before_node(
+        &self,
+        node: &WorkflowNode,
+        input: &mut Value,
+        ctx: &mut ExecutionContext,
+    ) -> Result<(), NodeError> {
+        for hook in self.snapshot() {
+            isolate(hook.as_ref(), || hook.before_node(node, input, ctx))?;
+        }
+        Ok(())
+    }
+
+    pub fn after_node(
+        &self,
+        node: &WorkflowNode,
+        output: &mut Value,
+        duration: Duration,
+        ctx: &mut ExecutionContext,
+    ) -> Result<(), NodeError> {
+        for hook in self.snapshot().iter().rev() {
+            isolate(hook.as_ref(), || hook.after_node(node, output, duration, ctx))?;
+        }
+        Ok(())
+    }
+
+    pub fn node_failed(
+        &self,
+        node: &WorkflowNode,
+        error: &NodeError,
+        duration: Duration,
+        ctx: &ExecutionContext,
+    ) {
+        for hook in self.snapshot().iter().rev() {
+            let called = isolate(hook.as_ref(), || {
+                hook.node_failed(node, error, duration, ctx);
+                Ok(())
+            });
+            if let Err(e) = called {
+                tracing::warn!("{}", e);
+            }
+        }
+    }
+}
+
+fn isolate(hook: &dyn ExecutionHook, call: impl FnOnce() -> HookResult) -> Result<(), NodeError> {
+    match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(message)) => Err(NodeError::Hook {
+            hook: hook.name().to_string(),
+            message,
+        }),
+        Err(payload) => Err(NodeError::Hook {
+            hook: hook.name().to_string(),
+            message: format!("panicked: {}", panic_message(payload.as_ref())),
+        }),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    pub node_type: String,
+    pub runs: u64,
+    pub failures: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+// Counts and times node runs per node type since the app started.
+#[derive(Default)]
+pub struct MetricsHook {
+    by_type: Mutex<BTreeMap<String, NodeMetrics>>,
+}
+
+impl MetricsHook {
+    pub const NAME: &'static str = "metrics";
+
+    // By node type name.
+    pub fn snapshot(&self) -> Vec<NodeMetrics> {
+        self.by_type.lock().values().cloned().collect()
+    }
+
+    fn record(&self, node: &WorkflowNode, duration: Duration, failed: bool) {
+        let duration_ms = duration.as_millis() as u64;
+        let mut by_type = self.by_type.lock();
+        let metrics = by_type
+            .entry(node.node_type.clone())
+            .or_insert_with(|| NodeMetrics {
+                node_type: node.node_type.clone(),
+                ..NodeMetrics::default()
+            });
+        metrics.runs += 1;
+        metrics.failures += u64::from(failed);
+        metrics.total_ms += duration_ms;
+        metrics.max_ms = metrics.max_ms.max(duration_ms);
+    }
+}
+
+impl ExecutionHook for MetricsHook {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn after_node(
+        &self,
+        node: &WorkflowNode,
+        _output: &mut Value,
+        duration: Duration,
+        _ctx: &mut ExecutionContext,
+    ) -> HookResult {
+        self.record(node, duration, false);
+        Ok(())
+    }
+
+    fn node_failed(
+        &self,
+        node: &WorkflowNode,
+        _error: &NodeError,
+        duration: Duration,
+        _ctx: &ExecutionContext,
+    ) {
+        self.record(node, duration, true);
+    }
+}
+
+// Writes a line to the app log for every node run, naming the execution,
+// node and outcome. Inputs and outputs are left out, as they may hold secrets.
+pub struct AuditHook;
+
+impl AuditHook {
+    pub const NAME: &'static str = "audit";
+}
+
+impl ExecutionHook for AuditHook {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn after_node(
+        &self,
+        node: &WorkflowNode,
+        _output: &mut Value,
+        duration: Duration,
+        ctx: &mut ExecutionContext,
+    ) -> HookResult {
+        tracing::info!(
+            "execution {} ran node {} ({}) in {} ms{}",
+            ctx.execution_id,
+            node.id,
+            node.node_type,
+            duration.as_millis(),
+            if ctx.dry_run { " as a dry run" } else { "" }
+        );
+        Ok(())
+    }
+
+    fn node_failed(
+        &self,
+        node: &WorkflowNode,
+        error: &NodeError,
+        duration: Duration,
+        ctx: &ExecutionContext,
+    ) {
+        tracing::info!(
+            "execution {} failed node {} ({}) after {} ms: {}",
+            ctx.execution_id,
+            node.id,
+            node.node_type,
+            duration.as_millis(),
+            error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Priority;
+    use crate::test_support::{edge, engine, node, wait_for_runs, workflow};
+    use crate::workflow_engine::find_profile;
+    use crate::ExecutionStatus;
+    use serde_json::json;
+
+    // Replaces the input of node "d", panics before node "boom" and records
+    // every output.
+    #[derive(Default)]
+    struct TestHook {
+        outputs: Mutex<BTreeMap<String, Value>>,
+    }
+
+    impl ExecutionHook for TestHook {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn before_node(
+            &self,
+            node: &WorkflowNode,
+            input: &mut Value,
+            _ctx: &mut ExecutionContext,
+        ) -> HookResult {
+            match node.id.as_str() {
+                "d" => *input = json!({ "rewritten": true }),
+                "boom" => panic!("hook bug"),
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn after_node(
+            &self,
+            node: &WorkflowNode,
+            output: &mut Value,
+            _duration: Duration,
+            _ctx: &mut ExecutionContext,
+        ) -> HookResult {
+            self.outputs.lock().insert(node.id.clone(), output.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_rewrite_inputs_and_a_panic_fails_only_its_node() {
+        let (db, engine) = engine();
+        let hook = Arc::new(TestHook::default());
+        assert!(engine.hooks().register(hook.clone()));
+        assert!(!engine.hooks().register(Arc::new(TestHook::default())));
+
+        let panics = workflow(
+            "panics",
+            vec![
+                node("t", "trigger", json!({})),
+                node("boom", "delay", json!({ "duration_ms": 0 })),
+            ],
+            vec![edge("t", "boom")],
+        );
+        let rewrites = workflow(
+            "rewrites",
+            vec![
+                node("t", "trigger", json!({})),
+                node("d", "delay", json!({ "duration_ms": 0 })),
+            ],
+            vec![edge("t", "d")],
+        );
+        let profile = find_profile("default").unwrap();
+        let mut runs = Vec::new();
+        for workflow in [&panics, &rewrites] {
+            db.lock().create_workflow(workflow).unwrap();
+            let execution_id = engine
+                .execute_workflow(workflow, &profile, Priority::default(), None)
+                .unwrap();
+            wait_for_runs(&engine, &workflow.id).await;
+            runs.push(db.lock().get_execution(&execution_id).unwrap());
+        }
+
+        assert_eq!(runs[0].status, ExecutionStatus::Failed);
+        let error = runs[0].error.as_deref().unwrap();
+        assert!(error.contains("panicked: hook bug"), "{}", error);
+        // The engine carries on with the next run.
+        assert_eq!(runs[1].status, ExecutionStatus::Completed);
+        assert_eq!(hook.outputs.lock()["d"], json!({ "rewritten": true }));
+    }
+}
@@ -0,0 +1,118 @@
+/*!
+ * Limits - caps on workflow size, checked whenever a workflow is stored
+ *
+ * Counts are checked before any node is looked at, and each node's `data` is
+ * measured by streaming its JSON into a counter that gives up as soon as the
+ * cap is passed, so the check costs at most one pass over data that is within
+ * limits and never allocates.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use thiserror::Error;
+
+use crate::Workflow;
+
+pub const DEFAULT_MAX_NODES: usize = 5_000;
+pub const DEFAULT_MAX_EDGES: usize = 20_000;
+pub const DEFAULT_MAX_NODE_DATA_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowLimits {
+    #[serde(default = "default_max_nodes")]
+    pub max_nodes: usize,
+    #[serde(default = "default_max_edges")]
+    pub max_edges: usize,
+    // Size of one node's `data` serialized as compact JSON
+    #[serde(default = "default_max_node_data_bytes")]
+    pub max_node_data_bytes: usize,
+}
+
+fn default_max_nodes() -> usize {
+    DEFAULT_MAX_NODES
+}
+
+fn default_max_edges() -> usize {
+    DEFAULT_MAX_EDGES
+}
+
+fn default_max_node_data_bytes() -> usize {
+    DEFAULT_MAX_NODE_DATA_BYTES
+}
+
+impl Default for WorkflowLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: DEFAULT_MAX_NODES,
+            max_edges: DEFAULT_MAX_EDGES,
+            max_node_data_bytes: DEFAULT_MAX_NODE_DATA_BYTES,
+        }
+    }
+}
+
+// `limit` is the preference name, so the message says what to raise.
+#[derive(Debug, Clone, Error, Serialize)]
+#[error("workflow exceeds {limit} ({max}): {found}")]
+pub struct LimitExceeded {
+    pub limit: &'static str,
+    pub max: usize,
+    pub found: String,
+    // Set for `max_node_data_bytes`
+    pub node_id: Option<String>,
+}
+
+impl WorkflowLimits {
+    pub fn check(&self, workflow: &Workflow) -> Result<(), LimitExceeded> {
+        if workflow.nodes.len() > self.max_nodes {
+            return Err(LimitExceeded {
+                limit: "max_nodes",
+                max: self.max_nodes,
+                found: format!("{} nodes", workflow.nodes.len()),
+                node_id: None,
+            });
+        }
+        if workflow.edges.len() > self.max_edges {
+            return Err(LimitExceeded {
+                limit: "max_edges",
+                max: self.max_edges,
+                found: format!("{} edges", workflow.edges.len()),
+                node_id: None,
+            });
+        }
+        for node in &workflow.nodes {
+            let mut counter = SizeCounter {
+                size: 0,
+                max: self.max_node_data_bytes,
+            };
+            if serde_json::to_writer(&mut counter, &node.data).is_err() {
+                return Err(LimitExceeded {
+                    limit: "max_node_data_bytes",
+                    max: self.max_node_data_bytes,
+                    found: format!("data of node {}", node.id),
+                    node_id: Some(node.id.clone()),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Fails the write that takes `size` past `max`, which aborts serialization.
+struct SizeCounter {
+    size: usize,
+    max: usize,
+}
+
+impl io::Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.size += buf.len();
+        if self.size > self.max {
+            return Err(io::Error::other("size limit reached"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+/*!
+ * Listener - pieces shared by the local HTTP listeners
+ *
+ * The webhook listener and the control API each keep their settings as JSON
+ * in `app_meta`, check every request against a random shared token and
+ * report errors as `{"error": ...}`.
+ */
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::database::{Database, DatabaseError};
+
+const TOKEN_LEN: usize = 32;
+
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_LEN];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE64.encode(bytes)
+}
+
+// Takes as long for a near miss as for a wrong first byte.
+pub fn tokens_match(provided: &[u8], expected: &[u8]) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .iter()
+            .zip(expected)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+pub fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+// Settings stored under `key`; on first use, `defaults` are saved.
+pub fn load_settings<T, E>(db: &Database, key: &str, defaults: impl FnOnce() -> T) -> Result<T, E>
+where
+    T: Serialize + DeserializeOwned,
+    E: From<DatabaseError> + From<serde_json::Error>,
+{
+    if let Some(raw) = db.get_meta(key)? {
+        return Ok(serde_json::from_str(&raw)?);
+    }
+    let settings = defaults();
+    save_settings::<T, E>(db, key, &settings)?;
+    Ok(settings)
+}
+
+pub fn save_settings<T, E>(db: &Database, key: &str, settings: &T) -> Result<(), E>
+where
+    T: Serialize,
+    E: From<DatabaseError> + From<serde_json::Error>,
+{
+    db.set_meta(key, &serde_json::to_string(settings)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_db;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        token: String,
+    }
+
+    fn load(db: &Database) -> Result<Settings, DatabaseError> {
+        load_settings(db, "test.settings", || Settings {
+            token: generate_token(),
+        })
+    }
+
+    #[test]
+    fn tokens_compare_by_content() {
+        let token = generate_token();
+        assert_ne!(token, generate_token());
+        assert!(tokens_match(token.as_bytes(), token.clone().as_bytes()));
+        assert!(!tokens_match(&token.as_bytes()[1..], token.as_bytes()));
+        assert!(!tokens_match(b"", token.as_bytes()));
+
+        let mut near_miss = token.clone().into_bytes();
+        near_miss[TOKEN_LEN / 2] ^= 1;
+        assert!(!tokens_match(&near_miss, token.as_bytes()));
+    }
+
+    #[test]
+    fn first_load_saves_the_defaults() {
+        let db = temp_db();
+        let first = load(&db).unwrap();
+        assert_eq!(load(&db).unwrap(), first);
+
+        let rotated = Settings {
+            token: generate_token(),
+        };
+        save_settings::<_, DatabaseError>(&db, "test.settings", &rotated).unwrap();
+        assert_eq!(load(&db).unwrap(), rotated);
+    }
+}
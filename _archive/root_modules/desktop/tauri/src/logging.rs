@@ -0,0 +1,186 @@
+/*!
+ * Logging - the tracing subscriber, its level and the log file
+ *
+ * Events go to stdout and to `workflow.log` in the app data directory's
+ * `logs` folder. Once that file would pass `MAX_FILE_SIZE` it becomes
+ * `workflow.log.1`, older files shift up one and the oldest past
+ * `ROTATED_FILES` is overwritten, so logs never take more than
+ * `MAX_FILE_SIZE * (ROTATED_FILES + 1)` on disk. The level can be changed at
+ * runtime; it applies to both outputs.
+ */
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+// Under the app data directory.
+pub const LOGS_DIR: &str = "logs";
+
+const LOG_FILE: &str = "workflow.log";
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const ROTATED_FILES: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+struct Logging {
+    level: reload::Handle<LevelFilter, Registry>,
+    // None when the log file could not be opened
+    file: Option<PathBuf>,
+}
+
+static LOGGING: OnceCell<Logging> = OnceCell::new();
+
+// Installs the global subscriber at the default level. Without `dir`, or when
+// the file cannot be opened, logs go to stdout only.
+pub fn init(dir: Option<PathBuf>) {
+    let (filter, level) = reload::Layer::new(LevelFilter::from(LogLevel::default()));
+    let path = dir.map(|dir| dir.join(LOG_FILE));
+    let opened = path.as_deref().map(RotatingFile::open);
+
+    let (file_layer, failure) = match opened {
+        Some(Ok(file)) => (
+            Some(fmt::layer().with_ansi(false).with_writer(Mutex::new(file))),
+            None,
+        ),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(file_layer)
+        .init();
+
+    let file = match failure {
+        Some(e) => {
+            tracing::warn!("logging to stdout only, could not open log file: {}", e);
+            None
+        }
+        None => path,
+    };
+    let _ = LOGGING.set(Logging { level, file });
+}
+
+pub fn set_level(level: LogLevel) {
+    if let Some(logging) = LOGGING.get() {
+        if let Err(e) = logging.level.reload(LevelFilter::from(level)) {
+            tracing::warn!("failed to change log level: {}", e);
+        }
+    }
+}
+
+// The file currently being written.
+pub fn log_path() -> Option<&'static Path> {
+    LOGGING.get()?.file.as_deref()
+}
+
+// Opens `dir` in the platform's file manager.
+pub fn reveal(dir: &Path) -> io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let opener = "explorer";
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let opener = "xdg-open";
+
+    Command::new(opener).arg(dir).spawn().map(|_| ())
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Size-capped log file; see the module docs for the rotation scheme.
+struct RotatingFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = append(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+            size,
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Closed first, since Windows cannot rename an open file.
+        self.file = None;
+        for index in (1..ROTATED_FILES).rev() {
+            match fs::rename(self.rotated(index), self.rotated(index + 1)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.file = Some(File::create(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        let file = match self.file.take() {
+            Some(file) => file,
+            // A failed rotation left no file open
+            None => append(&self.path)?,
+        };
+        let written = self.file.insert(file).write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
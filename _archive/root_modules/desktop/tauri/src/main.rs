@@ -11,29 +11,132 @@
 use anyhow::Result;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tauri::{
-    AppHandle, CustomMenuItem, Manager, State, SystemTray, SystemTrayEvent,
-    SystemTrayMenu, SystemTrayMenuItem, Window, WindowEvent,
-};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State, SystemTray, SystemTrayEvent, Window, WindowEvent};
 use uuid::Uuid;
 
+mod activation;
+mod archive;
+mod audit;
+mod backup;
+mod blob;
+mod bundle;
+mod command_error;
 mod commands;
+mod control_api;
+mod credentials;
 mod database;
+mod deadlines;
+mod debugger;
+mod diagnostics;
+mod diagram;
+mod diff;
 mod encryption;
+mod environments;
+mod estimate;
+mod events;
+mod execution_log;
+mod expr;
+mod file_watcher;
+mod folders;
+mod graph;
+mod hooks;
+mod limits;
+mod listener;
+mod logging;
+mod node_cache;
+mod nodes;
+mod normalize;
+mod notifications;
+mod plans;
+mod profiles;
+mod rate_limit;
+mod recovery;
+mod resources;
+mod retention;
+mod run_blobs;
+mod sandbox;
+mod scheduler;
+mod schema;
+mod script;
+mod secrets;
+mod service;
+mod signing;
+mod sink;
+mod template;
+mod templates;
+#[cfg(test)]
+mod test_support;
+mod validation;
 mod workflow_engine;
+mod workflow_state;
+mod webhook_server;
 mod websocket_client;
+mod windows;
 
+use audit::{AuditEntry, AuditFilter};
+use command_error::CommandError;
+use activation::Activation;
 use commands::*;
-use database::Database;
-use workflow_engine::WorkflowEngine;
-use websocket_client::WebSocketClient;
+use credentials::{SecretKeyStore, StoredToken, TokenStore};
+use database::{Database, WorkflowSummary};
+use deadlines::DeadlineManager;
+use debugger::ContextSnapshot;
+use encryption::KdfParams;
+use environments::Environment;
+use events::{ExecutionEvent, ExecutionProgress};
+use file_watcher::FileWatchers;
+use folders::{Folder, FolderDeleteSummary, FolderListing};
+use graph::{FragmentMerge, GraphError, NodeImpact, WorkflowFragment};
+use hooks::AuditHook;
+use limits::WorkflowLimits;
+use logging::LogLevel;
+use nodes::NodeRegistry;
+use normalize::NormalizationReport;
+use notifications::NotificationRule;
+use plans::ExecutionPlan;
+use profiles::{ActiveProfile, ProfileError};
+use rate_limit::RateLimit;
+use resources::{ResourceMonitor, SystemInfo};
+use retention::RetentionPolicy;
+use run_blobs::BlobStore;
+use sandbox::SandboxLimits;
+use scheduler::Priority;
+use schema::FieldError;
+use secrets::SecretScope;
+use service::RunSettings;
+use sink::OutputSink;
+use validation::ValidationProfiles;
+use windows::WindowContexts;
+use workflow_engine::{
+    ActiveExecution, ConcurrencyPolicy, ExecutionProfile, RunningNode, SeedContext,
+    StopAllSummary, WorkflowEngine,
+};
+use websocket_client::{ConnectionTest, ExecutionUpdate, MessageHandler, WebSocketClient, WsMessage};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppState {
     pub machine_id: String,
+    pub machine_id_source: MachineIdSource,
+    // Persisted only in the OS keychain (see `credentials`)
+    #[serde(skip)]
     pub auth_token: Option<String>,
+    pub auth_token_issued_at: Option<chrono::DateTime<chrono::Utc>>,
+    // Encrypts workflow secrets; kept in the OS keychain (see `credentials`)
+    #[serde(skip)]
+    pub secret_key: String,
     pub user_preferences: UserPreferences,
+    // Name of the profile whose database is open
+    pub profile: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MachineIdSource {
+    Hardware,
+    PersistedFallback,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +145,73 @@ pub struct UserPreferences {
     pub auto_save: bool,
     pub notifications: bool,
     pub shortcuts: bool,
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    #[serde(default = "default_watts_per_core")]
+    pub watts_per_core: f64,
+    #[serde(default = "default_grams_co2_per_kwh")]
+    pub grams_co2_per_kwh: f64,
+    #[serde(default)]
+    pub execution_retention: RetentionPolicy,
+    #[serde(default = "default_min_free_memory_mb")]
+    pub min_free_memory_mb: u64,
+    // Keyed by bucket: a node type or a `rate_limit_bucket` name
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, RateLimit>,
+    // Runs a workflow may have waiting when `concurrency_policy` is queue
+    #[serde(default = "default_max_queue_depth")]
+    pub max_queue_depth: usize,
+    // Nodes that may run at once across every execution
+    #[serde(default = "default_max_concurrent_nodes")]
+    pub max_concurrent_nodes: usize,
+    // Earlier versions kept per workflow; 0 keeps none
+    #[serde(default = "default_max_versions")]
+    pub max_versions: usize,
+    #[serde(default)]
+    pub log_level: LogLevel,
+    #[serde(default)]
+    pub workflow_limits: WorkflowLimits,
+    // How long a run start's idempotency key dedupes repeats of it
+    #[serde(default = "default_idempotency_window_secs")]
+    pub idempotency_window_secs: u64,
+    // Caps on each plugin node call
+    #[serde(default)]
+    pub node_sandbox: SandboxLimits,
+    // Sync server endpoint; `WEBSOCKET_URL_ENV` overrides it when set
+    #[serde(default = "default_websocket_url")]
+    pub websocket_url: String,
+}
+
+fn default_watts_per_core() -> f64 {
+    resources::DEFAULT_WATTS_PER_CORE
+}
+
+fn default_grams_co2_per_kwh() -> f64 {
+    resources::DEFAULT_GRAMS_CO2_PER_KWH
+}
+
+fn default_min_free_memory_mb() -> u64 {
+    resources::DEFAULT_MIN_FREE_MEMORY_MB
+}
+
+fn default_max_queue_depth() -> usize {
+    workflow_engine::DEFAULT_MAX_QUEUE_DEPTH
+}
+
+fn default_max_concurrent_nodes() -> usize {
+    scheduler::DEFAULT_MAX_CONCURRENT_NODES
+}
+
+fn default_max_versions() -> usize {
+    database::DEFAULT_MAX_VERSIONS
+}
+
+fn default_idempotency_window_secs() -> u64 {
+    workflow_engine::DEFAULT_IDEMPOTENCY_WINDOW_SECS
+}
+
+fn default_websocket_url() -> String {
+    DEFAULT_WEBSOCKET_URL.to_string()
 }
 
 impl Default for UserPreferences {
@@ -51,6 +221,65 @@ impl Default for UserPreferences {
             auto_save: true,
             notifications: true,
             shortcuts: true,
+            concurrency_policy: ConcurrencyPolicy::default(),
+            watts_per_core: default_watts_per_core(),
+            grams_co2_per_kwh: default_grams_co2_per_kwh(),
+            execution_retention: RetentionPolicy::default(),
+            min_free_memory_mb: default_min_free_memory_mb(),
+            rate_limits: BTreeMap::new(),
+            max_queue_depth: default_max_queue_depth(),
+            max_concurrent_nodes: default_max_concurrent_nodes(),
+            max_versions: default_max_versions(),
+            log_level: LogLevel::default(),
+            workflow_limits: WorkflowLimits::default(),
+            idempotency_window_secs: default_idempotency_window_secs(),
+            node_sandbox: SandboxLimits::default(),
+            websocket_url: default_websocket_url(),
+        }
+    }
+}
+
+const PREFERENCES_KEY: &str = "user.preferences";
+
+// Preferences live in the profile's database, so each profile keeps its own.
+impl UserPreferences {
+    fn load(db: &Database) -> database::Result<Self> {
+        match db.get_meta(PREFERENCES_KEY)? {
+            Some(raw) => Ok(serde_json::from_str(&raw)?),
+            None => Ok(Self::default()),
+        }
+    }
+    
+    fn save(&self, db: &Database) -> database::Result<()> {
+        db.set_meta(PREFERENCES_KEY, &serde_json::to_string(self)?)
+    }
+    
+    fn apply(&self, engine: &mut WorkflowEngine, db: &mut Database) {
+        logging::set_level(self.log_level);
+        db.set_max_versions(self.max_versions);
+        db.set_workflow_limits(self.workflow_limits);
+        engine.set_concurrency_policy(self.concurrency_policy);
+        engine.set_max_queue_depth(self.max_queue_depth);
+        engine.set_idempotency_window(Duration::from_secs(self.idempotency_window_secs));
+        engine.resource_monitor().set_min_free_memory_mb(self.min_free_memory_mb);
+        engine.rate_limiter().set_limits(&self.rate_limits);
+        engine.worker_pool().set_capacity(self.max_concurrent_nodes);
+        engine.registry().set_sandbox_limits(self.node_sandbox);
+        engine.set_desktop_notifications(self.notifications);
+    }
+    
+    // The endpoint to connect to: the environment override when it holds a
+    // valid URL, so CI can point the app at a local server, else the preference.
+    fn websocket_url(&self) -> String {
+        match std::env::var(WEBSOCKET_URL_ENV) {
+            Ok(url) if !url.is_empty() => match websocket_client::validate_url(&url) {
+                Ok(()) => url,
+                Err(e) => {
+                    tracing::warn!("ignoring {}: {}", WEBSOCKET_URL_ENV, e);
+                    self.websocket_url.clone()
+                }
+            },
+            _ => self.websocket_url.clone(),
         }
     }
 }
@@ -65,6 +294,19 @@ pub struct Workflow {
     pub status: WorkflowStatus,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    // Re-run executions the app exited in the middle of on next launch
+    #[serde(default)]
+    pub resume_on_startup: bool,
+    // The most recently finished run. Kept up to date by the database as runs
+    // finish or are cleaned up; saving a workflow leaves them alone.
+    #[serde(default)]
+    pub last_execution_id: Option<String>,
+    #[serde(default)]
+    pub last_execution_status: Option<ExecutionStatus>,
+    #[serde(default)]
+    pub last_execution_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,126 +341,513 @@ pub enum WorkflowStatus {
     Archived,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Execution {
+    pub id: String,
+    pub workflow_id: String,
+    pub status: ExecutionStatus,
+    pub profile: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub error: Option<String>,
+    // Process CPU time sampled while the run was active
+    #[serde(default)]
+    pub cpu_time_ms: Option<u64>,
+    // Time the run's nodes spent held back by rate limits
+    #[serde(default)]
+    pub rate_limit_wait_ms: Option<u64>,
+    // Pinned executions are exempt from retention cleanup
+    #[serde(default)]
+    pub pinned: bool,
+    // Latest the run reported; None until it starts running nodes
+    #[serde(default)]
+    pub progress: Option<ExecutionProgress>,
+}
+
+// Dead-letter record of a failed run, kept with its input so it can be
+// replayed. Resolved once a replay succeeds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedExecution {
+    pub id: String,
+    // The run that first failed
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub profile: String,
+    // Node that failed on the latest attempt, if the failure was in a node
+    pub node_id: Option<String>,
+    pub input: serde_json::Value,
+    pub error: String,
+    pub failed_at: chrono::DateTime<chrono::Utc>,
+    pub replay_count: u32,
+    pub last_replay_id: Option<String>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Filters for a node search; unset fields match every node.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeQuery {
+    pub node_type: Option<String>,
+    // Case-insensitive substring of the node's serialized `data`
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeHit {
+    pub workflow_id: String,
+    pub workflow_name: String,
+    pub node_id: String,
+    pub node_type: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    // Flowchart diagrams, for documentation
+    Mermaid,
+    Dot,
+    // Standalone Python script; see `script`
+    Script,
+}
+
+// Entries available on a workflow's undo and redo stacks.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct UndoState {
+    pub undo: usize,
+    pub redo: usize,
+}
+
+// What a key rotation re-encrypted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub secrets: usize,
+    // Workflows re-encrypted at rest, with their snapshots; 0 when the
+    // at-rest passphrase was left as it was
+    pub workflows: usize,
+}
+
+// A saved earlier state of a workflow; see `get_workflow_version` for its content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowVersion {
+    pub version: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub name: String,
+    pub node_count: usize,
+    pub edge_count: usize,
+}
+
+// A workflow kept at hand in the tray menu.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedWorkflow {
+    pub workflow_id: String,
+    pub name: String,
+    pub pinned_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+    // Still running when the app exited unexpectedly
+    Interrupted,
+}
+
 fn create_tray() -> SystemTray {
-    let quit = CustomMenuItem::new("quit".to_string(), "Quit");
-    let hide = CustomMenuItem::new("hide".to_string(), "Hide");
-    let show = CustomMenuItem::new("show".to_string(), "Show");
-    let create_workflow = CustomMenuItem::new("create_workflow".to_string(), "Create Workflow");
+    SystemTray::new().with_menu(windows::tray_menu(&[], &[]))
+}
+
+const APP_TITLE: &str = "Workflow Platform";
+
+const DEFAULT_WEBSOCKET_URL: &str = "wss://api.workflow.com/ws";
+
+const WEBSOCKET_URL_ENV: &str = "WORKFLOW_WEBSOCKET_URL";
+
+const MACHINE_ID_KEY: &str = "machine_id";
+
+const REAUTH_WINDOW_SECS: i64 = 300;
+
+// Under the app data directory; shared by every profile.
+const PLUGINS_DIR: &str = "plugins";
+
+// How long quitting waits for running workflows to stop before exiting anyway.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long stopping every run waits before reporting runs that kept going.
+const STOP_ALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Prefer the hardware id; when it is unavailable, reuse the id persisted on a
+// previous launch so per-machine features don't see a new machine every start.
+fn resolve_machine_id(db: &Database) -> database::Result<(String, MachineIdSource)> {
+    derive_machine_id(db, machine_uid::get().map_err(|e| e.to_string()))
+}
+
+fn derive_machine_id(
+    db: &Database,
+    hardware_id: std::result::Result<String, String>,
+) -> database::Result<(String, MachineIdSource)> {
+    let stored = db.get_meta(MACHINE_ID_KEY)?;
+    
+    match hardware_id {
+        Ok(hardware_id) => {
+            if stored.as_deref() != Some(hardware_id.as_str()) {
+                if let Some(previous) = &stored {
+                    tracing::warn!(
+                        "machine id changed from {} to {}, re-deriving",
+                        previous,
+                        hardware_id
+                    );
+                }
+                db.set_meta(MACHINE_ID_KEY, &hardware_id)?;
+            }
+            Ok((hardware_id, MachineIdSource::Hardware))
+        }
+        Err(e) => {
+            tracing::warn!("hardware machine id unavailable: {}", e);
+            let machine_id = match stored {
+                Some(id) => id,
+                None => {
+                    let id = Uuid::new_v4().to_string();
+                    db.set_meta(MACHINE_ID_KEY, &id)?;
+                    id
+                }
+            };
+            Ok((machine_id, MachineIdSource::PersistedFallback))
+        }
+    }
+}
+
+// Workflows encrypted at rest with the machine key open with it; any others
+// stay locked, listed but unreadable, until `unlock_database` is given their
+// passphrase.
+fn unlock_with_machine_key(db: &Database, machine_id: &str) {
+    if let Err(e) = db.unlock(machine_id) {
+        tracing::warn!("encrypted workflows stay locked: {}", e);
+    }
+}
+
+// For commands that could lock the user out of their data, such as rotating
+// the secrets key: the session must have logged in within `REAUTH_WINDOW_SECS`.
+fn require_recent_login(
+    state: &AppState,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), CommandError> {
+    match (&state.auth_token, state.auth_token_issued_at) {
+        (Some(_), Some(issued_at)) if (now - issued_at).num_seconds() <= REAUTH_WINDOW_SECS => {
+            Ok(())
+        }
+        _ => Err(CommandError::Auth(
+            "log in again to confirm this change".to_string(),
+        )),
+    }
+}
+
+fn app_data_dir(app: &AppHandle) -> Result<std::path::PathBuf, CommandError> {
+    app.path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| CommandError::Internal("app data directory unavailable".to_string()))
+}
+
+// Names a non-default profile in the main window title, so it is always clear
+// which set of workflows is being edited.
+fn show_profile(app: &AppHandle, profile: &str) {
+    let title = if profile == profiles::DEFAULT_PROFILE {
+        APP_TITLE.to_string()
+    } else {
+        format!("{} ({})", APP_TITLE, profile)
+    };
+    if let Some(window) = app.get_window(windows::MAIN_WINDOW) {
+        if let Err(e) = window.set_title(&title) {
+            tracing::warn!("failed to set window title: {}", e);
+        }
+    }
+}
+
+// Stops running workflows, closes out their execution records and the
+// WebSocket connection, then exits the app.
+fn shutdown_and_exit(app: &AppHandle) {
+    let engine = app.state::<Arc<Mutex<WorkflowEngine>>>().inner().clone();
+    let db = app.state::<Arc<Mutex<Database>>>().inner().clone();
+    let ws_client = app.state::<Arc<Mutex<WebSocketClient>>>().inner().clone();
+    let app = app.clone();
     
-    let tray_menu = SystemTrayMenu::new()
-        .add_item(show)
-        .add_item(hide)
-        .add_native_item(SystemTrayMenuItem::Separator)
-        .add_item(create_workflow)
-        .add_native_item(SystemTrayMenuItem::Separator)
-        .add_item(quit);
+    tauri::async_runtime::spawn(async move {
+        let stopped = engine.lock().shutdown(SHUTDOWN_TIMEOUT);
+        if !stopped.await {
+            tracing::warn!("executions still running after {:?}, exiting anyway", SHUTDOWN_TIMEOUT);
+        }
+        
+        match db.lock().interrupt_running_executions("interrupted by shutdown") {
+            Ok(0) => {}
+            Ok(count) => tracing::warn!("marked {} unfinished executions as failed", count),
+            Err(e) => tracing::error!("failed to close out running executions: {}", e),
+        }
+        
+        ws_client.lock().disconnect();
+        app.exit(0);
+    });
+}
+
+// Starts a pinned workflow picked from the tray with the default profile.
+// There is no window to report to, so the outcome is only logged.
+fn run_from_tray(app: &AppHandle, workflow_id: &str) {
+    let engine = app.state::<Arc<Mutex<WorkflowEngine>>>().inner().clone();
+    let db = app.state::<Arc<Mutex<Database>>>().inner().clone();
+    let workflow_id = workflow_id.to_string();
     
-    SystemTray::new().with_menu(tray_menu)
+    tauri::async_runtime::spawn(async move {
+        let settings = RunSettings {
+            priority: Priority::High,
+            ..Default::default()
+        };
+        match service::execute_workflow(&db, &engine, &workflow_id, settings, None).await {
+            Ok(execution_id) => {
+                tracing::info!("started workflow {} from the tray as {}", workflow_id, execution_id)
+            }
+            Err(e) => tracing::error!("failed to start workflow {} from the tray: {}", workflow_id, e),
+        }
+    });
+}
+
+// Watcher failures (such as a missing folder) are logged rather than failing
+// the command that changed the workflow.
+fn sync_file_watch(watchers: &Mutex<FileWatchers>, workflow_id: &str) {
+    if let Err(e) = watchers.lock().sync(workflow_id) {
+        tracing::warn!("could not watch files for workflow {}: {}", workflow_id, e);
+    }
 }
 
 fn main() {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+    let context = tauri::generate_context!();
     
-    // Get machine ID
-    let machine_id = machine_uid::get().unwrap_or_else(|_| Uuid::new_v4().to_string());
-    
-    // Initialize app state
-    let app_state = Arc::new(Mutex::new(AppState {
-        machine_id,
-        auth_token: None,
-        user_preferences: UserPreferences::default(),
-    }));
+    // Initialize logging; the profile's level is applied with its preferences
+    logging::init(
+        tauri::api::path::app_data_dir(context.config()).map(|dir| dir.join(logging::LOGS_DIR)),
+    );
     
     // Build Tauri app
     tauri::Builder::default()
-        .manage(app_state)
         .system_tray(create_tray())
         .on_system_tray_event(|app, event| match event {
             SystemTrayEvent::LeftClick { .. } => {
-                let window = app.get_window("main").unwrap();
-                if window.is_visible().unwrap() {
-                    window.hide().unwrap();
-                } else {
-                    window.show().unwrap();
-                    window.set_focus().unwrap();
+                if let Some(window) = windows::target_window(app) {
+                    if window.is_visible().unwrap() {
+                        window.hide().unwrap();
+                    } else {
+                        windows::show_window(&window).unwrap();
+                    }
                 }
             }
             SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
                 "quit" => {
-                    std::process::exit(0);
+                    shutdown_and_exit(app);
                 }
                 "hide" => {
-                    let window = app.get_window("main").unwrap();
-                    window.hide().unwrap();
+                    if let Some(window) = windows::target_window(app) {
+                        window.hide().unwrap();
+                    }
                 }
                 "show" => {
-                    let window = app.get_window("main").unwrap();
-                    window.show().unwrap();
-                    window.set_focus().unwrap();
+                    if let Some(window) = windows::target_window(app) {
+                        windows::show_window(&window).unwrap();
+                    }
                 }
                 "create_workflow" => {
-                    let window = app.get_window("main").unwrap();
-                    window.emit("create-workflow", ()).unwrap();
+                    if let Some(window) = windows::target_window(app) {
+                        window.emit("create-workflow", ()).unwrap();
+                    }
+                }
+                id => {
+                    if let Some(workflow_id) = id.strip_prefix(windows::PINNED_MENU_PREFIX) {
+                        run_from_tray(app, workflow_id);
+                        return;
+                    }
+                    let window = id
+                        .strip_prefix(windows::WINDOW_MENU_PREFIX)
+                        .and_then(|label| app.get_window(label));
+                    if let Some(window) = window {
+                        windows::show_window(&window).unwrap();
+                    }
                 }
-                _ => {}
             },
             _ => {}
         })
+        .on_window_event(|event| match event.event() {
+            // Closing the last window quits, except on macOS where it hides as
+            // the main window always does there.
+            WindowEvent::CloseRequested { api, .. } => {
+                let window = event.window();
+                let last = window.app_handle().windows().len() <= 1;
+                #[cfg(target_os = "macos")]
+                if last || window.label() == windows::MAIN_WINDOW {
+                    api.prevent_close();
+                    window.hide().unwrap();
+                }
+                #[cfg(not(target_os = "macos"))]
+                if last {
+                    api.prevent_close();
+                    shutdown_and_exit(&window.app_handle());
+                }
+            }
+            WindowEvent::Destroyed => {
+                let app = event.window().app_handle();
+                let label = event.window().label();
+                app.state::<Arc<Mutex<WindowContexts>>>().lock().remove(label);
+                windows::refresh_tray(&app, Some(label));
+            }
+            _ => {}
+        })
         .setup(|app| {
-            // Initialize database
-            let db_path = app
-                .path_resolver()
-                .app_data_dir()
-                .unwrap()
-                .join("workflows.db");
+            // Initialize the selected profile's database
+            let data_dir = app.path_resolver().app_data_dir().unwrap();
+            let profile = profiles::startup_profile(&data_dir);
+            let mut db = Database::new(&profiles::database_path(&data_dir, &profile))?;
+            let preferences = UserPreferences::load(&db)?;
+            show_profile(&app.handle(), &profile);
             
-            let db = Database::new(&db_path)?;
-            app.manage(Arc::new(Mutex::new(db)));
+            // Resolve machine ID and initialize app state
+            let (machine_id, machine_id_source) = resolve_machine_id(&db)?;
+            let secret_key =
+                secrets::load_or_create_key(&mut db, &SecretKeyStore::new(&profile), &machine_id);
+            unlock_with_machine_key(&db, &machine_id);
+            let activation = Activation::load(&data_dir, &machine_id);
+            let credentials = TokenStore::new(&machine_id);
+            let stored = credentials.load();
+            app.manage(credentials);
             
-            // Initialize workflow engine
-            let engine = WorkflowEngine::new();
-            app.manage(Arc::new(Mutex::new(engine)));
+            let state = Arc::new(Mutex::new(AppState {
+                machine_id,
+                machine_id_source,
+                auth_token: stored.as_ref().map(|stored| stored.token.clone()),
+                auth_token_issued_at: stored.map(|stored| stored.issued_at),
+                secret_key: secret_key.clone(),
+                user_preferences: preferences.clone(),
+                profile,
+            }));
+            app.manage(state.clone());
             
-            // Initialize WebSocket client
-            let ws_client = WebSocketClient::new("wss://api.workflow.com/ws");
-            app.manage(Arc::new(Mutex::new(ws_client)));
+            let db = Arc::new(Mutex::new(db));
+            app.manage(db.clone());
+            retention::spawn_cleanup_task(state, db.clone());
+            retention::spawn_idempotency_sweep(db.clone());
             
-            // Set up window event handlers
-            let main_window = app.get_window("main").unwrap();
+            app.manage(Arc::new(Mutex::new(ValidationProfiles::default())));
             
-            main_window.on_window_event(move |event| match event {
-                WindowEvent::CloseRequested { api, .. } => {
-                    #[cfg(target_os = "macos")]
-                    {
-                        api.prevent_close();
-                        let window = main_window.clone();
-                        window.hide().unwrap();
+            // Initialize workflow engine
+            let mut registry = NodeRegistry::with_builtin();
+            registry.load_plugins(&data_dir.join(PLUGINS_DIR));
+            registry.set_premium_unlocked(activation.status().active);
+            app.manage(Arc::new(Mutex::new(activation)));
+            // Blobs left behind belong to runs of a previous session.
+            BlobStore::default().clear();
+            let mut engine = WorkflowEngine::with_registry(db.clone(), registry);
+            preferences.apply(&mut engine, &mut db.lock());
+            engine.set_secret_key(&secret_key);
+            engine.hooks().register(Arc::new(AuditHook));
+            templates::validate_builtin(engine.registry())?;
+            
+            // Forward execution events to the frontend
+            let mut events = engine.subscribe_events();
+            let handle = app.handle();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    match events.recv().await {
+                        Ok(event) => {
+                            match &event {
+                                ExecutionEvent::ResourceThrottled { .. } => {
+                                    let _ = handle.emit_all("resource-throttled", event.clone());
+                                }
+                                ExecutionEvent::InputRequested { .. } => {
+                                    let _ = handle.emit_all("input-requested", event.clone());
+                                }
+                                ExecutionEvent::BreakpointHit { .. } => {
+                                    let _ = handle.emit_all("breakpoint-hit", event.clone());
+                                }
+                                ExecutionEvent::DeadlineAtRisk { .. } => {
+                                    let _ = handle.emit_all("deadline-at-risk", event.clone());
+                                }
+                                ExecutionEvent::Notification { title, message, .. } => {
+                                    let shown = tauri::api::notification::Notification::new(
+                                        &handle.config().tauri.bundle.identifier,
+                                    )
+                                    .title(title)
+                                    .body(message)
+                                    .show();
+                                    if let Err(e) = shown {
+                                        tracing::warn!("failed to show notification: {}", e);
+                                    }
+                                }
+                                _ => {}
+                            }
+                            let _ = handle.emit_all("execution-event", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                            tracing::warn!("dropped {} execution events", skipped);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
                     }
                 }
-                _ => {}
             });
             
+            app.manage(engine.resource_monitor());
+            // Before anything can start new runs. Inside the async runtime,
+            // since resumed runs are spawned on it.
+            let recovered = tauri::async_runtime::block_on(async {
+                recovery::recover_executions(&db, &engine)
+            })?;
+            if recovered.interrupted > 0 || recovered.cancelled > 0 {
+                tracing::warn!(
+                    "closed out {} interrupted and {} stopped executions from the last session, resumed {}",
+                    recovered.interrupted,
+                    recovered.cancelled,
+                    recovered.resumed.len()
+                );
+            }
+            let engine = Arc::new(Mutex::new(engine));
+            app.manage(engine.clone());
+            let deadlines = DeadlineManager::default();
+            deadlines.spawn(db.clone(), engine.clone());
+            app.manage(deadlines);
+            webhook_server::spawn_listener(db.clone(), engine.clone())?;
+            control_api::spawn_listener(
+                db.clone(),
+                engine.clone(),
+                std::env::args().any(|arg| arg == control_api::LAUNCH_FLAG),
+            )?;
+            
+            let mut watchers = FileWatchers::new(db, engine);
+            watchers.sync_all()?;
+            app.manage(Arc::new(Mutex::new(watchers)));
+            
+            // Initialize WebSocket client
+            let ws_client = WebSocketClient::new(&preferences.websocket_url());
+            app.manage(Arc::new(Mutex::new(ws_client)));
+            
+            app.manage(Arc::new(Mutex::new(WindowContexts::default())));
+            windows::refresh_tray(&app.handle(), None);
+            
             // Register global shortcuts
             if let Ok(mut shortcuts) = app.global_shortcut_manager() {
+                let handle = app.handle();
                 shortcuts
                     .register("CmdOrCtrl+Shift+W", move || {
-                        if let Some(window) = app.get_window("main") {
+                        if let Some(window) = windows::target_window(&handle) {
                             if window.is_visible().unwrap() {
                                 window.hide().unwrap();
                             } else {
-                                window.show().unwrap();
-                                window.set_focus().unwrap();
+                                windows::show_window(&window).unwrap();
                             }
                         }
                     })
                     .unwrap();
                 
+                let handle = app.handle();
                 shortcuts
                     .register("CmdOrCtrl+Shift+N", move || {
-                        if let Some(window) = app.get_window("main") {
+                        if let Some(window) = windows::target_window(&handle) {
                             window.emit("quick-create-workflow", ()).unwrap();
                         }
                     })
@@ -236,38 +865,172 @@ fn main() {
             // Workflow commands
             create_workflow,
             get_workflows,
+            get_workflow_summaries,
             get_workflow,
             update_workflow,
+            autosave_workflow,
+            undo_workflow,
+            redo_workflow,
+            get_undo_state,
+            get_workflow_versions,
+            get_workflow_version,
+            restore_workflow_version,
+            diff_workflows,
+            diff_workflow_versions,
             delete_workflow,
+            delete_node,
+            merge_into_workflow,
+            copy_node_config,
+            toggle_node_enabled,
+            analyze_node_impact,
+            update_node_positions,
+            search_nodes,
+            get_workflow_tags,
+            set_workflow_tags,
+            pin_workflow,
+            unpin_workflow,
+            get_pinned_workflows,
+            create_folder,
+            move_folder,
+            move_workflow_to_folder,
+            list_folder,
+            delete_folder,
+            get_output_sink,
+            set_output_sink,
+            get_notification_rules,
+            set_notification_rules,
+            delete_workflow_permanently,
+            restore_workflow,
+            list_trash,
+            purge_trash,
             execute_workflow,
+            compile_workflow,
+            execute_plan,
+            execute_from_node,
+            list_execution_profiles,
             stop_workflow,
+            stop_all_executions,
+            list_running_nodes,
+            kill_node,
+            pause_workflow,
+            resume_workflow,
+            debug_step,
+            debug_continue,
+            get_execution_context,
+            emergency_pause_all,
+            resume_all_executions,
+            provide_input,
+            get_execution_event_replay,
+            is_workflow_running,
+            get_queue_depth,
+            list_active_executions,
+            get_execution,
+            get_executions,
+            list_failed_executions,
+            clear_node_cache,
+            replay_execution,
+            set_execution_pinned,
+            cleanup_old_executions,
+            prune_executions,
+            get_execution_energy_estimate,
             
             // Node commands
             get_node_types,
+            list_plugins,
+            get_node_metrics,
+            get_required_scopes,
+            get_node_schema,
             validate_node_config,
+            preview_node,
+            resolve_templates,
+            test_edge,
+            validate_workflow,
+            validate_all_workflows,
+            get_validation_profile,
             
             // System commands
             get_system_info,
             get_machine_id,
+            get_machine_id_source,
             
             // Preferences commands
             get_preferences,
             update_preferences,
+            get_active_profile,
+            list_profiles,
+            switch_profile,
             
             // File operations
             export_workflow,
             import_workflow,
+            create_incremental_backup,
+            restore_from_incremental_chain,
+            diff_against_backup,
+            backup_database,
+            restore_database,
+            get_database_stats,
+            vacuum_database,
+            export_bundle,
+            import_bundle,
+            export_all_workflows,
+            import_all_workflows,
+            export_execution_log,
+            generate_signing_key,
+            export_signed_workflow,
+            verify_signed_workflow,
+            get_webhook_settings,
+            set_webhook_listener,
+            rotate_webhook_secret,
+            get_control_api_settings,
+            set_control_api_listener,
+            rotate_control_api_token,
+            list_templates,
+            create_from_template,
+            save_as_template,
             
             // Encryption
             encrypt_data,
             decrypt_data,
+            test_encryption_health,
+            run_diagnostics,
+            set_log_level,
+            get_log_path,
+            open_log_folder,
+            activate,
+            get_activation_status,
+            estimate_execution,
+            create_deadline_schedule,
+            get_deadline_schedules,
+            delete_deadline_schedule,
+            set_secret,
+            delete_secret,
+            get_secrets,
+            create_environment,
+            get_environments,
+            set_active_environment,
+            set_environment_var,
+            get_state,
+            set_state,
+            increment_state,
+            rotate_encryption_key,
+            enable_encryption,
+            unlock_database,
+            get_audit_log,
             
             // WebSocket
             connect_websocket,
             disconnect_websocket,
             send_websocket_message,
+            test_websocket_connection,
+            set_websocket_url,
+            
+            // Windows
+            open_workflow_window,
+            get_window_context,
+            set_window_workflow,
+            list_windows,
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
 
@@ -277,21 +1040,44 @@ async fn login(
     username: String,
     password: String,
     state: State<'_, Arc<Mutex<AppState>>>,
-) -> Result<String, String> {
+    db: State<'_, Arc<Mutex<Database>>>,
+    credentials: State<'_, TokenStore>,
+) -> Result<String, CommandError> {
     // TODO: Implement actual authentication
-    let token = format!("token_{}", Uuid::new_v4());
-    state.lock().auth_token = Some(token.clone());
-    Ok(token)
+    let stored = StoredToken {
+        token: format!("token_{}", Uuid::new_v4()),
+        issued_at: chrono::Utc::now(),
+    };
+    credentials.save(&stored);
+    
+    {
+        let mut state = state.lock();
+        state.auth_token = Some(stored.token.clone());
+        state.auth_token_issued_at = Some(stored.issued_at);
+    }
+    audit::record_done(&db, &state, "login", format!("user {}", username));
+    Ok(stored.token)
 }
 
 #[tauri::command]
-async fn logout(state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
-    state.lock().auth_token = None;
+async fn logout(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    credentials: State<'_, TokenStore>,
+) -> Result<(), CommandError> {
+    credentials.clear();
+    
+    {
+        let mut state = state.lock();
+        state.auth_token = None;
+        state.auth_token_issued_at = None;
+    }
+    audit::record_done(&db, &state, "logout", "signed out");
     Ok(())
 }
 
 #[tauri::command]
-async fn get_auth_status(state: State<'_, Arc<Mutex<AppState>>>) -> Result<bool, String> {
+async fn get_auth_status(state: State<'_, Arc<Mutex<AppState>>>) -> Result<bool, CommandError> {
     Ok(state.lock().auth_token.is_some())
 }
 
@@ -300,7 +1086,7 @@ async fn create_workflow(
     name: String,
     description: Option<String>,
     db: State<'_, Arc<Mutex<Database>>>,
-) -> Result<Workflow, String> {
+) -> Result<Workflow, CommandError> {
     let workflow = Workflow {
         id: Uuid::new_v4().to_string(),
         name,
@@ -310,11 +1096,15 @@ async fn create_workflow(
         status: WorkflowStatus::Draft,
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        resume_on_startup: false,
+        last_execution_id: None,
+        last_execution_status: None,
+        last_execution_at: None,
     };
     
     db.lock()
-        .create_workflow(&workflow)
-        .map_err(|e| e.to_string())?;
+        .create_workflow(&workflow)?;
     
     Ok(workflow)
 }
@@ -322,117 +1112,1422 @@ async fn create_workflow(
 #[tauri::command]
 async fn get_workflows(
     db: State<'_, Arc<Mutex<Database>>>,
-) -> Result<Vec<Workflow>, String> {
-    db.lock()
-        .get_workflows()
-        .map_err(|e| e.to_string())
+) -> Result<Vec<Workflow>, CommandError> {
+    service::list_workflows(&db)
+        .await
+        .map_err(CommandError::from)
+}
+
+// Includes workflows still encrypted while the database is locked, which
+// `get_workflows` leaves out, so the UI can show them and ask to unlock.
+#[tauri::command]
+async fn get_workflow_summaries(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<WorkflowSummary>, CommandError> {
+    let readers = db.lock().readers();
+    readers.get_workflow_summaries().map_err(CommandError::from)
 }
 
 #[tauri::command]
 async fn get_workflow(
     id: String,
     db: State<'_, Arc<Mutex<Database>>>,
-) -> Result<Workflow, String> {
-    db.lock()
-        .get_workflow(&id)
-        .map_err(|e| e.to_string())
+) -> Result<Workflow, CommandError> {
+    service::get_workflow(&db, &id)
+        .await
+        .map_err(CommandError::from)
 }
 
+// With `expected_updated_at`, the save is rejected if the workflow changed
+// since the caller loaded it; without, it overwrites. A conflict error's
+// details hold the stored workflow so the UI can offer to merge or overwrite.
 #[tauri::command]
 async fn update_workflow(
-    workflow: Workflow,
+    mut workflow: Workflow,
+    expected_updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    app: AppHandle,
     db: State<'_, Arc<Mutex<Database>>>,
-) -> Result<(), String> {
-    db.lock()
-        .update_workflow(&workflow)
-        .map_err(|e| e.to_string())
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<NormalizationReport, CommandError> {
+    let report = normalize::normalize_workflow(engine.lock().registry(), &mut workflow);
+    
+    {
+        let mut db = db.lock();
+        match expected_updated_at {
+            Some(expected) => db.update_workflow_if_unchanged(&workflow, expected).map(|_| ())?,
+            None => db.update_workflow(&workflow)?,
+        }
+    }
+    sync_file_watch(&watchers, &workflow.id);
+    // A pinned workflow may have been renamed.
+    windows::refresh_tray(&app, None);
+    
+    Ok(report)
 }
 
+// Optimistic save for `auto_save`: returns the new `updated_at` to send as
+// `expected_updated_at` with the next save.
 #[tauri::command]
-async fn delete_workflow(
-    id: String,
+async fn autosave_workflow(
+    mut workflow: Workflow,
+    expected_updated_at: chrono::DateTime<chrono::Utc>,
     db: State<'_, Arc<Mutex<Database>>>,
-) -> Result<(), String> {
-    db.lock()
-        .delete_workflow(&id)
-        .map_err(|e| e.to_string())
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<chrono::DateTime<chrono::Utc>, CommandError> {
+    normalize::normalize_workflow(engine.lock().registry(), &mut workflow);
+    
+    let updated_at = db
+        .lock()
+        .update_workflow_if_unchanged(&workflow, expected_updated_at)?;
+    sync_file_watch(&watchers, &workflow.id);
+    
+    Ok(updated_at)
 }
 
 #[tauri::command]
-async fn execute_workflow(
+async fn undo_workflow(
     id: String,
-    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
     db: State<'_, Arc<Mutex<Database>>>,
-) -> Result<String, String> {
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<Option<Workflow>, CommandError> {
     let workflow = db.lock()
-        .get_workflow(&id)
-        .map_err(|e| e.to_string())?;
+        .undo_workflow(&id)?;
+    sync_file_watch(&watchers, &id);
     
-    let execution_id = engine.lock()
-        .execute_workflow(&workflow)
-        .map_err(|e| e.to_string())?;
+    Ok(workflow)
+}
+
+#[tauri::command]
+async fn redo_workflow(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<Option<Workflow>, CommandError> {
+    let workflow = db.lock()
+        .redo_workflow(&id)?;
+    sync_file_watch(&watchers, &id);
     
-    Ok(execution_id)
+    Ok(workflow)
 }
 
 #[tauri::command]
-async fn stop_workflow(
-    execution_id: String,
-    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
-) -> Result<(), String> {
-    engine.lock()
-        .stop_execution(&execution_id)
-        .map_err(|e| e.to_string())
+async fn get_undo_state(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<UndoState, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_undo_state(&id)
+        .map_err(CommandError::from)
 }
 
 #[tauri::command]
-async fn get_system_info() -> Result<serde_json::Value, String> {
-    use sysinfo::{System, SystemExt, CpuExt};
-    
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    
-    Ok(serde_json::json!({
-        "hostname": sys.host_name(),
-        "os": sys.name(),
-        "os_version": sys.os_version(),
-        "kernel_version": sys.kernel_version(),
-        "cpu": sys.cpus()[0].brand(),
-        "cpu_count": sys.cpus().len(),
-        "total_memory": sys.total_memory(),
-        "used_memory": sys.used_memory(),
-        "total_swap": sys.total_swap(),
-        "used_swap": sys.used_swap(),
-    }))
+async fn get_workflow_versions(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<WorkflowVersion>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_workflow_versions(&id)
+        .map_err(CommandError::from)
 }
 
 #[tauri::command]
-async fn get_machine_id(state: State<'_, Arc<Mutex<AppState>>>) -> Result<String, String> {
-    Ok(state.lock().machine_id.clone())
+async fn get_workflow_version(
+    id: String,
+    version: i64,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Workflow, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_workflow_version(&id, version)
+        .map_err(CommandError::from)
 }
 
 #[tauri::command]
-async fn get_preferences(
-    state: State<'_, Arc<Mutex<AppState>>>,
-) -> Result<UserPreferences, String> {
-    Ok(state.lock().user_preferences.clone())
+async fn restore_workflow_version(
+    id: String,
+    version: i64,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<Workflow, CommandError> {
+    let workflow = db.lock()
+        .restore_workflow_version(&id, version)?;
+    sync_file_watch(&watchers, &id);
+    
+    Ok(workflow)
 }
 
 #[tauri::command]
-async fn update_preferences(
-    preferences: UserPreferences,
+async fn delete_workflow(
+    id: String,
+    app: AppHandle,
     state: State<'_, Arc<Mutex<AppState>>>,
-) -> Result<(), String> {
-    state.lock().user_preferences = preferences;
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<(), CommandError> {
+    let deleted = db.lock().delete_workflow(&id);
+    audit::record(&db, &state, "delete_workflow", format!("workflow {}", id), &deleted);
+    deleted?;
+    sync_file_watch(&watchers, &id);
+    windows::refresh_tray(&app, None);
+    Ok(())
+}
+
+#[tauri::command]
+async fn pin_workflow(
+    id: String,
+    app: AppHandle,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    db.lock()
+        .pin_workflow(&id)?;
+    windows::refresh_tray(&app, None);
     Ok(())
 }
 
+// Returns whether the workflow was pinned.
+#[tauri::command]
+async fn unpin_workflow(
+    id: String,
+    app: AppHandle,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<bool, CommandError> {
+    let unpinned = db.lock()
+        .unpin_workflow(&id)?;
+    windows::refresh_tray(&app, None);
+    Ok(unpinned)
+}
+
+#[tauri::command]
+async fn get_pinned_workflows(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<PinnedWorkflow>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_pinned_workflows()
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn delete_node(
+    workflow_id: String,
+    node_id: String,
+    reconnect: bool,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<Workflow, CommandError> {
+    let workflow = {
+        let mut db = db.lock();
+        let mut workflow = db.get_workflow(&workflow_id)?;
+        
+        graph::delete_node(engine.lock().registry(), &mut workflow, &node_id, reconnect)?;
+        db.update_workflow(&workflow)?;
+        
+        db.get_workflow(&workflow_id)?
+    };
+    sync_file_watch(&watchers, &workflow_id);
+    
+    Ok(workflow)
+}
+
+// Pastes a fragment into the workflow, its nodes moved by `offset`. The
+// returned ids let the frontend select the pasted nodes.
+#[tauri::command]
+async fn merge_into_workflow(
+    target_id: String,
+    fragment: WorkflowFragment,
+    offset: Position,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<FragmentMerge, CommandError> {
+    let merge = {
+        let mut db = db.lock();
+        let mut workflow = db.get_workflow(&target_id)?;
+        
+        let merge = graph::merge_fragment(&mut workflow, fragment, &offset)?;
+        db.update_workflow(&workflow)?;
+        merge
+    };
+    sync_file_watch(&watchers, &target_id);
+    
+    Ok(merge)
+}
+
+// Copies a node's config onto a node of the same type, in the same workflow
+// or another. Fields named in `preserve`, such as a label, keep the target's
+// values. Returns the updated target workflow.
+#[tauri::command]
+async fn copy_node_config(
+    source_workflow_id: String,
+    source_node_id: String,
+    target_workflow_id: String,
+    target_node_id: String,
+    preserve: Option<Vec<String>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<Workflow, CommandError> {
+    let workflow = {
+        let mut db = db.lock();
+        let source = db
+            .get_workflow(&source_workflow_id)?
+            .nodes
+            .into_iter()
+            .find(|node| node.id == source_node_id)
+            .ok_or(GraphError::NodeNotFound(source_node_id))?;
+        let mut workflow = db.get_workflow(&target_workflow_id)?;
+        
+        let preserve = preserve.unwrap_or_default();
+        graph::copy_node_config(&source, &mut workflow, &target_node_id, &preserve)?;
+        db.update_workflow(&workflow)?;
+        
+        db.get_workflow(&target_workflow_id)?
+    };
+    sync_file_watch(&watchers, &target_workflow_id);
+    
+    Ok(workflow)
+}
+
+// Disabling a node skips it in runs, its input passed straight through, without
+// touching its edges; toggling again enables it.
 #[tauri::command]
-async fn encrypt_data(data: String, key: String) -> Result<String, String> {
-    encryption::encrypt(&data, &key).map_err(|e| e.to_string())
+async fn toggle_node_enabled(
+    workflow_id: String,
+    node_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Workflow, CommandError> {
+    let mut db = db.lock();
+    let mut workflow = db.get_workflow(&workflow_id)?;
+    
+    graph::toggle_node_disabled(&mut workflow, &node_id)?;
+    db.update_workflow(&workflow)?;
+    
+    Ok(db.get_workflow(&workflow_id)?)
 }
 
 #[tauri::command]
-async fn decrypt_data(data: String, key: String) -> Result<String, String> {
-    encryption::decrypt(&data, &key).map_err(|e| e.to_string())
-}
\ No newline at end of file
+async fn analyze_node_impact(
+    workflow_id: String,
+    node_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<NodeImpact, CommandError> {
+    let readers = db.lock().readers();
+    let workflow = readers.get_workflow(&workflow_id)?;
+    
+    Ok(graph::analyze_node_impact(engine.lock().registry(), &workflow, &node_id)?)
+}
+
+#[tauri::command]
+async fn update_node_positions(
+    workflow_id: String,
+    positions: Vec<(String, Position)>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<usize, CommandError> {
+    let positions: Vec<(String, Position)> = positions
+        .into_iter()
+        .map(|(node_id, position)| {
+            let position = Position {
+                x: normalize::clamp_coordinate(position.x),
+                y: normalize::clamp_coordinate(position.y),
+            };
+            (node_id, position)
+        })
+        .collect();
+    
+    db.lock()
+        .update_node_positions(&workflow_id, &positions)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn search_nodes(
+    node_type: Option<String>,
+    text: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<NodeHit>, CommandError> {
+    let query = NodeQuery {
+        node_type: node_type.filter(|node_type| !node_type.is_empty()),
+        text: text.filter(|text| !text.is_empty()),
+    };
+    let readers = db.lock().readers();
+    readers
+        .find_nodes(&query)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn get_workflow_tags(
+    workflow_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<String>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_workflow_tags(&workflow_id)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_workflow_tags(
+    workflow_id: String,
+    tags: Vec<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    let mut db = db.lock();
+    db.get_workflow(&workflow_id)?;
+    db.set_workflow_tags(&workflow_id, &tags)
+        .map_err(CommandError::from)
+}
+
+// A missing `parent_id` creates a top-level folder.
+#[tauri::command]
+async fn create_folder(
+    name: String,
+    parent_id: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Folder, CommandError> {
+    folders::create_folder(&db.lock(), &name, parent_id.as_deref())
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn move_folder(
+    id: String,
+    parent_id: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Folder, CommandError> {
+    folders::move_folder(&db.lock(), &id, parent_id.as_deref())
+        .map_err(CommandError::from)
+}
+
+// A missing `folder_id` moves the workflow to the root.
+#[tauri::command]
+async fn move_workflow_to_folder(
+    workflow_id: String,
+    folder_id: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    folders::move_workflow(&db.lock(), &workflow_id, folder_id.as_deref())
+        .map_err(CommandError::from)
+}
+
+// A missing `folder_id` lists the root.
+#[tauri::command]
+async fn list_folder(
+    folder_id: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<FolderListing, CommandError> {
+    folders::list_folder(&db.lock(), folder_id.as_deref())
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn delete_folder(
+    id: String,
+    cascade: bool,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<FolderDeleteSummary, CommandError> {
+    let summary = folders::delete_folder(&mut db.lock(), &id, cascade)?;
+    for workflow_id in &summary.workflows_trashed {
+        sync_file_watch(&watchers, workflow_id);
+    }
+    Ok(summary)
+}
+
+#[tauri::command]
+async fn get_output_sink(
+    workflow_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Option<OutputSink>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_output_sink(&workflow_id)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_output_sink(
+    workflow_id: String,
+    sink: Option<OutputSink>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    let db = db.lock();
+    db.get_workflow(&workflow_id)?;
+    db.set_output_sink(&workflow_id, sink.as_ref())
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn get_notification_rules(
+    workflow_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<NotificationRule>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_notification_rules(&workflow_id)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_notification_rules(
+    workflow_id: String,
+    rules: Vec<NotificationRule>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    notifications::validate(&rules)?;
+    let db = db.lock();
+    db.get_workflow(&workflow_id)?;
+    db.set_notification_rules(&workflow_id, &rules)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn delete_workflow_permanently(
+    id: String,
+    app: AppHandle,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<BTreeMap<String, usize>, CommandError> {
+    let removed = db.lock().delete_workflow_cascade(&id);
+    audit::record(
+        &db,
+        &state,
+        "delete_workflow_permanently",
+        format!("workflow {}", id),
+        &removed,
+    );
+    let removed = removed?;
+    sync_file_watch(&watchers, &id);
+    windows::refresh_tray(&app, None);
+    Ok(removed)
+}
+
+#[tauri::command]
+async fn restore_workflow(
+    id: String,
+    app: AppHandle,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<(), CommandError> {
+    db.lock()
+        .restore_workflow(&id)?;
+    sync_file_watch(&watchers, &id);
+    windows::refresh_tray(&app, None);
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_trash(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<Workflow>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .list_trash()
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn purge_trash(
+    older_than_days: u32,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<usize, CommandError> {
+    let purged = db.lock().purge_trash(older_than_days);
+    audit::record(
+        &db,
+        &state,
+        "purge_trash",
+        format!("trashed more than {} days ago", older_than_days),
+        &purged,
+    );
+    purged.map_err(CommandError::from)
+}
+
+// Started from the UI, so high priority unless `priority` says otherwise.
+#[tauri::command]
+async fn execute_workflow(
+    id: String,
+    profile: Option<String>,
+    debug: Option<bool>,
+    priority: Option<Priority>,
+    idempotency_key: Option<String>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    service::execute_workflow(
+        &db,
+        &engine,
+        &id,
+        RunSettings {
+            profile: profile.as_deref(),
+            debug: debug == Some(true),
+            priority: priority.unwrap_or(Priority::High),
+        },
+        idempotency_key.as_deref(),
+    )
+    .await
+    .map_err(CommandError::from)
+}
+
+// Freezes the workflow as it is now into a stored plan that `execute_plan`
+// runs regardless of later edits. Refused if the workflow has validation errors.
+#[tauri::command]
+async fn compile_workflow(
+    id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<ExecutionPlan, CommandError> {
+    let workflow = {
+        let readers = db.lock().readers();
+        readers.get_workflow(&id)?
+    };
+    let plan = plans::compile(engine.lock().registry(), &workflow)?;
+    Ok(db.lock().insert_execution_plan(&plan)?)
+}
+
+// Runs a compiled plan as a run of its source workflow; returns the execution id.
+#[tauri::command]
+async fn execute_plan(
+    plan_id: String,
+    profile: Option<String>,
+    priority: Option<Priority>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    let profile = workflow_engine::find_profile(
+        profile.as_deref().unwrap_or(workflow_engine::DEFAULT_PROFILE),
+    )?;
+    let (plan, workflow) = {
+        let readers = db.lock().readers();
+        plans::load(&readers, &plan_id)?
+    };
+    let execution_id = engine.lock().execute_workflow(
+        &workflow,
+        &profile,
+        priority.unwrap_or(Priority::High),
+        None,
+    )?;
+    tracing::info!(
+        "execution {} runs plan {} (version {} of workflow {}, hash {})",
+        execution_id,
+        plan.id,
+        plan.version,
+        plan.workflow_id,
+        plan.hash
+    );
+    Ok(execution_id)
+}
+
+// Runs `node_id` and everything downstream of it, skipping the nodes that
+// feed it; `seed_context` supplies their outputs (from a previous run or
+// entered by hand) and the run input. Runs at high priority, like a manual run.
+#[tauri::command]
+async fn execute_from_node(
+    workflow_id: String,
+    node_id: String,
+    seed_context: Option<SeedContext>,
+    profile: Option<String>,
+    debug: Option<bool>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    service::execute_from_node(
+        &db,
+        &engine,
+        &workflow_id,
+        &node_id,
+        seed_context.unwrap_or_default(),
+        RunSettings {
+            profile: profile.as_deref(),
+            debug: debug == Some(true),
+            priority: Priority::High,
+        },
+    )
+    .await
+    .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn list_execution_profiles() -> Result<Vec<ExecutionProfile>, CommandError> {
+    Ok(workflow_engine::execution_profiles())
+}
+
+#[tauri::command]
+async fn stop_workflow(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<(), CommandError> {
+    engine.lock()
+        .stop_execution(&execution_id)
+        .map_err(CommandError::from)
+}
+
+// Every node executing right now, with how long it has been running.
+#[tauri::command]
+async fn list_running_nodes(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<RunningNode>, CommandError> {
+    Ok(engine.lock().list_running_nodes())
+}
+
+// Fails one running node without cancelling its run; see
+// `WorkflowEngine::kill_node`.
+#[tauri::command]
+async fn kill_node(
+    execution_id: String,
+    node_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<(), CommandError> {
+    engine.lock()
+        .kill_node(&execution_id, &node_id)
+        .map_err(CommandError::from)
+}
+
+// Safe to call when nothing is running, and concurrently: each run is counted
+// by the call that stopped it.
+#[tauri::command]
+async fn stop_all_executions(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<StopAllSummary, CommandError> {
+    let stopped = engine.lock().stop_all_executions(STOP_ALL_TIMEOUT);
+    Ok(stopped.await)
+}
+
+#[tauri::command]
+async fn pause_workflow(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<(), CommandError> {
+    engine.lock()
+        .pause_execution(&execution_id)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn resume_workflow(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<(), CommandError> {
+    engine.lock()
+        .resume_execution(&execution_id)
+        .map_err(CommandError::from)
+}
+
+// Returns false if the run was not held at a breakpoint, in which case
+// nothing happens.
+#[tauri::command]
+async fn debug_step(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<bool, CommandError> {
+    engine.lock()
+        .debug_step(&execution_id)
+        .map_err(CommandError::from)
+}
+
+// Returns false if the run was not held at a breakpoint, in which case
+// nothing happens.
+#[tauri::command]
+async fn debug_continue(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<bool, CommandError> {
+    engine.lock()
+        .debug_continue(&execution_id)
+        .map_err(CommandError::from)
+}
+
+// None unless the run is held at a breakpoint.
+#[tauri::command]
+async fn get_execution_context(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Option<ContextSnapshot>, CommandError> {
+    engine.lock()
+        .execution_context(&execution_id)
+        .map_err(CommandError::from)
+}
+
+// Returns the ids of the runs it paused; runs already paused are skipped, so
+// repeated or concurrent calls are harmless.
+#[tauri::command]
+async fn emergency_pause_all(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<String>, CommandError> {
+    Ok(engine.lock().pause_all_executions())
+}
+
+#[tauri::command]
+async fn resume_all_executions(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<String>, CommandError> {
+    Ok(engine.lock().resume_all_executions())
+}
+
+// Returns the node's schema errors; empty means the values were accepted and
+// the run continues. On errors the node re-prompts and keeps waiting.
+#[tauri::command]
+async fn provide_input(
+    execution_id: String,
+    node_id: String,
+    values: serde_json::Value,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<FieldError>, CommandError> {
+    let errors = engine.lock()
+        .provide_input(&execution_id, &node_id, values)?;
+    errors
+        .await
+        .map_err(|_| CommandError::conflict("execution stopped before the input was checked"))
+}
+
+#[tauri::command]
+async fn get_execution_event_replay(
+    execution_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<ExecutionEvent>, CommandError> {
+    engine.lock()
+        .event_replay(&execution_id)
+        .ok_or_else(|| {
+            CommandError::NotFound(format!("no events retained for execution {}", execution_id))
+        })
+}
+
+#[tauri::command]
+async fn is_workflow_running(
+    id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<bool, CommandError> {
+    Ok(engine.lock().is_workflow_running(&id))
+}
+
+#[tauri::command]
+async fn get_queue_depth(
+    workflow_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<usize, CommandError> {
+    Ok(engine.lock().queue_depth(&workflow_id))
+}
+
+#[tauri::command]
+async fn list_active_executions(
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Vec<ActiveExecution>, CommandError> {
+    Ok(engine.lock().list_active_executions())
+}
+
+#[tauri::command]
+async fn get_execution(
+    execution_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<Execution, CommandError> {
+    service::execution_status(&db, &engine, &execution_id)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn get_executions(
+    workflow_id: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<Execution>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_executions(workflow_id.as_deref())
+        .map_err(CommandError::from)
+}
+
+// Returns how many cached node outputs were dropped.
+#[tauri::command]
+async fn clear_node_cache(
+    workflow_id: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<usize, CommandError> {
+    db.lock()
+        .clear_node_cache(&workflow_id)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn list_failed_executions(
+    workflow_id: Option<String>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<FailedExecution>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .list_failed_executions(workflow_id.as_deref(), since, until)
+        .map_err(CommandError::from)
+}
+
+// Re-runs a failed execution with its preserved input under the profile it
+// originally ran with, falling back to the default if that profile is gone.
+#[tauri::command]
+async fn replay_execution(
+    failed_id: String,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    let (failed, workflow) = {
+        let db = db.lock();
+        let failed = db.get_failed_execution(&failed_id)?;
+        if failed.resolved_at.is_some() {
+            return Err(CommandError::conflict(format!(
+                "failed execution {} is already resolved",
+                failed_id
+            )));
+        }
+        let workflow = db.get_workflow(&failed.workflow_id)?;
+        (failed, workflow)
+    };
+    
+    let profile = workflow_engine::find_profile(&failed.profile)
+        .or_else(|_| workflow_engine::find_profile(workflow_engine::DEFAULT_PROFILE))?;
+    
+    engine.lock()
+        .replay_failed_execution(&workflow, &profile, &failed)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_execution_pinned(
+    execution_id: String,
+    pinned: bool,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    db.lock()
+        .set_execution_pinned(&execution_id, pinned)
+        .map_err(CommandError::from)
+}
+
+// Applies the configured retention policy now instead of waiting for the
+// hourly cleanup; returns rows removed per table.
+#[tauri::command]
+async fn cleanup_old_executions(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<BTreeMap<String, usize>, CommandError> {
+    apply_retention(&state, &db)
+}
+
+// Same as `cleanup_old_executions`, but returns how many rows were removed
+// across the executions and their logs and metrics.
+#[tauri::command]
+async fn prune_executions(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<usize, CommandError> {
+    let removed = apply_retention(&state, &db)?;
+    tracing::info!("execution pruning removed {:?}", removed);
+    Ok(removed.values().sum())
+}
+
+fn apply_retention(
+    state: &Mutex<AppState>,
+    db: &Mutex<Database>,
+) -> Result<BTreeMap<String, usize>, CommandError> {
+    let policy = state.lock().user_preferences.execution_retention;
+    if !policy.is_enabled() {
+        return Ok(BTreeMap::new());
+    }
+
+    db.lock()
+        .cleanup_executions(&policy)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn get_system_info(
+    resources: State<'_, Arc<ResourceMonitor>>,
+) -> Result<SystemInfo, CommandError> {
+    Ok(resources.system_info())
+}
+
+#[tauri::command]
+async fn get_machine_id(state: State<'_, Arc<Mutex<AppState>>>) -> Result<String, CommandError> {
+    Ok(state.lock().machine_id.clone())
+}
+
+#[tauri::command]
+async fn get_machine_id_source(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<MachineIdSource, CommandError> {
+    Ok(state.lock().machine_id_source)
+}
+
+#[tauri::command]
+async fn get_preferences(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<UserPreferences, CommandError> {
+    Ok(state.lock().user_preferences.clone())
+}
+
+#[tauri::command]
+async fn update_preferences(
+    preferences: UserPreferences,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<(), CommandError> {
+    websocket_client::validate_url(&preferences.websocket_url)?;
+    preferences.save(&db.lock())?;
+    preferences.apply(&mut engine.lock(), &mut db.lock());
+    ws_client.lock().set_url(&preferences.websocket_url())?;
+    state.lock().user_preferences = preferences;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_active_profile(
+    app: AppHandle,
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<ActiveProfile, CommandError> {
+    let data_dir = app_data_dir(&app)?;
+    Ok(ActiveProfile::new(&data_dir, &state.lock().profile))
+}
+
+#[tauri::command]
+async fn list_profiles(app: AppHandle) -> Result<Vec<String>, CommandError> {
+    profiles::list_profiles(&app_data_dir(&app)?).map_err(CommandError::from)
+}
+
+// Closes the current database and opens the profile's, creating and migrating
+// it if needed, then reloads that profile's preferences. Refused while runs
+// are active, since they would finish against the wrong database.
+#[tauri::command]
+async fn switch_profile(
+    name: String,
+    app: AppHandle,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<ActiveProfile, CommandError> {
+    profiles::validate_name(&name)?;
+    let data_dir = app_data_dir(&app)?;
+    if state.lock().profile == name {
+        return Ok(ActiveProfile::new(&data_dir, &name));
+    }
+    
+    let machine_id = state.lock().machine_id.clone();
+    let (preferences, secret_key) = {
+        // Holding the engine keeps new runs from starting during the swap.
+        // Checked first, so a refused switch creates no database or key.
+        let mut engine = engine.lock();
+        let running = engine.list_active_executions().len();
+        if running > 0 {
+            return Err(ProfileError::ExecutionsRunning(running).into());
+        }
+
+        let mut next = Database::new(&profiles::database_path(&data_dir, &name))?;
+        unlock_with_machine_key(&next, &machine_id);
+        let secret_key =
+            secrets::load_or_create_key(&mut next, &SecretKeyStore::new(&name), &machine_id);
+        let preferences = UserPreferences::load(&next)?;
+        let mut db = db.lock();
+        *db = next;
+        preferences.apply(&mut engine, &mut db);
+        engine.set_secret_key(&secret_key);
+        (preferences, secret_key)
+    };
+    if let Err(e) = ws_client.lock().set_url(&preferences.websocket_url()) {
+        tracing::warn!("kept the websocket endpoint for profile {}: {}", name, e);
+    }
+    {
+        let mut state = state.lock();
+        state.user_preferences = preferences;
+        state.secret_key = secret_key;
+        state.profile = name.clone();
+    }
+    
+    if let Err(e) = watchers.lock().sync_all() {
+        tracing::warn!("failed to reload file watches for profile {}: {}", name, e);
+    }
+    app.state::<DeadlineManager>().reschedule();
+    if let Err(e) = profiles::save_active(&data_dir, &name) {
+        tracing::warn!("failed to remember profile {}: {}", name, e);
+    }
+    tracing::info!("switched to profile {}", name);
+    
+    show_profile(&app, &name);
+    windows::refresh_tray(&app, None);
+    let active = ActiveProfile::new(&data_dir, &name);
+    let _ = app.emit_all("profile-changed", active.clone());
+    Ok(active)
+}
+
+// Audited by size only; neither the data nor the key is logged.
+#[tauri::command]
+async fn encrypt_data(
+    data: String,
+    key: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    let encrypted = encryption::encrypt(&data, &key);
+    audit::record(&db, &state, "encrypt_data", format!("{} bytes", data.len()), &encrypted);
+    encrypted.map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn decrypt_data(
+    data: String,
+    key: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<String, CommandError> {
+    let decrypted = encryption::decrypt(&data, &key);
+    audit::record(&db, &state, "decrypt_data", format!("{} bytes", data.len()), &decrypted);
+    decrypted.map_err(CommandError::from)
+}
+
+// Re-encrypts every stored secret under a new secrets key, which the engine
+// and `set_secret` use from then on. Given `passphrase`, the workflows
+// encrypted at rest, their snapshots and the passphrase check move to it in
+// the same transaction, which needs the database unlocked. Needs a recent
+// login; audited without the passphrase.
+#[tauri::command]
+async fn rotate_encryption_key(
+    passphrase: Option<String>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    engine: State<'_, Arc<Mutex<WorkflowEngine>>>,
+) -> Result<KeyRotation, CommandError> {
+    let (profile, current) = {
+        let state = state.lock();
+        require_recent_login(&state, chrono::Utc::now())?;
+        (state.profile.clone(), state.secret_key.clone())
+    };
+    if passphrase.as_deref().is_some_and(|passphrase| passphrase.trim().is_empty()) {
+        return Err(CommandError::validation("passphrase must not be empty"));
+    }
+    let rotated = secrets::rotate_key(
+        &mut db.lock(),
+        &SecretKeyStore::new(&profile),
+        &current,
+        passphrase.as_deref(),
+    )
+    .map(|(key, rotation)| {
+        engine.lock().set_secret_key(&key);
+        state.lock().secret_key = key;
+        rotation
+    });
+    let summary = match &rotated {
+        Ok(rotation) => format!(
+            "{} secrets and {} workflows re-encrypted",
+            rotation.secrets, rotation.workflows
+        ),
+        Err(_) => "nothing re-encrypted".to_string(),
+    };
+    audit::record(&db, &state, "rotate_encryption_key", summary, &rotated);
+    rotated.map_err(CommandError::from)
+}
+
+// Encrypts every workflow at rest with `passphrase` and keeps it loaded, so
+// workflows saved from now on are encrypted too, even with none yet; returns
+// how many were encrypted. Needs a recent login and an unlocked database.
+// Audited without the passphrase.
+#[tauri::command]
+async fn enable_encryption(
+    passphrase: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<usize, CommandError> {
+    require_recent_login(&state.lock(), chrono::Utc::now())?;
+    if passphrase.trim().is_empty() {
+        return Err(CommandError::validation("passphrase must not be empty"));
+    }
+
+    let encrypted = db.lock().encrypt_workflows(&passphrase);
+    let summary = match &encrypted {
+        Ok(count) => format!("{} workflows encrypted", count),
+        Err(_) => "encryption not enabled".to_string(),
+    };
+    audit::record(&db, &state, "enable_encryption", summary, &encrypted);
+    encrypted.map_err(CommandError::from)
+}
+
+// Makes workflows encrypted at rest readable without a restart; returns how
+// many are encrypted. A passphrase that does not open them is refused and
+// leaves the database locked. Audited without the passphrase.
+#[tauri::command]
+async fn unlock_database(
+    passphrase: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    watchers: State<'_, Arc<Mutex<FileWatchers>>>,
+) -> Result<usize, CommandError> {
+    let unlocked = db.lock().unlock(&passphrase);
+    let summary = match &unlocked {
+        Ok(count) => format!("{} encrypted workflows", count),
+        Err(_) => "database still locked".to_string(),
+    };
+    audit::record(&db, &state, "unlock_database", summary, &unlocked);
+    let unlocked = unlocked?;
+    
+    // Watches for workflows that were unreadable until now
+    if let Err(e) = watchers.lock().sync_all() {
+        tracing::warn!("failed to reload file watches after unlocking: {}", e);
+    }
+    Ok(unlocked)
+}
+
+#[tauri::command]
+async fn get_audit_log(
+    filter: Option<AuditFilter>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<AuditEntry>, CommandError> {
+    let readers = db.lock().readers();
+    readers
+        .get_audit_log(&filter.unwrap_or_default())
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_secret(
+    scope: SecretScope,
+    key: String,
+    value: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    let secret_key = state.lock().secret_key.clone();
+    secrets::set_secret(&db.lock(), &scope, &key, &value, &secret_key)
+        .map_err(CommandError::from)
+}
+
+// Returns false if the scope had no secret named `key`.
+#[tauri::command]
+async fn delete_secret(
+    scope: SecretScope,
+    key: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<bool, CommandError> {
+    secrets::delete_secret(&db.lock(), &scope, &key).map_err(CommandError::from)
+}
+
+// Names only; values never leave the backend.
+#[tauri::command]
+async fn get_secrets(
+    scope: SecretScope,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<String>, CommandError> {
+    secrets::secret_names(&db.lock(), &scope).map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn create_environment(
+    name: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    environments::create_environment(&db.lock(), &name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn get_environments(
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Vec<Environment>, CommandError> {
+    environments::list_environments(&db.lock()).map_err(CommandError::from)
+}
+
+// Applies to runs started afterwards; `name: null` deactivates environments.
+#[tauri::command]
+async fn set_active_environment(
+    name: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    environments::set_active_environment(&db.lock(), name.as_deref())
+        .map_err(CommandError::from)
+}
+
+// `value: null` removes the variable.
+#[tauri::command]
+async fn set_environment_var(
+    environment: String,
+    key: String,
+    value: Option<String>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    environments::set_environment_var(&db.lock(), &environment, &key, value.as_deref())
+        .map_err(CommandError::from)
+}
+
+// null while `key` is unset.
+#[tauri::command]
+async fn get_state(
+    workflow_id: String,
+    key: String,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<Option<serde_json::Value>, CommandError> {
+    let db = db.lock();
+    db.get_workflow(&workflow_id)?;
+    workflow_state::get(&db, &workflow_id, &key).map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_state(
+    workflow_id: String,
+    key: String,
+    value: serde_json::Value,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<(), CommandError> {
+    let db = db.lock();
+    db.get_workflow(&workflow_id)?;
+    workflow_state::set(&db, &workflow_id, &key, &value).map_err(CommandError::from)
+}
+
+// Adds `by` (default 1) to the integer at `key`, as a node in a run would,
+// and returns the new value.
+#[tauri::command]
+async fn increment_state(
+    workflow_id: String,
+    key: String,
+    by: Option<i64>,
+    db: State<'_, Arc<Mutex<Database>>>,
+) -> Result<i64, CommandError> {
+    let mut db = db.lock();
+    db.get_workflow(&workflow_id)?;
+    workflow_state::increment(&mut db, &workflow_id, &key, by.unwrap_or(1))
+        .map_err(CommandError::from)
+}
+
+// Server-pushed updates are forwarded to every window.
+impl MessageHandler for AppHandle {
+    fn execution_update(&self, update: ExecutionUpdate) {
+        let _ = self.emit_all("ws-execution-update", update);
+    }
+}
+
+#[tauri::command]
+async fn connect_websocket(
+    app: AppHandle,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<(), CommandError> {
+    ws_client
+        .lock()
+        .connect(Arc::new(app))
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn disconnect_websocket(
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<(), CommandError> {
+    ws_client.lock().disconnect();
+    Ok(())
+}
+
+// Queued until the socket is open; fails only if `connect_websocket` has not
+// been called.
+#[tauri::command]
+async fn send_websocket_message(
+    message: WsMessage,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<(), CommandError> {
+    ws_client.lock().send(message).map_err(CommandError::from)
+}
+
+// Checks `url`, or the configured endpoint, on a socket of its own; the live
+// connection is left alone.
+#[tauri::command]
+async fn test_websocket_connection(
+    url: Option<String>,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<ConnectionTest, CommandError> {
+    let url = url.unwrap_or_else(|| ws_client.lock().url().to_string());
+    tokio::task::spawn_blocking(move || {
+        websocket_client::test_connection(&url, websocket_client::CONNECTION_TEST_TIMEOUT)
+    })
+    .await
+    .map_err(|e| CommandError::Internal(e.to_string()))
+}
+
+// Saves `url` as the profile's `websocket_url` preference and moves a live
+// connection over to it. A malformed URL is refused before anything changes.
+// Returns the endpoint now in use, which is the environment override if set.
+#[tauri::command]
+async fn set_websocket_url(
+    url: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    db: State<'_, Arc<Mutex<Database>>>,
+    ws_client: State<'_, Arc<Mutex<WebSocketClient>>>,
+) -> Result<String, CommandError> {
+    websocket_client::validate_url(&url)?;
+    let mut preferences = state.lock().user_preferences.clone();
+    preferences.websocket_url = url;
+    preferences.save(&db.lock())?;
+    let effective = preferences.websocket_url();
+    ws_client.lock().set_url(&effective)?;
+    state.lock().user_preferences = preferences;
+    Ok(effective)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn logged_in(issued_at: Option<chrono::DateTime<chrono::Utc>>) -> AppState {
+        AppState {
+            machine_id: "machine-id".to_string(),
+            machine_id_source: MachineIdSource::Hardware,
+            auth_token: issued_at.map(|_| "token".to_string()),
+            auth_token_issued_at: issued_at,
+            secret_key: "machine-id".to_string(),
+            user_preferences: UserPreferences::default(),
+            profile: "default".to_string(),
+        }
+    }
+
+    #[test]
+    fn key_rotation_needs_a_recent_login() {
+        let now = chrono::Utc::now();
+        let recent = now - chrono::Duration::seconds(REAUTH_WINDOW_SECS - 1);
+        let stale = now - chrono::Duration::seconds(REAUTH_WINDOW_SECS + 1);
+
+        assert!(require_recent_login(&logged_in(Some(recent)), now).is_ok());
+        assert!(matches!(
+            require_recent_login(&logged_in(Some(stale)), now),
+            Err(CommandError::Auth(_))
+        ));
+        assert!(matches!(
+            require_recent_login(&logged_in(None), now),
+            Err(CommandError::Auth(_))
+        ));
+    }
+
+    #[test]
+    fn fallback_machine_id_survives_restarts() {
+        let path = test_support::temp_db_path();
+        let unavailable = || Err("no hardware id".to_string());
+
+        let first = derive_machine_id(&Database::new(&path).unwrap(), unavailable()).unwrap();
+        assert_eq!(first.1, MachineIdSource::PersistedFallback);
+
+        for _ in 0..3 {
+            let db = Database::new(&path).unwrap();
+            assert_eq!(derive_machine_id(&db, unavailable()).unwrap(), first);
+        }
+    }
+
+    #[test]
+    fn hardware_machine_id_replaces_the_stored_one() {
+        let path = test_support::temp_db_path();
+        let db = Database::new(&path).unwrap();
+        derive_machine_id(&db, Err("no hardware id".to_string())).unwrap();
+
+        let resolved = derive_machine_id(&db, Ok("hw-1".to_string())).unwrap();
+        assert_eq!(resolved, ("hw-1".to_string(), MachineIdSource::Hardware));
+
+        let db = Database::new(&path).unwrap();
+        assert_eq!(db.get_meta(MACHINE_ID_KEY).unwrap().as_deref(), Some("hw-1"));
+    }
+}
@@ -0,0 +1,53 @@
+/*!
+ * Node cache - memoized outputs of deterministic nodes
+ *
+ * A node opts in with `cacheable: true` in its `data`, which only takes effect
+ * for executors that declare themselves deterministic. Entries are keyed by
+ * node type and a hash of the rendered config plus the node's input, and
+ * expire after the node's `cache_ttl_ms`.
+ */
+
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use crate::nodes::NodeExecutor;
+
+pub const CACHEABLE_KEY: &str = "cacheable";
+pub const TTL_KEY: &str = "cache_ttl_ms";
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+pub const MAX_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+// Whether the node asked for caching; see `applies` for whether it gets it.
+pub fn requested(data: &Value) -> bool {
+    data.get(CACHEABLE_KEY).and_then(Value::as_bool) == Some(true)
+}
+
+pub fn applies(executor: &dyn NodeExecutor, data: &Value) -> bool {
+    requested(data) && executor.is_deterministic(data)
+}
+
+pub fn ttl(data: &Value) -> Duration {
+    data.get(TTL_KEY)
+        .and_then(Value::as_u64)
+        .filter(|ttl_ms| *ttl_ms > 0)
+        .map(|ttl_ms| Duration::from_millis(ttl_ms).min(MAX_TTL))
+        .unwrap_or(DEFAULT_TTL)
+}
+
+// `config` is the node's rendered `data`, so values pulled in through
+// templates are part of the key. The caching keys themselves are left out so
+// changing the TTL does not orphan existing entries.
+pub fn input_hash(config: &Value, input: &Value) -> serde_json::Result<String> {
+    let mut config = config.clone();
+    if let Value::Object(data) = &mut config {
+        data.remove(CACHEABLE_KEY);
+        data.remove(TTL_KEY);
+    }
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(&serde_json::json!({ "config": config, "input": input }))?
+        .hash(&mut hasher);
+    Ok(format!("{:016x}", hasher.finish()))
+}
@@ -0,0 +1,94 @@
+/*!
+ * Call workflow node - runs another workflow as part of this run
+ *
+ * The workflow named by `workflow_id` runs with this node's input as its run
+ * input, and its terminal output becomes this node's output. The nested run
+ * shares the caller's stop, pause and profile; its events carry the caller's
+ * execution id followed by `/<node id>`. A workflow that could end up calling
+ * itself, directly or through other workflows, is rejected before it runs.
+ */
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result, config_field};
+use crate::WorkflowNode;
+
+pub const NODE_TYPE: &str = "call_workflow";
+
+pub struct CallWorkflowNodeExecutor;
+
+pub fn workflow_id(data: &Value) -> Result<String> {
+    config_field::<String>(data, "workflow_id")?
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| NodeError::InvalidConfig("workflow_id is required".to_string()))
+}
+
+#[async_trait]
+impl NodeExecutor for CallWorkflowNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        NODE_TYPE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Call Workflow"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["workflow_id"])
+    }
+
+    // The called workflow's nodes take workers of their own; holding one
+    // here while they wait for theirs could starve the pool.
+    fn needs_worker(&self) -> bool {
+        false
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["workflow_id"],
+            "properties": {
+                "workflow_id": {
+                    "type": "string",
+                    "minLength": 1,
+                    "title": "Workflow",
+                },
+            },
+        })
+    }
+
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        match workflow_id(data) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let workflow_id = workflow_id(&node.data)?;
+        let runner = ctx.workflows.as_ref().ok_or_else(|| {
+            NodeError::Failed("other workflows can only be called from a workflow run".to_string())
+        })?;
+        runner.run(&workflow_id, input, ctx).await
+    }
+}
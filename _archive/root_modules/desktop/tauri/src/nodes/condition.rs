@@ -0,0 +1,109 @@
+/*!
+ * Condition node - evaluates a boolean `expression` against the run
+ *
+ * The expression sees `upstream` (this node's input) alongside `input`,
+ * `nodes`, `vars` and `secrets`, and must produce true or false. The node
+ * outputs `{ "result": <bool>, "data": <upstream> }` so downstream nodes can
+ * act on the outcome and still read what came in.
+ */
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{DEFAULT_PORT, Connections, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result, config_field};
+use crate::expr::{self, ExprError, Expression};
+use crate::WorkflowNode;
+
+pub struct ConditionNodeExecutor;
+
+fn expression(data: &Value) -> Result<Expression> {
+    let source: String = config_field(data, "expression")?
+        .ok_or_else(|| NodeError::InvalidConfig("expression is required".to_string()))?;
+    Expression::parse(&source)
+        .map_err(|e| NodeError::InvalidConfig(format!("expression '{}': {}", source, e)))
+}
+
+#[async_trait]
+impl NodeExecutor for ConditionNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        "condition"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Condition"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Object,
+        }])
+    }
+
+    // Something to test, and somewhere for the result to go.
+    fn connections(&self) -> Connections {
+        Connections {
+            min_inputs: 1,
+            min_outputs: 1,
+            ..Connections::default()
+        }
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["expression"])
+    }
+
+    // Cache keys cover the config and the input only.
+    fn is_deterministic(&self, data: &Value) -> bool {
+        expression(data).is_ok_and(|expression| {
+            expression.references().iter().all(|name| *name == expr::UPSTREAM)
+        })
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["expression"],
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "title": "Expression",
+                },
+            },
+        })
+    }
+
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        match expression(data) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let scope = expr::node_scope(ctx.template_scope(), &input);
+        let result = match expression(&node.data)?.evaluate(&scope)? {
+            Value::Bool(result) => result,
+            other => {
+                return Err(ExprError::TypeMismatch(format!(
+                    "condition must be a boolean, got {}",
+                    other
+                ))
+                .into())
+            }
+        };
+        Ok(serde_json::json!({ "result": result, "data": input }))
+    }
+}
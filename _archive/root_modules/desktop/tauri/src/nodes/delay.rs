@@ -0,0 +1,114 @@
+/*!
+ * Delay node - waits for `duration_ms` and passes its input through
+ */
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result, config_field};
+use crate::WorkflowNode;
+
+// How often a countdown progress event is emitted while waiting.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct DelayNodeExecutor;
+
+fn duration_ms(data: &Value) -> Result<u64> {
+    let duration: i64 = config_field(data, "duration_ms")?
+        .ok_or_else(|| NodeError::InvalidConfig("duration_ms is required".to_string()))?;
+    u64::try_from(duration)
+        .map_err(|_| NodeError::InvalidConfig("duration_ms must be non-negative".to_string()))
+}
+
+#[async_trait]
+impl NodeExecutor for DelayNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        "delay"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Delay"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["duration_ms"])
+    }
+
+    fn needs_worker(&self) -> bool {
+        false
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["duration_ms"],
+            "properties": {
+                "duration_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "title": "Duration (ms)",
+                },
+            },
+        })
+    }
+
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        match duration_ms(data) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let total = Duration::from_millis(duration_ms(&node.data)?);
+        let mut remaining = total;
+
+        // Pausing interrupts the wait and keeps the remaining time; stopping
+        // abandons it immediately.
+        while !remaining.is_zero() {
+            ctx.control.wait_while_paused().await;
+            if ctx.control.is_cancelled() {
+                return Err(NodeError::Cancelled);
+            }
+
+            ctx.emit_progress(
+                &node.id,
+                serde_json::json!({
+                    "remaining_ms": remaining.as_millis() as u64,
+                    "total_ms": total.as_millis() as u64,
+                }),
+            );
+
+            let tick = remaining.min(PROGRESS_INTERVAL);
+            let started = Instant::now();
+            tokio::select! {
+                _ = tokio::time::sleep(tick) => remaining -= tick,
+                _ = ctx.control.paused() => {
+                    remaining = remaining.saturating_sub(started.elapsed());
+                }
+            }
+        }
+
+        Ok(input)
+    }
+}
@@ -0,0 +1,131 @@
+/*!
+ * File watch node - entry point for runs started when a matching file is
+ * created or modified; emits `{ path, event }`
+ */
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeExecutor, Port, PortType, Result};
+use crate::WorkflowNode;
+
+pub const NODE_TYPE: &str = "file_watch";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchSpec {
+    pub path: PathBuf,
+    #[serde(default = "default_glob")]
+    pub glob: String,
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+fn default_glob() -> String {
+    "*".to_string()
+}
+
+impl WatchSpec {
+    pub fn from_config(data: &Value) -> Option<Self> {
+        serde_json::from_value::<Self>(data.clone())
+            .ok()
+            .filter(|spec| !spec.path.as_os_str().is_empty())
+    }
+
+    // True when `file` is inside the watched folder (directly, unless
+    // recursive) and its file name matches the glob.
+    pub fn matches(&self, file: &Path) -> bool {
+        let in_scope = if self.recursive {
+            file.starts_with(&self.path)
+        } else {
+            file.parent() == Some(self.path.as_path())
+        };
+        in_scope
+            && file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| glob_match(&self.glob, name))
+    }
+}
+
+// `*` matches any run of characters and `?` any single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub struct FileWatchNodeExecutor;
+
+#[async_trait]
+impl NodeExecutor for FileWatchNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        NODE_TYPE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "File Watch Trigger"
+    }
+
+    // Started by a file event, so nothing feeds it.
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["path", "glob", "recursive"])
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["path"],
+            "properties": {
+                "path": { "type": "string", "minLength": 1, "title": "Folder" },
+                "glob": { "type": "string", "minLength": 1, "default": default_glob() },
+                "recursive": { "type": "boolean", "default": false },
+            },
+        })
+    }
+
+    // The run input is `{ path, event }` for the file that fired the trigger.
+    async fn execute(
+        &self,
+        _node: &WorkflowNode,
+        _input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        Ok(ctx.input.clone())
+    }
+}
@@ -0,0 +1,307 @@
+/*!
+ * HTTP node - performs an HTTP request and outputs `{ status, headers, body }`
+ *
+ * With `stream`, the response body is also sent to the UI as text chunks while
+ * it downloads. With `binary`, the body is kept as a blob and `body` is its
+ * reference, for downloads such as files and images. `timeout_ms` covers the
+ * whole download either way.
+ */
+
+use async_trait::async_trait;
+use reqwest::redirect::Policy;
+use reqwest::{Method, Response};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result};
+use crate::WorkflowNode;
+
+pub const REDACTED: &str = "[REDACTED]";
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "proxy-authorization"];
+
+// Chunks a streamed body may read ahead of the UI events.
+const STREAM_BUFFER: usize = 16;
+
+#[derive(Debug, Deserialize)]
+struct HttpConfig {
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    body: Option<Value>,
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u64,
+    #[serde(default = "default_follow_redirects")]
+    follow_redirects: bool,
+    #[serde(default = "default_max_redirects")]
+    max_redirects: usize,
+    #[serde(default)]
+    ignore_http_errors: bool,
+    #[serde(default)]
+    stream: bool,
+    #[serde(default)]
+    binary: bool,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_follow_redirects() -> bool {
+    true
+}
+
+fn default_max_redirects() -> usize {
+    10
+}
+
+// Copy of `headers` safe to write to logs.
+pub fn redact_headers(headers: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            if SENSITIVE_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                (name.clone(), REDACTED.to_string())
+            } else {
+                (name.clone(), value.clone())
+            }
+        })
+        .collect()
+}
+
+fn is_side_effecting(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn read_failed(e: reqwest::Error) -> NodeError {
+    NodeError::Failed(format!("failed to read response body: {}", e))
+}
+
+// Takes the longest prefix of `pending` that is complete UTF-8, keeping a
+// character split across network chunks for the next one.
+fn take_text(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => pending.len(),
+    };
+    let rest = pending.split_off(complete);
+    String::from_utf8_lossy(&std::mem::replace(pending, rest)).into_owned()
+}
+
+// Reads the body chunk by chunk, streaming each as text, and returns it whole.
+async fn stream_body(mut response: Response, node_id: &str, ctx: &ExecutionContext) -> Result<String> {
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    let read = async move {
+        let mut pending = Vec::new();
+        while let Some(bytes) = response.chunk().await.map_err(read_failed)? {
+            pending.extend_from_slice(&bytes);
+            let text = take_text(&mut pending);
+            if !text.is_empty() && sender.send(Value::String(text)).await.is_err() {
+                return Ok(());
+            }
+        }
+        if !pending.is_empty() {
+            let text = String::from_utf8_lossy(&pending).into_owned();
+            let _ = sender.send(Value::String(text)).await;
+        }
+        Ok(())
+    };
+    let ((), chunks) = tokio::try_join!(read, ctx.stream_output(node_id, receiver))?;
+    Ok(chunks.iter().filter_map(Value::as_str).collect())
+}
+
+pub struct HttpNodeExecutor;
+
+#[async_trait]
+impl NodeExecutor for HttpNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        "http"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "HTTP Request"
+    }
+
+    fn required_scopes(&self) -> &'static [&'static str] {
+        &["network"]
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Object,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "method",
+            "url",
+            "headers",
+            "body",
+            "timeout_ms",
+            "follow_redirects",
+            "max_redirects",
+            "ignore_http_errors",
+            "stream",
+            "binary",
+        ])
+    }
+
+    // Only safe methods given literally; a templated method may resolve to
+    // one with side effects.
+    fn is_deterministic(&self, data: &Value) -> bool {
+        let method = data.get("method").and_then(Value::as_str).unwrap_or("GET");
+        Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+            .is_ok_and(|method| !is_side_effecting(&method))
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["url"],
+            "properties": {
+                "method": { "type": "string", "minLength": 1, "default": default_method() },
+                "url": { "type": "string", "minLength": 1, "title": "URL" },
+                "headers": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                },
+                "body": {},
+                "timeout_ms": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "default": default_timeout_ms(),
+                },
+                "follow_redirects": { "type": "boolean", "default": default_follow_redirects() },
+                "max_redirects": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "default": default_max_redirects(),
+                },
+                "ignore_http_errors": { "type": "boolean", "default": false },
+                "stream": { "type": "boolean", "default": false },
+                "binary": { "type": "boolean", "default": false },
+            },
+        })
+    }
+
+    // Streamed chunks are text, which a binary body is not.
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        let set = |key| data.get(key).and_then(Value::as_bool) == Some(true);
+        if set("stream") && set("binary") {
+            vec!["stream and binary cannot both be set".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        _input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let config: HttpConfig = serde_json::from_value(ctx.render(&node.data)?)
+            .map_err(|e| NodeError::InvalidConfig(e.to_string()))?;
+
+        let method = Method::from_bytes(config.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| NodeError::InvalidConfig(format!("invalid method: {}", config.method)))?;
+
+        tracing::info!(
+            "node {}: {} {} headers={:?}",
+            node.id,
+            method,
+            config.url,
+            redact_headers(&config.headers)
+        );
+
+        if ctx.dry_run && is_side_effecting(&method) {
+            return Ok(serde_json::json!({
+                "status": 0,
+                "headers": {},
+                "body": null,
+                "dry_run": true,
+            }));
+        }
+
+        let redirect = if config.follow_redirects {
+            Policy::limited(config.max_redirects)
+        } else {
+            Policy::none()
+        };
+        let client = reqwest::Client::builder()
+            .redirect(redirect)
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .map_err(|e| NodeError::Failed(e.to_string()))?;
+
+        let mut request = client.request(method, &config.url);
+        for (name, value) in &config.headers {
+            request = request.header(name, value);
+        }
+        request = match config.body {
+            Some(Value::String(body)) => request.body(body),
+            Some(body) => request.json(&body),
+            None => request,
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| NodeError::Failed(format!("request to {} failed: {}", config.url, e)))?;
+
+        let status = response.status();
+        let headers: BTreeMap<String, String> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let body = if config.binary {
+            let content_type = headers.get("content-type").cloned();
+            let bytes = response.bytes().await.map_err(read_failed)?;
+            ctx.put_blob(&bytes, content_type, None).await?.to_value()
+        } else {
+            let text = if config.stream {
+                stream_body(response, &node.id, ctx).await?
+            } else {
+                response.text().await.map_err(read_failed)?
+            };
+            serde_json::from_str(&text).unwrap_or(Value::String(text))
+        };
+
+        if !status.is_success() && !config.ignore_http_errors {
+            return Err(NodeError::Failed(format!(
+                "HTTP {} from {}",
+                status, config.url
+            )));
+        }
+
+        Ok(serde_json::json!({
+            "status": status.as_u16(),
+            "headers": headers,
+            "body": body,
+        }))
+    }
+}
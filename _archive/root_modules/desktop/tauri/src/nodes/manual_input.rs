@@ -0,0 +1,194 @@
+/*!
+ * Manual input node - holds the run until a user supplies values matching the
+ * node's `fields` schema, then emits those values
+ */
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result, config_field};
+use crate::events::ExecutionEvent;
+use crate::execution_log::LogLevel;
+use crate::schema::{self, FieldError};
+use crate::WorkflowNode;
+
+pub const NODE_TYPE: &str = "manual_input";
+
+const DEFAULT_TIMEOUT_MS: u64 = 60 * 60 * 1000;
+
+#[derive(Debug)]
+struct Submission {
+    values: Value,
+    reply: oneshot::Sender<Vec<FieldError>>,
+}
+
+type InputKey = (String, String);
+
+// Nodes currently waiting for input, keyed by execution id and node id.
+#[derive(Debug, Clone, Default)]
+pub struct PendingInputs {
+    waiting: Arc<Mutex<HashMap<InputKey, mpsc::UnboundedSender<Submission>>>>,
+}
+
+impl PendingInputs {
+    // Hands `values` to the waiting node. The receiver yields the schema
+    // errors found, empty once the values are accepted. None if that node is
+    // not waiting for input.
+    pub fn provide(
+        &self,
+        execution_id: &str,
+        node_id: &str,
+        values: Value,
+    ) -> Option<oneshot::Receiver<Vec<FieldError>>> {
+        let key = (execution_id.to_string(), node_id.to_string());
+        let sender = self.waiting.lock().get(&key).cloned()?;
+        let (reply, errors) = oneshot::channel();
+        sender.send(Submission { values, reply }).ok()?;
+        Some(errors)
+    }
+
+    fn register(
+        &self,
+        execution_id: &str,
+        node_id: &str,
+    ) -> (Registration, mpsc::UnboundedReceiver<Submission>) {
+        let key = (execution_id.to_string(), node_id.to_string());
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.waiting.lock().insert(key.clone(), sender);
+        let registration = Registration {
+            inputs: self.clone(),
+            key,
+        };
+        (registration, receiver)
+    }
+}
+
+// Stops accepting input for a node when its wait ends for any reason.
+struct Registration {
+    inputs: PendingInputs,
+    key: InputKey,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.inputs.waiting.lock().remove(&self.key);
+    }
+}
+
+pub struct ManualInputNodeExecutor;
+
+fn fields(data: &Value) -> Result<Value> {
+    config_field::<Value>(data, "fields")?
+        .filter(Value::is_object)
+        .ok_or_else(|| NodeError::InvalidConfig("fields must be a JSON Schema object".to_string()))
+}
+
+#[async_trait]
+impl NodeExecutor for ManualInputNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        NODE_TYPE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Manual Input"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Object,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["fields", "prompt", "timeout_ms"])
+    }
+
+    fn needs_worker(&self) -> bool {
+        false
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["fields"],
+            "properties": {
+                "fields": { "type": "object", "title": "Fields (JSON Schema)" },
+                "prompt": { "type": "string", "title": "Prompt" },
+                "timeout_ms": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "default": DEFAULT_TIMEOUT_MS,
+                    "title": "Timeout (ms)",
+                },
+            },
+        })
+    }
+
+    // Requests input, then waits without polling for values that satisfy
+    // `fields`. Rejected values re-prompt with their errors; the timeout runs
+    // from the first prompt.
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        _input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let fields = fields(&node.data)?;
+        let prompt: Option<String> = config_field(&node.data, "prompt")?;
+        let timeout_ms: u64 = config_field(&node.data, "timeout_ms")?.unwrap_or(DEFAULT_TIMEOUT_MS);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(timeout_ms);
+
+        let (_registration, mut submissions) =
+            ctx.pending_inputs.register(&ctx.execution_id, &node.id);
+        ctx.log(LogLevel::Info, "waiting for manual input");
+
+        let mut errors = Vec::new();
+        loop {
+            ctx.events.emit(ExecutionEvent::InputRequested {
+                execution_id: ctx.execution_id.clone(),
+                node_id: node.id.clone(),
+                schema: fields.clone(),
+                prompt: prompt.clone(),
+                errors: errors.clone(),
+            });
+
+            let submission = tokio::select! {
+                _ = ctx.control.cancelled() => return Err(NodeError::Cancelled),
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(NodeError::Failed(format!(
+                        "no input received within {} ms",
+                        timeout_ms
+                    )))
+                }
+                submission = submissions.recv() => submission,
+            };
+            // The sender lives in `pending_inputs` until the registration drops.
+            let Some(Submission { values, reply }) = submission else {
+                return Err(NodeError::Failed("input channel closed".to_string()));
+            };
+
+            errors = schema::validate(&fields, &values);
+            let _ = reply.send(errors.clone());
+            if errors.is_empty() {
+                return Ok(values);
+            }
+            ctx.log(
+                LogLevel::Warn,
+                format!("input rejected with {} field errors", errors.len()),
+            );
+        }
+    }
+}
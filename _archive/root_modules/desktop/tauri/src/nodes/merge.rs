@@ -0,0 +1,200 @@
+/*!
+ * Merge node - combines the outputs of the branches that fan in to it
+ *
+ * The engine runs it only once every upstream node has run, and hands it each
+ * incoming branch separately, in delivery order. Strategies:
+ * `wait_all` (default) needs every branch and keys the outputs by source node;
+ * `combine_object` merges object outputs key by key, later branches winning;
+ * `concat_array` concatenates array outputs; `first_wins` takes the branch
+ * delivered first. With `timeout_ms`, the nodes still to run on the other
+ * branches must finish that long after the first branch arrives, or the merge
+ * fails.
+ */
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+use super::{DEFAULT_PORT, Connections, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result, config_field};
+use crate::WorkflowNode;
+
+const STRATEGIES: &[&str] = &["wait_all", "combine_object", "concat_array", "first_wins"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Strategy {
+    #[default]
+    WaitAll,
+    CombineObject,
+    ConcatArray,
+    FirstWins,
+}
+
+pub struct MergeNodeExecutor;
+
+fn strategy(data: &Value) -> Result<Strategy> {
+    Ok(config_field(data, "strategy")?.unwrap_or_default())
+}
+
+// Input the engine delivers: the branches that produced an output, in the
+// order they did, and the source nodes that have not.
+pub fn branch_input(delivered: Vec<(String, Value)>, pending: Vec<String>) -> Value {
+    let branches: Vec<Value> = delivered
+        .into_iter()
+        .map(|(source, output)| serde_json::json!({ "source": source, "output": output }))
+        .collect();
+    serde_json::json!({ "branches": branches, "pending": pending })
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    source: String,
+    output: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchInput {
+    branches: Vec<Branch>,
+    #[serde(default)]
+    pending: Vec<String>,
+}
+
+fn merge(strategy: Strategy, input: BranchInput) -> Result<Value> {
+    if strategy == Strategy::WaitAll && !input.pending.is_empty() {
+        return Err(NodeError::Failed(format!(
+            "merge is still waiting for branches from: {}",
+            input.pending.join(", ")
+        )));
+    }
+    if input.branches.is_empty() {
+        return Err(NodeError::Failed("merge received no branches".to_string()));
+    }
+
+    Ok(match strategy {
+        Strategy::WaitAll => Value::Object(
+            input
+                .branches
+                .into_iter()
+                .map(|branch| (branch.source, branch.output))
+                .collect(),
+        ),
+        // Non-object outputs are kept under their source node id.
+        Strategy::CombineObject => {
+            let mut combined = Map::new();
+            for branch in input.branches {
+                match branch.output {
+                    Value::Object(fields) => combined.extend(fields),
+                    output => {
+                        combined.insert(branch.source, output);
+                    }
+                }
+            }
+            Value::Object(combined)
+        }
+        // Non-array outputs are appended as single elements.
+        Strategy::ConcatArray => {
+            let mut items = Vec::new();
+            for branch in input.branches {
+                match branch.output {
+                    Value::Array(values) => items.extend(values),
+                    output => items.push(output),
+                }
+            }
+            Value::Array(items)
+        }
+        Strategy::FirstWins => input
+            .branches
+            .into_iter()
+            .next()
+            .map(|branch| branch.output)
+            .unwrap_or_default(),
+    })
+}
+
+#[async_trait]
+impl NodeExecutor for MergeNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        "merge"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Merge"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn connections(&self) -> Connections {
+        Connections {
+            min_inputs: 1,
+            ..Connections::default()
+        }
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["strategy", "timeout_ms"])
+    }
+
+    fn receives_branches(&self) -> bool {
+        true
+    }
+
+    fn branch_timeout(&self, data: &Value) -> Option<Duration> {
+        data.get("timeout_ms")
+            .and_then(Value::as_u64)
+            .map(Duration::from_millis)
+    }
+
+    fn is_deterministic(&self, _data: &Value) -> bool {
+        true
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "strategy": {
+                    "type": "string",
+                    "enum": STRATEGIES,
+                    "title": "Strategy",
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "title": "Branch timeout (ms)",
+                },
+            },
+        })
+    }
+
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        match strategy(data) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        _ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let strategy = strategy(&node.data)?;
+        let input: BranchInput = serde_json::from_value(input)
+            .map_err(|e| NodeError::Failed(format!("invalid merge input: {}", e)))?;
+        merge(strategy, input)
+    }
+}
@@ -0,0 +1,602 @@
+/*!
+ * Nodes - executor contract, execution context and the node registry
+ */
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::debugger::Debugger;
+use crate::environments;
+use crate::events::{EventBus, ExecutionEvent};
+use crate::execution_log::{ExecutionLog, LogLevel};
+use crate::expr::ExprError;
+use crate::rate_limit::{RateLimitWait, RateLimiter};
+use crate::run_blobs::{Blob, BlobStore};
+use crate::sandbox::{Exceeded, SandboxLimits};
+use crate::secrets::SecretValues;
+use crate::template::{self, TemplateError};
+use crate::workflow_state::StateStore;
+use crate::WorkflowNode;
+
+pub mod call_workflow;
+pub mod condition;
+pub mod delay;
+pub mod file_watch;
+pub mod http;
+pub mod manual_input;
+pub mod merge;
+pub mod plugin;
+pub mod state;
+pub mod transform;
+pub mod trigger;
+pub mod webhook;
+pub mod write_file;
+
+#[derive(Debug, Error)]
+pub enum NodeError {
+    #[error("invalid node config: {0}")]
+    InvalidConfig(String),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    Expression(#[from] ExprError),
+    #[error("workflow calls itself: {}", .0.join(" -> "))]
+    CallCycle(Vec<String>),
+    #[error("no executor registered for node type: {0}")]
+    UnknownType(String),
+    #[error("node type {0} needs an activated license")]
+    Locked(String),
+    #[error("node stopped: it {0}")]
+    ResourceExceeded(Exceeded),
+    #[error("{0}")]
+    Failed(String),
+    #[error("hook {hook}: {message}")]
+    Hook { hook: String, message: String },
+    #[error("node cancelled")]
+    Cancelled,
+    #[error("node killed while running")]
+    Killed,
+}
+
+pub type Result<T> = std::result::Result<T, NodeError>;
+
+// Stop and pause signals for one execution, shared by the engine and executors.
+#[derive(Debug, Clone)]
+pub struct ExecutionControl {
+    cancel: CancellationToken,
+    paused: Arc<watch::Sender<bool>>,
+    // Only consulted when the run has breakpoints enabled
+    debugger: Debugger,
+}
+
+impl Default for ExecutionControl {
+    fn default() -> Self {
+        let (paused, _) = watch::channel(false);
+        Self {
+            cancel: CancellationToken::new(),
+            paused: Arc::new(paused),
+            debugger: Debugger::default(),
+        }
+    }
+}
+
+impl ExecutionControl {
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+
+    // Returns false if the execution was already paused.
+    pub fn pause(&self) -> bool {
+        !self.paused.send_replace(true)
+    }
+
+    // Returns false if the execution was not paused.
+    pub fn resume(&self) -> bool {
+        self.paused.send_replace(false)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    // Resolves once the execution is paused (or cancelled).
+    pub async fn paused(&self) {
+        let mut paused = self.paused.subscribe();
+        tokio::select! {
+            _ = paused.wait_for(|paused| *paused) => {}
+            _ = self.cancel.cancelled() => {}
+        }
+    }
+
+    // Resolves immediately unless paused, in which case it waits for a resume
+    // (or cancellation) without polling.
+    pub async fn wait_while_paused(&self) {
+        let mut paused = self.paused.subscribe();
+        tokio::select! {
+            _ = paused.wait_for(|paused| !*paused) => {}
+            _ = self.cancel.cancelled() => {}
+        }
+    }
+}
+
+// Runs the workflow a `call_workflow` node calls, as part of the calling run.
+#[async_trait]
+pub trait WorkflowRunner: Send + Sync + std::fmt::Debug {
+    // `caller` is the calling run's context while its `call_workflow` node
+    // runs. Returns the called workflow's terminal output.
+    async fn run(&self, workflow_id: &str, input: Value, caller: &ExecutionContext) -> Result<Value>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionContext {
+    pub execution_id: String,
+    pub workflow_id: String,
+    // Payload the run was started with (trigger input)
+    pub input: Value,
+    pub node_outputs: HashMap<String, Value>,
+    pub variables: HashMap<String, Value>,
+    // Decrypted values of the secrets this run's templates reference
+    pub secrets: SecretValues,
+    // Variables of the environment active when the run started
+    pub environment: BTreeMap<String, String>,
+    // Side-effecting executors simulate their effect instead of performing it
+    pub dry_run: bool,
+    pub control: ExecutionControl,
+    pub events: EventBus,
+    pub logs: ExecutionLog,
+    // Node being executed; set by the engine before each node runs.
+    pub current_node: Option<String>,
+    pub pending_inputs: manual_input::PendingInputs,
+    // Engine-wide limiter, shared with every other running execution
+    pub rate_limiter: Arc<RateLimiter>,
+    pub rate_limit_wait: RateLimitWait,
+    // None outside an engine run, e.g. in node previews
+    pub workflows: Option<Arc<dyn WorkflowRunner>>,
+    // Workflows whose `call_workflow` nodes led to this run, outermost first
+    pub call_stack: Vec<String>,
+    pub blobs: BlobStore,
+    // Backs `get_state` and friends; None outside an engine run
+    pub state: Option<StateStore>,
+}
+
+impl ExecutionContext {
+    pub fn new(execution_id: &str, workflow_id: &str, input: Value) -> Self {
+        Self {
+            execution_id: execution_id.to_string(),
+            workflow_id: workflow_id.to_string(),
+            input,
+            ..Self::default()
+        }
+    }
+
+    // The JSON scope templates resolve against: `input.*`, `nodes.<id>.*`,
+    // `vars.*`, `secrets.*`, `env.*`.
+    pub fn template_scope(&self) -> Value {
+        serde_json::json!({
+            "input": self.input,
+            "nodes": self.node_outputs,
+            "vars": self.variables,
+            "secrets": self.secrets.scope(),
+            "env": environments::scope(&self.environment, &self.secrets),
+        })
+    }
+
+    pub fn render(&self, value: &Value) -> Result<Value> {
+        Ok(template::render_value(value, &self.template_scope())?)
+    }
+
+    // Appends to the run's log, attributed to the node being executed.
+    pub fn log(&self, level: LogLevel, message: impl Into<String>) {
+        self.logs.push(level, self.current_node.as_deref(), message, None);
+    }
+
+    // Waits for a slot in the rate limit `bucket`, counting the wait towards
+    // the run's total. Fails only if the execution is stopped meanwhile.
+    pub async fn acquire(&self, bucket: &str) -> Result<Duration> {
+        let waited = self.rate_limiter.acquire(bucket, &self.control).await?;
+        self.rate_limit_wait.add(waited);
+        Ok(waited)
+    }
+
+    // Keeps `bytes` until the run ends. Output the returned reference in
+    // their place; see `run_blobs`.
+    pub async fn put_blob(
+        &self,
+        bytes: &[u8],
+        content_type: Option<String>,
+        file_name: Option<String>,
+    ) -> Result<Blob> {
+        self.blobs
+            .put(&self.execution_id, bytes, content_type, file_name)
+            .await
+    }
+
+    pub async fn read_blob(&self, blob: &Blob) -> Result<Vec<u8>> {
+        self.blobs.read(&self.execution_id, blob).await
+    }
+
+    fn state_store(&self) -> Result<&StateStore> {
+        self.state
+            .as_ref()
+            .ok_or_else(|| NodeError::Failed("workflow state is only available in a run".to_string()))
+    }
+
+    // The value this workflow's runs last stored at `key`, if any.
+    pub fn get_state(&self, key: &str) -> Result<Option<Value>> {
+        self.state_store()?
+            .get(&self.workflow_id, key)
+            .map_err(|e| NodeError::Failed(e.to_string()))
+    }
+
+    pub fn set_state(&self, key: &str, value: &Value) -> Result<()> {
+        self.state_store()?
+            .set(&self.workflow_id, key, value)
+            .map_err(|e| NodeError::Failed(e.to_string()))
+    }
+
+    // Atomic across concurrent runs of the workflow; returns the new value.
+    pub fn increment_state(&self, key: &str, by: i64) -> Result<i64> {
+        self.state_store()?
+            .increment(&self.workflow_id, key, by)
+            .map_err(|e| NodeError::Failed(e.to_string()))
+    }
+
+    pub fn emit_progress(&self, node_id: &str, progress: Value) {
+        self.events.emit(ExecutionEvent::NodeProgress {
+            execution_id: self.execution_id.clone(),
+            node_id: node_id.to_string(),
+            progress,
+        });
+    }
+
+    // Forwards each chunk received on `chunks` as a `NodeOutputChunk` event
+    // the moment it arrives, and returns them all once every sender is
+    // dropped. Stopping the execution ends the stream at once: the receiver
+    // is dropped, so the producer's next send fails.
+    pub async fn stream_output(
+        &self,
+        node_id: &str,
+        mut chunks: mpsc::Receiver<Value>,
+    ) -> Result<Vec<Value>> {
+        let mut received = Vec::new();
+        loop {
+            tokio::select! {
+                chunk = chunks.recv() => match chunk {
+                    Some(chunk) => {
+                        self.events.emit(ExecutionEvent::NodeOutputChunk {
+                            execution_id: self.execution_id.clone(),
+                            node_id: node_id.to_string(),
+                            chunk: chunk.clone(),
+                        });
+                        received.push(chunk);
+                    }
+                    None => return Ok(received),
+                },
+                _ = self.control.cancelled() => return Err(NodeError::Cancelled),
+            }
+        }
+    }
+}
+
+// Port an edge without a handle attaches to.
+pub const DEFAULT_PORT: &str = "default";
+
+// JSON type carried by a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortType {
+    Any,
+    Object,
+    Array,
+    String,
+    Number,
+    Boolean,
+}
+
+impl PortType {
+    // Whether values from an output of type `self` may feed an input of
+    // type `input`. `Any` on either side connects to everything.
+    pub fn feeds(self, input: PortType) -> bool {
+        self == PortType::Any || input == PortType::Any || self == input
+    }
+
+    // Whether `value` is of this type.
+    pub fn admits(self, value: &Value) -> bool {
+        match self {
+            PortType::Any => true,
+            PortType::Object => value.is_object(),
+            PortType::Array => value.is_array(),
+            PortType::String => value.is_string(),
+            PortType::Number => value.is_number(),
+            PortType::Boolean => value.is_boolean(),
+        }
+    }
+}
+
+impl std::fmt::Display for PortType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PortType::Any => "any",
+            PortType::Object => "object",
+            PortType::Array => "array",
+            PortType::String => "string",
+            PortType::Number => "number",
+            PortType::Boolean => "boolean",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Port {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub port_type: PortType,
+}
+
+// Bounds on the number of edges into and out of a node; None is unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connections {
+    pub min_inputs: usize,
+    pub max_inputs: Option<usize>,
+    pub min_outputs: usize,
+    pub max_outputs: Option<usize>,
+}
+
+#[async_trait]
+pub trait NodeExecutor: Send + Sync {
+    fn node_type(&self) -> &'static str;
+
+    fn display_name(&self) -> &'static str;
+
+    // Token scopes the session needs before a node of this type may run.
+    fn required_scopes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // Keys this executor reads from `data`; None if it accepts arbitrary keys.
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    // Typed ports edges may attach to, by handle (`DEFAULT_PORT` for edges
+    // without one). None leaves the node's wiring unchecked.
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        None
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        None
+    }
+
+    // Checked by `validate_graph`. A node without input ports already rejects
+    // every incoming edge, so entry points need not declare `max_inputs`.
+    fn connections(&self) -> Connections {
+        Connections::default()
+    }
+
+    // Named input handles that collect every incoming value. Any other handle
+    // holds a single value, so several producers feeding it is ambiguous.
+    fn multi_value_inputs(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // Whether the node gets each incoming branch separately, as built by
+    // `merge::branch_input`, instead of the outputs keyed by source node.
+    fn receives_branches(&self) -> bool {
+        false
+    }
+
+    // Whether running the node takes one of the engine's workers. Nodes that
+    // only wait, on a timer or a person, leave them to nodes doing work.
+    fn needs_worker(&self) -> bool {
+        true
+    }
+
+    // How long after its first branch arrives the node may wait for the
+    // rest; only consulted when it receives branches.
+    fn branch_timeout(&self, _data: &Value) -> Option<Duration> {
+        None
+    }
+
+    // JSON Schema for the node's `data`, used to check configs and to generate
+    // config forms in the UI. Editor-only keys (label, notes) need not appear.
+    fn config_schema(&self) -> Value {
+        serde_json::json!({ "type": "object" })
+    }
+
+    // Returns a message per problem found in the node's `data` beyond what
+    // `config_schema` can express; only consulted once the schema is satisfied.
+    fn validate_config(&self, _data: &Value) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Whether the output depends only on the rendered config and the input,
+    // which lets `cacheable: true` serve it from the node cache.
+    fn is_deterministic(&self, _data: &Value) -> bool {
+        false
+    }
+
+    // Premium types only run while the app is activated; see `activation`.
+    fn is_premium(&self) -> bool {
+        false
+    }
+
+    // `input` is the upstream output delivered to this node.
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTypeInfo {
+    pub node_type: String,
+    pub display_name: String,
+    pub required_scopes: Vec<String>,
+    pub premium: bool,
+    // Premium, and the app is not activated
+    pub locked: bool,
+    pub connections: Connections,
+}
+
+#[derive(Default)]
+pub struct NodeRegistry {
+    executors: BTreeMap<String, Arc<dyn NodeExecutor>>,
+    // Every plugin found at startup, whether it registered or not
+    plugins: Vec<plugin::PluginInfo>,
+    premium_unlocked: AtomicBool,
+    // Shared with every plugin executor
+    sandbox_limits: Arc<parking_lot::Mutex<SandboxLimits>>,
+}
+
+impl NodeRegistry {
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register(Arc::new(trigger::TriggerNodeExecutor));
+        registry.register(Arc::new(http::HttpNodeExecutor));
+        registry.register(Arc::new(delay::DelayNodeExecutor));
+        registry.register(Arc::new(transform::TransformNodeExecutor));
+        registry.register(Arc::new(webhook::WebhookNodeExecutor));
+        registry.register(Arc::new(file_watch::FileWatchNodeExecutor));
+        registry.register(Arc::new(manual_input::ManualInputNodeExecutor));
+        registry.register(Arc::new(merge::MergeNodeExecutor));
+        registry.register(Arc::new(condition::ConditionNodeExecutor));
+        registry.register(Arc::new(call_workflow::CallWorkflowNodeExecutor));
+        registry.register(Arc::new(write_file::WriteFileNodeExecutor));
+        registry.register(Arc::new(state::StateNodeExecutor));
+        registry
+    }
+
+    // Registers the node types of the WebAssembly plugins in `dir`. Built-in
+    // types take precedence; a plugin claiming one fails to load.
+    pub fn load_plugins(&mut self, dir: &Path) {
+        let plugins = plugin::load_plugins(self, dir);
+        self.plugins.extend(plugins);
+    }
+
+    pub fn plugins(&self) -> &[plugin::PluginInfo] {
+        &self.plugins
+    }
+
+    pub fn register(&mut self, executor: Arc<dyn NodeExecutor>) {
+        self.executors
+            .insert(executor.node_type().to_string(), executor);
+    }
+
+    pub fn get(&self, node_type: &str) -> Option<Arc<dyn NodeExecutor>> {
+        self.executors.get(node_type).cloned()
+    }
+
+    // Follows the activation status; shared registries see the change at once.
+    pub fn set_premium_unlocked(&self, unlocked: bool) {
+        self.premium_unlocked.store(unlocked, Ordering::SeqCst);
+    }
+
+    // Applies to plugin calls that start after it.
+    pub fn set_sandbox_limits(&self, limits: SandboxLimits) {
+        *self.sandbox_limits.lock() = limits;
+    }
+
+    pub fn is_locked(&self, executor: &dyn NodeExecutor) -> bool {
+        executor.is_premium() && !self.premium_unlocked.load(Ordering::SeqCst)
+    }
+
+    // The executor to run a node of `node_type` with.
+    pub fn runnable(&self, node_type: &str) -> Result<Arc<dyn NodeExecutor>> {
+        let executor = self
+            .get(node_type)
+            .ok_or_else(|| NodeError::UnknownType(node_type.to_string()))?;
+        if self.is_locked(executor.as_ref()) {
+            return Err(NodeError::Locked(node_type.to_string()));
+        }
+        Ok(executor)
+    }
+
+    pub fn node_types(&self) -> Vec<NodeTypeInfo> {
+        self.executors
+            .values()
+            .map(|executor| NodeTypeInfo {
+                node_type: executor.node_type().to_string(),
+                display_name: executor.display_name().to_string(),
+                required_scopes: executor
+                    .required_scopes()
+                    .iter()
+                    .map(|scope| scope.to_string())
+                    .collect(),
+                premium: executor.is_premium(),
+                locked: self.is_locked(executor.as_ref()),
+                connections: executor.connections(),
+            })
+            .collect()
+    }
+
+    // Union of the scopes needed by every node, sorted. Unknown node types
+    // contribute nothing; validation reports them separately.
+    pub fn required_scopes(&self, nodes: &[WorkflowNode]) -> Vec<String> {
+        nodes
+            .iter()
+            .filter_map(|node| self.get(&node.node_type))
+            .flat_map(|executor| executor.required_scopes().iter().map(|scope| scope.to_string()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+// Returns the config field as the given type, or a descriptive config error.
+pub fn config_field<T: serde::de::DeserializeOwned>(
+    data: &Value,
+    field: &str,
+) -> Result<Option<T>> {
+    match data.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| NodeError::InvalidConfig(format!("{}: {}", field, e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::node;
+    use serde_json::json;
+
+    #[test]
+    fn required_scopes_come_from_the_node_types_used() {
+        let registry = NodeRegistry::with_builtin();
+        let nodes = vec![
+            node("t", "trigger", json!({})),
+            node("fetch", "http", json!({ "url": "https://example.com" })),
+            node("again", "http", json!({ "url": "https://example.org" })),
+            node("save", write_file::NODE_TYPE, json!({})),
+            node("ghost", "no-such-type", json!({})),
+        ];
+        assert_eq!(registry.required_scopes(&nodes), ["filesystem", "network"]);
+        assert!(registry.required_scopes(&nodes[..1]).is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+/*!
+ * Plugin nodes - node executors loaded from WebAssembly modules
+ *
+ * Every `.wasm` file in the plugins directory is compiled at startup and
+ * registers one node type. Modules run sandboxed: they get no imports (no
+ * filesystem, network or clock) and a fuel budget per call, each call runs in
+ * a fresh instance, and calls are held to the limits in `sandbox`. A plugin
+ * that fails to load is reported by `list_plugins` and does not affect the
+ * others.
+ *
+ * ABI version 1. A module exports:
+ * - `memory`
+ * - `abi_version() -> i32`, returning 1
+ * - `alloc(len: i32) -> i32`, a buffer the host writes a request into
+ * - `describe() -> i64`, the descriptor JSON: `node_type`, `display_name`,
+ *   and optionally `config_schema`, `config_keys`, `deterministic` and
+ *   `premium`
+ * - `execute(ptr: i32, len: i32) -> i64`, taking `{ "config", "input" }` and
+ *   returning `{ "output": ... }` or `{ "error": "message" }`
+ *
+ * An i64 result points at JSON in `memory`: offset in the high 32 bits,
+ * length in the low 32 bits.
+ */
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use wasmtime::{Config, Engine, Instance, Module, Store, Trap, UpdateDeadline};
+
+use super::{ExecutionContext, NodeError, NodeExecutor, NodeRegistry, Result};
+use crate::sandbox::{self, CallMonitor, Exceeded, SandboxLimits};
+use crate::WorkflowNode;
+
+pub const ABI_VERSION: i32 = 1;
+
+// Instructions a module may run per call.
+const FUEL_PER_CALL: u64 = 2_000_000_000;
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{0:#}")]
+    Wasm(#[from] wasmtime::Error),
+    #[error("unsupported plugin ABI version {0} (expected {ABI_VERSION})")]
+    UnsupportedAbi(i32),
+    #[error("invalid response from plugin: {0}")]
+    InvalidResponse(String),
+    #[error("node type {0} is already registered")]
+    DuplicateType(String),
+    #[error("plugin imports {0}, but plugins are given no imports")]
+    HasImports(String),
+    #[error(transparent)]
+    ResourceExceeded(#[from] Exceeded),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginLoadStatus {
+    Loaded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub path: PathBuf,
+    pub status: PluginLoadStatus,
+    // Set once the descriptor was read, even if registration then failed
+    pub node_type: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+    node_type: String,
+    display_name: String,
+    #[serde(default)]
+    config_schema: Option<Value>,
+    #[serde(default)]
+    config_keys: Option<Vec<String>>,
+    #[serde(default)]
+    deterministic: bool,
+    #[serde(default)]
+    premium: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Output(Value),
+    Error(String),
+}
+
+// Loads every `.wasm` file in `dir`, in name order. A missing directory
+// simply means no plugins.
+pub fn load_plugins(registry: &mut NodeRegistry, dir: &Path) -> Vec<PluginInfo> {
+    let mut paths = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("failed to read plugin directory {}: {}", dir.display(), e);
+            }
+            return Vec::new();
+        }
+    };
+    paths.sort();
+
+    let engine = match sandbox_engine() {
+        Ok(engine) => engine,
+        Err(e) => {
+            tracing::error!("failed to set up the plugin runtime: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let limits = registry.sandbox_limits.clone();
+    paths
+        .into_iter()
+        .map(|path| {
+            let mut node_type = None;
+            let loaded = load_plugin(&engine, &path, &limits).and_then(|executor| {
+                node_type = Some(executor.node_type.to_string());
+                if registry.get(executor.node_type).is_some() {
+                    return Err(PluginError::DuplicateType(executor.node_type.to_string()));
+                }
+                registry.register(std::sync::Arc::new(executor));
+                Ok(())
+            });
+            match loaded {
+                Ok(()) => {
+                    tracing::info!("loaded plugin {}", path.display());
+                    PluginInfo {
+                        path,
+                        status: PluginLoadStatus::Loaded,
+                        node_type,
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("failed to load plugin {}: {}", path.display(), e);
+                    PluginInfo {
+                        path,
+                        status: PluginLoadStatus::Failed,
+                        node_type,
+                        error: Some(e.to_string()),
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+// Ticks the engine's epoch for as long as the app runs, which is when calls
+// check their limits.
+fn sandbox_engine() -> std::result::Result<Engine, PluginError> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+    let ticking = engine.clone();
+    std::thread::Builder::new()
+        .name("plugin-epoch".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(sandbox::CHECK_INTERVAL);
+            ticking.increment_epoch();
+        })?;
+    Ok(engine)
+}
+
+fn load_plugin(
+    engine: &Engine,
+    path: &Path,
+    limits: &Arc<Mutex<SandboxLimits>>,
+) -> std::result::Result<PluginNodeExecutor, PluginError> {
+    let bytes = std::fs::read(path)?;
+    let module = Module::new(engine, bytes)?;
+    let imports: Vec<String> = module
+        .imports()
+        .map(|import| format!("{}.{}", import.module(), import.name()))
+        .collect();
+    if !imports.is_empty() {
+        return Err(PluginError::HasImports(imports.join(", ")));
+    }
+
+    let monitor = CallMonitor::new(*limits.lock(), Arc::default());
+    let (mut store, instance) = instantiate(&module, monitor)?;
+    let version = instance
+        .get_typed_func::<(), i32>(&mut store, "abi_version")?
+        .call(&mut store, ())
+        .map_err(|e| call_failed(&mut store, e))?;
+    if version != ABI_VERSION {
+        return Err(PluginError::UnsupportedAbi(version));
+    }
+    let packed = instance
+        .get_typed_func::<(), i64>(&mut store, "describe")?
+        .call(&mut store, ())
+        .map_err(|e| call_failed(&mut store, e))?;
+    let descriptor: Descriptor =
+        serde_json::from_slice(&read_packed(&mut store, &instance, packed)?)
+            .map_err(|e| PluginError::InvalidResponse(format!("descriptor: {}", e)))?;
+    // Check the entrypoints now rather than on the first run.
+    instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+    instance.get_typed_func::<(i32, i32), i64>(&mut store, "execute")?;
+
+    // Registered types live for the whole process, like the built-in ones.
+    let leak = |s: String| -> &'static str { Box::leak(s.into_boxed_str()) };
+    Ok(PluginNodeExecutor {
+        node_type: leak(descriptor.node_type),
+        display_name: leak(descriptor.display_name),
+        config_schema: descriptor
+            .config_schema
+            .unwrap_or_else(|| serde_json::json!({ "type": "object" })),
+        config_keys: descriptor
+            .config_keys
+            .map(|keys| &*Vec::leak(keys.into_iter().map(leak).collect())),
+        deterministic: descriptor.deterministic,
+        premium: descriptor.premium,
+        module,
+        limits: limits.clone(),
+    })
+}
+
+// A fresh, import-free instance with its own fuel budget, watched by `monitor`.
+fn instantiate(
+    module: &Module,
+    monitor: CallMonitor,
+) -> std::result::Result<(Store<CallMonitor>, Instance), PluginError> {
+    let mut store = Store::new(module.engine(), monitor);
+    store.limiter(|monitor| monitor);
+    store.set_fuel(FUEL_PER_CALL)?;
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(|mut store| {
+        store.data_mut().check()?;
+        Ok(UpdateDeadline::Continue(1))
+    });
+    let instance = Instance::new(&mut store, module, &[])
+        .map_err(|e| call_failed(&mut store, e))?;
+    Ok((store, instance))
+}
+
+// Tells a breached limit apart from the module failing on its own.
+fn call_failed(store: &mut Store<CallMonitor>, error: wasmtime::Error) -> PluginError {
+    if let Some(exceeded) = store.data_mut().take_exceeded() {
+        return exceeded.into();
+    }
+    if error.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) {
+        return Exceeded::Fuel(FUEL_PER_CALL).into();
+    }
+    error.into()
+}
+
+fn read_packed(
+    store: &mut Store<CallMonitor>,
+    instance: &Instance,
+    packed: i64,
+) -> std::result::Result<Vec<u8>, PluginError> {
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .ok_or_else(|| PluginError::InvalidResponse("no exported memory".to_string()))?;
+    let offset = (packed as u64 >> 32) as usize;
+    let len = (packed as u64 & 0xffff_ffff) as usize;
+    let mut buf = vec![0; len];
+    memory
+        .read(&*store, offset, &mut buf)
+        .map_err(|_| PluginError::InvalidResponse("result outside of memory".to_string()))?;
+    Ok(buf)
+}
+
+fn call_execute(
+    module: &Module,
+    request: &[u8],
+    monitor: CallMonitor,
+) -> std::result::Result<Response, PluginError> {
+    let (mut store, instance) = instantiate(module, monitor)?;
+    let len = i32::try_from(request.len())
+        .map_err(|_| PluginError::InvalidResponse("request too large".to_string()))?;
+    let ptr = instance
+        .get_typed_func::<i32, i32>(&mut store, "alloc")?
+        .call(&mut store, len)
+        .map_err(|e| call_failed(&mut store, e))?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| PluginError::InvalidResponse("no exported memory".to_string()))?;
+    memory
+        .write(&mut store, ptr as u32 as usize, request)
+        .map_err(|_| {
+            PluginError::InvalidResponse("alloc returned a buffer outside of memory".to_string())
+        })?;
+
+    let packed = instance
+        .get_typed_func::<(i32, i32), i64>(&mut store, "execute")?
+        .call(&mut store, (ptr, len))
+        .map_err(|e| call_failed(&mut store, e))?;
+    serde_json::from_slice(&read_packed(&mut store, &instance, packed)?)
+        .map_err(|e| PluginError::InvalidResponse(e.to_string()))
+}
+
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+pub struct PluginNodeExecutor {
+    node_type: &'static str,
+    display_name: &'static str,
+    config_schema: Value,
+    config_keys: Option<&'static [&'static str]>,
+    deterministic: bool,
+    premium: bool,
+    module: Module,
+    // The registry's, so changes apply to the next call
+    limits: Arc<Mutex<SandboxLimits>>,
+}
+
+#[async_trait]
+impl NodeExecutor for PluginNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        self.node_type
+    }
+
+    fn display_name(&self) -> &'static str {
+        self.display_name
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        self.config_keys
+    }
+
+    fn config_schema(&self) -> Value {
+        self.config_schema.clone()
+    }
+
+    fn is_deterministic(&self, _data: &Value) -> bool {
+        self.deterministic
+    }
+
+    fn is_premium(&self) -> bool {
+        self.premium
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let request = serde_json::to_vec(&serde_json::json!({
+            "config": ctx.render(&node.data)?,
+            "input": input,
+        }))
+        .map_err(|e| NodeError::Failed(e.to_string()))?;
+
+        // A cancelled call is interrupted at the next tick of its blocking
+        // thread; its result is discarded. So is one whose future is dropped,
+        // as when the node is killed.
+        let module = self.module.clone();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let _stop_on_drop = StopOnDrop(stopped.clone());
+        let monitor = CallMonitor::new(*self.limits.lock(), stopped.clone());
+        let call = tokio::task::spawn_blocking(move || call_execute(&module, &request, monitor));
+        let response = tokio::select! {
+            joined = call => match joined
+                .map_err(|e| NodeError::Failed(format!("plugin {} panicked: {}", self.node_type, e)))?
+            {
+                Ok(response) => response,
+                Err(PluginError::ResourceExceeded(exceeded)) => {
+                    return Err(NodeError::ResourceExceeded(exceeded))
+                }
+                Err(e) => return Err(NodeError::Failed(format!("plugin {}: {}", self.node_type, e))),
+            },
+            _ = ctx.control.cancelled() => {
+                stopped.store(true, Ordering::SeqCst);
+                return Err(NodeError::Cancelled);
+            }
+        };
+
+        match response {
+            Response::Output(output) => Ok(output),
+            Response::Error(message) => Err(NodeError::Failed(message)),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+/*!
+ * State node - reads or updates a value the workflow keeps across runs and
+ * outputs `{ key, value }`
+ *
+ * `get` outputs the stored value (null while unset), `set` stores `value`, or
+ * the node's input when unset, and `increment` adds `by` (default 1) to the
+ * integer at `key` atomically. See `workflow_state`.
+ */
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result};
+use crate::WorkflowNode;
+
+pub const NODE_TYPE: &str = "workflow_state";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+    #[default]
+    Get,
+    Set,
+    Increment,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateConfig {
+    key: String,
+    #[serde(default)]
+    operation: Operation,
+    #[serde(default)]
+    value: Option<Value>,
+    #[serde(default = "default_by")]
+    by: i64,
+}
+
+fn default_by() -> i64 {
+    1
+}
+
+pub struct StateNodeExecutor;
+
+#[async_trait]
+impl NodeExecutor for StateNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        NODE_TYPE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Workflow State"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Object,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["key", "operation", "value", "by"])
+    }
+
+    fn needs_worker(&self) -> bool {
+        false
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["key"],
+            "properties": {
+                "key": { "type": "string", "minLength": 1, "maxLength": 128, "title": "Key" },
+                "operation": {
+                    "type": "string",
+                    "enum": ["get", "set", "increment"],
+                    "default": "get",
+                },
+                "value": {},
+                "by": { "type": "integer", "default": 1 },
+            },
+        })
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let config: StateConfig = serde_json::from_value(ctx.render(&node.data)?)
+            .map_err(|e| NodeError::InvalidConfig(e.to_string()))?;
+
+        // A dry run reads the state but leaves it as it was.
+        let value = match config.operation {
+            Operation::Get => ctx.get_state(&config.key)?.unwrap_or(Value::Null),
+            Operation::Set => {
+                let value = config.value.unwrap_or(input);
+                if !ctx.dry_run {
+                    ctx.set_state(&config.key, &value)?;
+                }
+                value
+            }
+            Operation::Increment if ctx.dry_run => {
+                let current = match ctx.get_state(&config.key)? {
+                    Some(value) => value.as_i64().ok_or_else(|| {
+                        NodeError::Failed(format!("state {} does not hold an integer", config.key))
+                    })?,
+                    None => 0,
+                };
+                let value = current.checked_add(config.by).ok_or_else(|| {
+                    NodeError::Failed(format!("incrementing state {} would overflow", config.key))
+                })?;
+                Value::from(value)
+            }
+            Operation::Increment => Value::from(ctx.increment_state(&config.key, config.by)?),
+        };
+
+        let mut output = serde_json::json!({
+            "key": config.key,
+            "value": value,
+        });
+        if ctx.dry_run && !matches!(config.operation, Operation::Get) {
+            output["dry_run"] = Value::Bool(true);
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,448 @@
+/*!
+ * Transform node - remaps the upstream output with a list of path mappings
+ *
+ * Paths are dotted (`user.address.city`) with `[n]` indices; `[*]` maps every
+ * element of a list, so `items[*].name` -> `names[*]` keeps the list shape.
+ * A mapping may compute its value with an `expression` instead of reading a
+ * `source` path; see `expr` for the language.
+ */
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result};
+use crate::expr::{self, Expression};
+use crate::WorkflowNode;
+
+#[derive(Debug, Deserialize)]
+struct TransformConfig {
+    mappings: Vec<Mapping>,
+}
+
+// Exactly one of `source` and `expression` is set.
+#[derive(Debug, Deserialize)]
+struct Mapping {
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    expression: Option<String>,
+    target: String,
+    #[serde(default)]
+    function: Option<String>,
+    // When set, a missing source path or a null expression result yields
+    // this value instead.
+    #[serde(default)]
+    default: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Each,
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let invalid = || NodeError::InvalidConfig(format!("invalid path: {}", path));
+    let mut segments = Vec::new();
+    if path.trim().is_empty() || path.trim() == "$" {
+        return Ok(segments);
+    }
+
+    for part in path.split('.') {
+        let (key, mut rest) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        } else if rest.is_empty() {
+            return Err(invalid());
+        }
+        while !rest.is_empty() {
+            let end = rest.find(']').ok_or_else(invalid)?;
+            let inner = rest[1..end].trim();
+            segments.push(if inner == "*" {
+                Segment::Each
+            } else {
+                Segment::Index(inner.parse().map_err(|_| invalid())?)
+            });
+            rest = &rest[end + 1..];
+            if !rest.is_empty() && !rest.starts_with('[') {
+                return Err(invalid());
+            }
+        }
+    }
+    Ok(segments)
+}
+
+// Highest index a target path may write to. Slots before it are filled with
+// null, so an unbounded index would allocate whatever the path asks for.
+const MAX_TARGET_INDEX: usize = 9_999;
+
+fn parse_target(path: &str) -> Result<Vec<Segment>> {
+    let segments = parse_path(path)?;
+    for segment in &segments {
+        if let Segment::Index(index) = segment {
+            if *index > MAX_TARGET_INDEX {
+                return Err(NodeError::InvalidConfig(format!(
+                    "target path '{}' writes index {}, past the limit of {}",
+                    path, index, MAX_TARGET_INDEX
+                )));
+            }
+        }
+    }
+    Ok(segments)
+}
+
+fn describe(segments: &[Segment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Key(key) if path.is_empty() => path.push_str(key),
+            Segment::Key(key) => {
+                path.push('.');
+                path.push_str(key);
+            }
+            Segment::Index(index) => path.push_str(&format!("[{}]", index)),
+            Segment::Each => path.push_str("[*]"),
+        }
+    }
+    if path.is_empty() {
+        "$".to_string()
+    } else {
+        path
+    }
+}
+
+// Reads `segments` from `value`; `[*]` yields an array with one entry per element.
+fn read(value: &Value, segments: &[Segment], walked: usize, full: &[Segment]) -> Result<Value> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Ok(value.clone());
+    };
+    let missing = || {
+        NodeError::Failed(format!(
+            "source path '{}' not found: nothing at '{}'",
+            describe(full),
+            describe(&full[..=walked])
+        ))
+    };
+
+    match segment {
+        Segment::Key(key) => {
+            let next = value.as_object().and_then(|map| map.get(key)).ok_or_else(missing)?;
+            read(next, rest, walked + 1, full)
+        }
+        Segment::Index(index) => {
+            let next = value.as_array().and_then(|items| items.get(*index)).ok_or_else(missing)?;
+            read(next, rest, walked + 1, full)
+        }
+        Segment::Each => {
+            let items = value.as_array().ok_or_else(|| {
+                NodeError::Failed(format!(
+                    "source path '{}' expects a list at '{}'",
+                    describe(full),
+                    describe(&full[..walked])
+                ))
+            })?;
+            items
+                .iter()
+                .map(|item| read(item, rest, walked + 1, full))
+                .collect::<Result<Vec<_>>>()
+                .map(Value::Array)
+        }
+    }
+}
+
+// Writes `value` at `segments` inside `target`, creating objects and lists as
+// needed. At a `[*]` segment `value` must be a list; element i goes to slot i.
+fn write(target: &mut Value, segments: &[Segment], value: Value, full: &[Segment]) -> Result<()> {
+    let Some((segment, rest)) = segments.split_first() else {
+        *target = value;
+        return Ok(());
+    };
+
+    match segment {
+        Segment::Key(key) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            let slot = target
+                .as_object_mut()
+                .map(|map| map.entry(key.clone()).or_insert(Value::Null))
+                .expect("target is an object");
+            write(slot, rest, value, full)
+        }
+        Segment::Index(index) => {
+            if !target.is_array() {
+                *target = Value::Array(Vec::new());
+            }
+            let items = target.as_array_mut().expect("target is an array");
+            if items.len() <= *index {
+                items.resize(index + 1, Value::Null);
+            }
+            write(&mut items[*index], rest, value, full)
+        }
+        Segment::Each => {
+            let Value::Array(values) = value else {
+                return Err(NodeError::InvalidConfig(format!(
+                    "target path '{}' uses [*] but the source is not a list",
+                    describe(full)
+                )));
+            };
+            if !target.is_array() {
+                *target = Value::Array(Vec::new());
+            }
+            let items = target.as_array_mut().expect("target is an array");
+            if items.len() < values.len() {
+                items.resize(values.len(), Value::Null);
+            }
+            for (slot, value) in items.iter_mut().zip(values) {
+                write(slot, rest, value, full)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn apply_function(name: &str, value: Value) -> Result<Value> {
+    // Functions apply element-wise to lists produced by `[*]`.
+    if let Value::Array(items) = value {
+        return items
+            .into_iter()
+            .map(|item| apply_function(name, item))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array);
+    }
+
+    match name {
+        "upper" => Ok(match value {
+            Value::String(s) => Value::String(s.to_uppercase()),
+            other => other,
+        }),
+        "lower" => Ok(match value {
+            Value::String(s) => Value::String(s.to_lowercase()),
+            other => other,
+        }),
+        "to_number" => match &value {
+            Value::Number(_) => Ok(value),
+            Value::Bool(b) => Ok(Value::from(*b as i64)),
+            Value::String(s) => {
+                let trimmed = s.trim();
+                if let Ok(int) = trimmed.parse::<i64>() {
+                    Ok(Value::from(int))
+                } else {
+                    trimmed
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(serde_json::Number::from_f64)
+                        .map(Value::Number)
+                        .ok_or_else(|| NodeError::Failed(format!("cannot convert '{}' to a number", s)))
+                }
+            }
+            other => Err(NodeError::Failed(format!("cannot convert {} to a number", other))),
+        },
+        "to_string" => Ok(match value {
+            Value::String(s) => Value::String(s),
+            other => Value::String(other.to_string()),
+        }),
+        other => Err(NodeError::InvalidConfig(format!("unknown function: {}", other))),
+    }
+}
+
+const FUNCTIONS: &[&str] = &["upper", "lower", "to_number", "to_string"];
+
+fn parse_config(data: &Value) -> Result<TransformConfig> {
+    let config: TransformConfig = serde_json::from_value(data.clone())
+        .map_err(|e| NodeError::InvalidConfig(e.to_string()))?;
+    for mapping in &config.mappings {
+        match (&mapping.source, &mapping.expression) {
+            (Some(source), None) => {
+                parse_path(source)?;
+            }
+            (None, Some(expression)) => {
+                Expression::parse(expression).map_err(|e| {
+                    NodeError::InvalidConfig(format!("expression '{}': {}", expression, e))
+                })?;
+            }
+            _ => {
+                return Err(NodeError::InvalidConfig(format!(
+                    "mapping to '{}' needs exactly one of source and expression",
+                    mapping.target
+                )))
+            }
+        }
+        parse_target(&mapping.target)?;
+        if let Some(function) = &mapping.function {
+            if !FUNCTIONS.contains(&function.as_str()) {
+                return Err(NodeError::InvalidConfig(format!("unknown function: {}", function)));
+            }
+        }
+    }
+    Ok(config)
+}
+
+fn mapped_value(mapping: &Mapping, input: &Value, scope: &Value) -> Result<Value> {
+    if let Some(expression) = &mapping.expression {
+        let value = Expression::parse(expression)?.evaluate(scope)?;
+        return Ok(match (value, &mapping.default) {
+            (Value::Null, Some(default)) => default.clone(),
+            (value, _) => value,
+        });
+    }
+
+    let source = parse_path(mapping.source.as_deref().unwrap_or_default())?;
+    match (read(input, &source, 0, &source), &mapping.default) {
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(default)) => Ok(default.clone()),
+        (Err(e), None) => Err(e),
+    }
+}
+
+// `scope` is what expressions see; build it with `expr::node_scope`.
+pub fn transform(data: &Value, input: &Value, scope: &Value) -> Result<Value> {
+    let config = parse_config(data)?;
+    let mut output = Value::Object(Map::new());
+
+    for mapping in &config.mappings {
+        let target = parse_target(&mapping.target)?;
+        let value = mapped_value(mapping, input, scope)?;
+        let value = match &mapping.function {
+            Some(function) => apply_function(function, value)?,
+            None => value,
+        };
+        write(&mut output, &target, value, &target)?;
+    }
+
+    Ok(output)
+}
+
+pub struct TransformNodeExecutor;
+
+#[async_trait]
+impl NodeExecutor for TransformNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        "transform"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Transform"
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Object,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["mappings"])
+    }
+
+    // Cache keys cover the config and the input only, so expressions reading
+    // anything besides `upstream` rule caching out.
+    fn is_deterministic(&self, data: &Value) -> bool {
+        let Ok(config) = parse_config(data) else {
+            return false;
+        };
+        config
+            .mappings
+            .iter()
+            .filter_map(|mapping| mapping.expression.as_deref())
+            .all(|source| {
+                Expression::parse(source).is_ok_and(|expression| {
+                    expression.references().iter().all(|name| *name == expr::UPSTREAM)
+                })
+            })
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["mappings"],
+            "properties": {
+                "mappings": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["target"],
+                        "properties": {
+                            "source": { "type": "string" },
+                            "expression": { "type": "string" },
+                            "target": { "type": "string" },
+                            "function": {
+                                "type": "string",
+                                "enum": FUNCTIONS,
+                            },
+                            "default": {},
+                        },
+                        "additionalProperties": false,
+                    },
+                },
+            },
+        })
+    }
+
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        match parse_config(data) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let scope = expr::node_scope(ctx.template_scope(), &input);
+        transform(&node.data, &input, &scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mapping(source: &str, target: &str) -> Value {
+        json!({ "mappings": [{ "source": source, "target": target }] })
+    }
+
+    #[test]
+    fn target_index_fills_the_slots_before_it() {
+        let output = transform(&mapping("name", "names[2]"), &json!({ "name": "ada" }), &json!({}))
+            .unwrap();
+        assert_eq!(output, json!({ "names": [null, null, "ada"] }));
+    }
+
+    #[test]
+    fn target_index_past_the_limit_is_rejected() {
+        let data = mapping("name", &format!("names[{}]", usize::MAX));
+        let err = transform(&data, &json!({ "name": "ada" }), &json!({})).unwrap_err();
+        assert!(matches!(err, NodeError::InvalidConfig(_)), "{}", err);
+        assert_eq!(TransformNodeExecutor.validate_config(&data).len(), 1);
+
+        let limit = mapping("name", &format!("names[{}]", MAX_TARGET_INDEX));
+        assert!(TransformNodeExecutor.validate_config(&limit).is_empty());
+        let past = mapping("name", &format!("names[{}]", MAX_TARGET_INDEX + 1));
+        assert_eq!(TransformNodeExecutor.validate_config(&past).len(), 1);
+    }
+
+    #[test]
+    fn source_indices_are_only_read() {
+        let data = mapping("items[5000000]", "first");
+        let err = transform(&data, &json!({ "items": [1] }), &json!({})).unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+    }
+}
@@ -0,0 +1,51 @@
+/*!
+ * Trigger node - entry point that emits the run's input payload
+ */
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeExecutor, Port, PortType, Result};
+use crate::WorkflowNode;
+
+pub struct TriggerNodeExecutor;
+
+#[async_trait]
+impl NodeExecutor for TriggerNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        "trigger"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Manual Trigger"
+    }
+
+    // Starts a run, so nothing feeds it.
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&[])
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({ "type": "object", "properties": {} })
+    }
+
+    async fn execute(
+        &self,
+        _node: &WorkflowNode,
+        _input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        Ok(ctx.input.clone())
+    }
+}
@@ -0,0 +1,82 @@
+/*!
+ * Webhook node - entry point for runs started by an HTTP request to `/hooks/<path>`
+ */
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeExecutor, Port, PortType, Result, config_field};
+use crate::WorkflowNode;
+
+pub const NODE_TYPE: &str = "webhook";
+
+pub struct WebhookNodeExecutor;
+
+// The configured path without surrounding slashes, if set.
+pub fn hook_path(data: &Value) -> Option<String> {
+    config_field::<String>(data, "path")
+        .ok()
+        .flatten()
+        .map(|path| path.trim().trim_matches('/').to_string())
+        .filter(|path| !path.is_empty())
+}
+
+#[async_trait]
+impl NodeExecutor for WebhookNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        NODE_TYPE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Webhook Trigger"
+    }
+
+    // Started by a request, so nothing feeds it.
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["path"])
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["path"],
+            "properties": {
+                "path": { "type": "string", "minLength": 1, "title": "Path" },
+            },
+        })
+    }
+
+    fn validate_config(&self, data: &Value) -> Vec<String> {
+        match hook_path(data) {
+            Some(path) if path.chars().all(|c| c.is_ascii_alphanumeric() || "-_/".contains(c)) => {
+                Vec::new()
+            }
+            Some(path) => vec![format!(
+                "path {} may only contain letters, digits, '-', '_' and '/'",
+                path
+            )],
+            None => vec!["path must not be empty".to_string()],
+        }
+    }
+
+    // The run input is the request body.
+    async fn execute(
+        &self,
+        _node: &WorkflowNode,
+        _input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        Ok(ctx.input.clone())
+    }
+}
@@ -0,0 +1,124 @@
+/*!
+ * Write file node - writes `content`, or its input when unset, to `path` and
+ * outputs `{ path, size }`
+ *
+ * A blob reference is written as the blob's bytes, a string as UTF-8 text and
+ * any other value as JSON. With `create_dirs`, missing parent folders are
+ * created first.
+ */
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+use super::{DEFAULT_PORT, ExecutionContext, NodeError, NodeExecutor, Port, PortType, Result};
+use crate::execution_log::LogLevel;
+use crate::run_blobs::Blob;
+use crate::WorkflowNode;
+
+pub const NODE_TYPE: &str = "write_file";
+
+#[derive(Debug, Deserialize)]
+struct WriteFileConfig {
+    path: PathBuf,
+    #[serde(default)]
+    content: Option<Value>,
+    #[serde(default)]
+    create_dirs: bool,
+}
+
+async fn content_bytes(content: Value, ctx: &ExecutionContext) -> Result<Vec<u8>> {
+    if let Some(blob) = Blob::from_value(&content) {
+        return ctx.read_blob(&blob).await;
+    }
+    match content {
+        Value::String(text) => Ok(text.into_bytes()),
+        other => serde_json::to_vec(&other).map_err(|e| NodeError::Failed(e.to_string())),
+    }
+}
+
+pub struct WriteFileNodeExecutor;
+
+#[async_trait]
+impl NodeExecutor for WriteFileNodeExecutor {
+    fn node_type(&self) -> &'static str {
+        NODE_TYPE
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Write File"
+    }
+
+    fn required_scopes(&self) -> &'static [&'static str] {
+        &["filesystem"]
+    }
+
+    fn input_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Any,
+        }])
+    }
+
+    fn output_ports(&self) -> Option<&'static [Port]> {
+        Some(&[Port {
+            name: DEFAULT_PORT,
+            port_type: PortType::Object,
+        }])
+    }
+
+    fn config_keys(&self) -> Option<&'static [&'static str]> {
+        Some(&["path", "content", "create_dirs"])
+    }
+
+    fn config_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["path"],
+            "properties": {
+                "path": { "type": "string", "minLength": 1, "title": "File" },
+                "content": {},
+                "create_dirs": { "type": "boolean", "default": false },
+            },
+        })
+    }
+
+    async fn execute(
+        &self,
+        node: &WorkflowNode,
+        input: Value,
+        ctx: &ExecutionContext,
+    ) -> Result<Value> {
+        let config: WriteFileConfig = serde_json::from_value(ctx.render(&node.data)?)
+            .map_err(|e| NodeError::InvalidConfig(e.to_string()))?;
+        let bytes = content_bytes(config.content.unwrap_or(input), ctx).await?;
+
+        if ctx.dry_run {
+            return Ok(serde_json::json!({
+                "path": config.path,
+                "size": bytes.len(),
+                "dry_run": true,
+            }));
+        }
+
+        let failed = |e: std::io::Error| {
+            NodeError::Failed(format!("failed to write {}: {}", config.path.display(), e))
+        };
+        if config.create_dirs {
+            if let Some(parent) = config.path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(failed)?;
+            }
+        }
+        tokio::fs::write(&config.path, &bytes).await.map_err(failed)?;
+        ctx.log(
+            LogLevel::Info,
+            format!("wrote {} bytes to {}", bytes.len(), config.path.display()),
+        );
+
+        Ok(serde_json::json!({
+            "path": config.path,
+            "size": bytes.len(),
+        }))
+    }
+}
@@ -0,0 +1,154 @@
+/*!
+ * Normalize - cleans up workflows received from the frontend before they are stored
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::debugger;
+use crate::node_cache;
+use crate::nodes::NodeRegistry;
+use crate::rate_limit;
+use crate::workflow_engine;
+use crate::Workflow;
+
+// Positions further out than this are treated as corrupt and pulled back in.
+const POSITION_LIMIT: f64 = 1_000_000.0;
+
+// Keys that any node's `data` may carry: editor-only ones plus the engine's
+// rate limit, caching, breakpoint and disabled settings.
+const COMMON_KEYS: &[&str] = &[
+    "label",
+    "notes",
+    workflow_engine::DISABLED_KEY,
+    rate_limit::BUCKET_KEY,
+    node_cache::CACHEABLE_KEY,
+    node_cache::TTL_KEY,
+    debugger::BREAKPOINT_KEY,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizationChange {
+    pub node_id: Option<String>,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NormalizationReport {
+    pub changes: Vec<NormalizationChange>,
+}
+
+impl NormalizationReport {
+    fn record(&mut self, node_id: Option<&str>, field: &str, message: impl Into<String>) {
+        self.changes.push(NormalizationChange {
+            node_id: node_id.map(str::to_string),
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+pub fn clamp_coordinate(value: f64) -> f64 {
+    if value.is_finite() {
+        value.clamp(-POSITION_LIMIT, POSITION_LIMIT)
+    } else {
+        0.0
+    }
+}
+
+// Normalizes `workflow` in place. Node `data` keys are kept in sorted order by
+// `serde_json::Map`, so the stored JSON is canonical once unknown keys are gone.
+pub fn normalize_workflow(registry: &NodeRegistry, workflow: &mut Workflow) -> NormalizationReport {
+    let mut report = NormalizationReport::default();
+
+    let name = workflow.name.trim();
+    if name != workflow.name {
+        workflow.name = name.to_string();
+        report.record(None, "name", "trimmed surrounding whitespace");
+    }
+    if let Some(description) = &workflow.description {
+        let trimmed = description.trim();
+        if trimmed.is_empty() {
+            workflow.description = None;
+            report.record(None, "description", "removed empty description");
+        } else if trimmed != description {
+            workflow.description = Some(trimmed.to_string());
+            report.record(None, "description", "trimmed surrounding whitespace");
+        }
+    }
+
+    for node in &mut workflow.nodes {
+        let node_type = node.node_type.trim();
+        if node_type != node.node_type {
+            node.node_type = node_type.to_string();
+            report.record(Some(&node.id), "node_type", "trimmed surrounding whitespace");
+        }
+
+        let (x, y) = (clamp_coordinate(node.position.x), clamp_coordinate(node.position.y));
+        if x.to_bits() != node.position.x.to_bits() || y.to_bits() != node.position.y.to_bits() {
+            report.record(
+                Some(&node.id),
+                "position",
+                format!("clamped ({}, {}) to ({}, {})", node.position.x, node.position.y, x, y),
+            );
+            node.position.x = x;
+            node.position.y = y;
+        }
+
+        let known = registry
+            .get(&node.node_type)
+            .and_then(|executor| executor.config_keys());
+        if let (Some(known), Value::Object(data)) = (known, &mut node.data) {
+            let unknown: Vec<String> = data
+                .keys()
+                .filter(|key| {
+                    !known.contains(&key.as_str()) && !COMMON_KEYS.contains(&key.as_str())
+                })
+                .cloned()
+                .collect();
+            for key in unknown {
+                data.remove(&key);
+                report.record(Some(&node.id), &format!("data.{}", key), "removed unknown config key");
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{node, temp_db, workflow};
+    use serde_json::json;
+
+    #[test]
+    fn extra_keys_and_a_padded_name_are_normalized_on_save() {
+        let registry = NodeRegistry::with_builtin();
+        let mut saved = workflow(
+            "wf-1",
+            vec![node(
+                "wait",
+                "delay",
+                json!({ "duration_ms": 10, "label": "Wait", "leftover": true }),
+            )],
+            vec![],
+        );
+        saved.name = "  Nightly sync \n".to_string();
+
+        let report = normalize_workflow(&registry, &mut saved);
+        let fields: Vec<&str> = report.changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(fields, ["name", "data.leftover"]);
+
+        let db = temp_db();
+        db.create_workflow(&saved).unwrap();
+        let stored = db.get_workflow("wf-1").unwrap();
+        assert_eq!(stored.name, "Nightly sync");
+        assert_eq!(stored.nodes[0].data, json!({ "duration_ms": 10, "label": "Wait" }));
+
+        // Already normalized, so a second pass changes nothing.
+        let mut again = stored;
+        assert!(normalize_workflow(&registry, &mut again).changes.is_empty());
+    }
+}
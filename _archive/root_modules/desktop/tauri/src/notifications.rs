@@ -0,0 +1,219 @@
+/*!
+ * Notifications - messages sent when a run of a workflow finishes
+ *
+ * A workflow has any number of rules, each naming the outcomes it fires on, a
+ * channel, and a title and message. Both are templates rendered against the
+ * finished run: `workflow.id` and `.name`; `execution.id`, `.status`,
+ * `.error`, `.started_at`, `.finished_at` and `.duration_ms`; and `output`,
+ * the run's final output. A template that fails to render is sent as written,
+ * so a broken reference never silences a failure alert.
+ *
+ * Desktop notifications are shown by the app, from a `Notification` event,
+ * and only while the `notifications` preference is on. Webhook and email
+ * rules are delivered from here on a task of their own, after the run is
+ * recorded; a delivery failure is logged and never changes the run's outcome.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::template;
+use crate::ExecutionStatus;
+
+const DEFAULT_TITLE: &str = "{{ workflow.name }}: {{ execution.status }}";
+const DEFAULT_MESSAGE: &str = "Execution {{ execution.id }} {{ execution.status }} after {{ execution.duration_ms }} ms";
+
+#[derive(Debug, Error)]
+pub enum NotificationError {
+    #[error("invalid notification rule {index}: {reason}")]
+    InvalidRule { index: usize, reason: String },
+}
+
+pub type Result<T> = std::result::Result<T, NotificationError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    Success,
+    // Failed runs; stopped runs only notify `always` rules
+    Failure,
+    Always,
+}
+
+impl NotifyOn {
+    pub fn matches(self, status: ExecutionStatus) -> bool {
+        match self {
+            NotifyOn::Success => status == ExecutionStatus::Completed,
+            NotifyOn::Failure => status == ExecutionStatus::Failed,
+            NotifyOn::Always => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Desktop,
+    // POSTs `{ "title", "message", "context" }`, the context being what the
+    // templates were rendered against.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    // POSTs `{ "to", "subject", "body" }` to an HTTP mail-sending endpoint.
+    Email {
+        endpoint: String,
+        to: Vec<String>,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_title() -> String {
+    DEFAULT_TITLE.to_string()
+}
+
+fn default_message() -> String {
+    DEFAULT_MESSAGE.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRule {
+    pub on: NotifyOn,
+    pub channel: NotificationChannel,
+    #[serde(default = "default_title")]
+    pub title: String,
+    #[serde(default = "default_message")]
+    pub message: String,
+}
+
+// Rejects rules that could never be delivered, before they are stored.
+pub fn validate(rules: &[NotificationRule]) -> Result<()> {
+    for (index, rule) in rules.iter().enumerate() {
+        let invalid = |reason: String| NotificationError::InvalidRule { index, reason };
+        for text in [&rule.title, &rule.message] {
+            template::find_references(text).map_err(|e| invalid(e.to_string()))?;
+        }
+        match &rule.channel {
+            NotificationChannel::Desktop => {}
+            NotificationChannel::Webhook { url, .. } => {
+                reqwest::Url::parse(url).map_err(|e| invalid(format!("url {}: {}", url, e)))?;
+            }
+            NotificationChannel::Email { endpoint, to, .. } => {
+                reqwest::Url::parse(endpoint)
+                    .map_err(|e| invalid(format!("endpoint {}: {}", endpoint, e)))?;
+                if to.is_empty() {
+                    return Err(invalid("email needs at least one recipient".to_string()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// A notification rendered for one finished run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub channel: NotificationChannel,
+    pub title: String,
+    pub message: String,
+}
+
+// The rules matching `status`, rendered against `context`.
+pub fn render(rules: &[NotificationRule], status: ExecutionStatus, context: &Value) -> Vec<Notification> {
+    rules
+        .iter()
+        .filter(|rule| rule.on.matches(status))
+        .map(|rule| Notification {
+            channel: rule.channel.clone(),
+            title: render_text(&rule.title, context),
+            message: render_text(&rule.message, context),
+        })
+        .collect()
+}
+
+fn render_text(text: &str, context: &Value) -> String {
+    match template::render_str(text, context) {
+        Ok(Value::String(rendered)) => rendered,
+        Ok(Value::Null) => String::new(),
+        Ok(value) => value.to_string(),
+        Err(e) => {
+            tracing::warn!("notification template '{}' not rendered: {}", text, e);
+            text.to_string()
+        }
+    }
+}
+
+// Sends webhook and email notifications one after another; desktop ones are
+// left to the caller. Failures are only logged.
+pub async fn deliver(notifications: Vec<Notification>, context: Value) {
+    let client = reqwest::Client::new();
+    for notification in notifications {
+        let result = match &notification.channel {
+            NotificationChannel::Desktop => continue,
+            NotificationChannel::Webhook {
+                url,
+                headers,
+                timeout_ms,
+            } => {
+                let body = serde_json::json!({
+                    "title": notification.title,
+                    "message": notification.message,
+                    "context": context,
+                });
+                post(&client, url, headers, *timeout_ms, &body).await
+            }
+            NotificationChannel::Email {
+                endpoint,
+                to,
+                headers,
+                timeout_ms,
+            } => {
+                let body = serde_json::json!({
+                    "to": to,
+                    "subject": notification.title,
+                    "body": notification.message,
+                });
+                post(&client, endpoint, headers, *timeout_ms, &body).await
+            }
+        };
+        if let Err(e) = result {
+            tracing::warn!(
+                "failed to deliver notification '{}' for workflow {}: {}",
+                notification.title,
+                context["workflow"]["id"],
+                e
+            );
+        }
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    timeout_ms: u64,
+    body: &Value,
+) -> anyhow::Result<()> {
+    let mut request = client
+        .post(url)
+        .timeout(Duration::from_millis(timeout_ms))
+        .json(body);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+/*!
+ * Plans - workflows frozen into immutable, hash-stamped execution plans
+ *
+ * Compiling validates a workflow and records its nodes, in the order they
+ * run, and its edges. Running the plan runs exactly those, however the
+ * workflow has been edited since, so a reviewed plan is what runs. Plans are
+ * numbered per workflow, and compiling content that already has a plan
+ * returns that plan. Workflows called by call-workflow nodes are not frozen;
+ * they load as they are when the run reaches them.
+ */
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::database::{DatabaseError, ReadPool};
+use crate::nodes::NodeRegistry;
+use crate::validation::{self, Severity};
+use crate::workflow_engine::{self, EngineError};
+use crate::{Workflow, WorkflowEdge, WorkflowNode};
+
+#[derive(Debug, Error)]
+pub enum PlanError {
+    #[error("workflow {workflow_id} does not compile: {errors}")]
+    Invalid { workflow_id: String, errors: String },
+    #[error("plan {0} does not match its hash; it was altered after it was compiled")]
+    HashMismatch(String),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+    #[error("failed to hash plan: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, PlanError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionPlan {
+    pub id: String,
+    pub workflow_id: String,
+    // 1 for a workflow's first plan; assigned when the plan is stored
+    pub version: i64,
+    pub name: String,
+    // Node ids in the order they run
+    pub order: Vec<String>,
+    // In `order`
+    pub nodes: Vec<WorkflowNode>,
+    pub edges: Vec<WorkflowEdge>,
+    // SHA-256 of the order, nodes and edges, hex-encoded
+    pub hash: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Compact JSON with object keys sorted, so equal content hashes equally.
+fn content_hash(order: &[String], nodes: &[WorkflowNode], edges: &[WorkflowEdge]) -> Result<String> {
+    let content = serde_json::json!({
+        "order": order,
+        "nodes": nodes,
+        "edges": edges,
+    });
+    Ok(format!("{:x}", Sha256::digest(serde_json::to_vec(&content)?)))
+}
+
+// Fails on any validation error; warnings do not stop a compile. The plan is
+// not stored yet and has no version.
+pub fn compile(registry: &NodeRegistry, workflow: &Workflow) -> Result<ExecutionPlan> {
+    let (report, _) = validation::validate_workflow(registry, workflow);
+    let errors: Vec<String> = report
+        .issues
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.message)
+        .collect();
+    if !errors.is_empty() {
+        return Err(PlanError::Invalid {
+            workflow_id: workflow.id.clone(),
+            errors: errors.join("; "),
+        });
+    }
+
+    let order = workflow_engine::topological_order(workflow)?;
+    let position: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(index, id)| (id.as_str(), index))
+        .collect();
+    let mut nodes = workflow.nodes.clone();
+    nodes.sort_by_key(|node| position[node.id.as_str()]);
+
+    let hash = content_hash(&order, &nodes, &workflow.edges)?;
+    Ok(ExecutionPlan {
+        id: Uuid::new_v4().to_string(),
+        workflow_id: workflow.id.clone(),
+        version: 0,
+        name: workflow.name.clone(),
+        order,
+        nodes,
+        edges: workflow.edges.clone(),
+        hash,
+        created_at: chrono::Utc::now(),
+    })
+}
+
+// The plan and the workflow to run for it: the source workflow with the
+// plan's name, nodes and edges. Fails if the source workflow is gone or the
+// stored plan no longer matches its hash.
+pub fn load(readers: &ReadPool, plan_id: &str) -> Result<(ExecutionPlan, Workflow)> {
+    let plan = readers.get_execution_plan(plan_id)?;
+    if content_hash(&plan.order, &plan.nodes, &plan.edges)? != plan.hash {
+        return Err(PlanError::HashMismatch(plan_id.to_string()));
+    }
+    let source = readers.get_workflow(&plan.workflow_id)?;
+    let workflow = Workflow {
+        name: plan.name.clone(),
+        nodes: plan.nodes.clone(),
+        edges: plan.edges.clone(),
+        ..source
+    };
+    Ok((plan, workflow))
+}
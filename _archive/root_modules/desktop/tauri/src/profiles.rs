@@ -0,0 +1,124 @@
+/*!
+ * Profiles - named, isolated databases in the app data dir
+ *
+ * The default profile keeps the original `workflows.db`; any other profile
+ * uses `workflows-<name>.db`. `--profile <name>` selects one at launch,
+ * otherwise the profile last switched to is reopened.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+const ACTIVE_PROFILE_FILE: &str = "active_profile";
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("invalid profile name {0:?}: use letters, digits, '-' and '_'")]
+    InvalidName(String),
+    #[error("cannot switch profiles while {0} executions are running")]
+    ExecutionsRunning(usize),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ProfileError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveProfile {
+    pub name: String,
+    pub database_path: String,
+}
+
+impl ActiveProfile {
+    pub fn new(data_dir: &Path, name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            database_path: database_path(data_dir, name).to_string_lossy().to_string(),
+        }
+    }
+}
+
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ProfileError::InvalidName(name.to_string()))
+    }
+}
+
+pub fn database_path(data_dir: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT_PROFILE {
+        data_dir.join("workflows.db")
+    } else {
+        data_dir.join(format!("workflows-{}.db", name))
+    }
+}
+
+// Value of `--profile <name>` or `--profile=<name>`, if given.
+pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+// The profile to open at launch: the command line wins, then the profile
+// last switched to, then the default. Invalid names fall back with a warning.
+pub fn startup_profile(data_dir: &Path) -> String {
+    let requested = from_args(std::env::args().skip(1)).or_else(|| {
+        std::fs::read_to_string(data_dir.join(ACTIVE_PROFILE_FILE))
+            .ok()
+            .map(|name| name.trim().to_string())
+    });
+    match requested {
+        Some(name) if validate_name(&name).is_ok() => name,
+        Some(name) => {
+            tracing::warn!("ignoring invalid profile name {:?}", name);
+            DEFAULT_PROFILE.to_string()
+        }
+        None => DEFAULT_PROFILE.to_string(),
+    }
+}
+
+pub fn save_active(data_dir: &Path, name: &str) -> Result<()> {
+    std::fs::write(data_dir.join(ACTIVE_PROFILE_FILE), name)?;
+    Ok(())
+}
+
+// Profiles with a database in `data_dir`, default first.
+pub fn list_profiles(data_dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(data_dir)? {
+        let file_name = entry?.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        if file_name == "workflows.db" {
+            names.push(DEFAULT_PROFILE.to_string());
+        } else if let Some(name) = file_name
+            .strip_prefix("workflows-")
+            .and_then(|rest| rest.strip_suffix(".db"))
+        {
+            if name != DEFAULT_PROFILE && validate_name(name).is_ok() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort_by(|a, b| (a != DEFAULT_PROFILE, a).cmp(&(b != DEFAULT_PROFILE, b)));
+    Ok(names)
+}
@@ -0,0 +1,140 @@
+/*!
+ * Rate limit - request budgets shared by every running execution
+ *
+ * A bucket allows `requests` node runs per `interval_ms`, measured over a
+ * sliding window. Nodes draw from the bucket named by their
+ * `rate_limit_bucket` config key, else from the bucket named after their node
+ * type; buckets without a configured limit never hold a node back.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::num::{NonZeroU32, NonZeroU64};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::nodes::{ExecutionControl, NodeError, Result};
+use crate::WorkflowNode;
+
+// Node config key naming a shared bucket, e.g. one per external API.
+pub const BUCKET_KEY: &str = "rate_limit_bucket";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub requests: NonZeroU32,
+    pub interval_ms: NonZeroU64,
+}
+
+impl RateLimit {
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.get())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    // Start times of the latest grants, oldest first; may lie in the future
+    // for callers still waiting on their slot.
+    grants: VecDeque<Instant>,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            grants: VecDeque::new(),
+        }
+    }
+
+    // Claims the earliest slot at or after `now` that keeps the window within
+    // the limit. Slots are handed out in call order.
+    fn reserve(&mut self, now: Instant) -> Instant {
+        let requests = self.limit.requests.get() as usize;
+        let slot = if self.grants.len() < requests {
+            now
+        } else {
+            let oldest = self.grants[self.grants.len() - requests];
+            (oldest + self.limit.interval()).max(now)
+        };
+        self.grants.push_back(slot);
+        while self.grants.len() > requests {
+            self.grants.pop_front();
+        }
+        slot
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    // Replaces the configured limits. Buckets that keep a limit keep their
+    // recent grants, so reconfiguring does not reset the window.
+    pub fn set_limits(&self, limits: &BTreeMap<String, RateLimit>) {
+        let mut buckets = self.buckets.lock();
+        buckets.retain(|name, _| limits.contains_key(name));
+        for (name, limit) in limits {
+            buckets
+                .entry(name.clone())
+                .and_modify(|bucket| bucket.limit = *limit)
+                .or_insert_with(|| Bucket::new(*limit));
+        }
+    }
+
+    pub fn limits(&self) -> BTreeMap<String, RateLimit> {
+        self.buckets
+            .lock()
+            .iter()
+            .map(|(name, bucket)| (name.clone(), bucket.limit))
+            .collect()
+    }
+
+    // Waits for a slot in `bucket` and returns how long that took. Stopping
+    // the execution ends the wait; the claimed slot is not given back.
+    pub async fn acquire(&self, bucket: &str, control: &ExecutionControl) -> Result<Duration> {
+        let now = Instant::now();
+        let slot = match self.buckets.lock().get_mut(bucket) {
+            Some(bucket) => bucket.reserve(now),
+            None => return Ok(Duration::ZERO),
+        };
+        if slot <= now {
+            return Ok(Duration::ZERO);
+        }
+        tokio::select! {
+            _ = control.cancelled() => Err(NodeError::Cancelled),
+            _ = tokio::time::sleep_until(slot) => Ok(slot - now),
+        }
+    }
+}
+
+pub fn bucket_for(node: &WorkflowNode) -> &str {
+    node.data
+        .get(BUCKET_KEY)
+        .and_then(|bucket| bucket.as_str())
+        .filter(|bucket| !bucket.is_empty())
+        .unwrap_or(&node.node_type)
+}
+
+// Total time one execution spent waiting on rate limits, shared by clones of
+// its ExecutionContext.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitWait {
+    waited_ms: Arc<AtomicU64>,
+}
+
+impl RateLimitWait {
+    pub fn add(&self, waited: Duration) {
+        self.waited_ms
+            .fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn total_ms(&self) -> u64 {
+        self.waited_ms.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,144 @@
+/*!
+ * Recovery - closes out executions a previous launch left running
+ *
+ * Runs during setup, before the UI loads. An execution still marked running
+ * was cut short by the app exiting unexpectedly. If the user had asked to
+ * stop it, it is recorded as cancelled. Otherwise it is marked interrupted,
+ * and it is run again with its original input when its workflow has
+ * `resume_on_startup` set.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::database::{self, Database};
+use crate::workflow_engine::{self, WorkflowEngine};
+
+pub const INTERRUPTED_ERROR: &str = "interrupted: the app exited during the run";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterruptedExecution {
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub profile: String,
+    // Trigger payload the run was started with
+    pub input: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumedExecution {
+    pub interrupted_id: String,
+    pub execution_id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecoverySummary {
+    pub interrupted: usize,
+    // Runs the user had asked to stop before the app exited
+    pub cancelled: usize,
+    pub resumed: Vec<ResumedExecution>,
+}
+
+// A run that cannot be resumed (deleted workflow, engine refusing it) is left
+// interrupted and logged; only database errors fail recovery.
+pub fn recover_executions(
+    db: &Mutex<Database>,
+    engine: &WorkflowEngine,
+) -> database::Result<RecoverySummary> {
+    let (interrupted, cancelled) = db.lock().reconcile_running_executions(INTERRUPTED_ERROR)?;
+    let mut summary = RecoverySummary {
+        interrupted: interrupted.len(),
+        cancelled,
+        resumed: Vec::new(),
+    };
+
+    for execution in &interrupted {
+        let workflow = match db.lock().get_workflow(&execution.workflow_id) {
+            Ok(workflow) => workflow,
+            Err(database::DatabaseError::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        if !workflow.resume_on_startup {
+            continue;
+        }
+
+        let profile = workflow_engine::find_profile(&execution.profile)
+            .or_else(|_| workflow_engine::find_profile(workflow_engine::DEFAULT_PROFILE));
+        match profile.and_then(|profile| engine.restart_interrupted(&workflow, &profile, execution)) {
+            Ok(execution_id) => summary.resumed.push(ResumedExecution {
+                interrupted_id: execution.execution_id.clone(),
+                execution_id,
+            }),
+            Err(e) => tracing::warn!(
+                "could not resume interrupted execution {}: {}",
+                execution.execution_id,
+                e
+            ),
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{engine, execution, node, wait_for_runs, workflow};
+    use crate::ExecutionStatus;
+    use serde_json::json;
+
+    fn running(db: &Database, id: &str, workflow_id: &str) {
+        let mut run = execution(id, workflow_id, chrono::Utc::now());
+        run.status = ExecutionStatus::Running;
+        run.finished_at = None;
+        db.insert_execution(&run).unwrap();
+        db.set_execution_input(id, &json!({ "order": id })).unwrap();
+    }
+
+    #[tokio::test]
+    async fn running_rows_are_interrupted_and_resumed_unless_stopped() {
+        let (db, engine) = engine();
+        {
+            let db = db.lock();
+            let mut resumable =
+                workflow("resumable", vec![node("t", "trigger", json!({}))], vec![]);
+            resumable.resume_on_startup = true;
+            db.create_workflow(&resumable).unwrap();
+            db.create_workflow(&workflow("plain", vec![node("t", "trigger", json!({}))], vec![]))
+                .unwrap();
+
+            running(&db, "crashed", "resumable");
+            running(&db, "stopped", "resumable");
+            db.mark_stop_requested("stopped").unwrap();
+            running(&db, "left", "plain");
+        }
+
+        let summary = recover_executions(&db, &engine).unwrap();
+        assert_eq!((summary.interrupted, summary.cancelled), (2, 1));
+        assert_eq!(summary.resumed.len(), 1);
+        assert_eq!(summary.resumed[0].interrupted_id, "crashed");
+        wait_for_runs(&engine, "resumable").await;
+
+        let stored = db.lock();
+        for (id, status) in [
+            ("crashed", ExecutionStatus::Interrupted),
+            ("left", ExecutionStatus::Interrupted),
+            ("stopped", ExecutionStatus::Cancelled),
+            (summary.resumed[0].execution_id.as_str(), ExecutionStatus::Completed),
+        ] {
+            let execution = stored.get_execution(id).unwrap();
+            assert_eq!(execution.status, status, "{}", id);
+            assert!(execution.finished_at.is_some(), "{}", id);
+        }
+        assert_eq!(
+            stored.get_execution("left").unwrap().error.as_deref(),
+            Some(INTERRUPTED_ERROR)
+        );
+        drop(stored);
+
+        // Nothing is left running, so a second launch has nothing to do.
+        let again = recover_executions(&db, &engine).unwrap();
+        assert_eq!((again.interrupted, again.cancelled), (0, 0));
+        assert!(again.resumed.is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+/*!
+ * Resources - process resource sampling and derived estimates
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::{Disks, Pid, System};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const DEFAULT_WATTS_PER_CORE: f64 = 15.0;
+// Rough global average grid intensity.
+pub const DEFAULT_GRAMS_CO2_PER_KWH: f64 = 475.0;
+pub const DEFAULT_MIN_FREE_MEMORY_MB: u64 = 256;
+
+pub const BYTES_PER_MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfo {
+    pub hostname: Option<String>,
+    pub os: Option<String>,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub cpu: Option<String>,
+    pub cpu_count: usize,
+    pub total_memory: u64,
+    pub used_memory: u64,
+    pub available_memory: u64,
+    pub total_swap: u64,
+    pub used_swap: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskSpace {
+    pub mount_point: String,
+    pub total_space: u64,
+    pub available_space: u64,
+}
+
+// Shared view of host resources. The engine asks it before each node whether
+// free memory is below the configured floor; zero disables the check.
+pub struct ResourceMonitor {
+    system: Mutex<System>,
+    min_free_memory_mb: AtomicU64,
+}
+
+impl Default for ResourceMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_FREE_MEMORY_MB)
+    }
+}
+
+impl ResourceMonitor {
+    pub fn new(min_free_memory_mb: u64) -> Self {
+        Self {
+            system: Mutex::new(System::new()),
+            min_free_memory_mb: AtomicU64::new(min_free_memory_mb),
+        }
+    }
+
+    pub fn system_info(&self) -> SystemInfo {
+        let mut sys = self.system.lock();
+        sys.refresh_memory();
+        sys.refresh_cpu();
+
+        SystemInfo {
+            hostname: System::host_name(),
+            os: System::name(),
+            os_version: System::os_version(),
+            kernel_version: System::kernel_version(),
+            cpu: sys.cpus().first().map(|cpu| cpu.brand().to_string()),
+            cpu_count: sys.cpus().len(),
+            total_memory: sys.total_memory(),
+            used_memory: sys.used_memory(),
+            available_memory: sys.available_memory(),
+            total_swap: sys.total_swap(),
+            used_swap: sys.used_swap(),
+        }
+    }
+
+    pub fn available_memory_mb(&self) -> u64 {
+        let mut sys = self.system.lock();
+        sys.refresh_memory();
+        sys.available_memory() / BYTES_PER_MB
+    }
+
+    pub fn min_free_memory_mb(&self) -> u64 {
+        self.min_free_memory_mb.load(Ordering::Relaxed)
+    }
+
+    pub fn set_min_free_memory_mb(&self, min_free_memory_mb: u64) {
+        self.min_free_memory_mb.store(min_free_memory_mb, Ordering::Relaxed);
+    }
+
+    // Space on the disk holding `path`, i.e. the one with the longest mount
+    // point that prefixes it. None if no mounted disk matches.
+    pub fn disk_space(&self, path: &Path) -> Option<DiskSpace> {
+        let disks = Disks::new_with_refreshed_list();
+        disks
+            .list()
+            .iter()
+            .filter(|disk| path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .map(|disk| DiskSpace {
+                mount_point: disk.mount_point().to_string_lossy().to_string(),
+                total_space: disk.total_space(),
+                available_space: disk.available_space(),
+            })
+    }
+
+    // Available memory in MB when it is below the floor, `None` otherwise.
+    pub fn memory_pressure(&self) -> Option<u64> {
+        let floor = self.min_free_memory_mb();
+        if floor == 0 {
+            return None;
+        }
+        Some(self.available_memory_mb()).filter(|available| *available < floor)
+    }
+}
+
+// Integrates this process's CPU usage over the lifetime of a run. Usage is
+// process-wide, so runs that overlap each account for the shared CPU time.
+pub struct CpuTimeSampler {
+    cpu_time_us: Arc<AtomicU64>,
+    stop: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl CpuTimeSampler {
+    pub fn start() -> Self {
+        let cpu_time_us = Arc::new(AtomicU64::new(0));
+        let stop = CancellationToken::new();
+        let task = tokio::spawn(sample_cpu_time(cpu_time_us.clone(), stop.clone()));
+        Self {
+            cpu_time_us,
+            stop,
+            task,
+        }
+    }
+
+    // Stops sampling and returns the CPU time observed, in milliseconds.
+    pub async fn finish(self) -> u64 {
+        self.stop.cancel();
+        let _ = self.task.await;
+        self.cpu_time_us.load(Ordering::Relaxed) / 1000
+    }
+}
+
+async fn sample_cpu_time(cpu_time_us: Arc<AtomicU64>, stop: CancellationToken) {
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        tracing::warn!("cpu sampling unavailable: cannot resolve current pid");
+        return;
+    };
+    let mut sys = System::new();
+    sys.refresh_process(pid);
+
+    let mut last = Instant::now();
+    loop {
+        let stopped = tokio::select! {
+            _ = stop.cancelled() => true,
+            _ = tokio::time::sleep(CPU_SAMPLE_INTERVAL) => false,
+        };
+
+        let elapsed = last.elapsed();
+        last = Instant::now();
+        cpu_time_us.fetch_add(process_cpu_time(&mut sys, pid, elapsed), Ordering::Relaxed);
+        if stopped {
+            break;
+        }
+    }
+}
+
+// CPU time spent by `pid` since the previous refresh; `cpu_usage` is a
+// percentage of one core, so 200% over one second is two core-seconds.
+pub fn process_cpu_time(sys: &mut System, pid: Pid, elapsed: Duration) -> u64 {
+    if !sys.refresh_process(pid) {
+        return 0;
+    }
+    sys.process(pid)
+        .map(|process| (process.cpu_usage() as f64 / 100.0 * elapsed.as_micros() as f64) as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnergyEstimate {
+    pub execution_id: String,
+    pub cpu_time_ms: u64,
+    pub watts_per_core: f64,
+    pub energy_wh: f64,
+    pub co2_grams: f64,
+    // Always true: this is a CPU-time heuristic, not a measurement.
+    pub is_estimate: bool,
+    pub method: String,
+}
+
+pub fn estimate_energy(
+    execution_id: &str,
+    cpu_time_ms: u64,
+    watts_per_core: f64,
+    grams_co2_per_kwh: f64,
+) -> EnergyEstimate {
+    let joules = cpu_time_ms as f64 / 1000.0 * watts_per_core;
+    let energy_wh = joules / 3600.0;
+
+    EnergyEstimate {
+        execution_id: execution_id.to_string(),
+        cpu_time_ms,
+        watts_per_core,
+        energy_wh,
+        co2_grams: energy_wh / 1000.0 * grams_co2_per_kwh,
+        is_estimate: true,
+        method: "sampled process cpu time x watts per core".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{execution, temp_db};
+    use crate::ExecutionStatus;
+
+    #[test]
+    fn energy_estimate_scales_with_recorded_cpu_time() {
+        let mut db = temp_db();
+        for (id, cpu_time_ms) in [("short", 1_500), ("long", 4_500)] {
+            db.insert_execution(&execution(id, "wf", chrono::Utc::now())).unwrap();
+            db.finish_execution(id, ExecutionStatus::Completed, None, Some(cpu_time_ms), None)
+                .unwrap();
+        }
+        let estimate = |id: &str| {
+            let cpu_time_ms = db.get_execution(id).unwrap().cpu_time_ms.unwrap();
+            estimate_energy(id, cpu_time_ms, 15.0, 400.0)
+        };
+
+        let short = estimate("short");
+        let long = estimate("long");
+        assert!(short.is_estimate && long.is_estimate);
+        assert_eq!(short.cpu_time_ms, 1_500);
+        // 1.5 s at 15 W is 22.5 J
+        assert!((short.energy_wh - 22.5 / 3600.0).abs() < 1e-12);
+        assert!((long.energy_wh - 3.0 * short.energy_wh).abs() < 1e-12);
+        assert!((long.co2_grams - 3.0 * short.co2_grams).abs() < 1e-12);
+        assert_eq!(estimate_energy("idle", 0, 15.0, 400.0).energy_wh, 0.0);
+    }
+}
@@ -0,0 +1,65 @@
+/*!
+ * Retention - pruning of old execution history and expired idempotency keys
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::database::Database;
+use crate::AppState;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const IDEMPOTENCY_SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Limits beyond which unpinned, finished executions are deleted. Either limit
+// may be unset; with both unset nothing is ever pruned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    #[serde(default)]
+    pub max_per_workflow: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days.is_some() || self.max_per_workflow.is_some()
+    }
+}
+
+// Applies the retention policy from the current preferences once an hour.
+pub fn spawn_cleanup_task(state: Arc<Mutex<AppState>>, db: Arc<Mutex<Database>>) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let policy = state.lock().user_preferences.execution_retention;
+            if !policy.is_enabled() {
+                continue;
+            }
+            match db.lock().cleanup_executions(&policy) {
+                Ok(removed) => tracing::info!("execution cleanup removed {:?}", removed),
+                Err(e) => tracing::warn!("execution cleanup failed: {}", e),
+            }
+        }
+    });
+}
+
+// Expired keys no longer dedupe anything, so this only keeps the table small.
+pub fn spawn_idempotency_sweep(db: Arc<Mutex<Database>>) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(IDEMPOTENCY_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            match db.lock().sweep_idempotency_keys() {
+                Ok(0) => {}
+                Ok(removed) => tracing::debug!("swept {} expired idempotency keys", removed),
+                Err(e) => tracing::warn!("idempotency key sweep failed: {}", e),
+            }
+        }
+    });
+}
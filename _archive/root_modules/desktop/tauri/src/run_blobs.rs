@@ -0,0 +1,150 @@
+/*!
+ * Run Blobs - binary payloads passed between nodes by reference
+ *
+ * A node that produces bytes stores them with `ExecutionContext::put_blob`
+ * and outputs the returned `Blob`, `{ "$blob": id, size, content_type,
+ * file_name }`, in their place; a later node reads them back with
+ * `read_blob`. So a download reaches a file write without being base64'd
+ * through every node's JSON.
+ *
+ * Blobs are files in a directory per run under the system temp directory;
+ * runs started by call-workflow nodes share their caller's. The directory is
+ * deleted when the run ends, however it ends. References in a run's output
+ * dangle after that, so outputs holding one are never cached.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::nodes::{NodeError, Result};
+
+pub const BLOB_KEY: &str = "$blob";
+
+const BLOB_DIR: &str = "workflow-blobs";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Blob {
+    #[serde(rename = "$blob")]
+    pub id: String,
+    pub size: u64,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub file_name: Option<String>,
+}
+
+impl Blob {
+    // Some when `value` is a blob reference.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        value.get(BLOB_KEY)?;
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).expect("blob references serialize")
+    }
+}
+
+// Whether `value` holds a blob reference at any depth.
+pub fn contains_blob(value: &Value) -> bool {
+    match value {
+        Value::Object(fields) => {
+            fields.contains_key(BLOB_KEY) || fields.values().any(contains_blob)
+        }
+        Value::Array(items) => items.iter().any(contains_blob),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl Default for BlobStore {
+    fn default() -> Self {
+        Self {
+            root: std::env::temp_dir().join(BLOB_DIR),
+        }
+    }
+}
+
+impl BlobStore {
+    // A nested run's id extends its caller's as `<caller>/<node>`, so it maps
+    // to the outermost run's directory.
+    fn run_dir(&self, execution_id: &str) -> PathBuf {
+        let run = execution_id.split('/').next().unwrap_or(execution_id);
+        self.root.join(run)
+    }
+
+    pub async fn put(
+        &self,
+        execution_id: &str,
+        bytes: &[u8],
+        content_type: Option<String>,
+        file_name: Option<String>,
+    ) -> Result<Blob> {
+        let dir = self.run_dir(execution_id);
+        let id = Uuid::new_v4().to_string();
+        let written = match tokio::fs::create_dir_all(&dir).await {
+            Ok(()) => tokio::fs::write(dir.join(&id), bytes).await,
+            Err(e) => Err(e),
+        };
+        written.map_err(|e| NodeError::Failed(format!("failed to store blob: {}", e)))?;
+        Ok(Blob {
+            id,
+            size: bytes.len() as u64,
+            content_type,
+            file_name,
+        })
+    }
+
+    pub async fn read(&self, execution_id: &str, blob: &Blob) -> Result<Vec<u8>> {
+        // Ids are uuids; anything else could name a path outside the run's directory.
+        let id = Uuid::parse_str(&blob.id)
+            .map_err(|_| NodeError::InvalidConfig(format!("invalid blob id {:?}", blob.id)))?;
+        match tokio::fs::read(self.run_dir(execution_id).join(id.to_string())).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(NodeError::Failed(format!(
+                "blob {} is not available to this run",
+                blob.id
+            ))),
+            Err(e) => Err(NodeError::Failed(format!("failed to read blob {}: {}", blob.id, e))),
+        }
+    }
+
+    // Deletes the run's blobs when dropped, so they go whether the run
+    // completes, fails, is stopped or panics.
+    pub fn cleanup_guard(&self, execution_id: &str) -> BlobCleanup {
+        BlobCleanup {
+            dir: self.run_dir(execution_id),
+        }
+    }
+
+    // Deletes every run's blobs. For startup, when any left behind belong to
+    // runs of a previous session.
+    pub fn clear(&self) {
+        remove_dir(&self.root);
+    }
+}
+
+pub struct BlobCleanup {
+    dir: PathBuf,
+}
+
+impl Drop for BlobCleanup {
+    fn drop(&mut self) {
+        remove_dir(&self.dir);
+    }
+}
+
+fn remove_dir(dir: &Path) {
+    match std::fs::remove_dir_all(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("failed to delete blobs in {}: {}", dir.display(), e),
+    }
+}
@@ -0,0 +1,190 @@
+/*!
+ * Sandbox - wall-clock, CPU and memory caps on plugin node calls
+ *
+ * Plugin nodes run code nobody reviewed, so every call is watched while it
+ * runs. The plugin runtime ticks every `CHECK_INTERVAL`, and on each tick the
+ * call is checked on its own thread: past its timeout or CPU cap, or once its
+ * run is stopped, it is interrupted where it stands. Memory is checked as the
+ * module asks to grow it. A call that breaches a cap fails its node with
+ * `ResourceExceeded`; nothing outside that call is affected.
+ *
+ * The timeout and the memory cap are exact. CPU time is sampled with sysinfo,
+ * which only sees the whole process, so a call is charged the process's CPU
+ * time while it runs, at most one core's worth: calls running alongside can
+ * push each other over. Each limit is off when set to 0.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, System};
+use thiserror::Error;
+use wasmtime::ResourceLimiter;
+
+use crate::resources::{self, BYTES_PER_MB};
+
+pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+pub const DEFAULT_MAX_CPU_MS: u64 = 10_000;
+pub const DEFAULT_MAX_MEMORY_MB: u64 = 64;
+
+// How often running calls are checked against their limits.
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(10);
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxLimits {
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_max_cpu_ms")]
+    pub max_cpu_ms: u64,
+    // Linear memory one call's module instance may grow to
+    #[serde(default = "default_max_memory_mb")]
+    pub max_memory_mb: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+fn default_max_cpu_ms() -> u64 {
+    DEFAULT_MAX_CPU_MS
+}
+
+fn default_max_memory_mb() -> u64 {
+    DEFAULT_MAX_MEMORY_MB
+}
+
+impl Default for SandboxLimits {
+    fn default() -> Self {
+        Self {
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            max_cpu_ms: DEFAULT_MAX_CPU_MS,
+            max_memory_mb: DEFAULT_MAX_MEMORY_MB,
+        }
+    }
+}
+
+// The cap a call was stopped for, with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "resource", content = "limit")]
+pub enum Exceeded {
+    #[error("ran longer than {0} ms")]
+    WallTime(u64),
+    #[error("used more than {0} ms of CPU time")]
+    CpuTime(u64),
+    #[error("tried to grow memory past {0} MB")]
+    Memory(u64),
+    #[error("ran more than {0} instructions")]
+    Fuel(u64),
+}
+
+struct CpuSample {
+    sys: System,
+    pid: Pid,
+    last: Instant,
+    cpu_time_us: u64,
+}
+
+// State of one call, kept in its store: the store's resource limiter, and the
+// check run on every tick.
+pub struct CallMonitor {
+    limits: SandboxLimits,
+    started: Instant,
+    stopped: Arc<AtomicBool>,
+    // None when the process cannot be sampled; the CPU cap is then not enforced
+    cpu: Option<CpuSample>,
+    exceeded: Option<Exceeded>,
+}
+
+impl CallMonitor {
+    // Setting `stopped` interrupts the call at the next tick.
+    pub fn new(limits: SandboxLimits, stopped: Arc<AtomicBool>) -> Self {
+        let cpu = (limits.max_cpu_ms > 0)
+            .then(sysinfo::get_current_pid)
+            .and_then(|pid| pid.ok())
+            .map(|pid| {
+                let mut sys = System::new();
+                sys.refresh_process(pid);
+                CpuSample {
+                    sys,
+                    pid,
+                    last: Instant::now(),
+                    cpu_time_us: 0,
+                }
+            });
+        Self {
+            limits,
+            started: Instant::now(),
+            stopped,
+            cpu,
+            exceeded: None,
+        }
+    }
+
+    // The cap that stopped the call, if one did.
+    pub fn take_exceeded(&mut self) -> Option<Exceeded> {
+        self.exceeded.take()
+    }
+
+    fn exceed(&mut self, exceeded: Exceeded) -> wasmtime::Error {
+        self.exceeded = Some(exceeded);
+        wasmtime::Error::msg(exceeded.to_string())
+    }
+
+    // Runs on the calling thread at every tick; an error interrupts the call.
+    pub fn check(&mut self) -> wasmtime::Result<()> {
+        if self.stopped.load(Ordering::SeqCst) {
+            return Err(wasmtime::Error::msg("call stopped"));
+        }
+        let timeout_ms = self.limits.timeout_ms;
+        if timeout_ms > 0 && self.started.elapsed() >= Duration::from_millis(timeout_ms) {
+            return Err(self.exceed(Exceeded::WallTime(timeout_ms)));
+        }
+
+        let max_cpu_ms = self.limits.max_cpu_ms;
+        let Some(cpu) = self.cpu.as_mut() else {
+            return Ok(());
+        };
+        let elapsed = cpu.last.elapsed();
+        if elapsed < CPU_SAMPLE_INTERVAL {
+            return Ok(());
+        }
+        cpu.last = Instant::now();
+        let sampled = resources::process_cpu_time(&mut cpu.sys, cpu.pid, elapsed);
+        cpu.cpu_time_us += sampled.min(elapsed.as_micros() as u64);
+        if cpu.cpu_time_us > max_cpu_ms * 1000 {
+            return Err(self.exceed(Exceeded::CpuTime(max_cpu_ms)));
+        }
+        Ok(())
+    }
+}
+
+impl ResourceLimiter for CallMonitor {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let max_memory_mb = self.limits.max_memory_mb;
+        if max_memory_mb > 0 && desired as u64 > max_memory_mb * BYTES_PER_MB {
+            return Err(self.exceed(Exceeded::Memory(max_memory_mb)));
+        }
+        Ok(maximum.is_none_or(|maximum| desired <= maximum))
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        desired: u32,
+        maximum: Option<u32>,
+    ) -> wasmtime::Result<bool> {
+        Ok(maximum.is_none_or(|maximum| desired <= maximum))
+    }
+
+    // Each call gets a fresh instance of one module.
+    fn instances(&self) -> usize {
+        1
+    }
+}
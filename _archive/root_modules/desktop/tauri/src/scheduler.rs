@@ -0,0 +1,175 @@
+/*!
+ * Scheduler - execution priorities and the worker pool nodes run on
+ *
+ * Every running execution needs a worker for each node it runs. When all
+ * workers are busy, nodes wait, and a freed worker goes to the waiting node of
+ * the highest-priority run, oldest first within a priority. Runs give their
+ * worker back between nodes, so a user-started run overtakes background runs
+ * at their next node boundary instead of waiting for them to finish.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+use crate::nodes::{ExecutionControl, NodeError, Result};
+
+// Nodes that may run at once across every execution.
+pub const DEFAULT_MAX_CONCURRENT_NODES: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    // Batch and bulk runs that may wait
+    Low,
+    // Trigger-started runs: webhooks, file watches, the control API
+    #[default]
+    Normal,
+    // Runs a user started and is watching
+    High,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    capacity: usize,
+    busy: usize,
+    next_ticket: u64,
+    // Highest priority first, then in arrival order
+    waiting: BTreeMap<(Reverse<Priority>, u64), oneshot::Sender<()>>,
+}
+
+impl PoolState {
+    // Hands a free worker to the next waiter, if any. Returns false when
+    // nobody was waiting.
+    fn hand_over(&mut self) -> bool {
+        while let Some((_, waiter)) = self.waiting.pop_first() {
+            if waiter.send(()).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn release(&mut self) {
+        if self.busy > self.capacity || !self.hand_over() {
+            self.busy -= 1;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WorkerPool {
+    state: Mutex<PoolState>,
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(PoolState {
+                capacity: DEFAULT_MAX_CONCURRENT_NODES,
+                busy: 0,
+                next_ticket: 0,
+                waiting: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+// A worker held by one node run; dropping it frees the worker.
+#[derive(Debug)]
+pub struct WorkerSlot {
+    pool: Arc<WorkerPool>,
+}
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        self.pool.state.lock().release();
+    }
+}
+
+impl WorkerPool {
+    // At least one. Lowering it lets busy workers finish; raising it starts
+    // waiting nodes right away.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state.lock();
+        state.capacity = capacity.max(1);
+        while state.busy < state.capacity && state.hand_over() {
+            state.busy += 1;
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.state.lock().capacity
+    }
+
+    // Waits for a worker and returns it with how long that took. Stopping
+    // the execution ends the wait.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        priority: Priority,
+        control: &ExecutionControl,
+    ) -> Result<(WorkerSlot, Duration)> {
+        let started = Instant::now();
+        let mut waiter = {
+            let mut state = self.state.lock();
+            if state.busy < state.capacity && state.waiting.is_empty() {
+                state.busy += 1;
+                return Ok((WorkerSlot { pool: self.clone() }, Duration::ZERO));
+            }
+            let key = (Reverse(priority), state.next_ticket);
+            state.next_ticket += 1;
+            let (grant, granted) = oneshot::channel();
+            state.waiting.insert(key, grant);
+            Waiter {
+                pool: self.clone(),
+                key,
+                granted,
+                served: false,
+            }
+        };
+
+        tokio::select! {
+            granted = &mut waiter.granted => {
+                granted.map_err(|_| NodeError::Cancelled)?;
+                Ok((waiter.serve(), started.elapsed()))
+            }
+            _ = control.cancelled() => Err(NodeError::Cancelled),
+        }
+    }
+}
+
+// A queued request for a worker. Dropped before being granted one, it leaves
+// the queue, giving back a worker handed over in the meantime.
+struct Waiter {
+    pool: Arc<WorkerPool>,
+    key: (Reverse<Priority>, u64),
+    granted: oneshot::Receiver<()>,
+    // Set once the granted worker has moved into a slot
+    served: bool,
+}
+
+impl Waiter {
+    fn serve(&mut self) -> WorkerSlot {
+        self.served = true;
+        WorkerSlot {
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if self.served {
+            return;
+        }
+        let mut state = self.pool.state.lock();
+        if state.waiting.remove(&self.key).is_none() && self.granted.try_recv().is_ok() {
+            state.release();
+        }
+    }
+}
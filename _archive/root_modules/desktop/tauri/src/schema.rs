@@ -0,0 +1,162 @@
+/*!
+ * Schema - node config validation against the JSON Schema each executor publishes
+ *
+ * Only the keywords the built-in schemas use are checked: `type`, `properties`,
+ * `required`, `additionalProperties`, `items`, `enum`, `minimum`, `maximum`,
+ * `minLength` and `minItems`. Annotations such as `title`, `description` and
+ * `default` are there for generated forms and are ignored here.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    // Dotted path into the config (`mappings[0].source`); empty for the config itself.
+    pub path: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(path: &str, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+fn label(path: &str) -> &str {
+    if path.is_empty() {
+        "config"
+    } else {
+        path
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn matches_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+pub fn validate(schema: &Value, value: &Value) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    validate_at(schema, value, "", &mut errors);
+    errors
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<FieldError>) {
+    let expected: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !expected.is_empty() && !expected.iter().any(|t| matches_type(value, t)) {
+        errors.push(FieldError::new(
+            path,
+            format!("{} must be of type {}", label(path), expected.join(" or ")),
+        ));
+        return;
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(FieldError::new(
+                path,
+                format!("{} must be one of {}", label(path), allowed.join(", ")),
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if matches!(map.get(key), None | Some(Value::Null)) {
+                        let path = child_path(path, key);
+                        errors.push(FieldError::new(&path, format!("{} is required", path)));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, item) in map {
+                let item_path = child_path(path, key);
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(item_schema) => validate_at(item_schema, item, &item_path, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => errors.push(FieldError::new(
+                            &item_path,
+                            format!("{} is not a known field", item_path),
+                        )),
+                        Some(item_schema @ Value::Object(_)) => {
+                            validate_at(item_schema, item, &item_path, errors)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push(FieldError::new(
+                        path,
+                        format!("{} must have at least {} item(s)", label(path), min),
+                    ));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, index), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if (s.chars().count() as u64) < min {
+                    errors.push(FieldError::new(
+                        path,
+                        format!("{} must be at least {} character(s)", label(path), min),
+                    ));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < min {
+                    errors.push(FieldError::new(
+                        path,
+                        format!("{} must be at least {}", label(path), min),
+                    ));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > max {
+                    errors.push(FieldError::new(
+                        path,
+                        format!("{} must be at most {}", label(path), max),
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,393 @@
+/*!
+ * Secrets - encrypted values that node templates reference as `{{ secrets.NAME }}`
+ *
+ * A secret is global or scoped to one workflow; a workflow secret shadows a
+ * global one of the same name. Values are encrypted with the profile's
+ * secrets key (see `credentials::SecretKeyStore`) in `workflow_secrets`
+ * (global secrets under an empty workflow id) and are only decrypted by the
+ * engine when a run that references them starts. Everything else sees names
+ * only.
+ */
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+use crate::credentials::SecretKeyStore;
+use crate::database::{Database, DatabaseError};
+use crate::encryption::{self, EncryptionError, KdfParams};
+use crate::template;
+use crate::{Execution, KeyRotation, Workflow};
+
+// Template root secrets are addressed under.
+pub const SCOPE_ROOT: &str = "secrets";
+
+const GLOBAL_SCOPE_ID: &str = "";
+const MAX_NAME_LEN: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("invalid secret name {0:?}: use letters, digits and '_', not starting with a digit")]
+    InvalidName(String),
+    #[error("workflow id must not be empty")]
+    EmptyWorkflowId,
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("cannot decrypt secret {name}: {source}")]
+    Decrypt {
+        name: String,
+        #[source]
+        source: EncryptionError,
+    },
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error("cannot store the secrets key: {0}")]
+    KeyNotStored(String),
+}
+
+pub type Result<T> = std::result::Result<T, SecretError>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SecretScope {
+    Global,
+    Workflow { workflow_id: String },
+}
+
+impl SecretScope {
+    // The `workflow_id` the scope's secrets are stored under.
+    fn storage_id(&self) -> Result<&str> {
+        match self {
+            SecretScope::Global => Ok(GLOBAL_SCOPE_ID),
+            SecretScope::Workflow { workflow_id } if workflow_id.is_empty() => {
+                Err(SecretError::EmptyWorkflowId)
+            }
+            SecretScope::Workflow { workflow_id } => Ok(workflow_id),
+        }
+    }
+}
+
+// 256 random bits
+pub fn generate_key() -> String {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    BASE64.encode(key)
+}
+
+// The profile's secrets key, created on first use. Secrets stored before the
+// profile had one were encrypted with the machine id, which is not secret, so
+// they are moved over to the new key. Without a usable keychain the machine id
+// stays the key, as a key that could not be kept would lose every secret.
+pub fn load_or_create_key(db: &mut Database, store: &SecretKeyStore, machine_id: &str) -> String {
+    match store.load() {
+        Ok(Some(key)) => return key,
+        Ok(None) => {}
+        Err(e) => {
+            tracing::warn!("secrets key unavailable, using the machine id: {}", e);
+            return machine_id.to_string();
+        }
+    }
+
+    let key = generate_key();
+    if let Err(e) = store.save(&key) {
+        tracing::warn!("cannot store a secrets key, using the machine id: {}", e);
+        return machine_id.to_string();
+    }
+    match db.rotate_encryption_key(machine_id, &key, None) {
+        Ok(rotation) if rotation.secrets == 0 => {}
+        Ok(rotation) => tracing::info!("moved {} secrets to the new secrets key", rotation.secrets),
+        Err(e) => {
+            // Dropped again so the next start retries the move.
+            tracing::warn!("secrets stay encrypted with the machine id: {}", e);
+            store.clear();
+            return machine_id.to_string();
+        }
+    }
+    key
+}
+
+// Re-encrypts every secret under a new random key that `store` keeps from
+// then on, and returns it with what was re-encrypted; see
+// `Database::rotate_encryption_key` for `passphrase`. The key is stored
+// first, so a failed rotation puts `current` back and changes nothing.
+pub fn rotate_key(
+    db: &mut Database,
+    store: &SecretKeyStore,
+    current: &str,
+    passphrase: Option<&str>,
+) -> Result<(String, KeyRotation)> {
+    let key = generate_key();
+    store.save(&key).map_err(SecretError::KeyNotStored)?;
+    match db.rotate_encryption_key(current, &key, passphrase) {
+        Ok(rotated) => Ok((key, rotated)),
+        Err(e) => {
+            if let Err(restore) = store.save(current) {
+                tracing::error!("failed to put the previous secrets key back: {}", restore);
+            }
+            Err(e.into())
+        }
+    }
+}
+
+// Names must be usable as a template path segment.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(SecretError::InvalidName(name.to_string()))
+    }
+}
+
+pub fn set_secret(
+    db: &Database,
+    scope: &SecretScope,
+    name: &str,
+    value: &str,
+    key: &str,
+) -> Result<()> {
+    validate_name(name)?;
+    let params = KdfParams::load(db)?;
+    let ciphertext = encryption::encrypt_with(value, key, &params)?;
+    db.set_workflow_secret(scope.storage_id()?, name, &ciphertext)?;
+    Ok(())
+}
+
+// Returns false if no such secret existed.
+pub fn delete_secret(db: &Database, scope: &SecretScope, name: &str) -> Result<bool> {
+    Ok(db.delete_workflow_secret(scope.storage_id()?, name)?)
+}
+
+pub fn secret_names(db: &Database, scope: &SecretScope) -> Result<Vec<String>> {
+    Ok(db.get_workflow_secret_names(scope.storage_id()?)?)
+}
+
+// Names of the secrets the workflow's node templates reference.
+pub fn referenced_names(workflow: &Workflow) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for node in &workflow.nodes {
+        for reference in template::value_references(&node.data) {
+            let mut segments = reference.split('.');
+            if segments.next() == Some(SCOPE_ROOT) {
+                if let Some(name) = segments.next() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+// Decrypts the named secrets visible to the workflow. Names with no stored
+// secret are left out, so the template reports them as unresolved.
+pub fn resolve(
+    db: &Database,
+    workflow_id: &str,
+    names: &BTreeSet<String>,
+    key: &str,
+) -> Result<BTreeMap<String, String>> {
+    if names.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let params = KdfParams::load(db)?;
+
+    let mut secrets = BTreeMap::new();
+    for name in names {
+        let ciphertext = match db.get_workflow_secret(workflow_id, name)? {
+            Some(ciphertext) => Some(ciphertext),
+            None => db.get_workflow_secret(GLOBAL_SCOPE_ID, name)?,
+        };
+        if let Some(ciphertext) = ciphertext {
+            let value = encryption::decrypt_with(&ciphertext, key, &params).map_err(|source| {
+                SecretError::Decrypt {
+                    name: name.clone(),
+                    source,
+                }
+            })?;
+            secrets.insert(name.clone(), value);
+        }
+    }
+    Ok(secrets)
+}
+
+// Every secret value visible to the workflow, for scrubbing exports.
+pub fn visible_values(
+    db: &Database,
+    workflow_id: &str,
+    key: &str,
+) -> Result<BTreeMap<String, String>> {
+    let mut names: BTreeSet<String> = db
+        .get_workflow_secret_names(GLOBAL_SCOPE_ID)?
+        .into_iter()
+        .collect();
+    names.extend(db.get_workflow_secret_names(workflow_id)?);
+    resolve(db, workflow_id, &names, key)
+}
+
+// Decrypted values held by a run. Debug output lists names only, so the
+// values never reach a `{:?}` of the execution context.
+#[derive(Clone, Default)]
+pub struct SecretValues(BTreeMap<String, String>);
+
+impl From<BTreeMap<String, String>> for SecretValues {
+    fn from(values: BTreeMap<String, String>) -> Self {
+        Self(values)
+    }
+}
+
+impl std::fmt::Debug for SecretValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+impl SecretValues {
+    // The `secrets` object templates resolve against.
+    pub fn scope(&self) -> Value {
+        Value::Object(
+            self.0
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                .collect(),
+        )
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        self.0.values().map(String::as_str)
+    }
+}
+
+fn placeholder(name: &str) -> String {
+    format!("{{{{ {}.{} }}}}", SCOPE_ROOT, name)
+}
+
+// Replaces secret values found in `text` with their template reference.
+// Longer values go first so one value containing another is not split.
+fn scrub_text(text: &str, secrets: &BTreeMap<String, String>) -> Option<String> {
+    let mut by_length: Vec<(&String, &String)> =
+        secrets.iter().filter(|(_, value)| !value.is_empty()).collect();
+    by_length.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+
+    let mut scrubbed = text.to_string();
+    for (name, value) in by_length {
+        scrubbed = scrubbed.replace(value.as_str(), &placeholder(name));
+    }
+    (scrubbed != text).then_some(scrubbed)
+}
+
+fn scrub_value(value: &mut Value, secrets: &BTreeMap<String, String>) -> usize {
+    match value {
+        Value::String(text) => match scrub_text(text, secrets) {
+            Some(scrubbed) => {
+                *text = scrubbed;
+                1
+            }
+            None => 0,
+        },
+        Value::Array(items) => items.iter_mut().map(|item| scrub_value(item, secrets)).sum(),
+        Value::Object(map) => map.values_mut().map(|item| scrub_value(item, secrets)).sum(),
+        _ => 0,
+    }
+}
+
+// Replaces secret values pasted inline into node configs, and any echoed in
+// execution errors, with `{{ secrets.NAME }}` before they leave the app.
+// Returns how many strings were changed.
+pub fn scrub_export(
+    workflow: &mut Workflow,
+    executions: &mut [Execution],
+    secrets: &BTreeMap<String, String>,
+) -> usize {
+    let mut scrubbed = 0;
+    for node in &mut workflow.nodes {
+        scrubbed += scrub_value(&mut node.data, secrets);
+    }
+    for error in executions.iter_mut().filter_map(|execution| execution.error.as_mut()) {
+        if let Some(text) = scrub_text(error, secrets) {
+            *error = text;
+            scrubbed += 1;
+        }
+    }
+    scrubbed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::KdfParams;
+    use crate::test_support::temp_db;
+
+    fn cheap_db() -> Database {
+        let db = temp_db();
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+        .save(&db)
+        .unwrap();
+        db
+    }
+
+    fn mock_keychain() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+    }
+
+    #[test]
+    fn rotating_stores_the_key_secrets_now_open_with() {
+        mock_keychain();
+        let mut db = cheap_db();
+        set_secret(&db, &SecretScope::Global, "API_KEY", "s3cret", "machine-id").unwrap();
+        let store = SecretKeyStore::new("default");
+
+        let (key, rotated) = rotate_key(&mut db, &store, "machine-id", None).unwrap();
+        assert_eq!(rotated.secrets, 1);
+        assert_ne!(key, "machine-id");
+        assert_eq!(store.load().unwrap(), Some(key.clone()));
+        assert_eq!(visible_values(&db, "wf-1", &key).unwrap()["API_KEY"], "s3cret");
+        assert!(visible_values(&db, "wf-1", "machine-id").is_err());
+    }
+
+    #[test]
+    fn failed_rotation_keeps_the_current_key() {
+        mock_keychain();
+        let mut db = cheap_db();
+        set_secret(&db, &SecretScope::Global, "API_KEY", "s3cret", "machine-id").unwrap();
+        let store = SecretKeyStore::new("default");
+
+        assert!(rotate_key(&mut db, &store, "not-the-key", None).is_err());
+        assert_eq!(store.load().unwrap().as_deref(), Some("not-the-key"));
+        assert_eq!(visible_values(&db, "wf-1", "machine-id").unwrap()["API_KEY"], "s3cret");
+    }
+
+    #[test]
+    fn first_key_is_random_and_takes_over_machine_id_secrets() {
+        mock_keychain();
+        let mut db = cheap_db();
+        set_secret(&db, &SecretScope::Global, "API_KEY", "s3cret", "machine-id").unwrap();
+        let store = SecretKeyStore::new("default");
+
+        let key = load_or_create_key(&mut db, &store, "machine-id");
+        assert_ne!(key, "machine-id");
+        assert_eq!(store.load().unwrap(), Some(key.clone()));
+        assert_eq!(visible_values(&db, "wf-1", &key).unwrap()["API_KEY"], "s3cret");
+        assert!(visible_values(&db, "wf-1", "machine-id").is_err());
+
+        // Later starts reuse it.
+        assert_eq!(load_or_create_key(&mut db, &store, "machine-id"), key);
+        assert_ne!(generate_key(), key);
+    }
+}
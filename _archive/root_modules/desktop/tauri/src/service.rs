@@ -0,0 +1,125 @@
+/*!
+ * Service - workflow and execution operations shared by the Tauri commands
+ * and the control API
+ *
+ * Reads go through the database's read pool, so they run alongside each
+ * other and alongside writes rather than queueing on the database lock.
+ */
+
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+use crate::scheduler::Priority;
+use crate::workflow_engine::{self, EngineError, ExecutionProfile, SeedContext, WorkflowEngine};
+use crate::{Execution, ExecutionStatus, Workflow};
+
+#[derive(Debug, Error)]
+pub enum ServiceError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+}
+
+pub type Result<T> = std::result::Result<T, ServiceError>;
+
+pub async fn list_workflows(db: &Mutex<Database>) -> Result<Vec<Workflow>> {
+    let readers = db.lock().readers();
+    Ok(readers.get_workflows()?)
+}
+
+pub async fn get_workflow(db: &Mutex<Database>, id: &str) -> Result<Workflow> {
+    let readers = db.lock().readers();
+    Ok(readers.get_workflow(id)?)
+}
+
+// How to start a run: the execution profile (the default one if None),
+// whether to debug it, and its scheduling priority.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunSettings<'a> {
+    pub profile: Option<&'a str>,
+    pub debug: bool,
+    pub priority: Priority,
+}
+
+impl RunSettings<'_> {
+    // Debug runs hold at breakpoints whatever the profile says.
+    fn profile(&self) -> Result<ExecutionProfile> {
+        let mut profile = workflow_engine::find_profile(
+            self.profile.unwrap_or(workflow_engine::DEFAULT_PROFILE),
+        )?;
+        if self.debug {
+            profile.options.breakpoints_enabled = true;
+        }
+        Ok(profile)
+    }
+}
+
+// Starts a run and returns its execution id. A repeat of an earlier start's
+// `idempotency_key` returns that start's execution id without running again.
+pub async fn execute_workflow(
+    db: &Mutex<Database>,
+    engine: &Mutex<WorkflowEngine>,
+    id: &str,
+    settings: RunSettings<'_>,
+    idempotency_key: Option<&str>,
+) -> Result<String> {
+    let profile = settings.profile()?;
+    let readers = db.lock().readers();
+    let workflow = readers.get_workflow(id)?;
+    Ok(engine
+        .lock()
+        .execute_workflow(&workflow, &profile, settings.priority, idempotency_key)?)
+}
+
+// Starts a run of `node_id` and the nodes downstream of it, seeded with the
+// outputs of the nodes feeding them.
+pub async fn execute_from_node(
+    db: &Mutex<Database>,
+    engine: &Mutex<WorkflowEngine>,
+    id: &str,
+    node_id: &str,
+    seed: SeedContext,
+    settings: RunSettings<'_>,
+) -> Result<String> {
+    let profile = settings.profile()?;
+    let readers = db.lock().readers();
+    let workflow = readers.get_workflow(id)?;
+    Ok(engine
+        .lock()
+        .execute_from_node(&workflow, &profile, node_id, seed, settings.priority)?)
+}
+
+// The recorded execution. A run that has started but not yet been recorded
+// is reported as running.
+pub async fn execution_status(
+    db: &Mutex<Database>,
+    engine: &Mutex<WorkflowEngine>,
+    execution_id: &str,
+) -> Result<Execution> {
+    let readers = db.lock().readers();
+    let recorded = readers.get_execution(execution_id);
+    match recorded {
+        Err(DatabaseError::NotFound(_)) => engine
+            .lock()
+            .list_active_executions()
+            .into_iter()
+            .find(|active| active.execution_id == execution_id)
+            .map(|active| Execution {
+                id: active.execution_id,
+                workflow_id: active.workflow_id,
+                status: ExecutionStatus::Running,
+                profile: active.profile,
+                started_at: active.started_at,
+                finished_at: None,
+                error: None,
+                cpu_time_ms: None,
+                rate_limit_wait_ms: None,
+                pinned: false,
+                progress: None,
+            })
+            .ok_or_else(|| EngineError::ExecutionNotFound(execution_id.to_string()).into()),
+        recorded => Ok(recorded?),
+    }
+}
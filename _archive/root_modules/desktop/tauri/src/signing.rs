@@ -0,0 +1,220 @@
+/*!
+ * Signing - Ed25519-signed workflow exports for tamper-evident sharing
+ *
+ * `export_signed` writes the workflow as canonical JSON to `path` and a
+ * detached signature over those exact bytes to `path` + `.sig`.
+ */
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+use crate::secrets;
+use crate::Workflow;
+
+pub const SIGNATURE_ALGORITHM: &str = "Ed25519";
+const SIGNATURE_EXTENSION: &str = "sig";
+
+#[derive(Debug, Error)]
+pub enum SigningError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid signed workflow: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("unsupported signature algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+    // Kept as a bare code so the frontend can match on it.
+    #[error("signature_invalid")]
+    SignatureInvalid,
+}
+
+pub type Result<T> = std::result::Result<T, SigningError>;
+
+// Both keys are base64-encoded 32-byte Ed25519 keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKeyPair {
+    pub signing_key: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetachedSignature {
+    pub algorithm: String,
+    pub public_key: String,
+    pub signature: String,
+    pub signed_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedExportSummary {
+    pub path: String,
+    pub signature_path: String,
+    pub workflow_id: String,
+    pub public_key: String,
+    // Strings where a secret value was replaced by its `{{ secrets.NAME }}` reference
+    pub secrets_scrubbed: usize,
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; 32]> {
+    BASE64
+        .decode(encoded.trim())
+        .map_err(|e| SigningError::InvalidKey(e.to_string()))?
+        .try_into()
+        .map_err(|_| SigningError::InvalidKey("expected 32 bytes".to_string()))
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
+
+// Compact JSON with object keys sorted. Verification checks the signature
+// over the file's bytes as written and never re-serializes.
+fn canonical_json(workflow: &Workflow) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&serde_json::to_value(workflow)?)?)
+}
+
+pub fn generate_keypair() -> SigningKeyPair {
+    let signing_key = SigningKey::generate(&mut OsRng);
+    SigningKeyPair {
+        signing_key: BASE64.encode(signing_key.to_bytes()),
+        public_key: BASE64.encode(signing_key.verifying_key().to_bytes()),
+    }
+}
+
+pub fn export_signed(
+    db: &Database,
+    workflow_id: &str,
+    signing_key: &str,
+    path: &Path,
+    secrets: &BTreeMap<String, String>,
+) -> Result<SignedExportSummary> {
+    let signing_key = SigningKey::from_bytes(&decode_key(signing_key)?);
+    let mut workflow = db.get_workflow(workflow_id)?;
+    workflow.deleted_at = None;
+    // Scrubbed before signing, so the signature covers what is shared.
+    let secrets_scrubbed = secrets::scrub_export(&mut workflow, &mut [], secrets);
+
+    let payload = canonical_json(&workflow)?;
+    let signature = DetachedSignature {
+        algorithm: SIGNATURE_ALGORITHM.to_string(),
+        public_key: BASE64.encode(signing_key.verifying_key().to_bytes()),
+        signature: BASE64.encode(signing_key.sign(&payload).to_bytes()),
+        signed_at: chrono::Utc::now(),
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let sig_path = signature_path(path);
+    std::fs::write(path, &payload)?;
+    std::fs::write(&sig_path, serde_json::to_vec_pretty(&signature)?)?;
+
+    Ok(SignedExportSummary {
+        path: path.to_string_lossy().to_string(),
+        signature_path: sig_path.to_string_lossy().to_string(),
+        workflow_id: workflow_id.to_string(),
+        public_key: signature.public_key,
+        secrets_scrubbed,
+    })
+}
+
+// Checks the detached signature against the caller's trusted public key, not
+// the one recorded in the signature file, and only then parses the workflow.
+pub fn verify_signed(path: &Path, public_key: &str) -> Result<Workflow> {
+    let verifying_key = VerifyingKey::from_bytes(&decode_key(public_key)?)
+        .map_err(|e| SigningError::InvalidKey(e.to_string()))?;
+
+    let payload = std::fs::read(path)?;
+    let detached: DetachedSignature = serde_json::from_slice(&std::fs::read(signature_path(path))?)?;
+    if detached.algorithm != SIGNATURE_ALGORITHM {
+        return Err(SigningError::UnsupportedAlgorithm(detached.algorithm));
+    }
+
+    let signature_bytes: [u8; 64] = BASE64
+        .decode(detached.signature.trim())
+        .map_err(|_| SigningError::SignatureInvalid)?
+        .try_into()
+        .map_err(|_| SigningError::SignatureInvalid)?;
+    verifying_key
+        .verify(&payload, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| SigningError::SignatureInvalid)?;
+
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{node, temp_db, temp_dir, workflow};
+    use serde_json::json;
+
+    fn exported(secrets: &BTreeMap<String, String>) -> (SigningKeyPair, PathBuf) {
+        let db = temp_db();
+        let shared = workflow(
+            "shared",
+            vec![node(
+                "fetch",
+                "http",
+                json!({ "url": "https://example.com", "headers": { "Authorization": "tok-123" } }),
+            )],
+            vec![],
+        );
+        db.create_workflow(&shared).unwrap();
+
+        let keys = generate_keypair();
+        let path = temp_dir().join("shared.workflow.json");
+        let summary = export_signed(&db, "shared", &keys.signing_key, &path, secrets).unwrap();
+        assert_eq!(summary.public_key, keys.public_key);
+        (keys, path)
+    }
+
+    #[test]
+    fn signed_export_round_trips() {
+        let secrets = BTreeMap::from([("API_TOKEN".to_string(), "tok-123".to_string())]);
+        let (keys, path) = exported(&secrets);
+
+        let verified = verify_signed(&path, &keys.public_key).unwrap();
+        assert_eq!(verified.id, "shared");
+        assert_eq!(
+            verified.nodes[0].data["headers"]["Authorization"],
+            "{{ secrets.API_TOKEN }}"
+        );
+    }
+
+    #[test]
+    fn tampering_fails_verification() {
+        let (keys, path) = exported(&BTreeMap::new());
+        let original = std::fs::read(&path).unwrap();
+
+        let tampered = String::from_utf8(original.clone())
+            .unwrap()
+            .replace("https://example.com", "https://evil.example");
+        std::fs::write(&path, tampered).unwrap();
+        assert!(matches!(
+            verify_signed(&path, &keys.public_key),
+            Err(SigningError::SignatureInvalid)
+        ));
+
+        // An untouched file still fails against a key it was not signed with.
+        std::fs::write(&path, &original).unwrap();
+        let stranger = generate_keypair();
+        assert!(matches!(
+            verify_signed(&path, &stranger.public_key),
+            Err(SigningError::SignatureInvalid)
+        ));
+        assert!(verify_signed(&path, &keys.public_key).is_ok());
+    }
+}
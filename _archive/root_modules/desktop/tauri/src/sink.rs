@@ -0,0 +1,222 @@
+/*!
+ * Sink - forwards node outputs to an external destination as a run progresses
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputSink {
+    // Each record is POSTed as a JSON body.
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    // Records are appended as newline-delimited JSON.
+    File { path: PathBuf },
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkRecord {
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub node_id: String,
+    pub output: serde_json::Value,
+    pub produced_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Delivers records in order on a background task so a slow or failing sink
+// never holds up the run; delivery failures are only logged.
+pub struct SinkWriter {
+    sender: mpsc::UnboundedSender<SinkRecord>,
+    task: JoinHandle<()>,
+}
+
+impl SinkWriter {
+    pub fn start(sink: OutputSink) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(deliver(sink, receiver));
+        Self { sender, task }
+    }
+
+    pub fn push(&self, record: SinkRecord) {
+        let _ = self.sender.send(record);
+    }
+
+    // Waits until every pushed record has been delivered (or has failed).
+    pub async fn finish(self) {
+        drop(self.sender);
+        let _ = self.task.await;
+    }
+}
+
+async fn deliver(sink: OutputSink, mut receiver: mpsc::UnboundedReceiver<SinkRecord>) {
+    let client = reqwest::Client::new();
+
+    while let Some(record) = receiver.recv().await {
+        let result = match &sink {
+            OutputSink::Http {
+                url,
+                headers,
+                timeout_ms,
+            } => send_http(&client, url, headers, *timeout_ms, &record).await,
+            OutputSink::File { path } => append_file(path, &record).await,
+        };
+        if let Err(e) = result {
+            tracing::warn!(
+                "output sink failed for node {} of execution {}: {}",
+                record.node_id,
+                record.execution_id,
+                e
+            );
+        }
+    }
+}
+
+async fn send_http(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    timeout_ms: u64,
+    record: &SinkRecord,
+) -> anyhow::Result<()> {
+    let mut request = client
+        .post(url)
+        .timeout(Duration::from_millis(timeout_ms))
+        .json(record);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+async fn append_file(path: &PathBuf, record: &SinkRecord) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Priority;
+    use crate::test_support::{edge, engine, node, wait_for_runs, workflow};
+    use crate::workflow_engine::find_profile;
+    use crate::ExecutionStatus;
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use parking_lot::Mutex;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    // Collects every record POSTed to it.
+    async fn mock_http_sink() -> (OutputSink, Arc<Mutex<Vec<SinkRecord>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let router = Router::new()
+            .route(
+                "/outputs",
+                post(
+                    |State(received): State<Arc<Mutex<Vec<SinkRecord>>>>,
+                     Json(record): Json<SinkRecord>| async move {
+                        received.lock().push(record);
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let sink = OutputSink::Http {
+            url: format!("http://{}/outputs", addr),
+            headers: BTreeMap::new(),
+            timeout_ms: default_timeout_ms(),
+        };
+        (sink, received)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn node_outputs_are_posted_as_nodes_complete() {
+        let (db, engine) = engine();
+        let chain = workflow(
+            "chain",
+            vec![
+                node("t", "trigger", json!({})),
+                node("a", "delay", json!({ "duration_ms": 20 })),
+                node("b", "delay", json!({ "duration_ms": 20 })),
+            ],
+            vec![edge("t", "a"), edge("a", "b")],
+        );
+        let (sink, received) = mock_http_sink().await;
+        {
+            let db = db.lock();
+            db.create_workflow(&chain).unwrap();
+            db.set_output_sink(&chain.id, Some(&sink)).unwrap();
+        }
+
+        let profile = find_profile("default").unwrap();
+        let execution_id = engine
+            .execute_workflow(&chain, &profile, Priority::default(), None)
+            .unwrap();
+        wait_for_runs(&engine, &chain.id).await;
+
+        let received = received.lock();
+        let nodes: Vec<&str> = received.iter().map(|r| r.node_id.as_str()).collect();
+        assert_eq!(nodes, ["t", "a", "b"]);
+        assert!(received
+            .iter()
+            .all(|r| r.execution_id == execution_id && r.workflow_id == chain.id));
+        assert!(received.windows(2).all(|w| w[0].produced_at <= w[1].produced_at));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_unreachable_sink_does_not_fail_the_run() {
+        let (db, engine) = engine();
+        let single = workflow("single", vec![node("t", "trigger", json!({}))], vec![]);
+        let sink = OutputSink::Http {
+            url: "http://127.0.0.1:9/outputs".to_string(),
+            headers: BTreeMap::new(),
+            timeout_ms: 500,
+        };
+        {
+            let db = db.lock();
+            db.create_workflow(&single).unwrap();
+            db.set_output_sink(&single.id, Some(&sink)).unwrap();
+        }
+
+        let profile = find_profile("default").unwrap();
+        let execution_id = engine
+            .execute_workflow(&single, &profile, Priority::default(), None)
+            .unwrap();
+        wait_for_runs(&engine, &single.id).await;
+        assert_eq!(
+            db.lock().get_execution(&execution_id).unwrap().status,
+            ExecutionStatus::Completed
+        );
+    }
+}
@@ -0,0 +1,185 @@
+/*!
+ * Template - `{{ path.to.value }}` resolution against a JSON scope
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    #[error("unresolved template reference: {0}")]
+    Unresolved(String),
+    #[error("unterminated template expression in: {0}")]
+    Unterminated(String),
+}
+
+pub type Result<T> = std::result::Result<T, TemplateError>;
+
+// Looks up a dotted path such as `nodes.fetch.body.items[0].id` (or `items.0.id`).
+pub fn lookup_path<'a>(scope: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = scope;
+    for segment in path.split('.') {
+        let (key, indices) = split_indices(segment)?;
+        if !key.is_empty() {
+            current = match current {
+                Value::Object(map) => map.get(key)?,
+                Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        for index in indices {
+            current = current.as_array()?.get(index)?;
+        }
+    }
+    Some(current)
+}
+
+fn split_indices(segment: &str) -> Option<(&str, Vec<usize>)> {
+    let (key, mut rest) = match segment.find('[') {
+        Some(pos) => (&segment[..pos], &segment[pos..]),
+        None => return Some((segment, Vec::new())),
+    };
+
+    let mut indices = Vec::new();
+    while !rest.is_empty() {
+        let end = rest.find(']')?;
+        indices.push(rest[1..end].trim().parse().ok()?);
+        rest = &rest[end + 1..];
+        if !rest.is_empty() && !rest.starts_with('[') {
+            return None;
+        }
+    }
+    Some((key, indices))
+}
+
+// Returns the trimmed reference of every `{{ }}` expression in `text`.
+pub fn find_references(text: &str) -> Result<Vec<String>> {
+    let mut references = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| TemplateError::Unterminated(text.to_string()))?;
+        references.push(after[..end].trim().to_string());
+        rest = &after[end + 2..];
+    }
+    Ok(references)
+}
+
+// References in every string within `value`, walking arrays and objects.
+// Malformed templates are skipped; they are reported when the value renders.
+pub fn value_references(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(text) => find_references(text).unwrap_or_default(),
+        Value::Array(items) => items.iter().flat_map(value_references).collect(),
+        Value::Object(map) => map.values().flat_map(value_references).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// A string that is exactly one template keeps the referenced value's JSON type;
+// otherwise references are interpolated into the surrounding text.
+pub fn render_str(text: &str, scope: &Value) -> Result<Value> {
+    let trimmed = text.trim();
+    if trimmed.starts_with("{{") && trimmed.ends_with("}}") && trimmed.matches("{{").count() == 1 {
+        let reference = trimmed[2..trimmed.len() - 2].trim();
+        return lookup_path(scope, reference)
+            .cloned()
+            .ok_or_else(|| TemplateError::Unresolved(reference.to_string()));
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| TemplateError::Unterminated(text.to_string()))?;
+        let reference = after[..end].trim();
+        match lookup_path(scope, reference) {
+            Some(Value::String(s)) => output.push_str(s),
+            Some(value) => output.push_str(&value.to_string()),
+            None => return Err(TemplateError::Unresolved(reference.to_string())),
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(Value::String(output))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Resolution {
+    Resolved { value: Value },
+    Unresolved,
+    Malformed { message: String },
+}
+
+// What each reference in the strings inside `value` resolves to in `scope`,
+// without rendering anything. Malformed template text is keyed by the text.
+pub fn resolve_references(value: &Value, scope: &Value) -> BTreeMap<String, Resolution> {
+    let mut resolved = BTreeMap::new();
+    collect_resolutions(value, scope, &mut resolved);
+    resolved
+}
+
+fn collect_resolutions(value: &Value, scope: &Value, resolved: &mut BTreeMap<String, Resolution>) {
+    match value {
+        Value::String(text) => match find_references(text) {
+            Ok(references) => {
+                for reference in references {
+                    let resolution = match lookup_path(scope, &reference) {
+                        Some(value) => Resolution::Resolved {
+                            value: value.clone(),
+                        },
+                        None => Resolution::Unresolved,
+                    };
+                    resolved.insert(reference, resolution);
+                }
+            }
+            Err(e) => {
+                resolved.insert(
+                    text.clone(),
+                    Resolution::Malformed {
+                        message: e.to_string(),
+                    },
+                );
+            }
+        },
+        Value::Array(items) => {
+            for item in items {
+                collect_resolutions(item, scope, resolved);
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values() {
+                collect_resolutions(item, scope, resolved);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Resolves templates in every string nested inside `value`.
+pub fn render_value(value: &Value, scope: &Value) -> Result<Value> {
+    match value {
+        Value::String(s) if s.contains("{{") => render_str(s, scope),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| render_value(item, scope))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array),
+        Value::Object(map) => {
+            let mut rendered = serde_json::Map::with_capacity(map.len());
+            for (key, item) in map {
+                rendered.insert(key.clone(), render_value(item, scope)?);
+            }
+            Ok(Value::Object(rendered))
+        }
+        other => Ok(other.clone()),
+    }
+}
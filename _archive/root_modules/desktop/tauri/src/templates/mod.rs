@@ -0,0 +1,208 @@
+/*!
+ * Templates - built-in and user-saved starter workflows
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::database::{Database, DatabaseError};
+use crate::nodes::NodeRegistry;
+use crate::validation::{self, Severity};
+use crate::{Workflow, WorkflowEdge, WorkflowNode, WorkflowStatus};
+
+const BUILTIN_TEMPLATES: &[&str] = &[
+    include_str!("builtin/blank-trigger.json"),
+    include_str!("builtin/fetch-and-reshape.json"),
+    include_str!("builtin/delayed-webhook.json"),
+];
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("template not found: {0}")]
+    NotFound(String),
+    #[error("invalid template {template_id}: {message}")]
+    Invalid { template_id: String, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, CatalogError>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateSource {
+    #[default]
+    Builtin,
+    User,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub source: TemplateSource,
+    pub nodes: Vec<WorkflowNode>,
+    pub edges: Vec<WorkflowEdge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplatePreview {
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub node_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateSummary {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub source: TemplateSource,
+    pub preview: TemplatePreview,
+}
+
+impl WorkflowTemplate {
+    pub fn summary(&self) -> TemplateSummary {
+        TemplateSummary {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            source: self.source,
+            preview: TemplatePreview {
+                node_count: self.nodes.len(),
+                edge_count: self.edges.len(),
+                node_types: self
+                    .nodes
+                    .iter()
+                    .map(|node| node.node_type.clone())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect(),
+            },
+        }
+    }
+
+    pub fn from_workflow(workflow: &Workflow, name: &str, description: &str) -> Self {
+        Self {
+            id: format!("user-{}", Uuid::new_v4()),
+            name: name.to_string(),
+            description: description.to_string(),
+            source: TemplateSource::User,
+            nodes: workflow.nodes.clone(),
+            edges: workflow.edges.clone(),
+        }
+    }
+
+    // A fresh draft workflow; node and edge ids are regenerated so several
+    // workflows made from one template never share ids.
+    pub fn instantiate(&self, name: &str) -> Workflow {
+        let node_ids: HashMap<&str, String> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), Uuid::new_v4().to_string()))
+            .collect();
+        let remap = |id: &str| node_ids.get(id).cloned().unwrap_or_else(|| id.to_string());
+
+        let now = chrono::Utc::now();
+        Workflow {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            description: Some(self.description.clone()),
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| WorkflowNode {
+                    id: remap(&node.id),
+                    ..node.clone()
+                })
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .map(|edge| WorkflowEdge {
+                    id: Uuid::new_v4().to_string(),
+                    source: remap(&edge.source),
+                    target: remap(&edge.target),
+                    ..edge.clone()
+                })
+                .collect(),
+            status: WorkflowStatus::Draft,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            resume_on_startup: false,
+            last_execution_id: None,
+            last_execution_status: None,
+            last_execution_at: None,
+        }
+    }
+}
+
+pub fn validate_template(registry: &NodeRegistry, template: &WorkflowTemplate) -> Result<()> {
+    let (report, _) = validation::validate_workflow(registry, &template.instantiate(&template.name));
+    let errors: Vec<String> = report
+        .issues
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.message)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(CatalogError::Invalid {
+            template_id: template.id.clone(),
+            message: errors.join("; "),
+        })
+    }
+}
+
+fn parse_builtin(raw: &str) -> Result<WorkflowTemplate> {
+    serde_json::from_str(raw).map_err(|e| CatalogError::Invalid {
+        template_id: "<builtin>".to_string(),
+        message: e.to_string(),
+    })
+}
+
+pub fn builtin_templates() -> Vec<WorkflowTemplate> {
+    // Every entry is checked by `validate_builtin` during setup.
+    BUILTIN_TEMPLATES
+        .iter()
+        .filter_map(|raw| parse_builtin(raw).ok())
+        .collect()
+}
+
+// Called from setup so a malformed built-in template stops the app at launch
+// instead of surfacing when a user picks it.
+pub fn validate_builtin(registry: &NodeRegistry) -> Result<()> {
+    let mut ids = BTreeSet::new();
+    for raw in BUILTIN_TEMPLATES {
+        let template = parse_builtin(raw)?;
+        if !ids.insert(template.id.clone()) {
+            return Err(CatalogError::Invalid {
+                template_id: template.id,
+                message: "duplicate template id".to_string(),
+            });
+        }
+        validate_template(registry, &template)?;
+    }
+    Ok(())
+}
+
+// Built-in templates followed by user templates, oldest first.
+pub fn all_templates(db: &Database) -> Result<Vec<WorkflowTemplate>> {
+    let mut templates = builtin_templates();
+    templates.extend(db.get_user_templates()?);
+    Ok(templates)
+}
+
+pub fn find_template(db: &Database, template_id: &str) -> Result<WorkflowTemplate> {
+    if let Some(template) = builtin_templates().into_iter().find(|t| t.id == template_id) {
+        return Ok(template);
+    }
+    db.get_user_template(template_id)?
+        .ok_or_else(|| CatalogError::NotFound(template_id.to_string()))
+}
@@ -0,0 +1,102 @@
+/*!
+ * Test Support - fixtures shared by the unit tests
+ */
+
+use parking_lot::Mutex;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::workflow_engine::WorkflowEngine;
+use crate::{Execution, ExecutionStatus, Position, Workflow, WorkflowEdge, WorkflowNode, WorkflowStatus};
+
+// A fresh path under the system temp dir; nothing is created there yet.
+pub fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("workflow-test-{}", Uuid::new_v4()))
+}
+
+pub fn temp_db_path() -> PathBuf {
+    temp_dir().join("workflows.db")
+}
+
+pub fn temp_db() -> Database {
+    Database::new(&temp_db_path()).expect("failed to open test database")
+}
+
+pub fn engine() -> (Arc<Mutex<Database>>, WorkflowEngine) {
+    let db = Arc::new(Mutex::new(temp_db()));
+    let engine = WorkflowEngine::new(db.clone());
+    (db, engine)
+}
+
+// Waits until no run of `workflow_id` is active, failing the test after 10s.
+pub async fn wait_for_runs(engine: &WorkflowEngine, workflow_id: &str) {
+    let waited = tokio::time::timeout(Duration::from_secs(10), async {
+        while engine.is_workflow_running(workflow_id) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(waited.is_ok(), "workflow {} still running after 10s", workflow_id);
+}
+
+pub fn node(id: &str, node_type: &str, data: serde_json::Value) -> WorkflowNode {
+    WorkflowNode {
+        id: id.to_string(),
+        node_type: node_type.to_string(),
+        position: Position { x: 0.0, y: 0.0 },
+        data,
+    }
+}
+
+pub fn edge(source: &str, target: &str) -> WorkflowEdge {
+    WorkflowEdge {
+        id: format!("{}-{}", source, target),
+        source: source.to_string(),
+        target: target.to_string(),
+        source_handle: None,
+        target_handle: None,
+    }
+}
+
+pub fn workflow(id: &str, nodes: Vec<WorkflowNode>, edges: Vec<WorkflowEdge>) -> Workflow {
+    let now = chrono::Utc::now();
+    Workflow {
+        id: id.to_string(),
+        name: format!("Workflow {}", id),
+        description: None,
+        nodes,
+        edges,
+        status: WorkflowStatus::Draft,
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+        resume_on_startup: false,
+        last_execution_id: None,
+        last_execution_status: None,
+        last_execution_at: None,
+    }
+}
+
+// A finished run of `workflow_id` that started at `started_at`.
+pub fn execution(
+    id: &str,
+    workflow_id: &str,
+    started_at: chrono::DateTime<chrono::Utc>,
+) -> Execution {
+    Execution {
+        id: id.to_string(),
+        workflow_id: workflow_id.to_string(),
+        status: ExecutionStatus::Completed,
+        profile: "default".to_string(),
+        started_at,
+        finished_at: Some(started_at),
+        error: None,
+        cpu_time_ms: None,
+        rate_limit_wait_ms: None,
+        pinned: false,
+        progress: None,
+    }
+}
@@ -0,0 +1,697 @@
+/*!
+ * Validation - structural and per-node checks for workflows
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::Instant;
+
+use crate::node_cache;
+use crate::nodes::{NodeRegistry, Port, DEFAULT_PORT};
+use crate::schema;
+use crate::workflow_engine;
+use crate::{Workflow, WorkflowNode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub code: String,
+    pub message: String,
+    pub node_id: Option<String>,
+    // Dotted path into the node's config when the issue concerns one field
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl ValidationIssue {
+    pub fn error(code: &str, message: impl Into<String>, node_id: Option<&str>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: code.to_string(),
+            message: message.into(),
+            node_id: node_id.map(str::to_string),
+            path: None,
+        }
+    }
+
+    pub fn at_path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn warning(code: &str, message: impl Into<String>, node_id: Option<&str>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, node_id)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    fn from_issues(issues: Vec<ValidationIssue>) -> Self {
+        Self {
+            valid: !issues.iter().any(|issue| issue.severity == Severity::Error),
+            issues,
+        }
+    }
+}
+
+// A workflow that failed validation, with its errors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidWorkflow {
+    pub workflow_id: String,
+    pub name: String,
+    pub errors: Vec<ValidationIssue>,
+}
+
+// The outcome of validating every stored workflow. Warnings don't make a
+// workflow invalid and aren't listed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibraryValidationSummary {
+    pub total: usize,
+    pub valid: usize,
+    pub invalid: usize,
+    pub workflows: Vec<InvalidWorkflow>,
+}
+
+impl LibraryValidationSummary {
+    pub fn add(&mut self, workflow: &Workflow, report: ValidationReport) {
+        self.total += 1;
+        if report.valid {
+            self.valid += 1;
+            return;
+        }
+        self.invalid += 1;
+        self.workflows.push(InvalidWorkflow {
+            workflow_id: workflow.id.clone(),
+            name: workflow.name.clone(),
+            errors: report
+                .issues
+                .into_iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .collect(),
+        });
+    }
+}
+
+// Emitted after each workflow while validating the library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryValidationProgress {
+    pub validated: usize,
+    pub total: usize,
+    pub workflow_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeValidationTiming {
+    pub node_id: String,
+    pub node_type: String,
+    pub duration_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTypeValidationTiming {
+    pub node_type: String,
+    pub count: usize,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationProfile {
+    pub workflow_id: String,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub total_us: u64,
+    // Slowest first
+    pub nodes: Vec<NodeValidationTiming>,
+    pub node_types: Vec<NodeTypeValidationTiming>,
+}
+
+// Most recent validation profile per workflow id.
+#[derive(Default)]
+pub struct ValidationProfiles {
+    profiles: HashMap<String, ValidationProfile>,
+}
+
+impl ValidationProfiles {
+    pub fn record(&mut self, profile: ValidationProfile) {
+        self.profiles.insert(profile.workflow_id.clone(), profile);
+    }
+
+    pub fn get(&self, workflow_id: &str) -> Option<ValidationProfile> {
+        self.profiles.get(workflow_id).cloned()
+    }
+}
+
+pub fn validate_node_config(
+    registry: &NodeRegistry,
+    node_type: &str,
+    data: &serde_json::Value,
+) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    if node_type.trim().is_empty() {
+        issues.push(ValidationIssue::error(
+            "missing_node_type",
+            "node type must not be empty",
+            None,
+        ));
+    }
+    if !data.is_object() && !data.is_null() {
+        issues.push(ValidationIssue::error(
+            "invalid_config",
+            "node config must be a JSON object",
+            None,
+        ));
+        return issues;
+    }
+
+    match registry.get(node_type) {
+        Some(executor) => {
+            if registry.is_locked(executor.as_ref()) {
+                issues.push(ValidationIssue::error(
+                    "premium_locked",
+                    format!("{} nodes need an activated license", node_type),
+                    None,
+                ));
+            }
+            if node_cache::requested(data) && !executor.is_deterministic(data) {
+                issues.push(ValidationIssue::warning(
+                    "not_cacheable",
+                    format!(
+                        "{} nodes with this config are not deterministic; cacheable is ignored",
+                        node_type
+                    ),
+                    None,
+                ));
+            }
+            // A missing config is treated as an empty object.
+            let config = if data.is_null() {
+                serde_json::json!({})
+            } else {
+                data.clone()
+            };
+            let field_errors = schema::validate(&executor.config_schema(), &config);
+            if field_errors.is_empty() {
+                issues.extend(
+                    executor
+                        .validate_config(data)
+                        .into_iter()
+                        .map(|message| ValidationIssue::error("invalid_config", message, None)),
+                );
+            } else {
+                issues.extend(field_errors.into_iter().map(|error| {
+                    ValidationIssue::error("invalid_config", error.message, None).at_path(&error.path)
+                }));
+            }
+        }
+        None if !node_type.trim().is_empty() => issues.push(ValidationIssue::error(
+            "unknown_node_type",
+            format!("no executor registered for node type {}", node_type),
+            None,
+        )),
+        None => {}
+    }
+
+    issues
+}
+
+fn validate_node(registry: &NodeRegistry, node: &WorkflowNode) -> Vec<ValidationIssue> {
+    validate_node_config(registry, &node.node_type, &node.data)
+        .into_iter()
+        .map(|mut issue| {
+            issue.node_id = Some(node.id.clone());
+            issue
+        })
+        .collect()
+}
+
+pub fn validate_graph(registry: &NodeRegistry, workflow: &Workflow) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let mut node_ids = HashSet::new();
+    for node in &workflow.nodes {
+        if !node_ids.insert(node.id.as_str()) {
+            issues.push(ValidationIssue::error(
+                "duplicate_node_id",
+                format!("node id {} is used more than once", node.id),
+                Some(&node.id),
+            ));
+        }
+    }
+
+    for edge in &workflow.edges {
+        for endpoint in [&edge.source, &edge.target] {
+            if !node_ids.contains(endpoint.as_str()) {
+                issues.push(ValidationIssue::error(
+                    "dangling_edge",
+                    format!("edge {} references missing node {}", edge.id, endpoint),
+                    None,
+                ));
+            }
+        }
+    }
+
+    issues.extend(check_ports(registry, workflow));
+    issues.extend(check_connections(registry, workflow));
+
+    if workflow_engine::topological_order(workflow).is_err() {
+        issues.push(ValidationIssue::error(
+            "cycle_detected",
+            "workflow graph contains a cycle",
+            None,
+        ));
+    }
+
+    issues
+}
+
+fn find_port<'a>(ports: &'a [Port], handle: Option<&str>) -> Option<&'a Port> {
+    let name = handle.unwrap_or(DEFAULT_PORT);
+    ports.iter().find(|port| port.name == name)
+}
+
+// Each edge must leave a declared output port and enter a declared input port
+// of a compatible type. Endpoints whose executor declares no ports, or that
+// are missing or of an unknown type, are left to the other checks.
+fn check_ports(registry: &NodeRegistry, workflow: &Workflow) -> Vec<ValidationIssue> {
+    let nodes: HashMap<&str, &WorkflowNode> = workflow
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node))
+        .collect();
+    let executor = |id: &str| {
+        nodes
+            .get(id)
+            .and_then(|node| registry.get(&node.node_type))
+    };
+
+    let mut issues = Vec::new();
+    for edge in &workflow.edges {
+        let output = executor(&edge.source).and_then(|executor| executor.output_ports());
+        let input = executor(&edge.target).and_then(|executor| executor.input_ports());
+        let source_port = edge.source_handle.as_deref().unwrap_or(DEFAULT_PORT);
+        let target_port = edge.target_handle.as_deref().unwrap_or(DEFAULT_PORT);
+
+        let output = match output.map(|ports| find_port(ports, edge.source_handle.as_deref())) {
+            Some(None) => {
+                issues.push(ValidationIssue::error(
+                    "unknown_output_port",
+                    format!(
+                        "edge {} leaves {}.{}, which is not an output of that node",
+                        edge.id, edge.source, source_port
+                    ),
+                    Some(&edge.source),
+                ));
+                continue;
+            }
+            Some(Some(port)) => Some(port),
+            None => None,
+        };
+        let input = match input.map(|ports| find_port(ports, edge.target_handle.as_deref())) {
+            Some(None) => {
+                issues.push(ValidationIssue::error(
+                    "unknown_input_port",
+                    format!(
+                        "edge {} enters {}.{}, which is not an input of that node",
+                        edge.id, edge.target, target_port
+                    ),
+                    Some(&edge.target),
+                ));
+                continue;
+            }
+            Some(Some(port)) => Some(port),
+            None => None,
+        };
+
+        if let (Some(output), Some(input)) = (output, input) {
+            if !output.port_type.feeds(input.port_type) {
+                issues.push(ValidationIssue::error(
+                    "port_type_mismatch",
+                    format!(
+                        "edge {} connects {}.{} ({}) to {}.{} ({})",
+                        edge.id,
+                        edge.source,
+                        output.name,
+                        output.port_type,
+                        edge.target,
+                        input.name,
+                        input.port_type
+                    ),
+                    Some(&edge.target),
+                ));
+            }
+        }
+    }
+    issues
+}
+
+// Each node's edge counts against the bounds its executor declares. Edges to
+// missing nodes are not counted; they are reported as dangling. A node with
+// too few outputs still runs, its result is just unused, so that is a warning.
+fn check_connections(registry: &NodeRegistry, workflow: &Workflow) -> Vec<ValidationIssue> {
+    let mut inputs: HashMap<&str, usize> = HashMap::new();
+    let mut outputs: HashMap<&str, usize> = HashMap::new();
+    let node_ids: HashSet<&str> = workflow.nodes.iter().map(|node| node.id.as_str()).collect();
+    for edge in &workflow.edges {
+        if node_ids.contains(edge.source.as_str()) && node_ids.contains(edge.target.as_str()) {
+            *outputs.entry(edge.source.as_str()).or_default() += 1;
+            *inputs.entry(edge.target.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut issues = Vec::new();
+    for node in &workflow.nodes {
+        let Some(executor) = registry.get(&node.node_type) else {
+            continue;
+        };
+        let connections = executor.connections();
+        let inputs = inputs.get(node.id.as_str()).copied().unwrap_or(0);
+        let outputs = outputs.get(node.id.as_str()).copied().unwrap_or(0);
+        let node_id = Some(node.id.as_str());
+
+        if inputs < connections.min_inputs {
+            issues.push(ValidationIssue::error(
+                "too_few_inputs",
+                format!(
+                    "{} node {} needs at least {} incoming edges, has {}",
+                    node.node_type, node.id, connections.min_inputs, inputs
+                ),
+                node_id,
+            ));
+        }
+        if let Some(max) = connections.max_inputs.filter(|max| inputs > *max) {
+            issues.push(ValidationIssue::error(
+                "too_many_inputs",
+                format!(
+                    "{} node {} takes at most {} incoming edges, has {}",
+                    node.node_type, node.id, max, inputs
+                ),
+                node_id,
+            ));
+        }
+        if outputs < connections.min_outputs {
+            issues.push(ValidationIssue::warning(
+                "too_few_outputs",
+                format!(
+                    "{} node {} should have at least {} outgoing edges, has {}",
+                    node.node_type, node.id, connections.min_outputs, outputs
+                ),
+                node_id,
+            ));
+        }
+        if let Some(max) = connections.max_outputs.filter(|max| outputs > *max) {
+            issues.push(ValidationIssue::error(
+                "too_many_outputs",
+                format!(
+                    "{} node {} takes at most {} outgoing edges, has {}",
+                    node.node_type, node.id, max, outputs
+                ),
+                node_id,
+            ));
+        }
+    }
+    issues
+}
+
+fn reaches(workflow: &Workflow, from: &str, to: &str) -> bool {
+    let mut stack = vec![from];
+    let mut seen = HashSet::new();
+    while let Some(id) = stack.pop() {
+        if id == to {
+            return true;
+        }
+        if seen.insert(id) {
+            stack.extend(
+                workflow
+                    .edges
+                    .iter()
+                    .filter(|edge| edge.source == id)
+                    .map(|edge| edge.target.as_str()),
+            );
+        }
+    }
+    false
+}
+
+// Edges into the same single-value input handle from producers with no path
+// between them: which value the node sees depends on scheduling. Edges without
+// a target handle feed the default input, which merges per source and is safe.
+pub fn find_input_races(registry: &NodeRegistry, workflow: &Workflow) -> Vec<ValidationIssue> {
+    let mut producers: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for edge in &workflow.edges {
+        if let Some(handle) = &edge.target_handle {
+            let sources = producers
+                .entry((edge.target.as_str(), handle.as_str()))
+                .or_default();
+            if !sources.contains(&edge.source.as_str()) {
+                sources.push(edge.source.as_str());
+            }
+        }
+    }
+
+    let mut issues = Vec::new();
+    for ((target, handle), sources) in producers {
+        if sources.len() < 2 {
+            continue;
+        }
+        let multi_value = workflow
+            .nodes
+            .iter()
+            .find(|node| node.id == target)
+            .and_then(|node| registry.get(&node.node_type))
+            .is_some_and(|executor| executor.multi_value_inputs().contains(&handle));
+        if multi_value {
+            continue;
+        }
+
+        let mut racing = BTreeSet::new();
+        for (i, a) in sources.iter().enumerate() {
+            for b in &sources[i + 1..] {
+                if !reaches(workflow, a, b) && !reaches(workflow, b, a) {
+                    racing.insert(*a);
+                    racing.insert(*b);
+                }
+            }
+        }
+        if !racing.is_empty() {
+            issues.push(ValidationIssue::warning(
+                "concurrent_input_race",
+                format!(
+                    "input {} of node {} is fed by concurrent nodes {}; add a merge node so the value is deterministic",
+                    handle,
+                    target,
+                    racing.into_iter().collect::<Vec<_>>().join(", ")
+                ),
+                Some(target),
+            ));
+        }
+    }
+    issues
+}
+
+// A disabled node passes its input through, which is only one upstream
+// output when it has one upstream node; with several, the nodes after it get
+// all of them keyed by node id instead.
+fn find_ambiguous_passthroughs(workflow: &Workflow) -> Vec<ValidationIssue> {
+    workflow
+        .nodes
+        .iter()
+        .filter(|node| workflow_engine::is_disabled(node))
+        .filter_map(|node| {
+            let sources: BTreeSet<&str> = workflow
+                .edges
+                .iter()
+                .filter(|edge| edge.target == node.id)
+                .map(|edge| edge.source.as_str())
+                .collect();
+            (sources.len() > 1).then(|| {
+                ValidationIssue::warning(
+                    "ambiguous_passthrough",
+                    format!(
+                        "disabled node {} has {} inputs; the nodes after it get all of them as one object",
+                        node.id,
+                        sources.len()
+                    ),
+                    Some(&node.id),
+                )
+            })
+        })
+        .collect()
+}
+
+pub fn validate_workflow(
+    registry: &NodeRegistry,
+    workflow: &Workflow,
+) -> (ValidationReport, ValidationProfile) {
+    let started = Instant::now();
+    let mut issues = validate_graph(registry, workflow);
+    issues.extend(find_input_races(registry, workflow));
+    issues.extend(find_ambiguous_passthroughs(workflow));
+
+    let mut timings = Vec::with_capacity(workflow.nodes.len());
+    for node in &workflow.nodes {
+        let node_started = Instant::now();
+        issues.extend(validate_node(registry, node));
+        timings.push(NodeValidationTiming {
+            node_id: node.id.clone(),
+            node_type: node.node_type.clone(),
+            duration_us: node_started.elapsed().as_micros() as u64,
+        });
+    }
+
+    let mut by_type: HashMap<&str, NodeTypeValidationTiming> = HashMap::new();
+    for timing in &timings {
+        let entry = by_type
+            .entry(timing.node_type.as_str())
+            .or_insert_with(|| NodeTypeValidationTiming {
+                node_type: timing.node_type.clone(),
+                count: 0,
+                total_us: 0,
+                max_us: 0,
+            });
+        entry.count += 1;
+        entry.total_us += timing.duration_us;
+        entry.max_us = entry.max_us.max(timing.duration_us);
+    }
+    let mut node_types: Vec<NodeTypeValidationTiming> = by_type.into_values().collect();
+    node_types.sort_by(|a, b| b.total_us.cmp(&a.total_us).then(a.node_type.cmp(&b.node_type)));
+    timings.sort_by(|a, b| b.duration_us.cmp(&a.duration_us).then(a.node_id.cmp(&b.node_id)));
+
+    let profile = ValidationProfile {
+        workflow_id: workflow.id.clone(),
+        recorded_at: chrono::Utc::now(),
+        total_us: started.elapsed().as_micros() as u64,
+        nodes: timings,
+        node_types,
+    };
+
+    (ValidationReport::from_issues(issues), profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{edge, node, workflow};
+    use crate::WorkflowEdge;
+    use serde_json::json;
+
+    #[test]
+    fn validation_records_timing_for_every_node() {
+        let registry = NodeRegistry::with_builtin();
+        let workflow = workflow(
+            "timed",
+            vec![
+                node("t", "trigger", json!({})),
+                node("a", "delay", json!({ "duration_ms": 10 })),
+                node("b", "delay", json!({ "duration_ms": 20 })),
+            ],
+            vec![edge("t", "a"), edge("a", "b")],
+        );
+
+        let (report, profile) = validate_workflow(&registry, &workflow);
+        assert!(report.valid, "{:?}", report.issues);
+        assert_eq!(profile.workflow_id, "timed");
+
+        let mut timed: Vec<&str> = profile.nodes.iter().map(|timing| timing.node_id.as_str()).collect();
+        timed.sort_unstable();
+        assert_eq!(timed, ["a", "b", "t"]);
+        let node_total: u64 = profile.nodes.iter().map(|timing| timing.duration_us).sum();
+        assert!(profile.total_us >= node_total);
+
+        let delays = profile
+            .node_types
+            .iter()
+            .find(|timing| timing.node_type == "delay")
+            .unwrap();
+        assert_eq!(delays.count, 2);
+        assert_eq!(profile.node_types.iter().map(|timing| timing.count).sum::<usize>(), 3);
+
+        let mut profiles = ValidationProfiles::default();
+        profiles.record(profile);
+        assert_eq!(profiles.get("timed").unwrap().nodes.len(), 3);
+        assert!(profiles.get("other").is_none());
+    }
+
+    fn into_handle(source: &str, target: &str, handle: &str) -> WorkflowEdge {
+        WorkflowEdge {
+            target_handle: Some(handle.to_string()),
+            ..edge(source, target)
+        }
+    }
+
+    fn races(workflow: &Workflow) -> Vec<ValidationIssue> {
+        let registry = NodeRegistry::with_builtin();
+        validate_workflow(&registry, workflow)
+            .0
+            .issues
+            .into_iter()
+            .filter(|issue| issue.code == "concurrent_input_race")
+            .collect()
+    }
+
+    #[test]
+    fn concurrent_producers_into_one_input_are_a_race() {
+        let nodes = || {
+            vec![
+                node("t", "trigger", json!({})),
+                node("a", "delay", json!({ "duration_ms": 10 })),
+                node("b", "delay", json!({ "duration_ms": 20 })),
+                node("c", "delay", json!({ "duration_ms": 10 })),
+            ]
+        };
+
+        let parallel = workflow(
+            "parallel",
+            nodes(),
+            vec![
+                edge("t", "a"),
+                edge("t", "b"),
+                into_handle("a", "c", "value"),
+                into_handle("b", "c", "value"),
+            ],
+        );
+        let found = races(&parallel);
+        assert_eq!(found.len(), 1, "{:?}", found);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert_eq!(found[0].node_id.as_deref(), Some("c"));
+        assert!(found[0].message.contains("a, b"), "{}", found[0].message);
+
+        // Ordered producers, or separate handles, always deliver the same value.
+        let chained = workflow(
+            "chained",
+            nodes(),
+            vec![
+                edge("t", "a"),
+                edge("a", "b"),
+                into_handle("a", "c", "value"),
+                into_handle("b", "c", "value"),
+            ],
+        );
+        assert!(races(&chained).is_empty());
+        let separate = workflow(
+            "separate",
+            nodes(),
+            vec![
+                edge("t", "a"),
+                edge("t", "b"),
+                into_handle("a", "c", "left"),
+                into_handle("b", "c", "right"),
+            ],
+        );
+        assert!(races(&separate).is_empty());
+    }
+}
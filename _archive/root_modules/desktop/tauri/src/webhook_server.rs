@@ -0,0 +1,205 @@
+/*!
+ * Webhook server - local HTTP listener that starts workflows with a webhook node
+ *
+ * A request to `/hooks/<path>` (any method) runs the active workflow whose
+ * webhook node is configured with `<path>`, using the request body as the run
+ * input, and responds with the workflow's terminal output. Requests must
+ * carry the shared secret in the `X-Workflow-Secret` header.
+ *
+ * A request with an `Idempotency-Key` header already used with the same
+ * workflow inside the idempotency window starts nothing; the response names
+ * the execution the first request started, so a sender's retries run once.
+ */
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::{Json, Router};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+use crate::listener::{self, error_response};
+use crate::workflow_engine::{self, EngineError, WorkflowEngine};
+
+pub const SECRET_HEADER: &str = "x-workflow-secret";
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const DEFAULT_PORT: u16 = 8787;
+
+const SETTINGS_KEY: &str = "webhook.settings";
+
+#[derive(Debug, Error)]
+pub enum WebhookError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error("invalid webhook settings: {0}")]
+    Settings(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, WebhookError>;
+
+// The listener reads `enabled` and `port` at launch; the secret is checked
+// against the stored value on every request, so rotating it applies at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub secret: String,
+}
+
+impl WebhookSettings {
+    // Stored settings; on first use, defaults with a fresh secret are saved.
+    pub fn load(db: &Database) -> Result<Self> {
+        listener::load_settings(db, SETTINGS_KEY, || Self {
+            enabled: true,
+            port: DEFAULT_PORT,
+            secret: listener::generate_token(),
+        })
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        listener::save_settings(db, SETTINGS_KEY, self)
+    }
+
+    pub fn rotate_secret(&mut self) {
+        self.secret = listener::generate_token();
+    }
+}
+
+#[derive(Clone)]
+struct ListenerState {
+    db: Arc<Mutex<Database>>,
+    engine: Arc<Mutex<WorkflowEngine>>,
+}
+
+// Binds to localhost only. A bind failure is logged rather than failing
+// setup, so a busy port does not keep the app from starting.
+pub fn spawn_listener(db: Arc<Mutex<Database>>, engine: Arc<Mutex<WorkflowEngine>>) -> Result<()> {
+    let settings = WebhookSettings::load(&db.lock())?;
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    let router = Router::new()
+        .route("/hooks/*path", any(handle_hook))
+        .with_state(ListenerState { db, engine });
+    let addr = SocketAddr::from(([127, 0, 0, 1], settings.port));
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!("webhook listener could not bind {}: {}", addr, e);
+                return;
+            }
+        };
+        tracing::info!("webhook listener on http://{}", addr);
+        if let Err(e) = axum::serve(listener, router).await {
+            tracing::warn!("webhook listener stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+fn request_input(body: &Bytes) -> Value {
+    if body.is_empty() {
+        return Value::Null;
+    }
+    // Non-JSON bodies are passed through as text.
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+async fn handle_hook(
+    State(state): State<ListenerState>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let settings = WebhookSettings::load(&state.db.lock());
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let provided = headers
+        .get(SECRET_HEADER)
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    if !listener::tokens_match(provided, settings.secret.as_bytes()) {
+        return error_response(StatusCode::UNAUTHORIZED, "missing or invalid webhook secret");
+    }
+
+    let path = path.trim_matches('/');
+    let workflow = {
+        let db = state.db.lock();
+        match db.webhook_workflow_ids(path).as_deref() {
+            Ok([id]) => db.get_workflow(id).map_err(|e| e.to_string()),
+            Ok([]) => {
+                return error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("no active workflow listens on {}", path),
+                )
+            }
+            Ok(_) => {
+                return error_response(
+                    StatusCode::CONFLICT,
+                    format!("several workflows listen on {}", path),
+                )
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    let workflow = match workflow {
+        Ok(workflow) => workflow,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|key| !key.is_empty());
+    let started = workflow_engine::find_profile("default").and_then(|profile| {
+        state.engine.lock().execute_workflow_with_input(
+            &workflow,
+            &profile,
+            request_input(&body),
+            idempotency_key,
+        )
+    });
+    let (execution_id, output) = match started {
+        Ok(started) => started,
+        Err(e @ EngineError::AlreadyRunning { .. }) => {
+            return error_response(StatusCode::CONFLICT, e.to_string())
+        }
+        Err(e @ EngineError::QueueFull { .. }) => {
+            return error_response(StatusCode::TOO_MANY_REQUESTS, e.to_string())
+        }
+        Err(e @ EngineError::ShuttingDown) => {
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+        }
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let Some(output) = output else {
+        return Json(serde_json::json!({ "execution_id": execution_id, "duplicate": true }))
+            .into_response();
+    };
+
+    match output.await {
+        Ok(Ok(output)) => Json(output).into_response(),
+        Ok(Err(e)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": e.to_string(), "execution_id": execution_id })),
+        )
+            .into_response(),
+        Err(_) => error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "execution was dropped before it started",
+        ),
+    }
+}
@@ -0,0 +1,588 @@
+/*!
+ * WebSocket Client - typed protocol and connection to the sync server
+ *
+ * Messages are JSON objects tagged by `type`. The connection runs on its own
+ * thread, which sends queued messages, dispatches inbound ones to a
+ * `MessageHandler`, and pings the server on an interval; a ping left
+ * unanswered past the timeout counts as a dropped connection and triggers a
+ * reconnect, after which the client's subscriptions are sent again.
+ *
+ * `test_connection` checks an endpoint on a throwaway socket of its own, so
+ * it never disturbs the live connection.
+ */
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use websocket::native_tls::{HandshakeError, TlsConnector};
+use websocket::result::{StatusCode, WebSocketError, WebSocketOtherError};
+use websocket::stream::sync::{AsTcpStream, NetworkStream, Stream};
+use websocket::sync::Client;
+use websocket::url::Url;
+use websocket::{ClientBuilder, OwnedMessage};
+
+use crate::ExecutionStatus;
+
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+pub const DEFAULT_PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long a read blocks before the connection thread checks for outbound
+// messages, heartbeats and disconnect requests.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+// Covers a whole connection test, from name lookup to the pong.
+pub const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum WsError {
+    #[error("websocket is not connected")]
+    NotConnected,
+    #[error("websocket is already connected")]
+    AlreadyConnected,
+    #[error("invalid websocket url: {0}")]
+    InvalidUrl(String),
+}
+
+pub type Result<T> = std::result::Result<T, WsError>;
+
+type SyncClient = Client<Box<dyn NetworkStream + Send>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsMessage {
+    // Asks the server for updates on the workflow's executions.
+    Subscribe { workflow_id: String },
+    ExecutionUpdate(ExecutionUpdate),
+    Ping,
+    Pong,
+    // Inbound only: any `type` this version does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionUpdate {
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub status: ExecutionStatus,
+    #[serde(default)]
+    pub data: Value,
+}
+
+// Receives the inbound messages meant for the app; pings, pongs and
+// protocol errors are handled by the client itself.
+pub trait MessageHandler: Send + Sync {
+    fn execution_update(&self, update: ExecutionUpdate);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub ping_interval: Duration,
+    // How long a ping may go unanswered before the connection is dropped
+    pub pong_timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            ping_interval: DEFAULT_PING_INTERVAL,
+            pong_timeout: DEFAULT_PONG_TIMEOUT,
+        }
+    }
+}
+
+struct Connection {
+    // Kept to reconnect with when the endpoint changes
+    handler: Arc<dyn MessageHandler>,
+    outbound: Sender<WsMessage>,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+pub struct WebSocketClient {
+    url: String,
+    heartbeat: Heartbeat,
+    connection: Option<Connection>,
+}
+
+impl WebSocketClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            heartbeat: Heartbeat::default(),
+            connection: None,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Points the client at `url`, checked first so a malformed one leaves the
+    // live connection alone. A running connection is closed and reopened
+    // against the new endpoint; subscriptions are not carried over.
+    pub fn set_url(&mut self, url: &str) -> Result<()> {
+        validate_url(url)?;
+        if url == self.url {
+            return Ok(());
+        }
+        let handler = self
+            .connection
+            .as_ref()
+            .filter(|_| self.is_connected())
+            .map(|connection| connection.handler.clone());
+        self.disconnect();
+        self.url = url.to_string();
+        if let Some(handler) = handler {
+            self.connect(handler)?;
+        }
+        Ok(())
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    // Whether the connection thread is running; it may be between reconnects.
+    pub fn is_connected(&self) -> bool {
+        self.connection
+            .as_ref()
+            .is_some_and(|connection| !connection.thread.is_finished())
+    }
+
+    // Starts the connection thread, which keeps reconnecting until
+    // `disconnect`. Messages sent before the socket opens are queued.
+    pub fn connect(&mut self, handler: Arc<dyn MessageHandler>) -> Result<()> {
+        if self.is_connected() {
+            return Err(WsError::AlreadyConnected);
+        }
+        let (outbound, queued) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let session = Session {
+            url: self.url.clone(),
+            heartbeat: self.heartbeat,
+            queued,
+            stop: stop.clone(),
+            handler: handler.clone(),
+            subscriptions: BTreeSet::new(),
+        };
+        let thread = thread::Builder::new()
+            .name("websocket".to_string())
+            .spawn(move || session.run())
+            .expect("failed to spawn websocket thread");
+        self.connection = Some(Connection {
+            handler,
+            outbound,
+            stop,
+            thread,
+        });
+        Ok(())
+    }
+
+    pub fn send(&self, message: WsMessage) -> Result<()> {
+        let connection = self.connection.as_ref().ok_or(WsError::NotConnected)?;
+        connection
+            .outbound
+            .send(message)
+            .map_err(|_| WsError::NotConnected)
+    }
+
+    // Closes the socket; the thread exits within one poll interval.
+    pub fn disconnect(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.stop.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for WebSocketClient {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+// A ws:// or wss:// URL naming a host.
+pub fn validate_url(url: &str) -> Result<()> {
+    let parsed = Url::parse(url).map_err(|e| WsError::InvalidUrl(format!("{}: {}", url, e)))?;
+    if !matches!(parsed.scheme(), "ws" | "wss") {
+        return Err(WsError::InvalidUrl(format!(
+            "{}: unsupported scheme '{}'",
+            url,
+            parsed.scheme()
+        )));
+    }
+    match parsed.host_str() {
+        Some(host) if !host.is_empty() => Ok(()),
+        _ => Err(WsError::InvalidUrl(format!("{}: missing host", url))),
+    }
+}
+
+// Decodes an inbound text frame. Malformed messages and unknown types are
+// logged and skipped so one bad frame never takes the connection down.
+pub fn decode(text: &str) -> Option<WsMessage> {
+    match serde_json::from_str(text) {
+        Ok(WsMessage::Unknown) => {
+            let message_type = serde_json::from_str::<Value>(text)
+                .ok()
+                .and_then(|value| value.get("type").cloned())
+                .unwrap_or(Value::Null);
+            tracing::warn!("ignoring websocket message of unknown type {}", message_type);
+            None
+        }
+        Ok(message) => Some(message),
+        Err(e) => {
+            tracing::warn!("ignoring malformed websocket message: {}", e);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionFailure {
+    InvalidUrl,
+    // The host name did not resolve
+    Dns,
+    // Nothing accepted the TCP connection
+    Refused,
+    Tls,
+    Timeout,
+    // The server answered the upgrade with 401 or 403
+    AuthRejected,
+    // Any other refused or malformed upgrade, or a dropped socket
+    Handshake,
+    // The socket opened but the server never answered the ping
+    NoPong,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionTest {
+    pub url: String,
+    pub ok: bool,
+    pub secure: bool,
+    // From the start of the TCP connect to the end of the upgrade
+    pub handshake_ms: Option<u64>,
+    pub round_trip_ms: Option<u64>,
+    // The subprotocol the server picked, if any
+    pub protocol: Option<String>,
+    pub failure: Option<ConnectionFailure>,
+    pub error: Option<String>,
+}
+
+// Opens a fresh socket to `url`, completes the upgrade, sends a ping and waits
+// for the pong, all within `timeout`. Blocks; failures are reported in the
+// result rather than returned.
+pub fn test_connection(url: &str, timeout: Duration) -> ConnectionTest {
+    let deadline = Instant::now() + timeout;
+    let mut test = ConnectionTest {
+        url: url.to_string(),
+        ok: false,
+        secure: false,
+        handshake_ms: None,
+        round_trip_ms: None,
+        protocol: None,
+        failure: None,
+        error: None,
+    };
+    if let Err((failure, error)) = probe(&mut test, deadline) {
+        test.failure = Some(failure);
+        test.error = Some(error);
+    }
+    test
+}
+
+type Failure = (ConnectionFailure, String);
+
+fn probe(test: &mut ConnectionTest, deadline: Instant) -> std::result::Result<(), Failure> {
+    let invalid = |message: String| (ConnectionFailure::InvalidUrl, message);
+    let url = Url::parse(&test.url).map_err(|e| invalid(e.to_string()))?;
+    test.secure = match url.scheme() {
+        "ws" => false,
+        "wss" => true,
+        scheme => return Err(invalid(format!("unsupported scheme '{}'", scheme))),
+    };
+    let host = url
+        .host_str()
+        .ok_or_else(|| invalid("URL has no host".to_string()))?
+        .to_string();
+    let port = url
+        .port_or_known_default()
+        .unwrap_or(if test.secure { 443 } else { 80 });
+
+    let addresses: Vec<SocketAddr> = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| (ConnectionFailure::Dns, e.to_string()))?
+        .collect();
+    if addresses.is_empty() {
+        return Err((ConnectionFailure::Dns, format!("{} has no addresses", host)));
+    }
+
+    let started = Instant::now();
+    let tcp = connect_tcp(&addresses, deadline)?;
+    let stream: Box<dyn NetworkStream + Send> = if test.secure {
+        let connector = TlsConnector::new().map_err(|e| (ConnectionFailure::Tls, e.to_string()))?;
+        match connector.connect(&host, tcp) {
+            Ok(stream) => Box::new(stream),
+            Err(HandshakeError::Failure(e)) => return Err((ConnectionFailure::Tls, e.to_string())),
+            Err(HandshakeError::WouldBlock(_)) => {
+                return Err((ConnectionFailure::Timeout, "TLS handshake timed out".to_string()))
+            }
+        }
+    } else {
+        Box::new(tcp)
+    };
+    let mut client = ClientBuilder::from_url(&url)
+        .connect_on(stream)
+        .map_err(|e| upgrade_failure(&e, deadline))?;
+    test.handshake_ms = Some(started.elapsed().as_millis() as u64);
+    test.protocol = client.protocols().first().cloned();
+
+    let result = round_trip(&mut client, deadline);
+    let _ = client.send_message(&OwnedMessage::Close(None));
+    let _ = client.shutdown();
+    test.round_trip_ms = Some(result?.as_millis() as u64);
+    test.ok = true;
+    Ok(())
+}
+
+// Time left before `deadline`, or a timeout failure once it has passed.
+fn remaining(deadline: Instant) -> std::result::Result<Duration, Failure> {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+        return Err((ConnectionFailure::Timeout, "connection test timed out".to_string()));
+    }
+    Ok(left)
+}
+
+// Tries each resolved address in turn; reads and writes then time out at the
+// deadline.
+fn connect_tcp(
+    addresses: &[SocketAddr],
+    deadline: Instant,
+) -> std::result::Result<TcpStream, Failure> {
+    let mut last_error = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(address, remaining(deadline)?) {
+            Ok(tcp) => {
+                let left = remaining(deadline)?;
+                tcp.set_read_timeout(Some(left))
+                    .and_then(|()| tcp.set_write_timeout(Some(left)))
+                    .map_err(|e| (ConnectionFailure::Refused, e.to_string()))?;
+                return Ok(tcp);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let error = last_error.expect("at least one address was tried");
+    let failure = match error.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => ConnectionFailure::Timeout,
+        _ => ConnectionFailure::Refused,
+    };
+    Err((failure, error.to_string()))
+}
+
+fn upgrade_failure(error: &WebSocketError, deadline: Instant) -> Failure {
+    let failure = match error {
+        _ if Instant::now() >= deadline => ConnectionFailure::Timeout,
+        WebSocketError::IoError(e)
+            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+            ConnectionFailure::Timeout
+        }
+        WebSocketError::Other(other) => match other.downcast_ref::<WebSocketOtherError>() {
+            Some(WebSocketOtherError::StatusCodeError(
+                StatusCode::Unauthorized | StatusCode::Forbidden,
+            )) => ConnectionFailure::AuthRejected,
+            _ => ConnectionFailure::Handshake,
+        },
+        _ => ConnectionFailure::Handshake,
+    };
+    (failure, error.to_string())
+}
+
+// Sends a ping and waits for its pong, answering server pings meanwhile and
+// skipping anything else.
+fn round_trip(client: &mut SyncClient, deadline: Instant) -> std::result::Result<Duration, Failure> {
+    let dropped = |e: SessionEnd| (ConnectionFailure::Handshake, e.to_string());
+    let no_pong = || (ConnectionFailure::NoPong, "no pong before the timeout".to_string());
+
+    let sent = Instant::now();
+    send(client, &WsMessage::Ping).map_err(dropped)?;
+    loop {
+        let left = remaining(deadline).map_err(|_| no_pong())?;
+        client
+            .stream_ref()
+            .as_tcp()
+            .set_read_timeout(Some(left))
+            .map_err(|e| dropped(WebSocketError::from(e).into()))?;
+        match client.recv_message() {
+            Ok(OwnedMessage::Text(text)) => match decode(&text) {
+                Some(WsMessage::Pong) => return Ok(sent.elapsed()),
+                Some(WsMessage::Ping) => send(client, &WsMessage::Pong).map_err(dropped)?,
+                _ => {}
+            },
+            Ok(OwnedMessage::Ping(data)) => client
+                .send_message(&OwnedMessage::Pong(data))
+                .map_err(|e| dropped(e.into()))?,
+            Ok(OwnedMessage::Close(_)) => return Err(dropped(SessionEnd::Closed)),
+            Ok(_) => {}
+            Err(WebSocketError::IoError(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                return Err(no_pong())
+            }
+            Err(e) => return Err(dropped(e.into())),
+        }
+    }
+}
+
+// Why a session ended; every cause but a requested stop leads to a reconnect.
+#[derive(Debug, Error)]
+enum SessionEnd {
+    #[error("disconnected")]
+    Stopped,
+    #[error("no pong within {0:?}")]
+    PongTimeout(Duration),
+    #[error("closed by server")]
+    Closed,
+    #[error(transparent)]
+    Socket(#[from] WebSocketError),
+}
+
+struct Session {
+    url: String,
+    heartbeat: Heartbeat,
+    queued: Receiver<WsMessage>,
+    stop: Arc<AtomicBool>,
+    handler: Arc<dyn MessageHandler>,
+    // Workflow ids subscribed to, re-sent on every reconnect
+    subscriptions: BTreeSet<String>,
+}
+
+impl Session {
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    fn run(mut self) {
+        let mut delay = MIN_RECONNECT_DELAY;
+        while !self.stopped() {
+            let connected = ClientBuilder::new(&self.url)
+                .map_err(|e| e.to_string())
+                .and_then(|mut builder| builder.connect(None).map_err(|e| e.to_string()));
+            match connected {
+                Ok(mut client) => {
+                    tracing::info!("websocket connected to {}", self.url);
+                    delay = MIN_RECONNECT_DELAY;
+                    let end = match self.serve(&mut client) {
+                        Ok(never) => match never {},
+                        Err(end) => end,
+                    };
+                    if let SessionEnd::Stopped = end {
+                        let _ = client.send_message(&OwnedMessage::Close(None));
+                        let _ = client.shutdown();
+                        return;
+                    }
+                    tracing::warn!("websocket connection lost: {}", end);
+                    let _ = client.shutdown();
+                }
+                Err(e) => tracing::warn!("websocket connect to {} failed: {}", self.url, e),
+            }
+            self.sleep(delay);
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    // Sleeps in poll-sized steps so a disconnect is not held up by backoff.
+    fn sleep(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        while !self.stopped() && Instant::now() < until {
+            thread::sleep(POLL_INTERVAL.min(until - Instant::now()));
+        }
+    }
+
+    // Runs until the connection ends or a disconnect is requested.
+    fn serve(&mut self, client: &mut SyncClient) -> std::result::Result<Infallible, SessionEnd> {
+        client
+            .stream_ref()
+            .as_tcp()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(WebSocketError::from)?;
+
+        for workflow_id in self.subscriptions.clone() {
+            send(client, &WsMessage::Subscribe { workflow_id })?;
+        }
+
+        let mut last_ping = Instant::now();
+        let mut awaiting_pong: Option<Instant> = None;
+        loop {
+            if self.stopped() {
+                return Err(SessionEnd::Stopped);
+            }
+
+            loop {
+                match self.queued.try_recv() {
+                    Ok(message) => {
+                        if let WsMessage::Subscribe { workflow_id } = &message {
+                            self.subscriptions.insert(workflow_id.clone());
+                        }
+                        send(client, &message)?;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // The client was dropped without disconnecting.
+                    Err(TryRecvError::Disconnected) => return Err(SessionEnd::Stopped),
+                }
+            }
+
+            if let Some(sent) = awaiting_pong {
+                if sent.elapsed() >= self.heartbeat.pong_timeout {
+                    return Err(SessionEnd::PongTimeout(self.heartbeat.pong_timeout));
+                }
+            }
+            if last_ping.elapsed() >= self.heartbeat.ping_interval {
+                send(client, &WsMessage::Ping)?;
+                last_ping = Instant::now();
+                awaiting_pong.get_or_insert(last_ping);
+            }
+
+            match client.recv_message() {
+                Ok(OwnedMessage::Text(text)) => match decode(&text) {
+                    Some(WsMessage::Ping) => send(client, &WsMessage::Pong)?,
+                    Some(WsMessage::Pong) => awaiting_pong = None,
+                    Some(WsMessage::ExecutionUpdate(update)) => self.handler.execution_update(update),
+                    Some(message) => {
+                        tracing::warn!("ignoring unexpected websocket message from server: {:?}", message)
+                    }
+                    None => {}
+                },
+                Ok(OwnedMessage::Ping(data)) => client.send_message(&OwnedMessage::Pong(data))?,
+                Ok(OwnedMessage::Close(_)) => return Err(SessionEnd::Closed),
+                Ok(_) => {}
+                Err(WebSocketError::IoError(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn send<S: Stream>(
+    client: &mut Client<S>,
+    message: &WsMessage,
+) -> std::result::Result<(), SessionEnd> {
+    let text = serde_json::to_string(message).expect("websocket messages serialize");
+    client.send_message(&OwnedMessage::Text(text))?;
+    Ok(())
+}
@@ -0,0 +1,204 @@
+/*!
+ * Windows - workflow windows, their per-window context and the tray window list
+ *
+ * Every window has a context recording the workflow it shows. Workflow
+ * windows are labeled by workflow id, so opening a workflow that already has
+ * a window focuses it instead of opening a second one.
+ *
+ * The tray menu also lists pinned workflows, which run when clicked. It is
+ * rebuilt whenever a window opens or closes and whenever the pins, or the
+ * workflows they point at, change.
+ */
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{
+    AppHandle, CustomMenuItem, Manager, SystemTrayMenu, SystemTrayMenuItem, SystemTraySubmenu,
+    Window, WindowBuilder, WindowUrl,
+};
+
+use crate::database::Database;
+use crate::{PinnedWorkflow, Workflow};
+
+pub const MAIN_WINDOW: &str = "main";
+// Tray menu ids for window list entries are this prefix plus the window label.
+pub const WINDOW_MENU_PREFIX: &str = "window:";
+// Tray menu ids for pinned workflows are this prefix plus the workflow id.
+pub const PINNED_MENU_PREFIX: &str = "run:";
+// Pinned workflows listed in the tray; the rest are counted in a final entry.
+pub const TRAY_PINNED_LIMIT: usize = 10;
+
+const WORKFLOW_WINDOW_PREFIX: &str = "workflow-";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WindowContext {
+    pub workflow_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowSummary {
+    pub label: String,
+    pub title: String,
+    pub workflow_id: Option<String>,
+    pub visible: bool,
+    pub focused: bool,
+}
+
+#[derive(Default)]
+pub struct WindowContexts {
+    contexts: HashMap<String, WindowContext>,
+}
+
+impl WindowContexts {
+    pub fn get(&self, label: &str) -> WindowContext {
+        self.contexts.get(label).cloned().unwrap_or_default()
+    }
+
+    pub fn set_workflow(&mut self, label: &str, workflow_id: Option<String>) {
+        self.contexts.entry(label.to_string()).or_default().workflow_id = workflow_id;
+    }
+
+    pub fn remove(&mut self, label: &str) {
+        self.contexts.remove(label);
+    }
+}
+
+pub fn workflow_window_label(workflow_id: &str) -> String {
+    format!("{}{}", WORKFLOW_WINDOW_PREFIX, workflow_id)
+}
+
+// The window tray and shortcut actions apply to: the focused window, else
+// the main window, else any open window.
+pub fn target_window(app: &AppHandle) -> Option<Window> {
+    let windows = app.windows();
+    windows
+        .values()
+        .find(|window| window.is_focused().unwrap_or(false))
+        .or_else(|| windows.get(MAIN_WINDOW))
+        .or_else(|| windows.values().next())
+        .cloned()
+}
+
+pub fn list_windows(app: &AppHandle, contexts: &WindowContexts) -> Vec<WindowSummary> {
+    let mut windows: Vec<WindowSummary> = app
+        .windows()
+        .into_iter()
+        .map(|(label, window)| WindowSummary {
+            title: window.title().unwrap_or_else(|_| label.clone()),
+            workflow_id: contexts.get(&label).workflow_id,
+            visible: window.is_visible().unwrap_or(false),
+            focused: window.is_focused().unwrap_or(false),
+            label,
+        })
+        .collect();
+    // Main first, then workflow windows by title.
+    windows.sort_by(|a, b| {
+        (a.label != MAIN_WINDOW, &a.title).cmp(&(b.label != MAIN_WINDOW, &b.title))
+    });
+    windows
+}
+
+// Pinned workflows replace the Create Workflow entry; without any it stays.
+pub fn tray_menu(windows: &[WindowSummary], pinned: &[PinnedWorkflow]) -> SystemTrayMenu {
+    let quit = CustomMenuItem::new("quit".to_string(), "Quit");
+    let hide = CustomMenuItem::new("hide".to_string(), "Hide");
+    let show = CustomMenuItem::new("show".to_string(), "Show");
+    let create_workflow = CustomMenuItem::new("create_workflow".to_string(), "Create Workflow");
+
+    let mut menu = SystemTrayMenu::new().add_item(show).add_item(hide);
+    if windows.len() > 1 {
+        let list = windows.iter().fold(SystemTrayMenu::new(), |list, window| {
+            list.add_item(CustomMenuItem::new(
+                format!("{}{}", WINDOW_MENU_PREFIX, window.label),
+                window.title.clone(),
+            ))
+        });
+        menu = menu.add_submenu(SystemTraySubmenu::new("Windows", list));
+    }
+    menu = menu.add_native_item(SystemTrayMenuItem::Separator);
+    if pinned.is_empty() {
+        menu = menu.add_item(create_workflow);
+    } else {
+        let mut list = pinned.iter().take(TRAY_PINNED_LIMIT).fold(
+            SystemTrayMenu::new(),
+            |list, workflow| {
+                list.add_item(CustomMenuItem::new(
+                    format!("{}{}", PINNED_MENU_PREFIX, workflow.workflow_id),
+                    workflow.name.clone(),
+                ))
+            },
+        );
+        if pinned.len() > TRAY_PINNED_LIMIT {
+            let more = pinned.len() - TRAY_PINNED_LIMIT;
+            list = list.add_item(
+                CustomMenuItem::new("pinned_more".to_string(), format!("{} more...", more))
+                    .disabled(),
+            );
+        }
+        menu = menu.add_submenu(SystemTraySubmenu::new("Run Pinned Workflow", list));
+    }
+    menu.add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(quit)
+}
+
+// Rebuilds the tray menu. A window being destroyed may still be listed by the
+// app, so it is left out explicitly. If the pins cannot be read the menu is
+// built without them.
+pub fn refresh_tray(app: &AppHandle, closing: Option<&str>) {
+    let contexts = app.state::<Arc<Mutex<WindowContexts>>>();
+    let mut windows = list_windows(app, &contexts.lock());
+    windows.retain(|window| Some(window.label.as_str()) != closing);
+    let pinned = match app.try_state::<Arc<Mutex<Database>>>() {
+        Some(db) => {
+            let readers = db.lock().readers();
+            readers.get_pinned_workflows().unwrap_or_else(|e| {
+                tracing::warn!("failed to load pinned workflows for the tray: {}", e);
+                Vec::new()
+            })
+        }
+        None => Vec::new(),
+    };
+    if let Err(e) = app.tray_handle().set_menu(tray_menu(&windows, &pinned)) {
+        tracing::warn!("failed to update tray menu: {}", e);
+    }
+}
+
+pub fn show_window(window: &Window) -> tauri::Result<()> {
+    window.unminimize()?;
+    window.show()?;
+    window.set_focus()
+}
+
+// Focuses the workflow's window, opening it first if needed.
+pub fn open_workflow_window(
+    app: &AppHandle,
+    workflow: &Workflow,
+    contexts: &Mutex<WindowContexts>,
+) -> tauri::Result<Window> {
+    let label = workflow_window_label(&workflow.id);
+    if let Some(window) = app.get_window(&label) {
+        show_window(&window)?;
+        return Ok(window);
+    }
+
+    // Set before the window loads so its first context query sees the workflow.
+    contexts.lock().set_workflow(&label, Some(workflow.id.clone()));
+    let built = WindowBuilder::new(app, label.clone(), WindowUrl::App("index.html".into()))
+        .title(&workflow.name)
+        .inner_size(1400.0, 900.0)
+        .min_inner_size(800.0, 600.0)
+        .center()
+        .build();
+    match built {
+        Ok(window) => {
+            refresh_tray(app, None);
+            Ok(window)
+        }
+        Err(e) => {
+            contexts.lock().remove(&label);
+            Err(e)
+        }
+    }
+}
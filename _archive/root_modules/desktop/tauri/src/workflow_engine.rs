@@ -0,0 +1,2705 @@
+/*!
+ * Workflow Engine - runs workflow graphs and tracks active executions
+ */
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{broadcast, oneshot, Notify};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::database::{Database, DatabaseError, ReadPool};
+use crate::debugger::ContextSnapshot;
+use crate::environments;
+use crate::events::{self, EventBus, ExecutionEvent, ExecutionProgress};
+use crate::hooks::{HookChain, MetricsHook, NodeMetrics};
+use crate::execution_log::{self, LogEntry, LogLevel};
+use crate::nodes::manual_input::PendingInputs;
+use crate::notifications::{self, Notification, NotificationChannel};
+use crate::nodes::{call_workflow, merge};
+use crate::node_cache;
+use crate::recovery::InterruptedExecution;
+use crate::nodes::{
+    self, ExecutionContext, ExecutionControl, NodeError, NodeExecutor, NodeRegistry, NodeTypeInfo,
+    PortType, WorkflowRunner, DEFAULT_PORT,
+};
+use crate::rate_limit::{self, RateLimiter};
+use crate::scheduler::{Priority, WorkerPool};
+use crate::schema::FieldError;
+use crate::secrets::{self, SecretValues};
+use crate::resources::{CpuTimeSampler, ResourceMonitor};
+use crate::run_blobs;
+use crate::sink::{SinkRecord, SinkWriter};
+use crate::template::{self, Resolution};
+use crate::workflow_state::StateStore;
+use crate::{Execution, ExecutionStatus, FailedExecution, Workflow, WorkflowNode};
+
+// How often a run held for memory pressure re-checks free memory.
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// A node preview still running after this is stopped.
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum EngineError {
+    #[error("workflow {workflow_id} is already running (execution {execution_id})")]
+    AlreadyRunning {
+        workflow_id: String,
+        execution_id: String,
+    },
+    #[error("workflow {workflow_id} already has {depth} runs queued; try again once one finishes")]
+    QueueFull { workflow_id: String, depth: usize },
+    #[error("execution not found: {0}")]
+    ExecutionNotFound(String),
+    #[error("workflow graph contains a cycle")]
+    CycleDetected,
+    #[error("node not found in workflow: {0}")]
+    NodeNotFound(String),
+    #[error("edge not found in workflow: {0}")]
+    EdgeNotFound(String),
+    #[error("running from node {node_id} needs seeded outputs for: {}", .missing.join(", "))]
+    MissingSeed {
+        node_id: String,
+        missing: Vec<String>,
+    },
+    #[error("execution cancelled")]
+    Cancelled,
+    #[error("unknown execution profile: {0}")]
+    UnknownProfile(String),
+    #[error("the engine is shutting down")]
+    ShuttingDown,
+    #[error("execution {execution_id} is not waiting for input on node {node_id}")]
+    NotAwaitingInput {
+        execution_id: String,
+        node_id: String,
+    },
+    #[error("execution {execution_id} is not running node {node_id}")]
+    NodeNotRunning {
+        execution_id: String,
+        node_id: String,
+    },
+    #[error("preview of node {node_id} did not finish within {timeout_ms} ms")]
+    PreviewTimedOut { node_id: String, timeout_ms: u64 },
+    #[error("node {node_id} failed: {source}")]
+    NodeFailed {
+        node_id: String,
+        #[source]
+        source: NodeError,
+    },
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+}
+
+pub type Result<T> = std::result::Result<T, EngineError>;
+
+// Runs a workflow may have waiting under `ConcurrencyPolicy::Queue`.
+pub const DEFAULT_MAX_QUEUE_DEPTH: usize = 10;
+
+// How long a start's idempotency key keeps later starts with it from running.
+pub const DEFAULT_IDEMPOTENCY_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+// Set in a node's `data` to skip it without rewiring the graph.
+pub const DISABLED_KEY: &str = "disabled";
+
+pub fn is_disabled(node: &WorkflowNode) -> bool {
+    node.data.get(DISABLED_KEY).and_then(serde_json::Value::as_bool) == Some(true)
+}
+
+// What to do when a workflow is started while a previous run is still active.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConcurrencyPolicy {
+    #[default]
+    Reject,
+    Queue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionOptions {
+    // Most nodes of a run executing at once
+    pub max_parallelism: usize,
+    pub capture_io: bool,
+    // Off keeps only warnings and errors in the run's log
+    pub capture_logs: bool,
+    pub breakpoints_enabled: bool,
+    pub dry_run_externals: bool,
+}
+
+impl Default for ExecutionOptions {
+    fn default() -> Self {
+        Self {
+            max_parallelism: 4,
+            capture_io: false,
+            capture_logs: true,
+            breakpoints_enabled: false,
+            dry_run_externals: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionProfile {
+    pub name: String,
+    pub description: String,
+    pub options: ExecutionOptions,
+}
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub fn execution_profiles() -> Vec<ExecutionProfile> {
+    vec![
+        ExecutionProfile {
+            name: DEFAULT_PROFILE.to_string(),
+            description: "Balanced settings for everyday runs".to_string(),
+            options: ExecutionOptions::default(),
+        },
+        ExecutionProfile {
+            name: "fast".to_string(),
+            description: "High parallelism, no input/output or log capture".to_string(),
+            options: ExecutionOptions {
+                max_parallelism: 16,
+                capture_io: false,
+                capture_logs: false,
+                breakpoints_enabled: false,
+                dry_run_externals: false,
+            },
+        },
+        ExecutionProfile {
+            name: "debug".to_string(),
+            description: "Sequential run capturing inputs, outputs and logs, with breakpoints".to_string(),
+            options: ExecutionOptions {
+                max_parallelism: 1,
+                capture_io: true,
+                capture_logs: true,
+                breakpoints_enabled: true,
+                dry_run_externals: false,
+            },
+        },
+        ExecutionProfile {
+            name: "safe".to_string(),
+            description: "External side effects are simulated instead of performed".to_string(),
+            options: ExecutionOptions {
+                max_parallelism: 4,
+                capture_io: true,
+                capture_logs: true,
+                breakpoints_enabled: false,
+                dry_run_externals: true,
+            },
+        },
+    ]
+}
+
+pub fn find_profile(name: &str) -> Result<ExecutionProfile> {
+    execution_profiles()
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| EngineError::UnknownProfile(name.to_string()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveExecution {
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub profile: String,
+    pub priority: Priority,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StopFailure {
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StopAllSummary {
+    // Active runs that were stopped and have recorded their final status
+    pub stopped: usize,
+    // Queued runs dropped before they started
+    pub dropped_queued: usize,
+    pub failed: Vec<StopFailure>,
+}
+
+struct RunningExecution {
+    info: ActiveExecution,
+    control: ExecutionControl,
+}
+
+// A node executing right now, in a top-level or nested run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningNode {
+    pub execution_id: String,
+    pub workflow_id: String,
+    pub node_id: String,
+    pub node_type: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub elapsed_ms: u64,
+}
+
+struct NodeRun {
+    info: RunningNode,
+    started: std::time::Instant,
+    // Cancelled by `kill_node`
+    kill: CancellationToken,
+}
+
+// Lists the node as running until dropped, which also happens when its run's
+// future is dropped mid-node, as a killed `call_workflow` node's nested run is.
+struct NodeRunGuard<'a> {
+    shared: &'a EngineShared,
+    key: (String, String),
+}
+
+impl Drop for NodeRunGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.running_nodes.lock().remove(&self.key);
+    }
+}
+
+// Values a partial run starts from: the run input, and the outputs of the
+// nodes feeding its sub-graph, keyed by node id.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedContext {
+    pub input: serde_json::Value,
+    pub node_outputs: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodePreview {
+    pub output: serde_json::Value,
+    pub duration_ms: u64,
+    pub logs: Vec<LogEntry>,
+}
+
+// Stands in for a run's context when previewing templates: what the `input`,
+// `nodes` and `vars` scopes hold.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateSample {
+    #[serde(default)]
+    pub input: serde_json::Value,
+    #[serde(default)]
+    pub nodes: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub vars: HashMap<String, serde_json::Value>,
+}
+
+// What a node would receive along one edge, from `simulate_edge`. Ports are
+// named by the edge's handles; their types are None when the node declares
+// no ports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgeSimulation {
+    pub edge_id: String,
+    pub source: String,
+    pub target: String,
+    pub source_port: String,
+    pub source_type: Option<PortType>,
+    pub target_port: String,
+    pub target_type: Option<PortType>,
+    pub input: serde_json::Value,
+    // Whether the wiring and `input` match what the target declares
+    pub satisfied: bool,
+    pub problems: Vec<String>,
+}
+
+// Restricts a run to the sub-graph downstream of `from_node`.
+struct PartialRun {
+    from_node: String,
+    nodes: HashSet<String>,
+    seeded: HashMap<String, serde_json::Value>,
+}
+
+// Receives the run's terminal output (see `terminal_output`) once it finishes.
+type RunReply = oneshot::Sender<Result<serde_json::Value>>;
+
+// One run, from being requested (and possibly queued) until it finishes.
+struct RunRequest {
+    execution_id: String,
+    workflow: Workflow,
+    profile: ExecutionProfile,
+    input: serde_json::Value,
+    reply: Option<RunReply>,
+    priority: Priority,
+    // Dead-letter entry this run replays
+    replay_of: Option<String>,
+    partial: Option<PartialRun>,
+    idempotency_key: Option<String>,
+}
+
+impl RunRequest {
+    fn new(workflow: &Workflow, profile: &ExecutionProfile, input: serde_json::Value) -> Self {
+        Self {
+            execution_id: Uuid::new_v4().to_string(),
+            workflow: workflow.clone(),
+            profile: profile.clone(),
+            input,
+            reply: None,
+            priority: Priority::default(),
+            replay_of: None,
+            partial: None,
+            idempotency_key: None,
+        }
+    }
+}
+
+struct EngineShared {
+    db: Arc<Mutex<Database>>,
+    registry: NodeRegistry,
+    hooks: HookChain,
+    metrics: Arc<MetricsHook>,
+    events: EventBus,
+    // Keyed by workflow id: at most one running execution per workflow.
+    active: Mutex<HashMap<String, RunningExecution>>,
+    // Keyed by execution id and node id
+    running_nodes: Mutex<HashMap<(String, String), NodeRun>>,
+    queued: Mutex<HashMap<String, VecDeque<RunRequest>>>,
+    resources: Arc<ResourceMonitor>,
+    rate_limiter: Arc<RateLimiter>,
+    workers: Arc<WorkerPool>,
+    // Key secrets are decrypted with; runs get no secrets until it is set.
+    secret_key: Mutex<Option<String>>,
+    pending_inputs: PendingInputs,
+    // Whether desktop notification rules fire; other channels always do
+    desktop_notifications: AtomicBool,
+    shutting_down: AtomicBool,
+    // Notified whenever the last active execution finishes.
+    idle: Notify,
+    // Notified whenever any execution leaves the active set.
+    finished: Notify,
+}
+
+pub struct WorkflowEngine {
+    shared: Arc<EngineShared>,
+    concurrency_policy: ConcurrencyPolicy,
+    // Per workflow; starts beyond it are rejected rather than queued
+    max_queue_depth: usize,
+    idempotency_window: Duration,
+}
+
+impl WorkflowEngine {
+    pub fn new(db: Arc<Mutex<Database>>) -> Self {
+        Self::with_registry(db, NodeRegistry::with_builtin())
+    }
+
+    pub fn with_registry(db: Arc<Mutex<Database>>, registry: NodeRegistry) -> Self {
+        let hooks = HookChain::default();
+        let metrics = Arc::new(MetricsHook::default());
+        hooks.register(metrics.clone());
+        Self {
+            shared: Arc::new(EngineShared {
+                db,
+                registry,
+                hooks,
+                metrics,
+                events: EventBus::default(),
+                active: Mutex::new(HashMap::new()),
+                running_nodes: Mutex::new(HashMap::new()),
+                queued: Mutex::new(HashMap::new()),
+                resources: Arc::new(ResourceMonitor::default()),
+                rate_limiter: Arc::new(RateLimiter::default()),
+                workers: Arc::new(WorkerPool::default()),
+                secret_key: Mutex::new(None),
+                pending_inputs: PendingInputs::default(),
+                desktop_notifications: AtomicBool::new(true),
+                shutting_down: AtomicBool::new(false),
+                idle: Notify::new(),
+                finished: Notify::new(),
+            }),
+            concurrency_policy: ConcurrencyPolicy::default(),
+            max_queue_depth: DEFAULT_MAX_QUEUE_DEPTH,
+            idempotency_window: Duration::from_secs(DEFAULT_IDEMPOTENCY_WINDOW_SECS),
+        }
+    }
+
+    pub fn node_types(&self) -> Vec<NodeTypeInfo> {
+        self.shared.registry.node_types()
+    }
+
+    pub fn registry(&self) -> &NodeRegistry {
+        &self.shared.registry
+    }
+
+    // Hooks run around every node of every run, after the built-in metrics
+    // hook.
+    pub fn hooks(&self) -> &HookChain {
+        &self.shared.hooks
+    }
+
+    pub fn node_metrics(&self) -> Vec<NodeMetrics> {
+        self.shared.metrics.snapshot()
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ExecutionEvent> {
+        self.shared.events.subscribe()
+    }
+
+    // For events about a run raised outside the engine, such as by a
+    // deadline schedule.
+    pub fn emit_event(&self, event: ExecutionEvent) {
+        self.shared.events.emit(event);
+    }
+
+    // Events already emitted for a running or recently finished execution.
+    pub fn event_replay(&self, execution_id: &str) -> Option<Vec<ExecutionEvent>> {
+        self.shared.events.replay(execution_id)
+    }
+
+    pub fn set_concurrency_policy(&mut self, policy: ConcurrencyPolicy) {
+        self.concurrency_policy = policy;
+    }
+
+    pub fn set_max_queue_depth(&mut self, depth: usize) {
+        self.max_queue_depth = depth;
+    }
+
+    pub fn set_idempotency_window(&mut self, window: Duration) {
+        self.idempotency_window = window;
+    }
+
+    pub fn set_desktop_notifications(&self, enabled: bool) {
+        self.shared
+            .desktop_notifications
+            .store(enabled, Ordering::SeqCst);
+    }
+
+    // Runs of the workflow waiting for the active one to finish.
+    pub fn queue_depth(&self, workflow_id: &str) -> usize {
+        self.shared
+            .queued
+            .lock()
+            .get(workflow_id)
+            .map_or(0, VecDeque::len)
+    }
+
+    pub fn resource_monitor(&self) -> Arc<ResourceMonitor> {
+        self.shared.resources.clone()
+    }
+
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.shared.rate_limiter.clone()
+    }
+
+    pub fn worker_pool(&self) -> Arc<WorkerPool> {
+        self.shared.workers.clone()
+    }
+
+    pub fn set_secret_key(&self, key: &str) {
+        *self.shared.secret_key.lock() = Some(key.to_string());
+    }
+
+    // A start with the `idempotency_key` of an earlier start of the workflow,
+    // made within the idempotency window, returns that start's execution id
+    // instead of running again.
+    pub fn execute_workflow(
+        &self,
+        workflow: &Workflow,
+        profile: &ExecutionProfile,
+        priority: Priority,
+        idempotency_key: Option<&str>,
+    ) -> Result<String> {
+        self.start(RunRequest {
+            priority,
+            idempotency_key: idempotency_key.map(str::to_string),
+            ..RunRequest::new(workflow, profile, serde_json::Value::Null)
+        })
+    }
+
+    // Starts a run with `input` as the trigger payload. The receiver yields the
+    // terminal output when the run finishes, or errors if the run is dropped
+    // from the queue before starting. It is None when `idempotency_key`
+    // matched an earlier start, whose execution id is returned instead.
+    pub fn execute_workflow_with_input(
+        &self,
+        workflow: &Workflow,
+        profile: &ExecutionProfile,
+        input: serde_json::Value,
+        idempotency_key: Option<&str>,
+    ) -> Result<(String, Option<oneshot::Receiver<Result<serde_json::Value>>>)> {
+        let (reply, output) = oneshot::channel();
+        let run = RunRequest {
+            reply: Some(reply),
+            idempotency_key: idempotency_key.map(str::to_string),
+            ..RunRequest::new(workflow, profile, input)
+        };
+        let new_execution_id = run.execution_id.clone();
+        let execution_id = self.start(run)?;
+        let output = (execution_id == new_execution_id).then_some(output);
+        Ok((execution_id, output))
+    }
+
+    // Re-runs a dead-letter entry with its preserved input. The entry is
+    // resolved if the run succeeds and updated with the new error otherwise.
+    pub fn replay_failed_execution(
+        &self,
+        workflow: &Workflow,
+        profile: &ExecutionProfile,
+        failed: &FailedExecution,
+    ) -> Result<String> {
+        self.start(RunRequest {
+            replay_of: Some(failed.id.clone()),
+            ..RunRequest::new(workflow, profile, failed.input.clone())
+        })
+    }
+
+    // Starts a fresh run with the input of one cut short by the app exiting.
+    pub fn restart_interrupted(
+        &self,
+        workflow: &Workflow,
+        profile: &ExecutionProfile,
+        interrupted: &InterruptedExecution,
+    ) -> Result<String> {
+        self.start(RunRequest::new(workflow, profile, interrupted.input.clone()))
+    }
+
+    // Runs only `node_id` and the nodes downstream of it. Nodes feeding that
+    // sub-graph, by edge or by `nodes.<id>` template reference, are not run;
+    // `seed` must hold the output of every one of them.
+    pub fn execute_from_node(
+        &self,
+        workflow: &Workflow,
+        profile: &ExecutionProfile,
+        node_id: &str,
+        seed: SeedContext,
+        priority: Priority,
+    ) -> Result<String> {
+        if !workflow.nodes.iter().any(|node| node.id == node_id) {
+            return Err(EngineError::NodeNotFound(node_id.to_string()));
+        }
+        let nodes = downstream_nodes(workflow, node_id);
+        let missing: Vec<String> = subgraph_dependencies(workflow, &nodes)
+            .into_iter()
+            .filter(|id| !seed.node_outputs.contains_key(id))
+            .collect();
+        if !missing.is_empty() {
+            return Err(EngineError::MissingSeed {
+                node_id: node_id.to_string(),
+                missing,
+            });
+        }
+
+        self.start(RunRequest {
+            partial: Some(PartialRun {
+                from_node: node_id.to_string(),
+                nodes,
+                seeded: seed.node_outputs,
+            }),
+            priority,
+            ..RunRequest::new(workflow, profile, seed.input)
+        })
+    }
+
+    // Runs one node on its own, with `input` as both the run input and the
+    // node's input. Nothing is recorded, cached or broadcast, and templates
+    // see the active environment but no other node outputs or secrets. With
+    // `dry_run`, side-effecting executors return a mock instead of acting.
+    pub fn preview_node(
+        &self,
+        node: &WorkflowNode,
+        input: serde_json::Value,
+        dry_run: bool,
+    ) -> impl Future<Output = Result<NodePreview>> + Send + 'static {
+        let shared = self.shared.clone();
+        let node = node.clone();
+        async move {
+            if shared.shutting_down.load(Ordering::SeqCst) {
+                return Err(EngineError::ShuttingDown);
+            }
+            let node_failed = |source| EngineError::NodeFailed {
+                node_id: node.id.clone(),
+                source,
+            };
+            let executor = shared.registry.runnable(&node.node_type).map_err(node_failed)?;
+
+            let mut ctx = ExecutionContext::new(
+                &format!("preview-{}", Uuid::new_v4()),
+                "",
+                input.clone(),
+            );
+            let _blobs = ctx.blobs.cleanup_guard(&ctx.execution_id);
+            ctx.dry_run = dry_run;
+            ctx.current_node = Some(node.id.clone());
+            ctx.environment = environments::active_variables(&shared.db.lock())
+                .unwrap_or_else(|e| {
+                    tracing::warn!("failed to load environment for preview: {}", e);
+                    Default::default()
+                });
+
+            let started = std::time::Instant::now();
+            let output = tokio::time::timeout(PREVIEW_TIMEOUT, executor.execute(&node, input, &ctx))
+                .await
+                .map_err(|_| EngineError::PreviewTimedOut {
+                    node_id: node.id.clone(),
+                    timeout_ms: PREVIEW_TIMEOUT.as_millis() as u64,
+                })?;
+            let output = match output {
+                Ok(output) => output,
+                Err(NodeError::Cancelled) => return Err(EngineError::Cancelled),
+                Err(source) => return Err(node_failed(source)),
+            };
+            Ok(NodePreview {
+                output,
+                duration_ms: started.elapsed().as_millis() as u64,
+                logs: ctx.logs.entries(),
+            })
+        }
+    }
+
+    // How each node's templates would resolve against `sample`, with secrets
+    // and the active environment loaded as for a run and secret values
+    // masked. Nothing runs. Keyed by node id, then reference; nodes without
+    // templates are left out.
+    pub fn resolve_templates(
+        &self,
+        workflow: &Workflow,
+        sample: TemplateSample,
+    ) -> impl Future<Output = BTreeMap<String, BTreeMap<String, Resolution>>> + Send + 'static {
+        let shared = self.shared.clone();
+        let workflow = workflow.clone();
+        async move {
+            let mut ctx = ExecutionContext::new("", &workflow.id, sample.input);
+            ctx.node_outputs = sample.nodes;
+            ctx.variables = sample.vars;
+            // Secret references then show as unresolved, as they would in a run.
+            match resolve_secrets(&shared, &workflow).await {
+                Ok(values) => ctx.secrets = values,
+                Err(e) => tracing::warn!("failed to resolve secrets for {}: {}", workflow.id, e),
+            }
+            ctx.environment = environments::active_variables(&shared.db.lock())
+                .unwrap_or_else(|e| {
+                    tracing::warn!("failed to load environment for {}: {}", workflow.id, e);
+                    Default::default()
+                });
+
+            let scope = ctx.template_scope();
+            let mask = |resolution| match resolution {
+                Resolution::Resolved { value } => Resolution::Resolved {
+                    value: execution_log::mask_values(value, ctx.secrets.values()),
+                },
+                other => other,
+            };
+            workflow
+                .nodes
+                .iter()
+                .filter_map(|node| {
+                    let resolved: BTreeMap<String, Resolution> =
+                        template::resolve_references(&node.data, &scope)
+                            .into_iter()
+                            .map(|(reference, resolution)| (reference, mask(resolution)))
+                            .collect();
+                    (!resolved.is_empty()).then(|| (node.id.clone(), resolved))
+                })
+                .collect()
+        }
+    }
+
+    // Keys are checked and recorded while the caller holds the engine, so two
+    // starts with the same key cannot both run.
+    fn start(&self, run: RunRequest) -> Result<String> {
+        let Some(key) = run.idempotency_key.clone() else {
+            return self.start_run(run);
+        };
+        let workflow_id = run.workflow.id.clone();
+        let existing = self.shared.db.lock().get_idempotent_execution(&workflow_id, &key)?;
+        if let Some(execution_id) = existing {
+            tracing::info!(
+                "idempotency key {} of workflow {} already started execution {}",
+                key,
+                workflow_id,
+                execution_id
+            );
+            return Ok(execution_id);
+        }
+
+        let execution_id = self.start_run(run)?;
+        let window = chrono::Duration::from_std(self.idempotency_window)
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        let recorded = self.shared.db.lock().put_idempotency_key(
+            &workflow_id,
+            &key,
+            &execution_id,
+            chrono::Utc::now() + window,
+        );
+        // The run has started either way; a retry may just run it again.
+        if let Err(e) = recorded {
+            tracing::warn!("failed to record idempotency key of execution {}: {}", execution_id, e);
+        }
+        Ok(execution_id)
+    }
+
+    fn start_run(&self, run: RunRequest) -> Result<String> {
+        if self.shared.shutting_down.load(Ordering::SeqCst) {
+            return Err(EngineError::ShuttingDown);
+        }
+        let execution_id = run.execution_id.clone();
+        let workflow = &run.workflow;
+
+        let mut active = self.shared.active.lock();
+        if let Some(existing) = active.get(&workflow.id) {
+            match self.concurrency_policy {
+                ConcurrencyPolicy::Reject => {
+                    return Err(EngineError::AlreadyRunning {
+                        workflow_id: workflow.id.clone(),
+                        execution_id: existing.info.execution_id.clone(),
+                    });
+                }
+                ConcurrencyPolicy::Queue => {
+                    let mut queued = self.shared.queued.lock();
+                    let pending = queued.entry(workflow.id.clone()).or_default();
+                    if pending.len() >= self.max_queue_depth {
+                        return Err(EngineError::QueueFull {
+                            workflow_id: workflow.id.clone(),
+                            depth: pending.len(),
+                        });
+                    }
+                    pending.push_back(run);
+                    return Ok(execution_id);
+                }
+            }
+        }
+
+        let control = ExecutionControl::default();
+        active.insert(
+            workflow.id.clone(),
+            RunningExecution {
+                info: ActiveExecution {
+                    execution_id: execution_id.clone(),
+                    workflow_id: workflow.id.clone(),
+                    profile: run.profile.name.clone(),
+                    priority: run.priority,
+                    started_at: chrono::Utc::now(),
+                },
+                control: control.clone(),
+            },
+        );
+        drop(active);
+
+        spawn_execution(self.shared.clone(), run, control);
+        Ok(execution_id)
+    }
+
+    // A user stop is recorded so that, should the app exit before the run
+    // records its final status, startup recovery cancels it instead of
+    // resuming it.
+    fn record_stop_request(&self, execution_id: &str) {
+        if let Err(e) = self.shared.db.lock().mark_stop_requested(execution_id) {
+            tracing::warn!("failed to record stop of execution {}: {}", execution_id, e);
+        }
+    }
+
+    // A nested run's id resolves to the run it belongs to, whose controls it
+    // shares.
+    fn control_for(&self, execution_id: &str) -> Option<ExecutionControl> {
+        let execution_id = events::root_execution_id(execution_id);
+        self.shared
+            .active
+            .lock()
+            .values()
+            .find(|running| running.info.execution_id == execution_id)
+            .map(|running| running.control.clone())
+    }
+
+    pub fn stop_execution(&self, execution_id: &str) -> Result<()> {
+        if let Some(control) = self.control_for(execution_id) {
+            control.cancel();
+            self.record_stop_request(execution_id);
+            return Ok(());
+        }
+
+        let mut queued = self.shared.queued.lock();
+        for pending in queued.values_mut() {
+            if let Some(index) = pending.iter().position(|q| q.execution_id == execution_id) {
+                pending.remove(index);
+                return Ok(());
+            }
+        }
+
+        Err(EngineError::ExecutionNotFound(execution_id.to_string()))
+    }
+
+    pub fn pause_execution(&self, execution_id: &str) -> Result<()> {
+        let control = self
+            .control_for(execution_id)
+            .ok_or_else(|| EngineError::ExecutionNotFound(execution_id.to_string()))?;
+        if control.pause() {
+            self.shared.events.emit(ExecutionEvent::ExecutionPaused {
+                execution_id: execution_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn resume_execution(&self, execution_id: &str) -> Result<()> {
+        let control = self
+            .control_for(execution_id)
+            .ok_or_else(|| EngineError::ExecutionNotFound(execution_id.to_string()))?;
+        if control.resume() {
+            self.shared.events.emit(ExecutionEvent::ExecutionResumed {
+                execution_id: execution_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // Runs the next node of a run held at a breakpoint, then holds again.
+    // Returns false, doing nothing, if the run is not held.
+    pub fn debug_step(&self, execution_id: &str) -> Result<bool> {
+        let control = self
+            .control_for(execution_id)
+            .ok_or_else(|| EngineError::ExecutionNotFound(execution_id.to_string()))?;
+        Ok(control.debugger().step())
+    }
+
+    // Lets a run held at a breakpoint run on to the next one. Returns false,
+    // doing nothing, if the run is not held.
+    pub fn debug_continue(&self, execution_id: &str) -> Result<bool> {
+        let control = self
+            .control_for(execution_id)
+            .ok_or_else(|| EngineError::ExecutionNotFound(execution_id.to_string()))?;
+        Ok(control.debugger().resume())
+    }
+
+    // The context of a run held at a breakpoint; None while it is running.
+    pub fn execution_context(&self, execution_id: &str) -> Result<Option<ContextSnapshot>> {
+        let control = self
+            .control_for(execution_id)
+            .ok_or_else(|| EngineError::ExecutionNotFound(execution_id.to_string()))?;
+        Ok(control.debugger().held())
+    }
+
+    // Delivers values to a waiting manual input node. The receiver yields the
+    // node's schema errors, empty if the values were accepted.
+    pub fn provide_input(
+        &self,
+        execution_id: &str,
+        node_id: &str,
+        values: serde_json::Value,
+    ) -> Result<oneshot::Receiver<Vec<FieldError>>> {
+        self.shared
+            .pending_inputs
+            .provide(execution_id, node_id, values)
+            .ok_or_else(|| EngineError::NotAwaitingInput {
+                execution_id: execution_id.to_string(),
+                node_id: node_id.to_string(),
+            })
+    }
+
+    pub fn is_workflow_running(&self, workflow_id: &str) -> bool {
+        self.shared.active.lock().contains_key(workflow_id)
+    }
+
+    // Across every active run, nested runs included, longest running first.
+    pub fn list_running_nodes(&self) -> Vec<RunningNode> {
+        let mut nodes: Vec<RunningNode> = self
+            .shared
+            .running_nodes
+            .lock()
+            .values()
+            .map(|run| RunningNode {
+                elapsed_ms: run.started.elapsed().as_millis() as u64,
+                ..run.info.clone()
+            })
+            .collect();
+        nodes.sort_by_key(|node| Reverse(node.elapsed_ms));
+        nodes
+    }
+
+    // Aborts the node as if it had failed with `NodeError::Killed`: its
+    // future is dropped, giving back its worker, and the failure is recorded
+    // and dead-lettered like any other. Unlike `stop_execution`, the run is
+    // not cancelled: the nodes downstream of the killed one are skipped while
+    // independent branches carry on, then the run fails at the node and can
+    // be replayed. A node in a nested run fails its `call_workflow` node in
+    // turn.
+    pub fn kill_node(&self, execution_id: &str, node_id: &str) -> Result<()> {
+        let running = self.shared.running_nodes.lock();
+        let run = running
+            .get(&(execution_id.to_string(), node_id.to_string()))
+            .ok_or_else(|| EngineError::NodeNotRunning {
+                execution_id: execution_id.to_string(),
+                node_id: node_id.to_string(),
+            })?;
+        run.kill.cancel();
+        Ok(())
+    }
+
+    pub fn list_active_executions(&self) -> Vec<ActiveExecution> {
+        let mut executions: Vec<ActiveExecution> = self
+            .shared
+            .active
+            .lock()
+            .values()
+            .map(|running| running.info.clone())
+            .collect();
+        executions.sort_by_key(|execution| execution.started_at);
+        executions
+    }
+
+    // Drops queued runs and stops every active run, waiting up to `timeout`
+    // for them to record their final status; runs still going by then are
+    // reported as failed. Runs already being stopped are left to whoever
+    // stopped them, so concurrent calls never count a run twice. Like
+    // `shutdown`, the returned future does not borrow the engine.
+    pub fn stop_all_executions(
+        &self,
+        timeout: Duration,
+    ) -> impl Future<Output = StopAllSummary> + Send + 'static {
+        // Queued runs go first, under the active lock, so none is started in
+        // place of a run stopped here.
+        let (stopping, dropped_queued) = {
+            let active = self.shared.active.lock();
+            let dropped_queued = {
+                let mut queued = self.shared.queued.lock();
+                let count = queued.values().map(VecDeque::len).sum::<usize>();
+                queued.clear();
+                count
+            };
+            let stopping: Vec<ActiveExecution> = active
+                .values()
+                .filter(|running| !running.control.is_cancelled())
+                .map(|running| {
+                    running.control.cancel();
+                    running.info.clone()
+                })
+                .collect();
+            (stopping, dropped_queued)
+        };
+        for execution in &stopping {
+            self.record_stop_request(&execution.execution_id);
+        }
+
+        let shared = self.shared.clone();
+        async move {
+            let still_active = |stopping: &[ActiveExecution]| -> Vec<ActiveExecution> {
+                let active = shared.active.lock();
+                stopping
+                    .iter()
+                    .filter(|execution| {
+                        active.get(&execution.workflow_id).is_some_and(|running| {
+                            running.info.execution_id == execution.execution_id
+                        })
+                    })
+                    .cloned()
+                    .collect()
+            };
+            let drained = async {
+                loop {
+                    let finished = shared.finished.notified();
+                    if still_active(&stopping).is_empty() {
+                        break;
+                    }
+                    finished.await;
+                }
+            };
+            let _ = tokio::time::timeout(timeout, drained).await;
+
+            let failed: Vec<StopFailure> = still_active(&stopping)
+                .into_iter()
+                .map(|execution| StopFailure {
+                    execution_id: execution.execution_id,
+                    workflow_id: execution.workflow_id,
+                    error: format!("still running {:?} after being stopped", timeout),
+                })
+                .collect();
+            StopAllSummary {
+                stopped: stopping.len() - failed.len(),
+                dropped_queued,
+                failed,
+            }
+        }
+    }
+
+    // Pauses every active run that is not paused yet and returns their ids.
+    // Runs hold before their next node until resumed.
+    pub fn pause_all_executions(&self) -> Vec<String> {
+        let active = self.shared.active.lock();
+        let paused: Vec<String> = active
+            .values()
+            .filter(|running| running.control.pause())
+            .map(|running| running.info.execution_id.clone())
+            .collect();
+        drop(active);
+
+        for execution_id in &paused {
+            self.shared.events.emit(ExecutionEvent::ExecutionPaused {
+                execution_id: execution_id.clone(),
+            });
+        }
+        paused
+    }
+
+    // Resumes every paused run and returns their ids.
+    pub fn resume_all_executions(&self) -> Vec<String> {
+        let active = self.shared.active.lock();
+        let resumed: Vec<String> = active
+            .values()
+            .filter(|running| running.control.resume())
+            .map(|running| running.info.execution_id.clone())
+            .collect();
+        drop(active);
+
+        for execution_id in &resumed {
+            self.shared.events.emit(ExecutionEvent::ExecutionResumed {
+                execution_id: execution_id.clone(),
+            });
+        }
+        resumed
+    }
+
+    // Refuses new runs, drops queued ones and stops every active run. The
+    // returned future resolves once each stopped run has recorded its final
+    // status, or when `timeout` elapses; it yields false on timeout. It does
+    // not borrow the engine, so the engine lock need not be held while waiting.
+    pub fn shutdown(&self, timeout: Duration) -> impl Future<Output = bool> + Send + 'static {
+        self.shared.shutting_down.store(true, Ordering::SeqCst);
+        self.shared.queued.lock().clear();
+        for running in self.shared.active.lock().values() {
+            running.control.cancel();
+        }
+
+        let shared = self.shared.clone();
+        async move {
+            let drained = async {
+                loop {
+                    let idle = shared.idle.notified();
+                    if shared.active.lock().is_empty() {
+                        break;
+                    }
+                    idle.await;
+                }
+            };
+            tokio::time::timeout(timeout, drained).await.is_ok()
+        }
+    }
+}
+
+// Removes the execution from the active set when the task finishes, however it
+// finishes (success, failure, cancellation or panic), then starts the next
+// queued run for the same workflow.
+struct ActiveGuard {
+    shared: Arc<EngineShared>,
+    workflow_id: String,
+    execution_id: String,
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        let mut active = self.shared.active.lock();
+        let owned = active
+            .get(&self.workflow_id)
+            .map(|running| running.info.execution_id == self.execution_id)
+            .unwrap_or(false);
+        if !owned {
+            return;
+        }
+        active.remove(&self.workflow_id);
+        self.shared.finished.notify_waiters();
+
+        let next = {
+            let mut queued = self.shared.queued.lock();
+            // Highest priority first, then in the order they were queued.
+            let next = queued.get_mut(&self.workflow_id).and_then(|pending| {
+                let index = (0..pending.len()).min_by_key(|&i| Reverse(pending[i].priority))?;
+                pending.remove(index)
+            });
+            if queued.get(&self.workflow_id).is_some_and(|p| p.is_empty()) {
+                queued.remove(&self.workflow_id);
+            }
+            next
+        };
+
+        if let Some(next) = next {
+            let control = ExecutionControl::default();
+            active.insert(
+                self.workflow_id.clone(),
+                RunningExecution {
+                    info: ActiveExecution {
+                        execution_id: next.execution_id.clone(),
+                        workflow_id: self.workflow_id.clone(),
+                        profile: next.profile.name.clone(),
+                        priority: next.priority,
+                        started_at: chrono::Utc::now(),
+                    },
+                    control: control.clone(),
+                },
+            );
+            drop(active);
+            spawn_execution(self.shared.clone(), next, control);
+        } else if active.is_empty() {
+            self.shared.idle.notify_waiters();
+        }
+    }
+}
+
+// Decrypts only the secrets the workflow references, directly or through
+// `env`; key derivation is slow, so it runs off the async workers.
+async fn resolve_secrets(
+    shared: &EngineShared,
+    workflow: &Workflow,
+) -> std::result::Result<SecretValues, String> {
+    let mut names = secrets::referenced_names(workflow);
+    names.extend(environments::referenced_names(workflow));
+    if names.is_empty() {
+        return Ok(SecretValues::default());
+    }
+    let key = shared
+        .secret_key
+        .lock()
+        .clone()
+        .ok_or_else(|| "no secret key configured".to_string())?;
+    let db = shared.db.clone();
+    let workflow_id = workflow.id.clone();
+    tokio::task::spawn_blocking(move || {
+        secrets::resolve(&db.lock(), &workflow_id, &names, &key)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map(SecretValues::from)
+    .map_err(|e| e.to_string())
+}
+
+fn spawn_execution(shared: Arc<EngineShared>, run: RunRequest, control: ExecutionControl) {
+    tokio::spawn(async move {
+        let RunRequest {
+            execution_id,
+            workflow,
+            profile,
+            input,
+            reply,
+            priority,
+            replay_of,
+            partial,
+            idempotency_key: _,
+        } = run;
+        let db = shared.db.clone();
+        let events = shared.events.clone();
+        let _guard = ActiveGuard {
+            shared: shared.clone(),
+            workflow_id: workflow.id.clone(),
+            execution_id: execution_id.clone(),
+        };
+
+        let record = Execution {
+            id: execution_id.clone(),
+            workflow_id: workflow.id.clone(),
+            status: ExecutionStatus::Running,
+            profile: profile.name.clone(),
+            started_at: chrono::Utc::now(),
+            finished_at: None,
+            error: None,
+            cpu_time_ms: None,
+            rate_limit_wait_ms: None,
+            pinned: false,
+            progress: None,
+        };
+        let recorded = {
+            let db = db.lock();
+            db.insert_execution(&record)
+                .and_then(|_| db.set_execution_input(&execution_id, &input))
+        };
+        if let Err(e) = recorded {
+            tracing::warn!("failed to record execution {}: {}", execution_id, e);
+        }
+        events.emit(ExecutionEvent::ExecutionStarted {
+            execution_id: execution_id.clone(),
+            workflow_id: workflow.id.clone(),
+        });
+
+        let mut ctx = ExecutionContext::new(&execution_id, &workflow.id, input.clone());
+        let _blobs = ctx.blobs.cleanup_guard(&execution_id);
+        if !profile.options.capture_logs {
+            ctx.logs.keep_from(LogLevel::Warn);
+        }
+        ctx.dry_run = profile.options.dry_run_externals;
+        ctx.control = control.clone();
+        ctx.events = events.clone();
+        ctx.pending_inputs = shared.pending_inputs.clone();
+        ctx.rate_limiter = shared.rate_limiter.clone();
+        ctx.workflows = Some(Arc::new(NestedRuns {
+            shared: shared.clone(),
+            options: profile.options.clone(),
+            priority,
+        }));
+        ctx.state = Some(StateStore::new(shared.db.clone()));
+        let logs = ctx.logs.clone();
+        let rate_limit_wait = ctx.rate_limit_wait.clone();
+        if let Some(partial) = &partial {
+            ctx.node_outputs.extend(partial.seeded.clone());
+            logs.push(
+                LogLevel::Info,
+                None,
+                format!("partial run from node {}", partial.from_node),
+                None,
+            );
+        }
+
+        match resolve_secrets(&shared, &workflow).await {
+            Ok(values) => {
+                logs.mask(values.values());
+                ctx.secrets = values;
+            }
+            // Templates referencing them then fail as unresolved.
+            Err(e) => {
+                tracing::warn!("failed to resolve secrets for {}: {}", workflow.id, e);
+                logs.push(
+                    LogLevel::Warn,
+                    None,
+                    format!("secrets unavailable: {}", e),
+                    None,
+                );
+            }
+        }
+
+        match environments::active_variables(&db.lock()) {
+            Ok(variables) => ctx.environment = variables,
+            Err(e) => {
+                tracing::warn!("failed to load environment for {}: {}", workflow.id, e);
+                logs.push(
+                    LogLevel::Warn,
+                    None,
+                    format!("environment unavailable: {}", e),
+                    None,
+                );
+            }
+        }
+
+        let readers = db.lock().readers();
+        let sink = match readers.get_output_sink(&workflow.id) {
+            Ok(sink) => sink.map(SinkWriter::start),
+            Err(e) => {
+                tracing::warn!("failed to load output sink for {}: {}", workflow.id, e);
+                None
+            }
+        };
+
+        let sampler = CpuTimeSampler::start();
+        let result = tokio::select! {
+            _ = control.cancelled() => Err(EngineError::Cancelled),
+            result = run_workflow(
+                &shared,
+                &workflow,
+                ctx,
+                &profile.options,
+                priority,
+                partial.as_ref().map(|partial| &partial.nodes),
+                sink.as_ref(),
+            ) => result,
+        };
+        let cpu_time_ms = sampler.finish().await;
+        if let Some(sink) = sink {
+            sink.finish().await;
+        }
+
+        let (status, error) = match &result {
+            Ok(_) => (ExecutionStatus::Completed, None),
+            Err(EngineError::Cancelled) => (ExecutionStatus::Cancelled, None),
+            Err(e) => (ExecutionStatus::Failed, Some(e.to_string())),
+        };
+        match &error {
+            None => tracing::info!("execution {} finished: {:?}", execution_id, status),
+            Some(e) => tracing::warn!("execution {} failed: {}", execution_id, e),
+        }
+        let rate_limit_wait_ms = rate_limit_wait.total_ms();
+        let finished = serde_json::json!({
+            "status": status,
+            "rate_limit_wait_ms": rate_limit_wait_ms,
+        });
+        match &error {
+            None => logs.push(LogLevel::Info, None, "execution finished", Some(&finished)),
+            Some(e) => logs.push(
+                LogLevel::Error,
+                None,
+                format!("execution failed: {}", e),
+                Some(&finished),
+            ),
+        }
+        if let Err(e) = db.lock().insert_execution_log(&execution_id, &logs.entries()) {
+            tracing::warn!("failed to record execution {} log: {}", execution_id, e);
+        }
+        let recorded = db.lock().finish_execution(
+            &execution_id,
+            status,
+            error.as_deref(),
+            Some(cpu_time_ms),
+            Some(rate_limit_wait_ms),
+        );
+        if let Err(e) = recorded {
+            tracing::warn!("failed to record execution {} result: {}", execution_id, e);
+        }
+        // Failed runs are kept as dead letters with their input for replay. A
+        // replay updates the entry it came from instead of adding another.
+        let dead_letter = match (&result, &replay_of) {
+            (Ok(_), Some(failed_id)) => db.lock().resolve_failed_execution(failed_id, &execution_id),
+            (Ok(_), None) | (Err(EngineError::Cancelled), _) => Ok(()),
+            // Its input alone cannot reproduce a partial run, so it is not kept.
+            (Err(_), None) if partial.is_some() => Ok(()),
+            (Err(e), replay_of) => {
+                let node_id = match e {
+                    EngineError::NodeFailed { node_id, .. } => Some(node_id.clone()),
+                    _ => None,
+                };
+                match replay_of {
+                    Some(failed_id) => db.lock().record_replay_failure(
+                        failed_id,
+                        &execution_id,
+                        node_id.as_deref(),
+                        &e.to_string(),
+                    ),
+                    None => db.lock().insert_failed_execution(&FailedExecution {
+                        id: Uuid::new_v4().to_string(),
+                        execution_id: execution_id.clone(),
+                        workflow_id: workflow.id.clone(),
+                        profile: profile.name.clone(),
+                        node_id,
+                        input,
+                        error: e.to_string(),
+                        failed_at: chrono::Utc::now(),
+                        replay_count: 0,
+                        last_replay_id: None,
+                        resolved_at: None,
+                    }),
+                }
+            }
+        };
+        if let Err(e) = dead_letter {
+            tracing::warn!("failed to record dead letter for execution {}: {}", execution_id, e);
+        }
+        let finished_at = chrono::Utc::now();
+        let notification_context = serde_json::json!({
+            "workflow": { "id": workflow.id, "name": workflow.name },
+            "execution": {
+                "id": execution_id,
+                "status": status,
+                "error": error,
+                "started_at": record.started_at,
+                "finished_at": finished_at,
+                "duration_ms": (finished_at - record.started_at).num_milliseconds().max(0),
+            },
+            "output": result.as_ref().ok().map(|ctx| terminal_output(&workflow, ctx)),
+        });
+        events.emit(ExecutionEvent::ExecutionFinished {
+            execution_id: execution_id.clone(),
+            workflow_id: workflow.id.clone(),
+            status,
+            error,
+        });
+        match readers.get_notification_rules(&workflow.id) {
+            Ok(rules) => {
+                let rendered = notifications::render(&rules, status, &notification_context);
+                send_notifications(
+                    &shared,
+                    &execution_id,
+                    &workflow.id,
+                    rendered,
+                    notification_context,
+                );
+            }
+            Err(e) => {
+                tracing::warn!("failed to load notification rules for {}: {}", workflow.id, e)
+            }
+        }
+        if let Some(reply) = reply {
+            let _ = reply.send(result.map(|ctx| terminal_output(&workflow, &ctx)));
+        }
+    });
+}
+
+// Desktop notifications go out as events for the app to show; the others are
+// delivered off the run's task, so a slow endpoint holds nothing up.
+fn send_notifications(
+    shared: &EngineShared,
+    execution_id: &str,
+    workflow_id: &str,
+    notifications: Vec<Notification>,
+    context: serde_json::Value,
+) {
+    let (desktop, remote): (Vec<Notification>, Vec<Notification>) = notifications
+        .into_iter()
+        .partition(|notification| notification.channel == NotificationChannel::Desktop);
+    if shared.desktop_notifications.load(Ordering::SeqCst) {
+        for notification in desktop {
+            shared.events.emit(ExecutionEvent::Notification {
+                execution_id: execution_id.to_string(),
+                workflow_id: workflow_id.to_string(),
+                title: notification.title,
+                message: notification.message,
+            });
+        }
+    }
+    if !remote.is_empty() {
+        tokio::spawn(notifications::deliver(remote, context));
+    }
+}
+
+// Runs the workflows `call_workflow` nodes call, inside the calling run.
+// Nested runs are not recorded as executions of their own: their log entries
+// join the caller's, and a failure fails the calling node.
+struct NestedRuns {
+    shared: Arc<EngineShared>,
+    options: ExecutionOptions,
+    priority: Priority,
+}
+
+impl std::fmt::Debug for NestedRuns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NestedRuns")
+            .field("options", &self.options)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl WorkflowRunner for NestedRuns {
+    async fn run(
+        &self,
+        workflow_id: &str,
+        input: serde_json::Value,
+        caller: &ExecutionContext,
+    ) -> nodes::Result<serde_json::Value> {
+        let node_id = caller.current_node.clone().unwrap_or_default();
+        let mut call_stack = caller.call_stack.clone();
+        call_stack.push(caller.workflow_id.clone());
+        let workflow = {
+            let readers = self.shared.db.lock().readers();
+            let workflow = readers.get_workflow(workflow_id).map_err(|e| {
+                NodeError::Failed(format!("cannot load workflow {}: {}", workflow_id, e))
+            })?;
+            if let Some(cycle) = call_cycle(&readers, &call_stack, &workflow) {
+                return Err(NodeError::CallCycle(cycle));
+            }
+            workflow
+        };
+
+        let execution_id = format!("{}/{}", caller.execution_id, node_id);
+        let mut ctx = ExecutionContext::new(&execution_id, &workflow.id, input);
+        ctx.dry_run = caller.dry_run;
+        ctx.control = caller.control.clone();
+        ctx.events = caller.events.clone();
+        ctx.pending_inputs = caller.pending_inputs.clone();
+        ctx.rate_limiter = caller.rate_limiter.clone();
+        ctx.rate_limit_wait = caller.rate_limit_wait.clone();
+        ctx.workflows = caller.workflows.clone();
+        ctx.environment = caller.environment.clone();
+        ctx.blobs = caller.blobs.clone();
+        ctx.state = caller.state.clone();
+        ctx.call_stack = call_stack;
+        if !self.options.capture_logs {
+            ctx.logs.keep_from(LogLevel::Warn);
+        }
+        let logs = ctx.logs.clone();
+        match resolve_secrets(&self.shared, &workflow).await {
+            Ok(values) => {
+                caller.logs.mask(values.values());
+                logs.mask(values.values());
+                ctx.secrets = values;
+            }
+            Err(e) => logs.push(
+                LogLevel::Warn,
+                None,
+                format!("secrets unavailable: {}", e),
+                None,
+            ),
+        }
+
+        caller.events.emit(ExecutionEvent::SubWorkflowStarted {
+            execution_id: execution_id.clone(),
+            parent_execution_id: caller.execution_id.clone(),
+            node_id: node_id.clone(),
+            workflow_id: workflow.id.clone(),
+        });
+        let result = run_workflow(
+            &self.shared,
+            &workflow,
+            ctx,
+            &self.options,
+            self.priority,
+            None,
+            None,
+        )
+        .await;
+        caller.logs.append_nested(&node_id, logs.entries());
+
+        let (status, error) = match &result {
+            Ok(_) => (ExecutionStatus::Completed, None),
+            Err(EngineError::Cancelled) => (ExecutionStatus::Cancelled, None),
+            Err(e) => (ExecutionStatus::Failed, Some(e.to_string())),
+        };
+        caller.events.emit(ExecutionEvent::SubWorkflowFinished {
+            execution_id,
+            parent_execution_id: caller.execution_id.clone(),
+            node_id,
+            workflow_id: workflow.id.clone(),
+            status,
+            error,
+        });
+        match result {
+            Ok(ctx) => Ok(terminal_output(&workflow, &ctx)),
+            Err(EngineError::Cancelled) => Err(NodeError::Cancelled),
+            Err(e) => Err(NodeError::Failed(format!(
+                "called workflow {} failed: {}",
+                workflow.id, e
+            ))),
+        }
+    }
+}
+
+// Workflows `workflow` would end up calling back into, following its
+// `call_workflow` nodes through every workflow they call, whether or not a
+// run would reach them. Returns the chain of calls from the first workflow
+// in `call_stack` that is called again, or None. Workflows that cannot be
+// loaded are left out; calling them fails on its own.
+fn call_cycle(db: &ReadPool, call_stack: &[String], workflow: &Workflow) -> Option<Vec<String>> {
+    let mut path = call_stack.to_vec();
+    let mut visited = HashSet::new();
+    find_call_cycle(db, &mut path, workflow, &mut visited)
+}
+
+fn find_call_cycle(
+    db: &ReadPool,
+    path: &mut Vec<String>,
+    workflow: &Workflow,
+    visited: &mut HashSet<String>,
+) -> Option<Vec<String>> {
+    if let Some(start) = path.iter().position(|id| *id == workflow.id) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(workflow.id.clone());
+        return Some(cycle);
+    }
+    if !visited.insert(workflow.id.clone()) {
+        return None;
+    }
+    path.push(workflow.id.clone());
+    let called: BTreeSet<String> = workflow
+        .nodes
+        .iter()
+        .filter(|node| node.node_type == call_workflow::NODE_TYPE)
+        .filter_map(|node| call_workflow::workflow_id(&node.data).ok())
+        .collect();
+    for id in called {
+        let Ok(callee) = db.get_workflow(&id) else {
+            continue;
+        };
+        if let Some(cycle) = find_call_cycle(db, path, &callee, visited) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    None
+}
+
+// Nodes run in waves: each starts up to `max_parallelism` nodes whose
+// upstream nodes have all finished, and the next starts once all of them
+// have. Hooks, breakpoints and outputs are handled one node at a time.
+async fn run_workflow(
+    shared: &EngineShared,
+    workflow: &Workflow,
+    mut ctx: ExecutionContext,
+    options: &ExecutionOptions,
+    priority: Priority,
+    // Nodes outside it are skipped; their outputs are already in `ctx`.
+    only: Option<&HashSet<String>>,
+    sink: Option<&SinkWriter>,
+) -> Result<ExecutionContext> {
+    let order = topological_order(workflow)?;
+    // Order outputs arrived in, for nodes receiving branches. Seeded outputs
+    // of a partial run count as first.
+    let mut delivered: Vec<String> = ctx.node_outputs.keys().cloned().collect();
+    delivered.sort();
+    let deadlines = BranchDeadlines::new(&shared.registry, workflow);
+    for node_id in &delivered {
+        deadlines.delivered(workflow, node_id);
+    }
+    let mut pending: Vec<&WorkflowNode> = order
+        .iter()
+        .filter(|node_id| match only {
+            Some(only) => only.contains(*node_id),
+            None => true,
+        })
+        .filter_map(|node_id| workflow.nodes.iter().find(|node| &node.id == node_id))
+        .collect();
+    let total = pending.len();
+    let mut completed = 0;
+    report_progress(shared, &ctx, completed, total);
+    // The first node killed, which fails the run once the rest has run
+    let mut killed = None;
+
+    while !pending.is_empty() {
+        let waiting: HashSet<&str> = pending.iter().map(|node| node.id.as_str()).collect();
+        let ready: Vec<&WorkflowNode> = pending
+            .iter()
+            .copied()
+            .filter(|node| {
+                !workflow
+                    .edges
+                    .iter()
+                    .any(|edge| edge.target == node.id && waiting.contains(edge.source.as_str()))
+            })
+            .take(options.max_parallelism.max(1))
+            .collect();
+        pending.retain(|node| !ready.iter().any(|ready| ready.id == node.id));
+
+        let mut wave = Vec::with_capacity(ready.len());
+        for node in ready {
+            // Paused runs hold here between nodes until resumed or stopped.
+            ctx.control.wait_while_paused().await;
+            if ctx.control.is_cancelled() {
+                return Err(EngineError::Cancelled);
+            }
+
+            // A disabled node passes its input on as its output, so the nodes
+            // after it run as if their edge came straight from upstream.
+            if is_disabled(node) {
+                let output = node_input(workflow, &node.id, &ctx, None);
+                ctx.events.emit(ExecutionEvent::NodeSkipped {
+                    execution_id: ctx.execution_id.clone(),
+                    node_id: node.id.clone(),
+                });
+                ctx.logs.push(
+                    LogLevel::Info,
+                    Some(&node.id),
+                    "node disabled, input passed through",
+                    None,
+                );
+                ctx.node_outputs.insert(node.id.clone(), output);
+                deadlines.delivered(workflow, &node.id);
+                delivered.push(node.id.clone());
+                completed += 1;
+                report_progress(shared, &ctx, completed, total);
+                continue;
+            }
+            wait_for_memory(&shared.resources, &ctx, &node.id).await?;
+
+            let executor = shared
+                .registry
+                .runnable(&node.node_type)
+                .map_err(|source| EngineError::NodeFailed {
+                    node_id: node.id.clone(),
+                    source,
+                })?;
+
+            let mut input = node_input(
+                workflow,
+                &node.id,
+                &ctx,
+                executor.receives_branches().then_some(delivered.as_slice()),
+            );
+            if let Err(source) = shared.hooks.before_node(node, &mut input, &mut ctx) {
+                return Err(fail_node(shared, node, source, Duration::ZERO, &ctx));
+            }
+            if options.breakpoints_enabled && ctx.control.debugger().should_hold(node) {
+                hold_at_breakpoint(node, &input, &ctx).await?;
+            }
+            if options.capture_io {
+                tracing::debug!("node {} input: {}", node.id, input);
+            }
+
+            let cache_key = cache_key(executor.as_ref(), node, &input, &ctx);
+            let cached = cache_key.as_ref().and_then(|key| {
+                let readers = shared.db.lock().readers();
+                readers
+                    .get_cached_output(&node.node_type, key)
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("failed to read node cache for {}: {}", node.id, e);
+                        None
+                    })
+            });
+            wave.push(PreparedNode {
+                node,
+                executor,
+                input,
+                cache_key,
+                cached,
+            });
+        }
+
+        // Nodes running side by side each get a copy of the context naming
+        // them as the current node; the copies share the run's log and events.
+        let contexts: Vec<ExecutionContext> = if wave.len() > 1 {
+            wave.iter()
+                .map(|prepared| {
+                    let mut node_ctx = ctx.clone();
+                    node_ctx.current_node = Some(prepared.node.id.clone());
+                    node_ctx
+                })
+                .collect()
+        } else {
+            ctx.current_node = wave.first().map(|prepared| prepared.node.id.clone());
+            Vec::new()
+        };
+        let executed = join_nodes(
+            wave.into_iter()
+                .enumerate()
+                .map(|(i, prepared)| {
+                    let node_ctx = contexts.get(i).unwrap_or(&ctx);
+                    execute_node(shared, workflow, node_ctx, prepared, &deadlines, priority)
+                })
+                .collect(),
+        )
+        .await?;
+        ctx.current_node = None;
+
+        // In the order the nodes finished
+        for outcome in executed {
+            let ExecutedNode {
+                node,
+                mut output,
+                cache_key,
+                from_cache,
+                duration,
+            } = match outcome {
+                NodeOutcome::Ran(executed) => executed,
+                NodeOutcome::Killed { node, error } => {
+                    let skipped = downstream_nodes(workflow, &node.id);
+                    let waiting = pending.len();
+                    pending.retain(|pending| !skipped.contains(&pending.id));
+                    // The killed node and the branch it takes down are done with.
+                    completed += 1 + waiting - pending.len();
+                    report_progress(shared, &ctx, completed, total);
+                    killed.get_or_insert(error);
+                    continue;
+                }
+            };
+            // Blobs go with the run, so a cached reference would outlive its bytes.
+            let cacheable = !from_cache && !run_blobs::contains_blob(&output);
+            if let Some(key) = cache_key.as_ref().filter(|_| cacheable) {
+                let stored = shared.db.lock().put_cached_output(
+                    &node.node_type,
+                    key,
+                    &workflow.id,
+                    &output,
+                    node_cache::ttl(&node.data),
+                );
+                if let Err(e) = stored {
+                    tracing::warn!("failed to cache output of node {}: {}", node.id, e);
+                }
+            }
+            // Hooks see cached outputs as they were stored, so what they change
+            // is never cached.
+            if let Err(source) = shared.hooks.after_node(node, &mut output, duration, &mut ctx) {
+                return Err(fail_node(shared, node, source, duration, &ctx));
+            }
+            let duration_ms = duration.as_millis() as u64;
+            ctx.events.emit(ExecutionEvent::NodeCompleted {
+                execution_id: ctx.execution_id.clone(),
+                node_id: node.id.clone(),
+                duration_ms,
+            });
+            ctx.logs.push(
+                LogLevel::Info,
+                Some(&node.id),
+                format!("node completed in {} ms", duration_ms),
+                Some(&output),
+            );
+            // Cache hits and simulated effects would skew the timings estimates use.
+            if !from_cache && !ctx.dry_run {
+                if let Err(e) = shared.db.lock().record_node_duration(&node.node_type, duration_ms) {
+                    tracing::warn!("failed to record duration of node {}: {}", node.id, e);
+                }
+            }
+            if options.capture_io {
+                tracing::debug!("node {} output: {}", node.id, output);
+            }
+            if let Some(sink) = sink {
+                sink.push(SinkRecord {
+                    execution_id: ctx.execution_id.clone(),
+                    workflow_id: ctx.workflow_id.clone(),
+                    node_id: node.id.clone(),
+                    output: output.clone(),
+                    produced_at: chrono::Utc::now(),
+                });
+            }
+            ctx.node_outputs.insert(node.id.clone(), output);
+            delivered.push(node.id.clone());
+            completed += 1;
+            report_progress(shared, &ctx, completed, total);
+        }
+    }
+
+    match killed {
+        Some(error) => Err(error),
+        None => Ok(ctx),
+    }
+}
+
+// A node about to execute, with its input and any cached output.
+struct PreparedNode<'a> {
+    node: &'a WorkflowNode,
+    executor: Arc<dyn NodeExecutor>,
+    input: serde_json::Value,
+    cache_key: Option<String>,
+    cached: Option<serde_json::Value>,
+}
+
+enum NodeOutcome<'a> {
+    Ran(ExecutedNode<'a>),
+    // Failed by `kill_node`, which leaves the node's siblings running
+    Killed {
+        node: &'a WorkflowNode,
+        error: EngineError,
+    },
+}
+
+struct ExecutedNode<'a> {
+    node: &'a WorkflowNode,
+    output: serde_json::Value,
+    cache_key: Option<String>,
+    from_cache: bool,
+    duration: Duration,
+}
+
+// Runs a prepared node, or serves its cached output, waiting for its rate
+// limit bucket and a worker first.
+async fn execute_node<'a>(
+    shared: &EngineShared,
+    workflow: &Workflow,
+    ctx: &ExecutionContext,
+    prepared: PreparedNode<'a>,
+    deadlines: &BranchDeadlines,
+    priority: Priority,
+) -> Result<NodeOutcome<'a>> {
+    let PreparedNode {
+        node,
+        executor,
+        input,
+        cache_key,
+        cached,
+    } = prepared;
+
+    // Waiting here keeps the hold out of the node's duration. Acquiring
+    // only fails once the run is stopped. Cache hits make no request.
+    if cached.is_none() {
+        let bucket = rate_limit::bucket_for(node);
+        let waited = ctx.acquire(bucket).await.map_err(|_| EngineError::Cancelled)?;
+        if !waited.is_zero() {
+            ctx.logs.push(
+                LogLevel::Info,
+                Some(&node.id),
+                format!(
+                    "waited {} ms for rate limit bucket {}",
+                    waited.as_millis(),
+                    bucket
+                ),
+                None,
+            );
+        }
+    }
+
+    // Held only while the node runs, so higher-priority runs get the
+    // worker at this run's next node boundary.
+    let worker = if cached.is_none() && executor.needs_worker() {
+        let (worker, waited) = shared
+            .workers
+            .acquire(priority, &ctx.control)
+            .await
+            .map_err(|_| EngineError::Cancelled)?;
+        if !waited.is_zero() {
+            ctx.logs.push(
+                LogLevel::Info,
+                Some(&node.id),
+                format!("waited {} ms for a worker", waited.as_millis()),
+                None,
+            );
+        }
+        Some(worker)
+    } else {
+        None
+    };
+
+    ctx.events.emit(ExecutionEvent::NodeStarted {
+        execution_id: ctx.execution_id.clone(),
+        node_id: node.id.clone(),
+    });
+    ctx.logs.push(LogLevel::Info, Some(&node.id), "node started", Some(&input));
+    let started = std::time::Instant::now();
+    let kill = CancellationToken::new();
+    let _running = register_node_run(shared, ctx, node, started, kill.clone());
+
+    let from_cache = cached.is_some();
+    let output = match cached {
+        Some(output) => {
+            ctx.logs
+                .push(LogLevel::Info, Some(&node.id), "output served from node cache", None);
+            Ok(output)
+        }
+        None => {
+            let mut execution = std::pin::pin!(killable(&kill, executor.execute(node, input, ctx)));
+            loop {
+                // Taken before the deadline is read, so arming in between wakes it.
+                let armed_changed = deadlines.armed_changed.notified();
+                let Some(pending) = deadlines.for_node(&node.id) else {
+                    tokio::select! {
+                        output = &mut execution => break output,
+                        _ = armed_changed => continue,
+                    }
+                };
+                tokio::select! {
+                    output = &mut execution => break output,
+                    _ = tokio::time::sleep_until(pending.deadline) => {
+                        return Err(branch_timed_out(workflow, ctx, deadlines, &pending));
+                    }
+                    _ = armed_changed => {}
+                }
+            }
+        }
+    };
+    drop(worker);
+    let duration = started.elapsed();
+    if output.is_ok() {
+        deadlines.delivered(workflow, &node.id);
+    }
+    match output {
+        Ok(output) => Ok(NodeOutcome::Ran(ExecutedNode {
+            node,
+            output,
+            cache_key,
+            from_cache,
+            duration,
+        })),
+        Err(NodeError::Cancelled) => Err(EngineError::Cancelled),
+        Err(NodeError::Killed) => Ok(NodeOutcome::Killed {
+            node,
+            error: fail_node(shared, node, NodeError::Killed, duration, ctx),
+        }),
+        Err(source) => Err(fail_node(shared, node, source, duration, ctx)),
+    }
+}
+
+// Polls `nodes` together. Returns their results in the order they finished,
+// or the first error, dropping (and so cancelling) the nodes still running.
+async fn join_nodes<T>(nodes: Vec<impl Future<Output = Result<T>>>) -> Result<Vec<T>> {
+    let mut running: Vec<_> = nodes.into_iter().map(|node| Some(Box::pin(node))).collect();
+    let mut finished = Vec::with_capacity(running.len());
+    std::future::poll_fn(|cx| {
+        for slot in running.iter_mut() {
+            let Some(node) = slot else {
+                continue;
+            };
+            if let std::task::Poll::Ready(result) = node.as_mut().poll(cx) {
+                *slot = None;
+                match result {
+                    Ok(output) => finished.push(output),
+                    Err(e) => return std::task::Poll::Ready(Err(e)),
+                }
+            }
+        }
+        if running.iter().all(Option::is_none) {
+            std::task::Poll::Ready(Ok(()))
+        } else {
+            std::task::Poll::Pending
+        }
+    })
+    .await?;
+    Ok(finished)
+}
+
+fn register_node_run<'a>(
+    shared: &'a EngineShared,
+    ctx: &ExecutionContext,
+    node: &WorkflowNode,
+    started: std::time::Instant,
+    kill: CancellationToken,
+) -> NodeRunGuard<'a> {
+    let key = (ctx.execution_id.clone(), node.id.clone());
+    let info = RunningNode {
+        execution_id: ctx.execution_id.clone(),
+        workflow_id: ctx.workflow_id.clone(),
+        node_id: node.id.clone(),
+        node_type: node.node_type.clone(),
+        started_at: chrono::Utc::now(),
+        elapsed_ms: 0,
+    };
+    shared
+        .running_nodes
+        .lock()
+        .insert(key.clone(), NodeRun { info, started, kill });
+    NodeRunGuard { shared, key }
+}
+
+// Dropping `execution` on a kill cancels whatever the node was awaiting.
+async fn killable(
+    kill: &CancellationToken,
+    execution: impl Future<Output = nodes::Result<serde_json::Value>>,
+) -> nodes::Result<serde_json::Value> {
+    tokio::select! {
+        output = execution => output,
+        _ = kill.cancelled() => Err(NodeError::Killed),
+    }
+}
+
+// Nested runs have no execution record of their own; their progress is only
+// emitted, under their own execution id.
+fn report_progress(shared: &EngineShared, ctx: &ExecutionContext, completed: usize, total: usize) {
+    let progress = ExecutionProgress::new(completed, total);
+    ctx.events.emit(ExecutionEvent::ExecutionProgress {
+        execution_id: ctx.execution_id.clone(),
+        progress,
+    });
+    if ctx.call_stack.is_empty() {
+        if let Err(e) = shared.db.lock().set_execution_progress(&ctx.execution_id, &progress) {
+            tracing::warn!("failed to record progress of execution {}: {}", ctx.execution_id, e);
+        }
+    }
+}
+
+// Reports a node's failure to the run's events and log and to the hooks.
+fn fail_node(
+    shared: &EngineShared,
+    node: &WorkflowNode,
+    source: NodeError,
+    duration: Duration,
+    ctx: &ExecutionContext,
+) -> EngineError {
+    shared.hooks.node_failed(node, &source, duration, ctx);
+    ctx.events.emit(ExecutionEvent::NodeFailed {
+        execution_id: ctx.execution_id.clone(),
+        node_id: node.id.clone(),
+        error: source.to_string(),
+    });
+    ctx.logs.push(
+        LogLevel::Error,
+        Some(&node.id),
+        format!("node failed: {}", source),
+        None,
+    );
+    EngineError::NodeFailed {
+        node_id: node.id.clone(),
+        source,
+    }
+}
+
+// A node receiving branches with a branch timeout, once its first branch
+// has arrived.
+#[derive(Clone)]
+struct ArmedBranches {
+    node_id: String,
+    timeout: Duration,
+    deadline: tokio::time::Instant,
+}
+
+// Until a node receiving branches runs, every node upstream of it must
+// finish within its branch timeout of the first branch arriving. Nodes of a
+// wave arm it as they finish, while their siblings still run.
+struct BranchDeadlines {
+    state: Mutex<BranchState>,
+    // Notified whenever a node is armed, so running nodes pick up the deadline
+    armed_changed: Notify,
+}
+
+#[derive(Default)]
+struct BranchState {
+    // Nodes with a branch timeout, with the nodes upstream of each
+    waiting: Vec<(String, Duration, HashSet<String>)>,
+    armed: Vec<(ArmedBranches, HashSet<String>)>,
+    arrived: HashSet<String>,
+}
+
+impl BranchDeadlines {
+    fn new(registry: &NodeRegistry, workflow: &Workflow) -> Self {
+        let waiting = workflow
+            .nodes
+            .iter()
+            .filter_map(|node| {
+                let executor = registry.get(&node.node_type)?;
+                let timeout = executor
+                    .branch_timeout(&node.data)
+                    .filter(|_| executor.receives_branches())?;
+                Some((node.id.clone(), timeout, upstream_nodes(workflow, &node.id)))
+            })
+            .collect();
+        Self {
+            state: Mutex::new(BranchState {
+                waiting,
+                ..BranchState::default()
+            }),
+            armed_changed: Notify::new(),
+        }
+    }
+
+    // Arms the nodes `node_id` feeds directly.
+    fn delivered(&self, workflow: &Workflow, node_id: &str) {
+        let mut state = self.state.lock();
+        state.arrived.insert(node_id.to_string());
+        let (armed, waiting) = std::mem::take(&mut state.waiting)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(target, _, _)| {
+                workflow
+                    .edges
+                    .iter()
+                    .any(|edge| edge.source == node_id && edge.target == *target)
+            });
+        state.waiting = waiting;
+        if armed.is_empty() {
+            return;
+        }
+        let now = tokio::time::Instant::now();
+        state.armed.extend(armed.into_iter().map(|(node_id, timeout, upstream)| {
+            (
+                ArmedBranches {
+                    node_id,
+                    timeout,
+                    deadline: now + timeout,
+                },
+                upstream,
+            )
+        }));
+        self.armed_changed.notify_waiters();
+    }
+
+    // The earliest deadline `node_id` has to finish by, if it feeds an armed node.
+    fn for_node(&self, node_id: &str) -> Option<ArmedBranches> {
+        self.state
+            .lock()
+            .armed
+            .iter()
+            .filter(|(_, upstream)| upstream.contains(node_id))
+            .map(|(armed, _)| armed)
+            .min_by_key(|armed| armed.deadline)
+            .cloned()
+    }
+
+    // Branches into `node_id` whose output has not arrived
+    fn missing(&self, workflow: &Workflow, node_id: &str) -> Vec<String> {
+        let state = self.state.lock();
+        let mut missing: Vec<String> = workflow
+            .edges
+            .iter()
+            .filter(|edge| edge.target == node_id && !state.arrived.contains(&edge.source))
+            .map(|edge| edge.source.clone())
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    }
+}
+
+// Fails the node whose branches did not all arrive in time, naming the
+// branches still missing.
+fn branch_timed_out(
+    workflow: &Workflow,
+    ctx: &ExecutionContext,
+    deadlines: &BranchDeadlines,
+    armed: &ArmedBranches,
+) -> EngineError {
+    let missing = deadlines.missing(workflow, &armed.node_id);
+    let source = NodeError::Failed(format!(
+        "timed out after {} ms waiting for branches from: {}",
+        armed.timeout.as_millis(),
+        missing.join(", ")
+    ));
+    ctx.events.emit(ExecutionEvent::NodeFailed {
+        execution_id: ctx.execution_id.clone(),
+        node_id: armed.node_id.clone(),
+        error: source.to_string(),
+    });
+    ctx.logs.push(
+        LogLevel::Error,
+        Some(&armed.node_id),
+        format!("node failed: {}", source),
+        None,
+    );
+    EngineError::NodeFailed {
+        node_id: armed.node_id.clone(),
+        source,
+    }
+}
+
+// Key of the node's cache entry, or None when its output is not cached: the
+// node did not opt in, its executor is not deterministic, the run is a dry
+// run, or its config does not render (the executor reports that error).
+fn cache_key(
+    executor: &dyn NodeExecutor,
+    node: &WorkflowNode,
+    input: &serde_json::Value,
+    ctx: &ExecutionContext,
+) -> Option<String> {
+    if ctx.dry_run || !node_cache::applies(executor, &node.data) {
+        return None;
+    }
+    let config = ctx.render(&node.data).ok()?;
+    node_cache::input_hash(&config, input).ok()
+}
+
+async fn hold_at_breakpoint(
+    node: &WorkflowNode,
+    input: &serde_json::Value,
+    ctx: &ExecutionContext,
+) -> Result<()> {
+    let snapshot = ContextSnapshot::capture(ctx, &node.id, input);
+    ctx.logs.push(LogLevel::Info, Some(&node.id), "held at breakpoint", None);
+    ctx.events.emit(ExecutionEvent::BreakpointHit {
+        execution_id: ctx.execution_id.clone(),
+        context: snapshot.clone(),
+    });
+
+    tokio::select! {
+        _ = ctx.control.cancelled() => return Err(EngineError::Cancelled),
+        _ = ctx.control.debugger().hold(snapshot) => {}
+    }
+    // A pause requested while held still applies.
+    ctx.control.wait_while_paused().await;
+    if ctx.control.is_cancelled() {
+        return Err(EngineError::Cancelled);
+    }
+    Ok(())
+}
+
+// Holds before `node_id` while free memory is below the floor, announcing the
+// hold once. Cancellation ends the wait; a pause is honoured once it clears.
+async fn wait_for_memory(
+    resources: &ResourceMonitor,
+    ctx: &ExecutionContext,
+    node_id: &str,
+) -> Result<()> {
+    let Some(available_memory_mb) = resources.memory_pressure() else {
+        return Ok(());
+    };
+    tracing::info!(
+        "execution {} throttled before node {}: {} MB free",
+        ctx.execution_id,
+        node_id,
+        available_memory_mb
+    );
+    ctx.logs.push(
+        LogLevel::Warn,
+        Some(node_id),
+        format!("held for memory pressure: {} MB free", available_memory_mb),
+        None,
+    );
+    ctx.events.emit(ExecutionEvent::ResourceThrottled {
+        execution_id: ctx.execution_id.clone(),
+        node_id: node_id.to_string(),
+        available_memory_mb,
+        min_free_memory_mb: resources.min_free_memory_mb(),
+    });
+
+    while resources.memory_pressure().is_some() {
+        tokio::select! {
+            _ = ctx.control.cancelled() => return Err(EngineError::Cancelled),
+            _ = tokio::time::sleep(MEMORY_POLL_INTERVAL) => {}
+        }
+    }
+    ctx.control.wait_while_paused().await;
+    if ctx.control.is_cancelled() {
+        return Err(EngineError::Cancelled);
+    }
+    Ok(())
+}
+
+// A node with no incoming edges receives the run input; with one upstream node,
+// that node's output; with several, an object keyed by upstream node id. A
+// node receiving branches instead gets every upstream output in `delivered`
+// order, plus the upstream nodes with no output yet.
+fn node_input(
+    workflow: &Workflow,
+    node_id: &str,
+    ctx: &ExecutionContext,
+    branches: Option<&[String]>,
+) -> serde_json::Value {
+    let sources: Vec<&str> = workflow
+        .edges
+        .iter()
+        .filter(|edge| edge.target == node_id)
+        .map(|edge| edge.source.as_str())
+        .collect();
+
+    if let Some(delivered) = branches {
+        let arrived = delivered
+            .iter()
+            .filter(|id| sources.contains(&id.as_str()))
+            .filter_map(|id| Some((id.clone(), ctx.node_outputs.get(id)?.clone())))
+            .collect();
+        let mut pending: Vec<String> = sources
+            .iter()
+            .filter(|source| !ctx.node_outputs.contains_key(**source))
+            .map(|source| source.to_string())
+            .collect();
+        pending.sort();
+        pending.dedup();
+        return merge::branch_input(arrived, pending);
+    }
+
+    match sources.as_slice() {
+        [] => ctx.input.clone(),
+        [source] => ctx.node_outputs.get(*source).cloned().unwrap_or_default(),
+        _ => serde_json::Value::Object(
+            sources
+                .iter()
+                .map(|source| {
+                    (
+                        source.to_string(),
+                        ctx.node_outputs.get(*source).cloned().unwrap_or_default(),
+                    )
+                })
+                .collect(),
+        ),
+    }
+}
+
+// The input the edge's target would get if its source output
+// `source_output`, built as a run builds it. The engine passes a node's whole
+// output along each of its edges, so handles only pick the ports checked.
+// Other nodes feeding the target show with a null output. Nothing runs.
+pub fn simulate_edge(
+    registry: &NodeRegistry,
+    workflow: &Workflow,
+    edge_id: &str,
+    source_output: serde_json::Value,
+) -> Result<EdgeSimulation> {
+    let edge = workflow
+        .edges
+        .iter()
+        .find(|edge| edge.id == edge_id)
+        .ok_or_else(|| EngineError::EdgeNotFound(edge_id.to_string()))?;
+    let node = |id: &str| {
+        workflow
+            .nodes
+            .iter()
+            .find(|node| node.id == id)
+            .ok_or_else(|| EngineError::NodeNotFound(id.to_string()))
+    };
+    let source = registry.get(&node(&edge.source)?.node_type);
+    let target = registry.get(&node(&edge.target)?.node_type);
+
+    let mut problems = Vec::new();
+    let source_port = edge.source_handle.as_deref().unwrap_or(DEFAULT_PORT);
+    let target_port = edge.target_handle.as_deref().unwrap_or(DEFAULT_PORT);
+    let mut port_type = |ports: Option<&[nodes::Port]>, name: &str, side: &str, node_id: &str| {
+        let port = ports?.iter().find(|port| port.name == name);
+        if port.is_none() {
+            problems.push(format!("{}.{} is not an {} of that node", node_id, name, side));
+        }
+        port.map(|port| port.port_type)
+    };
+    let source_type = port_type(
+        source.as_ref().and_then(|executor| executor.output_ports()),
+        source_port,
+        "output",
+        &edge.source,
+    );
+    let target_type = port_type(
+        target.as_ref().and_then(|executor| executor.input_ports()),
+        target_port,
+        "input",
+        &edge.target,
+    );
+    if let (Some(output), Some(input)) = (source_type, target_type) {
+        if !output.feeds(input) {
+            problems.push(format!(
+                "{}.{} ({}) cannot feed {}.{} ({})",
+                edge.source, source_port, output, edge.target, target_port, input
+            ));
+        }
+    }
+
+    let mut ctx = ExecutionContext::new("", &workflow.id, serde_json::Value::Null);
+    ctx.node_outputs.insert(edge.source.clone(), source_output);
+    let delivered = [edge.source.clone()];
+    let branches = target.as_ref().is_some_and(|executor| executor.receives_branches());
+    let input = node_input(workflow, &edge.target, &ctx, branches.then_some(&delivered[..]));
+    if let Some(expected) = target_type {
+        if !expected.admits(&input) {
+            problems.push(format!("{} expects input of type {}", edge.target, expected));
+        }
+    }
+    if target.is_none() {
+        problems.push(format!("{} has an unknown node type", edge.target));
+    }
+
+    Ok(EdgeSimulation {
+        edge_id: edge.id.clone(),
+        source: edge.source.clone(),
+        target: edge.target.clone(),
+        source_port: source_port.to_string(),
+        source_type,
+        target_port: target_port.to_string(),
+        target_type,
+        input,
+        satisfied: problems.is_empty(),
+        problems,
+    })
+}
+
+// Output of the node with no outgoing edges; with several such nodes, an
+// object keyed by node id.
+pub fn terminal_output(workflow: &Workflow, ctx: &ExecutionContext) -> serde_json::Value {
+    let terminals: Vec<&str> = workflow
+        .nodes
+        .iter()
+        .filter(|node| !workflow.edges.iter().any(|edge| edge.source == node.id))
+        .map(|node| node.id.as_str())
+        .collect();
+
+    match terminals.as_slice() {
+        [] => serde_json::Value::Null,
+        [node_id] => ctx.node_outputs.get(*node_id).cloned().unwrap_or_default(),
+        _ => serde_json::Value::Object(
+            terminals
+                .iter()
+                .map(|node_id| {
+                    (
+                        node_id.to_string(),
+                        ctx.node_outputs.get(*node_id).cloned().unwrap_or_default(),
+                    )
+                })
+                .collect(),
+        ),
+    }
+}
+
+// `node_id` and every node reachable from it along edges.
+fn downstream_nodes(workflow: &Workflow, node_id: &str) -> HashSet<String> {
+    let mut reached = HashSet::from([node_id.to_string()]);
+    let mut frontier = vec![node_id];
+    while let Some(id) = frontier.pop() {
+        for edge in workflow.edges.iter().filter(|edge| edge.source == id) {
+            if reached.insert(edge.target.clone()) {
+                frontier.push(&edge.target);
+            }
+        }
+    }
+    reached
+}
+
+// Every node from which `node_id` can be reached along edges.
+fn upstream_nodes(workflow: &Workflow, node_id: &str) -> HashSet<String> {
+    let mut reached = HashSet::new();
+    let mut frontier = vec![node_id];
+    while let Some(id) = frontier.pop() {
+        for edge in workflow.edges.iter().filter(|edge| edge.target == id) {
+            if reached.insert(edge.source.clone()) {
+                frontier.push(&edge.source);
+            }
+        }
+    }
+    reached
+}
+
+// Nodes outside `nodes` whose outputs the sub-graph reads, through an edge
+// or a `nodes.<id>` template reference; sorted.
+fn subgraph_dependencies(workflow: &Workflow, nodes: &HashSet<String>) -> BTreeSet<String> {
+    let outside = |id: &str| {
+        !nodes.contains(id) && workflow.nodes.iter().any(|node| node.id == id)
+    };
+    let mut dependencies: BTreeSet<String> = workflow
+        .edges
+        .iter()
+        .filter(|edge| nodes.contains(&edge.target) && outside(&edge.source))
+        .map(|edge| edge.source.clone())
+        .collect();
+    for node in workflow.nodes.iter().filter(|node| nodes.contains(&node.id)) {
+        for reference in template::value_references(&node.data) {
+            let mut segments = reference.split('.');
+            if segments.next() != Some("nodes") {
+                continue;
+            }
+            // `nodes.fetch[0]` indexes the output of `fetch`.
+            let Some(id) = segments.next().and_then(|id| id.split('[').next()) else {
+                continue;
+            };
+            if outside(id) {
+                dependencies.insert(id.to_string());
+            }
+        }
+    }
+    dependencies
+}
+
+pub fn topological_order(workflow: &Workflow) -> Result<Vec<String>> {
+    let mut in_degree: HashMap<&str, usize> = workflow
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), 0))
+        .collect();
+    for edge in &workflow.edges {
+        if let Some(degree) = in_degree.get_mut(edge.target.as_str()) {
+            *degree += 1;
+        }
+    }
+
+    // Seed in declaration order so the result is deterministic.
+    let mut ready: VecDeque<&str> = workflow
+        .nodes
+        .iter()
+        .map(|node| node.id.as_str())
+        .filter(|id| in_degree[id] == 0)
+        .collect();
+
+    let mut order = Vec::with_capacity(workflow.nodes.len());
+    while let Some(id) = ready.pop_front() {
+        order.push(id.to_string());
+        for edge in workflow.edges.iter().filter(|edge| edge.source == id) {
+            if let Some(degree) = in_degree.get_mut(edge.target.as_str()) {
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push_back(edge.target.as_str());
+                }
+            }
+        }
+    }
+
+    if order.len() != workflow.nodes.len() {
+        return Err(EngineError::CycleDetected);
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{edge, engine, node, wait_for_runs, workflow};
+    use serde_json::json;
+
+    // A trigger fanning out to two independent 300 ms delays
+    fn fan_out() -> Workflow {
+        workflow(
+            "fan-out",
+            vec![
+                node("t", "trigger", json!({})),
+                node("a", "delay", json!({ "duration_ms": 300 })),
+                node("b", "delay", json!({ "duration_ms": 300 })),
+            ],
+            vec![edge("t", "a"), edge("t", "b")],
+        )
+    }
+
+    #[test]
+    fn debug_profile_captures_io_and_fast_does_not() {
+        let debug = find_profile("debug").unwrap().options;
+        let fast = find_profile("fast").unwrap().options;
+        assert!(debug.capture_io && debug.capture_logs && debug.breakpoints_enabled);
+        assert!(!fast.capture_io && !fast.capture_logs && !fast.breakpoints_enabled);
+        assert!(fast.max_parallelism > debug.max_parallelism);
+        assert!(matches!(find_profile("turbo"), Err(EngineError::UnknownProfile(_))));
+    }
+
+    #[tokio::test]
+    async fn fast_runs_branches_together_and_debug_one_at_a_time() {
+        let (db, engine) = engine();
+        let workflow = fan_out();
+        db.lock().create_workflow(&workflow).unwrap();
+
+        let mut runs = Vec::new();
+        for profile in ["fast", "debug"] {
+            let profile = find_profile(profile).unwrap();
+            let started = std::time::Instant::now();
+            let execution_id = engine
+                .execute_workflow(&workflow, &profile, Priority::default(), None)
+                .unwrap();
+            wait_for_runs(&engine, &workflow.id).await;
+            runs.push((execution_id, started.elapsed()));
+        }
+        let (fast_id, fast_elapsed) = &runs[0];
+        let (debug_id, debug_elapsed) = &runs[1];
+
+        let db = db.lock();
+        for execution_id in [fast_id, debug_id] {
+            assert_eq!(db.get_execution(execution_id).unwrap().status, ExecutionStatus::Completed);
+        }
+        assert!(*fast_elapsed < Duration::from_millis(550), "fast took {:?}", fast_elapsed);
+        assert!(*debug_elapsed >= Duration::from_millis(600), "debug took {:?}", debug_elapsed);
+
+        // Without log capture only warnings and errors are kept.
+        let fast_log = db.get_execution_log(fast_id).unwrap();
+        assert!(fast_log.iter().all(|entry| entry.level >= LogLevel::Warn), "{:?}", fast_log);
+        let debug_log = db.get_execution_log(debug_id).unwrap();
+        let started = debug_log
+            .iter()
+            .filter(|entry| entry.message == "node started")
+            .count();
+        assert_eq!(started, 3);
+    }
+
+    #[tokio::test]
+    async fn killing_a_node_skips_its_branch_and_not_the_others() {
+        let (db, engine) = engine();
+        let workflow = workflow(
+            "kill",
+            vec![
+                node("t", "trigger", json!({})),
+                node("stuck", "delay", json!({ "duration_ms": 10_000 })),
+                node("after-stuck", "delay", json!({ "duration_ms": 0 })),
+                node("other", "delay", json!({ "duration_ms": 200 })),
+                node("after-other", "delay", json!({ "duration_ms": 0 })),
+            ],
+            vec![
+                edge("t", "stuck"),
+                edge("stuck", "after-stuck"),
+                edge("t", "other"),
+                edge("other", "after-other"),
+            ],
+        );
+        db.lock().create_workflow(&workflow).unwrap();
+
+        let profile = find_profile("fast").unwrap();
+        let started = std::time::Instant::now();
+        let execution_id = engine
+            .execute_workflow(&workflow, &profile, Priority::default(), None)
+            .unwrap();
+        while !engine.list_running_nodes().iter().any(|node| node.node_id == "stuck") {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        engine.kill_node(&execution_id, "stuck").unwrap();
+        assert!(matches!(
+            engine.kill_node(&execution_id, "t"),
+            Err(EngineError::NodeNotRunning { .. })
+        ));
+        wait_for_runs(&engine, &workflow.id).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let execution = db.lock().get_execution(&execution_id).unwrap();
+        assert_eq!(execution.status, ExecutionStatus::Failed);
+        assert!(execution.error.unwrap().contains("killed"));
+
+        let completed: HashSet<String> = engine
+            .event_replay(&execution_id)
+            .unwrap()
+            .into_iter()
+            .filter_map(|event| match event {
+                ExecutionEvent::NodeCompleted { node_id, .. } => Some(node_id),
+                _ => None,
+            })
+            .collect();
+        let expected: HashSet<String> =
+            ["t", "other", "after-other"].into_iter().map(String::from).collect();
+        assert_eq!(completed, expected);
+    }
+
+    #[tokio::test]
+    async fn a_killed_branch_counts_toward_progress() {
+        let (db, engine) = engine();
+        let workflow = workflow(
+            "kill-progress",
+            vec![
+                node("t", "trigger", json!({})),
+                node("stuck", "delay", json!({ "duration_ms": 10_000 })),
+                node("after-stuck", "delay", json!({ "duration_ms": 0 })),
+                node("other", "delay", json!({ "duration_ms": 0 })),
+            ],
+            vec![edge("t", "stuck"), edge("stuck", "after-stuck"), edge("t", "other")],
+        );
+        db.lock().create_workflow(&workflow).unwrap();
+
+        let profile = find_profile("fast").unwrap();
+        let execution_id = engine
+            .execute_workflow(&workflow, &profile, Priority::default(), None)
+            .unwrap();
+        while !engine.list_running_nodes().iter().any(|node| node.node_id == "stuck") {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        engine.kill_node(&execution_id, "stuck").unwrap();
+        wait_for_runs(&engine, &workflow.id).await;
+
+        let execution = db.lock().get_execution(&execution_id).unwrap();
+        assert_eq!(execution.status, ExecutionStatus::Failed);
+        assert_eq!(execution.progress, Some(ExecutionProgress::new(4, 4)));
+        assert_eq!(execution.progress.unwrap().percent, 100);
+    }
+}
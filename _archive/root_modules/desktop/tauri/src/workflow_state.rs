@@ -0,0 +1,85 @@
+/*!
+ * Workflow State - JSON values a workflow keeps from one run to the next
+ *
+ * Counters, cursors, "last seen" markers: state is keyed per workflow and
+ * deleted with it. Every write goes through the database's one connection,
+ * and an increment reads and writes in a single transaction there, so runs of
+ * the same workflow executing at once never lose each other's updates.
+ */
+
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::database::{Database, DatabaseError};
+
+const MAX_KEY_LEN: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("invalid state key {0:?}: use 1 to 128 characters, none of them control characters")]
+    InvalidKey(String),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+}
+
+pub type Result<T> = std::result::Result<T, StateError>;
+
+fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && key.chars().count() <= MAX_KEY_LEN
+        && !key.chars().any(char::is_control);
+    if valid {
+        Ok(())
+    } else {
+        Err(StateError::InvalidKey(key.to_string()))
+    }
+}
+
+pub fn get(db: &Database, workflow_id: &str, key: &str) -> Result<Option<Value>> {
+    validate_key(key)?;
+    Ok(db.get_workflow_state(workflow_id, key)?)
+}
+
+pub fn set(db: &Database, workflow_id: &str, key: &str, value: &Value) -> Result<()> {
+    validate_key(key)?;
+    Ok(db.set_workflow_state(workflow_id, key, value)?)
+}
+
+// Returns the new value. Fails, changing nothing, if `key` holds anything but
+// an integer or the sum overflows.
+pub fn increment(db: &mut Database, workflow_id: &str, key: &str, by: i64) -> Result<i64> {
+    validate_key(key)?;
+    Ok(db.increment_workflow_state(workflow_id, key, by)?)
+}
+
+// The store as node executors reach it, through `ExecutionContext`.
+#[derive(Clone)]
+pub struct StateStore {
+    db: Arc<Mutex<Database>>,
+}
+
+impl std::fmt::Debug for StateStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateStore").finish_non_exhaustive()
+    }
+}
+
+impl StateStore {
+    pub fn new(db: Arc<Mutex<Database>>) -> Self {
+        Self { db }
+    }
+
+    pub fn get(&self, workflow_id: &str, key: &str) -> Result<Option<Value>> {
+        get(&self.db.lock(), workflow_id, key)
+    }
+
+    pub fn set(&self, workflow_id: &str, key: &str, value: &Value) -> Result<()> {
+        set(&self.db.lock(), workflow_id, key, value)
+    }
+
+    pub fn increment(&self, workflow_id: &str, key: &str, by: i64) -> Result<i64> {
+        increment(&mut self.db.lock(), workflow_id, key, by)
+    }
+}